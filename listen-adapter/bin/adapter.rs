@@ -2,15 +2,15 @@ use actix_cors::Cors;
 use actix_web::{middleware::Logger, web, App, HttpServer};
 use dotenv::dotenv;
 use listen_tracing::setup_tracing;
-use tracing::info;
+use tracing::{error, info};
 
 use listen_adapter::{
-    db::make_db,
+    db::{make_analytics_db, make_db},
     redis_client::make_redis_client,
     redis_subscriber::create_redis_subscriber,
     routes::{
-        get_candlesticks, get_chat, get_metadata, get_price, health_check, query_db, save_chat,
-        top_tokens, version, ws_route,
+        analytics, get_candlesticks, get_chat, get_metadata, get_pools, get_price, get_wallet_pnl,
+        get_wallet_trades, health_check, save_chat, screener, top_tokens, version, ws_route,
     },
     state::AppState,
 };
@@ -29,6 +29,23 @@ async fn main() -> std::io::Result<()> {
 
     let clickhouse_db = make_db().expect("Failed to create Clickhouse DB");
 
+    // the grants of the analytics user are what keeps the queries read-only
+    let analytics_db = make_analytics_db().expect("Failed to create analytics DB");
+    let analytics_db = match analytics_db.is_read_only().await {
+        Ok(true) => Some(analytics_db),
+        Ok(false) => {
+            error!("The analytics user is not read-only, /analytics is disabled");
+            None
+        }
+        Err(e) => {
+            error!(
+                "Failed to check the analytics user, /analytics is disabled: {}",
+                e
+            );
+            None
+        }
+    };
+
     let redis_client = make_redis_client()
         .await
         .expect("Failed to create Redis client");
@@ -37,6 +54,7 @@ async fn main() -> std::io::Result<()> {
         redis_subscriber,
         redis_client,
         clickhouse_db,
+        analytics_db,
     };
     let app_data = web::Data::new(app_state);
 
@@ -48,10 +66,14 @@ async fn main() -> std::io::Result<()> {
             .route("/ws", web::get().to(ws_route))
             .route("/healthz", web::get().to(health_check))
             .route("/top-tokens", web::get().to(top_tokens))
+            .route("/screener", web::get().to(screener))
             .route("/candlesticks", web::get().to(get_candlesticks))
             .route("/metadata", web::get().to(get_metadata))
-            .route("/query", web::post().to(query_db))
+            .route("/analytics", web::post().to(analytics))
             .route("/price", web::get().to(get_price))
+            .route("/pools", web::get().to(get_pools))
+            .route("/wallet-trades", web::get().to(get_wallet_trades))
+            .route("/wallet-pnl", web::get().to(get_wallet_pnl))
             // get and save chat routes are unauthenticated, those are for "shared" chats
             .route("/get-chat", web::get().to(get_chat))
             .route("/save-chat", web::post().to(save_chat))
@@ -0,0 +1,32 @@
+-- The read-only user the /analytics endpoint runs its queries as, run once
+-- as an admin user against CLICKHOUSE_DATABASE of the adapter, e.g.
+--   clickhouse-client --database "${CLICKHOUSE_DATABASE:-default}" --multiquery \
+--     < scripts/analytics_user.sql
+--
+-- readonly=2 lets a query set its own limits within the constraints below
+-- but never write or change readonly, the adapter refuses /analytics if its
+-- user is not read-only. The password is ANALYTICS_CLICKHOUSE_PASSWORD of
+-- the adapter, change it outside of local setups; the grants name no
+-- database so that they apply to the one the script runs against.
+
+CREATE SETTINGS PROFILE IF NOT EXISTS analytics_profile SETTINGS
+    readonly = 2,
+    max_execution_time = 10 MAX 60,
+    max_rows_to_read = 100000000 MAX 1000000000,
+    max_result_rows = 100000 MAX 100000,
+    max_memory_usage = 4000000000 READONLY,
+    max_threads = 4 READONLY;
+
+CREATE ROLE IF NOT EXISTS analytics_reader;
+
+GRANT SELECT ON price_updates TO analytics_reader;
+GRANT SELECT ON wallet_trades TO analytics_reader;
+GRANT SELECT ON candles_1m TO analytics_reader;
+GRANT SELECT ON candles_1h TO analytics_reader;
+GRANT SELECT ON candles_1d TO analytics_reader;
+
+CREATE USER IF NOT EXISTS analytics
+    IDENTIFIED WITH sha256_password BY 'analytics'
+    SETTINGS PROFILE 'analytics_profile';
+
+GRANT analytics_reader TO analytics;
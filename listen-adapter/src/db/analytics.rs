@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use super::{sql::QueryBuilder, ClickhouseDb};
+
+/// the tables analytics queries may read from
+pub const ANALYTICS_TABLES: [&str; 5] = [
+    "price_updates",
+    "wallet_trades",
+    "candles_1m",
+    "candles_1h",
+    "candles_1d",
+];
+
+#[derive(Debug, Clone)]
+pub struct AnalyticsLimits {
+    /// rows returned, the rest is cut off
+    pub max_rows: u64,
+    pub max_execution_secs: u64,
+    /// rows scanned before the query is aborted
+    pub max_rows_to_read: u64,
+}
+
+fn env_u64(key: &str, default: u64) -> u64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+pub static ANALYTICS_LIMITS: LazyLock<AnalyticsLimits> = LazyLock::new(|| AnalyticsLimits {
+    max_rows: env_u64("ANALYTICS_MAX_ROWS", 1000),
+    max_execution_secs: env_u64("ANALYTICS_MAX_EXECUTION_SECS", 10),
+    max_rows_to_read: env_u64("ANALYTICS_MAX_ROWS_TO_READ", 100_000_000),
+});
+
+#[derive(Debug, Error)]
+pub enum AnalyticsError {
+    #[error("{0}")]
+    Rejected(String),
+
+    #[error("Query failed: {0}")]
+    Clickhouse(#[from] clickhouse::error::Error),
+
+    #[error("Invalid row: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyticsResult {
+    pub rows: Vec<Value>,
+    /// more rows matched than were returned
+    pub truncated: bool,
+}
+
+static TABLE_REF: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(?:FROM|JOIN)\s+([^\s,()]+)(\s*\()?").unwrap());
+static COMMA_JOIN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bFROM\s+[^\s,()]+(\s+(AS\s+)?\w+)?\s*,").unwrap());
+static CTE_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b([A-Za-z_]\w*)\s+AS\s*\(").unwrap());
+static FORBIDDEN_CLAUSE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(SETTINGS|FORMAT|INTO\s+OUTFILE)\b").unwrap());
+// reads a table or a dictionary without a FROM or JOIN
+static IN_TABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bIN\s+([^\s(\[{][^\s()]*)").unwrap());
+static LOOKUP_FUNCTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\b(joinGet|dictGet|dictHas|dictIsIn)\w*\s*\(").unwrap());
+static PARAM_NAME: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Za-z_]\w*$").unwrap());
+
+fn reject<T>(reason: impl Into<String>) -> Result<T, AnalyticsError> {
+    Err(AnalyticsError::Rejected(reason.into()))
+}
+
+/// a single select that only reads the allow-listed tables or its own ctes,
+/// the grants of the analytics user are what actually enforces it
+pub fn validate_analytics_sql(sql: &str) -> Result<&str, AnalyticsError> {
+    let sql = sql.trim().trim_end_matches(';').trim_end();
+    let upper = sql.to_uppercase();
+    if !(upper.starts_with("SELECT") || upper.starts_with("WITH")) {
+        return reject("Only SELECT queries are allowed");
+    }
+    if sql.contains(';') {
+        return reject("Only a single statement is allowed");
+    }
+    if sql.contains("--") || sql.contains("/*") || sql.contains('#') {
+        return reject("Comments are not allowed");
+    }
+    if let Some(clause) = FORBIDDEN_CLAUSE.find(sql) {
+        return reject(format!("{} is not allowed", clause.as_str()));
+    }
+
+    if let Some(cap) = IN_TABLE.captures(sql) {
+        return reject(format!(
+            "IN {} is not allowed, use IN (SELECT ...)",
+            &cap[1]
+        ));
+    }
+    if let Some(cap) = LOOKUP_FUNCTION.captures(sql) {
+        return reject(format!("{} is not allowed", &cap[1]));
+    }
+
+    if COMMA_JOIN.is_match(sql) {
+        return reject("Comma joins are not allowed, use JOIN");
+    }
+
+    let ctes: HashSet<&str> = CTE_NAME
+        .captures_iter(sql)
+        .filter_map(|cap| cap.get(1))
+        .map(|name| name.as_str())
+        .collect();
+    for cap in TABLE_REF.captures_iter(sql) {
+        let table = &cap[1];
+        if cap.get(2).is_some() {
+            return reject(format!("Table function {} is not allowed", table));
+        }
+        if !ANALYTICS_TABLES.contains(&table) && !ctes.contains(table) {
+            return reject(format!(
+                "Table {} is not allowed, queries can read {}",
+                table,
+                ANALYTICS_TABLES.join(", ")
+            ));
+        }
+    }
+
+    Ok(sql)
+}
+
+/// the value of a `{name:Type}` query parameter
+fn param_value(name: &str, value: &Value) -> Result<String, AnalyticsError> {
+    if !PARAM_NAME.is_match(name) {
+        return reject(format!("Invalid param name: {}", name));
+    }
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        _ => reject(format!("Param {} must be a string, number or bool", name)),
+    }
+}
+
+impl ClickhouseDb {
+    /// whether the user of the connection can't write, `readonly=2` still
+    /// lets the queries set their limits
+    pub async fn is_read_only(&self) -> Result<bool, AnalyticsError> {
+        let readonly = self
+            .client
+            .query("SELECT toUInt8(getSetting('readonly'))")
+            .fetch_one::<u8>()
+            .await?;
+        Ok(readonly > 0)
+    }
+
+    /// runs a validated select, `params` fill its `{name:Type}` placeholders
+    /// and every row comes back as an object of its columns
+    pub async fn analytics_query(
+        &self,
+        sql: &str,
+        params: &BTreeMap<String, Value>,
+        limits: &AnalyticsLimits,
+    ) -> Result<AnalyticsResult, AnalyticsError> {
+        let sql = validate_analytics_sql(sql)?;
+
+        // `?` is a placeholder of the client, the select is taken as is
+        let mut query = QueryBuilder::new("SELECT formatRow('JSONEachRow', *) FROM (");
+        query
+            .push(&sql.replace('?', "??"))
+            .push(") LIMIT ?")
+            .bind(limits.max_rows + 1);
+
+        let mut query = query
+            .build(&self.client)
+            .with_option("max_execution_time", limits.max_execution_secs.to_string())
+            .with_option("max_rows_to_read", limits.max_rows_to_read.to_string())
+            .with_option("output_format_json_quote_64bit_integers", "0");
+        for (name, value) in params {
+            query = query.with_option(format!("param_{}", name), param_value(name, value)?);
+        }
+
+        let rows = query.fetch_all::<String>().await?;
+        let truncated = rows.len() as u64 > limits.max_rows;
+        let rows = rows
+            .iter()
+            .take(limits.max_rows as usize)
+            .map(|row| serde_json::from_str(row))
+            .collect::<Result<_, _>>()?;
+
+        Ok(AnalyticsResult { rows, truncated })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::make_analytics_db;
+
+    fn rejected(sql: &str) -> bool {
+        matches!(
+            validate_analytics_sql(sql),
+            Err(AnalyticsError::Rejected(_))
+        )
+    }
+
+    #[test]
+    fn test_validate_analytics_sql() {
+        assert!(validate_analytics_sql(
+            "SELECT pubkey, sum(swap_amount) FROM price_updates GROUP BY pubkey;"
+        )
+        .is_ok());
+        assert!(validate_analytics_sql(
+            "WITH buys AS (SELECT * FROM wallet_trades WHERE is_buy) \
+             SELECT b.pubkey FROM buys b JOIN price_updates p ON b.pubkey = p.pubkey"
+        )
+        .is_ok());
+        assert!(validate_analytics_sql("SELECT count() FROM (SELECT * FROM candles_1m)").is_ok());
+
+        assert!(rejected("DROP TABLE price_updates"));
+        assert!(rejected("SELECT 1; DROP TABLE price_updates"));
+        assert!(rejected("SELECT * FROM system.users"));
+        assert!(rejected("SELECT * FROM `system`.`users`"));
+        assert!(rejected("SELECT * FROM url('http://example.com', CSV)"));
+        assert!(rejected(
+            "SELECT * FROM price_updates SETTINGS max_execution_time = 0"
+        ));
+        assert!(rejected("SELECT * FROM/**/system.users"));
+        assert!(rejected("SELECT * FROM price_updates p, system.users"));
+        assert!(rejected(
+            "SELECT * FROM price_updates WHERE owner IN system.users"
+        ));
+        assert!(rejected(
+            "SELECT * FROM price_updates WHERE pubkey GLOBAL IN secret_table"
+        ));
+        assert!(rejected(
+            "SELECT joinGet('system.users', 'name', owner) FROM price_updates"
+        ));
+        assert!(rejected(
+            "SELECT dictGetString('secrets', 'value', toUInt64(1)) FROM price_updates"
+        ));
+        assert!(validate_analytics_sql(
+            "SELECT * FROM price_updates WHERE pubkey IN (SELECT pubkey FROM wallet_trades) \
+             AND owner IN {owners:Array(String)} AND quote_mint IN ['a', 'b']"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_param_value() {
+        assert_eq!(param_value("mint", &Value::from("abc")).unwrap(), "abc");
+        assert_eq!(param_value("limit", &Value::from(10)).unwrap(), "10");
+        assert!(param_value("a&readonly", &Value::from(0)).is_err());
+        assert!(param_value("mints", &serde_json::json!(["abc"])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_analytics_query() -> anyhow::Result<()> {
+        let db = make_analytics_db()?;
+        let result = db
+            .analytics_query(
+                "SELECT pubkey, count() AS trades FROM price_updates \
+                 WHERE timestamp > {since:UInt64} GROUP BY pubkey ORDER BY trades DESC",
+                &BTreeMap::from([("since".to_string(), Value::from(0))]),
+                &ANALYTICS_LIMITS,
+            )
+            .await?;
+        println!("{:#?}", result);
+
+        Ok(())
+    }
+}
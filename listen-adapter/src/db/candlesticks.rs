@@ -1,8 +1,8 @@
-use super::ClickhouseDb;
+use super::{sql::QueryBuilder, ClickhouseDb};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Candlestick {
     pub timestamp: u64, // TODO standardize to regular iso string
     pub open: f64,
@@ -12,7 +12,7 @@ pub struct Candlestick {
     pub volume: f64,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum CandlestickInterval {
     FifteenSeconds,
     ThirtySeconds,
@@ -25,8 +25,10 @@ pub enum CandlestickInterval {
     OneDay,
 }
 
-impl CandlestickInterval {
-    pub fn from_str(s: &str) -> Result<Self> {
+impl std::str::FromStr for CandlestickInterval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         match s {
             "15s" => Ok(CandlestickInterval::FifteenSeconds),
             "30s" => Ok(CandlestickInterval::ThirtySeconds),
@@ -40,19 +42,22 @@ impl CandlestickInterval {
             _ => Err(anyhow::anyhow!("Invalid interval: {}", s)),
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        match self {
-            CandlestickInterval::FifteenSeconds => "15 SECOND".to_string(),
-            CandlestickInterval::ThirtySeconds => "30 SECOND".to_string(),
-            CandlestickInterval::OneMinute => "1 MINUTE".to_string(),
-            CandlestickInterval::FiveMinutes => "5 MINUTE".to_string(),
-            CandlestickInterval::FifteenMinutes => "15 MINUTE".to_string(),
-            CandlestickInterval::ThirtyMinutes => "30 MINUTE".to_string(),
-            CandlestickInterval::OneHour => "1 HOUR".to_string(),
-            CandlestickInterval::FourHours => "4 HOUR".to_string(),
-            CandlestickInterval::OneDay => "1 DAY".to_string(),
-        }
+impl std::fmt::Display for CandlestickInterval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let interval = match self {
+            CandlestickInterval::FifteenSeconds => "15 SECOND",
+            CandlestickInterval::ThirtySeconds => "30 SECOND",
+            CandlestickInterval::OneMinute => "1 MINUTE",
+            CandlestickInterval::FiveMinutes => "5 MINUTE",
+            CandlestickInterval::FifteenMinutes => "15 MINUTE",
+            CandlestickInterval::ThirtyMinutes => "30 MINUTE",
+            CandlestickInterval::OneHour => "1 HOUR",
+            CandlestickInterval::FourHours => "4 HOUR",
+            CandlestickInterval::OneDay => "1 DAY",
+        };
+        f.write_str(interval)
     }
 }
 
@@ -71,53 +76,138 @@ impl<'de> serde::Deserialize<'de> for CandlestickInterval {
         D: serde::Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        CandlestickInterval::from_str(&s).map_err(serde::de::Error::custom)
+        s.parse().map_err(serde::de::Error::custom)
     }
 }
 
+impl CandlestickInterval {
+    /// the name the interval is requested with, e.g. `1m`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CandlestickInterval::FifteenSeconds => "15s",
+            CandlestickInterval::ThirtySeconds => "30s",
+            CandlestickInterval::OneMinute => "1m",
+            CandlestickInterval::FiveMinutes => "5m",
+            CandlestickInterval::FifteenMinutes => "15m",
+            CandlestickInterval::ThirtyMinutes => "30m",
+            CandlestickInterval::OneHour => "1h",
+            CandlestickInterval::FourHours => "4h",
+            CandlestickInterval::OneDay => "1d",
+        }
+    }
+
+    pub fn seconds(&self) -> u64 {
+        match self {
+            CandlestickInterval::FifteenSeconds => 15,
+            CandlestickInterval::ThirtySeconds => 30,
+            CandlestickInterval::OneMinute => 60,
+            CandlestickInterval::FiveMinutes => 300,
+            CandlestickInterval::FifteenMinutes => 900,
+            CandlestickInterval::ThirtyMinutes => 1800,
+            CandlestickInterval::OneHour => 3600,
+            CandlestickInterval::FourHours => 14400,
+            CandlestickInterval::OneDay => 86400,
+        }
+    }
+
+    /// the coarsest rollup table the interval can be built from, `None` for
+    /// intervals below a minute which are aggregated from `price_updates`
+    pub fn rollup(&self) -> Option<&'static str> {
+        let seconds = self.seconds();
+        [
+            ("candles_1d", 86400),
+            ("candles_1h", 3600),
+            ("candles_1m", 60),
+        ]
+        .into_iter()
+        .find(|(_, rollup_seconds)| seconds.is_multiple_of(*rollup_seconds))
+        .map(|(table, _)| table)
+    }
+}
+
+/// A page of candlesticks in chronological order, `next_cursor` is passed as
+/// the `cursor` of the request for the page before it
+#[derive(Debug)]
+pub struct CandlestickPage {
+    pub candlesticks: Vec<Candlestick>,
+    pub next_cursor: Option<u64>,
+}
+
 impl ClickhouseDb {
+    /// the latest `limit` candlesticks of the mint starting at or after `from`
+    /// and before `to`, buckets without trades are filled with the previous
+    /// close if `fill_gaps` is set
     pub async fn get_candlesticks(
         &self,
         mint: &str,
-        interval: &str,
+        interval: &CandlestickInterval,
+        from: Option<u64>,
+        to: Option<u64>,
         limit: Option<usize>,
-    ) -> Result<Vec<Candlestick>> {
-        let interval_seconds = match interval {
-            "15 SECOND" => 15,
-            "30 SECOND" => 30,
-            "1 MINUTE" => 60,
-            "5 MINUTE" => 300,
-            "15 MINUTE" => 900,
-            "30 MINUTE" => 1800,
-            "1 HOUR" => 3600,
-            "4 HOUR" => 14400,
-            "1 DAY" => 86400,
-            _ => return Err(anyhow::anyhow!("Invalid interval")),
-        };
+        fill_gaps: bool,
+    ) -> Result<CandlestickPage> {
+        let interval_seconds = interval.seconds();
+        let limit = limit.unwrap_or(200);
 
-        let query = format!(
-            r#"
-            SELECT
-                intDiv(timestamp, {interval_seconds}) * {interval_seconds} as interval_timestamp,
-                argMin(price, timestamp) as open,
-                max(price) as high,
-                min(price) as low,
-                argMax(price, timestamp) as close,
-                sum(swap_amount) as volume
-            FROM price_updates
-            WHERE pubkey = '{mint}'
-            GROUP BY interval_timestamp
-            ORDER BY interval_timestamp DESC
-            LIMIT {limit}
-            "#,
-            limit = limit.unwrap_or(200)
-        );
+        // whole buckets, the one `to` falls into included
+        let from = from.map_or(0, |from| from / interval_seconds * interval_seconds);
+        let to = to.map_or(u64::MAX, |to| {
+            to.div_ceil(interval_seconds) * interval_seconds
+        });
+
+        let mut query = match interval.rollup() {
+            Some(table) => {
+                let mut query = QueryBuilder::new(
+                    r#"
+                SELECT
+                    intDiv(bucket, ?) * ? as interval_timestamp,
+                    argMinMerge(open) as open,
+                    max(high) as high,
+                    min(low) as low,
+                    argMaxMerge(close) as close,
+                    sum(volume) as volume
+                FROM "#,
+                );
+                query.bind(interval_seconds).bind(interval_seconds);
+                query
+                    .push(table)
+                    .and_where("pubkey = ? AND bucket >= ? AND bucket < ?")
+                    .bind(mint)
+                    .bind(from)
+                    .bind(to);
+                query
+            }
+            None => {
+                let mut query = QueryBuilder::new(
+                    r#"
+                SELECT
+                    intDiv(timestamp, ?) * ? as interval_timestamp,
+                    argMin(price, timestamp) as open,
+                    max(price) as high,
+                    min(price) as low,
+                    argMax(price, timestamp) as close,
+                    sum(swap_amount) as volume
+                FROM price_updates
+                "#,
+                );
+                query.bind(interval_seconds).bind(interval_seconds);
+                query
+                    .and_where("pubkey = ? AND timestamp >= ? AND timestamp < ?")
+                    .bind(mint)
+                    .bind(from)
+                    .bind(to);
+                query
+            }
+        };
+        query
+            .push(" GROUP BY interval_timestamp ORDER BY interval_timestamp DESC LIMIT ?")
+            .bind(limit);
 
-        let result = self
-            .client
-            .query(&query)
+        let result = query
+            .build(&self.client)
             .fetch_all::<(u64, f64, f64, f64, f64, f64)>()
             .await?;
+        let full_page = result.len() == limit;
 
         let mut candlesticks = result
             .into_iter()
@@ -134,15 +224,92 @@ impl ClickhouseDb {
         // Reverse to maintain chronological order (oldest first)
         candlesticks.reverse();
 
+        if fill_gaps {
+            // a page before a cursor is filled up to it, not past the
+            // current bucket, the latest page up to the current bucket
+            let now = chrono::Utc::now().timestamp() as u64;
+            let until = match to {
+                u64::MAX => now / interval_seconds * interval_seconds,
+                to => to.min(now.div_ceil(interval_seconds) * interval_seconds),
+            };
+            candlesticks =
+                fill_gaps_with_previous_close(candlesticks, interval_seconds, Some(until), limit);
+        }
+
         // Post-process to remove extreme wicks
         filter_extreme_wicks(&mut candlesticks);
 
-        Ok(candlesticks)
+        let next_cursor = candlesticks
+            .first()
+            .filter(|first| (full_page || candlesticks.len() == limit) && first.timestamp > from)
+            .map(|first| first.timestamp);
+
+        Ok(CandlestickPage {
+            candlesticks,
+            next_cursor,
+        })
     }
 }
 
+impl ClickhouseDb {
+    /// signature, side and USD amount of the trades of the mint at or after
+    /// `since`
+    pub async fn get_trade_keys(&self, mint: &str, since: u64) -> Result<Vec<(String, bool, f64)>> {
+        let mut query =
+            QueryBuilder::new("SELECT signature, is_buy, swap_amount FROM price_updates");
+        query
+            .and_where("pubkey = ? AND timestamp >= ?")
+            .bind(mint)
+            .bind(since);
+
+        let result = query
+            .build(&self.client)
+            .fetch_all::<(String, bool, f64)>()
+            .await?;
+
+        Ok(result)
+    }
+}
+
+/// Inserts a flat candle at the previous close for every bucket without
+/// trades, up to the bucket before `until` if it is set; only the latest
+/// `limit` candlesticks are kept
+fn fill_gaps_with_previous_close(
+    candlesticks: Vec<Candlestick>,
+    interval_seconds: u64,
+    until: Option<u64>,
+    limit: usize,
+) -> Vec<Candlestick> {
+    // walks back from the latest bucket so that at most `limit` are built
+    let mut filled = Vec::with_capacity(limit);
+    let mut next_timestamp = until;
+    for candlestick in candlesticks.into_iter().rev() {
+        if let Some(next_timestamp) = next_timestamp {
+            let mut timestamp = next_timestamp - interval_seconds;
+            while timestamp > candlestick.timestamp && filled.len() < limit {
+                filled.push(Candlestick {
+                    timestamp,
+                    open: candlestick.close,
+                    high: candlestick.close,
+                    low: candlestick.close,
+                    close: candlestick.close,
+                    volume: 0.0,
+                });
+                timestamp -= interval_seconds;
+            }
+        }
+        if filled.len() == limit {
+            break;
+        }
+        next_timestamp = Some(candlestick.timestamp);
+        filled.push(candlestick);
+    }
+    filled.reverse();
+    filled
+}
+
 /// Filter out extreme price wicks from candlestick data
-fn filter_extreme_wicks(candlesticks: &mut Vec<Candlestick>) {
+fn filter_extreme_wicks(candlesticks: &mut [Candlestick]) {
     if candlesticks.len() <= 2 {
         return;
     }
@@ -178,7 +345,7 @@ fn filter_extreme_wicks(candlesticks: &mut Vec<Candlestick>) {
 mod tests {
     use crate::db::{candlesticks::Candlestick, make_db};
 
-    use super::{filter_extreme_wicks, CandlestickInterval};
+    use super::{fill_gaps_with_previous_close, filter_extreme_wicks, CandlestickInterval};
     use crate::routes::CandlestickParams;
 
     #[test]
@@ -193,6 +360,56 @@ mod tests {
         assert_eq!(interval.to_string(), "30 MINUTE");
     }
 
+    #[test]
+    fn test_candlestick_rollup() {
+        assert_eq!(CandlestickInterval::FifteenSeconds.rollup(), None);
+        assert_eq!(CandlestickInterval::OneMinute.rollup(), Some("candles_1m"));
+        assert_eq!(
+            CandlestickInterval::FifteenMinutes.rollup(),
+            Some("candles_1m")
+        );
+        assert_eq!(CandlestickInterval::FourHours.rollup(), Some("candles_1h"));
+        assert_eq!(CandlestickInterval::OneDay.rollup(), Some("candles_1d"));
+    }
+
+    fn candle(timestamp: u64, close: f64) -> Candlestick {
+        Candlestick {
+            timestamp,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_fill_gaps_with_previous_close() {
+        let candlesticks = vec![candle(60, 1.0), candle(240, 2.0)];
+        let filled = fill_gaps_with_previous_close(candlesticks, 60, Some(420), 10);
+        let summary: Vec<(u64, f64, f64)> = filled
+            .iter()
+            .map(|c| (c.timestamp, c.close, c.volume))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (60, 1.0, 1.0),
+                (120, 1.0, 0.0),
+                (180, 1.0, 0.0),
+                (240, 2.0, 1.0),
+                (300, 2.0, 0.0),
+                (360, 2.0, 0.0),
+            ]
+        );
+
+        // the latest buckets are kept
+        let candlesticks = vec![candle(0, 1.0), candle(6000, 2.0)];
+        let filled = fill_gaps_with_previous_close(candlesticks, 60, None, 3);
+        let timestamps: Vec<u64> = filled.iter().map(|c| c.timestamp).collect();
+        assert_eq!(timestamps, vec![5880, 5940, 6000]);
+    }
+
     #[test]
     fn test_deserialize_candlestick_params() {
         let payload = r#"{"mint": "not-important", "interval": "1m"}"#;
@@ -207,11 +424,15 @@ mod tests {
         let candlesticks = db
             .get_candlesticks(
                 "GJAFwWjJ3vnTsrQVabjBVK2TYB1YtRCQXRDfDgUnpump",
-                &CandlestickInterval::OneMinute.to_string(),
+                &CandlestickInterval::OneMinute,
+                None,
+                None,
                 None,
+                true,
             )
             .await
-            .unwrap();
+            .unwrap()
+            .candlesticks;
         println!("{:#?}", candlesticks);
         println!(
             "{:#?}",
@@ -229,9 +450,9 @@ mod tests {
 
     #[tokio::test]
     async fn test_filter_extreme_wicks() {
-        let mut candlesticks: Vec<Candlestick> = reqwest::get(format!(
-            "https://api.listen-rs.com/v1/adapter/candlesticks?mint=34HDZNbUkTyTrgYKy2ox43yp2f8PJ5hoM7xsrfNApump&interval=1h&limit=200"
-        ))
+        let mut candlesticks: Vec<Candlestick> = reqwest::get(
+            "https://api.listen-rs.com/v1/adapter/candlesticks?mint=34HDZNbUkTyTrgYKy2ox43yp2f8PJ5hoM7xsrfNApump&interval=1h&limit=200",
+        )
         .await
         .unwrap()
         .json()
@@ -4,11 +4,15 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::debug;
 
+pub mod analytics;
 pub mod candlesticks;
 pub mod query;
+pub mod screener;
+pub mod sql;
 pub mod top_tokens;
+pub mod wallet;
 
-#[derive(Debug, Deserialize, Row, Serialize)]
+#[derive(Debug, Clone, Deserialize, Row, Serialize)]
 pub struct PriceUpdate {
     pub name: String,
     pub pubkey: String,
@@ -22,10 +26,23 @@ pub struct PriceUpdate {
     pub multi_hop: bool,
     pub is_buy: bool,
     pub is_pump: bool,
+    /// empty in updates from indexers that do not publish it
+    #[serde(default)]
+    pub quote_mint: String,
+    /// false if the indexer had no metadata of the token, the name and the
+    /// market cap are empty then
+    #[serde(default = "metadata_known")]
+    pub has_metadata: bool,
+}
+
+/// updates from indexers that do not flag it
+fn metadata_known() -> bool {
+    true
 }
 
 pub struct ClickhouseDb {
     client: Client,
+    screener_cache: screener::ScreenerCache,
 }
 
 impl ClickhouseDb {
@@ -36,7 +53,10 @@ impl ClickhouseDb {
             .with_user(user)
             .with_database(database);
 
-        Self { client }
+        Self {
+            client,
+            screener_cache: Default::default(),
+        }
     }
 
     pub async fn ping(&self) -> Result<()> {
@@ -51,7 +71,7 @@ pub fn is_local() -> bool {
 }
 
 pub fn must_get_env(key: &str) -> String {
-    std::env::var(key).expect(&format!("{} must be set", key))
+    std::env::var(key).unwrap_or_else(|_| panic!("{} must be set", key))
 }
 
 pub fn make_db() -> Result<Arc<ClickhouseDb>> {
@@ -67,6 +87,22 @@ pub fn make_db() -> Result<Arc<ClickhouseDb>> {
     Ok(Arc::new(db))
 }
 
+/// the analytics queries run as a read-only user whose profile limits what
+/// a query can do on top of the limits the adapter sets, see
+/// scripts/analytics_user.sql; local setups need the user too
+pub fn make_analytics_db() -> Result<Arc<ClickhouseDb>> {
+    let db = match is_local() {
+        true => ClickhouseDb::new("http://localhost:8123", "analytics", "analytics", "default"),
+        false => ClickhouseDb::new(
+            must_get_env("CLICKHOUSE_URL").as_str(),
+            must_get_env("ANALYTICS_CLICKHOUSE_PASSWORD").as_str(),
+            must_get_env("ANALYTICS_CLICKHOUSE_USER").as_str(),
+            must_get_env("CLICKHOUSE_DATABASE").as_str(),
+        ),
+    };
+    Ok(Arc::new(db))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,24 +1,13 @@
-use crate::db::{ClickhouseDb, PriceUpdate};
+use crate::db::{sql::QueryBuilder, ClickhouseDb, PriceUpdate};
 use anyhow::Result;
 
 impl ClickhouseDb {
     pub async fn get_by_mint(&self, mint: &str) -> Result<Vec<PriceUpdate>> {
-        let query = format!(
-            r#"
-            SELECT * FROM price_updates
-            WHERE pubkey = '{mint}'
-            ORDER BY timestamp DESC
-            LIMIT 50
-            "#
-        );
+        let mut query = QueryBuilder::new("SELECT ?fields FROM price_updates");
+        query.and_where("pubkey = ?").bind(mint);
+        query.push(" ORDER BY timestamp DESC LIMIT 50");
 
-        let result = self.client.query(&query).fetch_all::<PriceUpdate>().await?;
-
-        Ok(result)
-    }
-
-    pub async fn generic_query(&self, sql: &str) -> Result<Vec<PriceUpdate>> {
-        let result = self.client.query(sql).fetch_all::<PriceUpdate>().await?;
+        let result = query.build(&self.client).fetch_all::<PriceUpdate>().await?;
 
         Ok(result)
     }
@@ -37,11 +26,4 @@ mod tests {
             .unwrap();
         println!("{:#?}", result);
     }
-
-    #[tokio::test]
-    async fn test_generic_query() {
-        let db = make_db().unwrap();
-        let result = db.generic_query("SELECT 1").await.unwrap();
-        println!("{:#?}", result);
-    }
 }
@@ -0,0 +1,487 @@
+use super::{sql::QueryBuilder, ClickhouseDb};
+use anyhow::Result;
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// the longest window the screener aggregates over
+pub const MAX_SCREENER_TIMEFRAME: u64 = 7 * 86400;
+
+/// how long a page of the screener is served before it is queried again
+const SCREENER_CACHE_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenerSort {
+    #[default]
+    Volume,
+    PriceChange,
+    Trades,
+    UniqueBuyers,
+    BuySellRatio,
+    MarketCap,
+    /// descending is the oldest first
+    Age,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl ScreenerSort {
+    fn column(&self) -> &'static str {
+        match self {
+            ScreenerSort::Volume => "volume",
+            ScreenerSort::PriceChange => "price_change",
+            ScreenerSort::Trades => "trades",
+            ScreenerSort::UniqueBuyers => "unique_buyers",
+            ScreenerSort::BuySellRatio => "buy_sell_ratio",
+            ScreenerSort::MarketCap => "market_cap",
+            ScreenerSort::Age => "first_seen",
+        }
+    }
+
+    /// the direction of the column, the largest comes first in descending
+    /// order but the oldest token has the smallest first_seen
+    fn direction(&self, order: SortOrder) -> &'static str {
+        match (order == SortOrder::Desc) != (*self == ScreenerSort::Age) {
+            true => "DESC",
+            false => "ASC",
+        }
+    }
+}
+
+/// Filters of the screener, the stats are over the last `timeframe` seconds
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ScreenerFilters {
+    pub timeframe: Option<u64>,
+    pub min_volume: Option<f64>,
+    pub min_market_cap: Option<f64>,
+    pub max_market_cap: Option<f64>,
+    /// percent
+    pub min_price_change: Option<f64>,
+    pub max_price_change: Option<f64>,
+    pub min_trades: Option<u64>,
+    pub min_unique_buyers: Option<u64>,
+    pub min_unique_traders: Option<u64>,
+    pub min_buy_sell_ratio: Option<f64>,
+    /// share of the volume of the most active wallet, 0 to 1
+    pub max_top_trader_share: Option<f64>,
+    /// seconds since the first trade of the token
+    pub min_age: Option<u64>,
+    pub max_age: Option<u64>,
+    #[serde(default)]
+    pub only_pumpfun_tokens: bool,
+}
+
+impl ScreenerFilters {
+    /// the filters as conditions on the stats of a token
+    fn push_conditions(&self, query: &mut QueryBuilder, now: u64) {
+        if let Some(min) = self.min_volume {
+            query.and_where("volume >= ?").bind(min);
+        }
+        if let Some(min) = self.min_market_cap {
+            query.and_where("market_cap >= ?").bind(min);
+        }
+        if let Some(max) = self.max_market_cap {
+            query.and_where("market_cap <= ?").bind(max);
+        }
+        if let Some(min) = self.min_price_change {
+            query.and_where("price_change >= ?").bind(min);
+        }
+        if let Some(max) = self.max_price_change {
+            query.and_where("price_change <= ?").bind(max);
+        }
+        if let Some(min) = self.min_trades {
+            query.and_where("trades >= ?").bind(min);
+        }
+        if let Some(min) = self.min_unique_buyers {
+            query.and_where("unique_buyers >= ?").bind(min);
+        }
+        if let Some(min) = self.min_unique_traders {
+            query.and_where("unique_traders >= ?").bind(min);
+        }
+        if let Some(min) = self.min_buy_sell_ratio {
+            query.and_where("buy_sell_ratio >= ?").bind(min);
+        }
+        if let Some(max) = self.max_top_trader_share {
+            query.and_where("top_trader_share <= ?").bind(max);
+        }
+        // an age is a bound on when the token first traded
+        if let Some(min) = self.min_age {
+            query
+                .and_where("first_seen <= ?")
+                .bind(now.saturating_sub(min));
+        }
+        if let Some(max) = self.max_age {
+            query
+                .and_where("first_seen >= ?")
+                .bind(now.saturating_sub(max));
+        }
+        if self.only_pumpfun_tokens {
+            query.and_where("is_pump");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Row)]
+pub struct ScreenerToken {
+    pub pubkey: String,
+    pub name: String,
+    pub price: f64,
+    pub market_cap: f64,
+    pub volume: f64,
+    pub price_change: f64,
+    pub trades: u64,
+    pub buys: u64,
+    pub sells: u64,
+    pub unique_buyers: u64,
+    pub unique_sellers: u64,
+    pub unique_traders: u64,
+    /// buys per sell
+    pub buy_sell_ratio: f64,
+    pub top_trader_share: f64,
+    pub first_seen: u64,
+    pub is_pump: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenerPage {
+    pub tokens: Vec<ScreenerToken>,
+    /// the offset of the next page while there are more tokens
+    pub next_offset: Option<usize>,
+}
+
+impl ScreenerPage {
+    /// the page out of the rows of a query for one more than `limit`
+    fn new(mut tokens: Vec<ScreenerToken>, limit: usize, offset: usize) -> Self {
+        let next_offset = (tokens.len() > limit).then_some(offset + limit);
+        tokens.truncate(limit);
+        Self {
+            tokens,
+            next_offset,
+        }
+    }
+}
+
+struct CachedPage {
+    computed_at: Instant,
+    page: ScreenerPage,
+}
+
+/// The pages of the screener per request, a request is queried once per
+/// SCREENER_CACHE_TTL however many clients send it
+#[derive(Default)]
+pub struct ScreenerCache {
+    pages: std::sync::Mutex<HashMap<String, Arc<Mutex<Option<CachedPage>>>>>,
+}
+
+impl ScreenerCache {
+    /// the slot of the request, the expired ones that nobody is querying are
+    /// dropped
+    fn slot(&self, key: String) -> Arc<Mutex<Option<CachedPage>>> {
+        let mut pages = self.pages.lock().unwrap();
+        pages.retain(|_, slot| {
+            slot.try_lock().map_or(true, |page| {
+                page.as_ref()
+                    .is_some_and(|page| page.computed_at.elapsed() < SCREENER_CACHE_TTL)
+            })
+        });
+        pages.entry(key).or_default().clone()
+    }
+}
+
+impl ClickhouseDb {
+    pub async fn screen_tokens(
+        &self,
+        filters: &ScreenerFilters,
+        sort: ScreenerSort,
+        order: SortOrder,
+        limit: usize,
+        offset: usize,
+    ) -> Result<ScreenerPage> {
+        // whole minutes so that close timeframes share the page
+        let timeframe = filters
+            .timeframe
+            .unwrap_or(86400)
+            .div_ceil(60)
+            .saturating_mul(60)
+            .min(MAX_SCREENER_TIMEFRAME);
+        let filters = ScreenerFilters {
+            timeframe: Some(timeframe),
+            ..filters.clone()
+        };
+
+        // requests for a page that is being queried wait for it
+        let key = serde_json::to_string(&(&filters, sort, order, limit, offset))?;
+        let slot = self.screener_cache.slot(key);
+        let mut cached = slot.lock().await;
+        if let Some(cached) = cached
+            .as_ref()
+            .filter(|cached| cached.computed_at.elapsed() < SCREENER_CACHE_TTL)
+        {
+            return Ok(cached.page.clone());
+        }
+
+        let now = chrono::Utc::now().timestamp() as u64;
+        let tokens = screener_query(&filters, timeframe, sort, order, limit, offset, now)
+            .build(&self.client)
+            .fetch_all::<ScreenerToken>()
+            .await?;
+        let page = ScreenerPage::new(tokens, limit, offset);
+        *cached = Some(CachedPage {
+            computed_at: Instant::now(),
+            page: page.clone(),
+        });
+        Ok(page)
+    }
+}
+
+/// the page of the tokens that pass the filters, one more than `limit` to
+/// tell whether there is a next page
+fn screener_query(
+    filters: &ScreenerFilters,
+    timeframe: u64,
+    sort: ScreenerSort,
+    order: SortOrder,
+    limit: usize,
+    offset: usize,
+    now: u64,
+) -> QueryBuilder {
+    let start_time = now.saturating_sub(timeframe);
+
+    // the trades are rolled up per wallet first so that the wallet
+    // diversity of a token comes from the same pass as its totals; the
+    // name and the market cap come from the swaps priced with the token
+    // metadata, tokens without any are not listed
+    //
+    // the legs of a quote asset, e.g. SOL/USDC inside a routed swap, and
+    // the intermediate legs of routed swaps, a token bought and sold in
+    // the same transaction, are not trades of a token
+    let mut query = QueryBuilder::new(
+        r#"
+            WITH
+                trades AS (
+                    SELECT *
+                    FROM price_updates
+                    WHERE timestamp >= ?
+                        AND pubkey NOT IN (
+                            SELECT DISTINCT quote_mint FROM price_updates WHERE timestamp >= ?
+                        )
+                        AND (signature, pubkey) NOT IN (
+                            SELECT signature, pubkey
+                            FROM price_updates
+                            WHERE timestamp >= ? AND multi_hop
+                            GROUP BY signature, pubkey
+                            HAVING uniqExact(is_buy) = 2
+                        )
+                ),
+                per_owner AS (
+                    SELECT
+                        pubkey,
+                        owner,
+                        sum(swap_amount) AS owner_volume,
+                        count() AS owner_trades,
+                        countIf(is_buy) AS owner_buys,
+                        countIf(NOT is_buy) AS owner_sells,
+                        argMin(price, timestamp) AS first_price,
+                        min(timestamp) AS first_timestamp,
+                        argMax(price, timestamp) AS last_price,
+                        argMaxIf(market_cap, timestamp, has_metadata) AS last_market_cap,
+                        argMaxIf(name, timestamp, has_metadata) AS last_name,
+                        maxIf(timestamp, has_metadata) AS last_metadata_timestamp,
+                        max(timestamp) AS last_timestamp,
+                        max(is_pump) AS owner_is_pump
+                    FROM trades
+                    GROUP BY pubkey, owner
+                ),
+                stats AS (
+                    SELECT
+                        pubkey,
+                        argMax(last_name, last_metadata_timestamp) AS name,
+                        argMax(last_price, last_timestamp) AS price,
+                        argMax(last_market_cap, last_metadata_timestamp) AS market_cap,
+                        max(last_metadata_timestamp) > 0 AS has_metadata,
+                        sum(owner_volume) AS volume,
+                        argMin(first_price, first_timestamp) AS open_price,
+                        if(open_price > 0, (price - open_price) / open_price * 100, 0) AS price_change,
+                        sum(owner_trades) AS trades,
+                        sum(owner_buys) AS buys,
+                        sum(owner_sells) AS sells,
+                        countIf(owner_buys > 0) AS unique_buyers,
+                        countIf(owner_sells > 0) AS unique_sellers,
+                        count() AS unique_traders,
+                        buys / greatest(sells, 1) AS buy_sell_ratio,
+                        if(volume > 0, max(owner_volume) / volume, 0) AS top_trader_share,
+                        min(first_timestamp) AS window_first_seen,
+                        max(owner_is_pump) AS is_pump
+                    FROM per_owner
+                    GROUP BY pubkey
+                ),
+                token_ages AS (
+                    SELECT pubkey, min(first_seen) AS seen_at
+                    FROM token_first_seen
+                    WHERE pubkey IN (
+                        SELECT DISTINCT pubkey FROM trades
+                    )
+                    GROUP BY pubkey
+                )
+            SELECT * FROM (
+                SELECT
+                    s.pubkey AS pubkey,
+                    s.name AS name,
+                    s.price AS price,
+                    s.market_cap AS market_cap,
+                    s.volume AS volume,
+                    s.price_change AS price_change,
+                    s.trades AS trades,
+                    s.buys AS buys,
+                    s.sells AS sells,
+                    s.unique_buyers AS unique_buyers,
+                    s.unique_sellers AS unique_sellers,
+                    s.unique_traders AS unique_traders,
+                    s.buy_sell_ratio AS buy_sell_ratio,
+                    s.top_trader_share AS top_trader_share,
+                    if(f.seen_at = 0, s.window_first_seen, f.seen_at) AS first_seen,
+                    s.is_pump AS is_pump
+                FROM stats s
+                -- tokens the rollup has not seen yet default to 0
+                LEFT JOIN token_ages f ON f.pubkey = s.pubkey
+                WHERE s.has_metadata
+            )
+            "#,
+    );
+    query.bind(start_time).bind(start_time).bind(start_time);
+    filters.push_conditions(&mut query, now);
+    query
+        .push(" ORDER BY ")
+        .push(sort.column())
+        .push(" ")
+        .push(sort.direction(order))
+        // ties by pubkey so that pages do not overlap
+        .push(", pubkey LIMIT ? OFFSET ?")
+        .bind(limit + 1)
+        .bind(offset);
+
+    query
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::make_db;
+
+    #[test]
+    fn test_deserialize_screener_sort() {
+        let sort: ScreenerSort = serde_json::from_str(r#""buy_sell_ratio""#).unwrap();
+        assert_eq!(sort, ScreenerSort::BuySellRatio);
+        assert!(serde_json::from_str::<ScreenerSort>(r#""volume; DROP""#).is_err());
+    }
+
+    fn token(pubkey: &str) -> ScreenerToken {
+        ScreenerToken {
+            pubkey: pubkey.to_string(),
+            name: pubkey.to_uppercase(),
+            price: 1.0,
+            market_cap: 1_000_000.0,
+            volume: 100.0,
+            price_change: 0.0,
+            trades: 10,
+            buys: 5,
+            sells: 5,
+            unique_buyers: 3,
+            unique_sellers: 3,
+            unique_traders: 5,
+            buy_sell_ratio: 1.0,
+            top_trader_share: 0.2,
+            first_seen: 1_000,
+            is_pump: true,
+        }
+    }
+
+    #[test]
+    fn test_screener_page() {
+        let page = ScreenerPage::new(vec![token("a"), token("b"), token("c")], 2, 4);
+        assert_eq!(page.tokens.len(), 2);
+        assert_eq!(page.next_offset, Some(6));
+
+        let page = ScreenerPage::new(vec![token("a")], 2, 4);
+        assert_eq!(page.tokens.len(), 1);
+        assert_eq!(page.next_offset, None);
+    }
+
+    #[test]
+    fn test_screener_query() {
+        let filters = ScreenerFilters {
+            min_volume: Some(150.0),
+            max_age: Some(6_000),
+            only_pumpfun_tokens: true,
+            ..Default::default()
+        };
+        let sql = |sort: ScreenerSort, order: SortOrder| {
+            screener_query(&filters, 3600, sort, order, 20, 40, 10_000)
+                .build(&clickhouse::Client::default())
+                .sql_display()
+                .to_string()
+        };
+
+        let query = sql(ScreenerSort::Volume, SortOrder::Desc);
+        assert!(query.contains("WHERE timestamp >= 6400"));
+        assert!(query.ends_with(
+            " WHERE volume >= 150 AND first_seen >= 4000 AND is_pump \
+             ORDER BY volume DESC, pubkey LIMIT 21 OFFSET 40"
+        ));
+
+        // the youngest tokens first
+        let query = sql(ScreenerSort::Age, SortOrder::Asc);
+        assert!(query.ends_with("ORDER BY first_seen DESC, pubkey LIMIT 21 OFFSET 40"));
+    }
+
+    #[test]
+    fn test_deserialize_screener_filters() {
+        let filters = actix_web::web::Query::<ScreenerFilters>::from_query(
+            "timeframe=3600&min_unique_buyers=25&max_age=86400&sort_by=trades",
+        )
+        .unwrap();
+        assert_eq!(filters.timeframe, Some(3600));
+        assert_eq!(filters.min_unique_buyers, Some(25));
+        assert_eq!(filters.max_age, Some(86400));
+        assert!(!filters.only_pumpfun_tokens);
+    }
+
+    #[tokio::test]
+    async fn test_screen_tokens() -> Result<()> {
+        let db = make_db()?;
+        let filters = ScreenerFilters {
+            timeframe: Some(3600),
+            min_unique_buyers: Some(10),
+            max_top_trader_share: Some(0.5),
+            max_age: Some(86400),
+            ..Default::default()
+        };
+        let page = db
+            .screen_tokens(&filters, ScreenerSort::UniqueBuyers, SortOrder::Desc, 10, 0)
+            .await?;
+
+        for token in page.tokens {
+            println!(
+                "{}: mcap=${:.2}, vol=${:.2}, buyers={}, ratio={:.2}, top={:.2}",
+                token.name,
+                token.market_cap,
+                token.volume,
+                token.unique_buyers,
+                token.buy_sell_ratio,
+                token.top_trader_share
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use clickhouse::{query::Query, Client};
+use serde::Serialize;
+
+/// A value bound to a `?` of a query, escaped by the clickhouse client
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Param {
+    String(String),
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+    Strings(Vec<String>),
+}
+
+impl From<&str> for Param {
+    fn from(value: &str) -> Self {
+        Param::String(value.to_string())
+    }
+}
+
+impl From<String> for Param {
+    fn from(value: String) -> Self {
+        Param::String(value)
+    }
+}
+
+impl From<u64> for Param {
+    fn from(value: u64) -> Self {
+        Param::UInt(value)
+    }
+}
+
+impl From<usize> for Param {
+    fn from(value: usize) -> Self {
+        Param::UInt(value as u64)
+    }
+}
+
+impl From<f64> for Param {
+    fn from(value: f64) -> Self {
+        Param::Float(value)
+    }
+}
+
+impl From<bool> for Param {
+    fn from(value: bool) -> Self {
+        Param::Bool(value)
+    }
+}
+
+impl From<Vec<String>> for Param {
+    fn from(value: Vec<String>) -> Self {
+        Param::Strings(value)
+    }
+}
+
+/// Builds a query out of static sql and bound params, values never end up
+/// formatted into the sql
+#[derive(Debug, Default)]
+pub struct QueryBuilder {
+    sql: String,
+    params: Vec<Param>,
+    has_where: bool,
+}
+
+impl QueryBuilder {
+    pub fn new(sql: &str) -> Self {
+        Self {
+            sql: sql.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn push(&mut self, sql: &str) -> &mut Self {
+        self.sql.push_str(sql);
+        self
+    }
+
+    /// binds the next `?` pushed
+    pub fn bind(&mut self, param: impl Into<Param>) -> &mut Self {
+        self.params.push(param.into());
+        self
+    }
+
+    /// pushes the condition after `WHERE` the first time and `AND` after
+    pub fn and_where(&mut self, condition: &str) -> &mut Self {
+        self.sql
+            .push_str(if self.has_where { " AND " } else { " WHERE " });
+        self.sql.push_str(condition);
+        self.has_where = true;
+        self
+    }
+
+    pub fn build(&self, client: &Client) -> Query {
+        debug_assert_eq!(
+            placeholders(&self.sql),
+            self.params.len(),
+            "every `?` needs a param: {}",
+            self.sql
+        );
+        self.params
+            .iter()
+            .fold(client.query(&self.sql), |query, param| query.bind(param))
+    }
+}
+
+/// the `?` the client binds, `??` and `?fields` are not placeholders
+fn placeholders(sql: &str) -> usize {
+    let mut count = 0;
+    let mut rest = sql;
+    while let Some(index) = rest.find('?') {
+        rest = &rest[index + 1..];
+        if let Some(after) = rest.strip_prefix('?') {
+            rest = after;
+        } else if !rest.starts_with("fields") {
+            count += 1;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_builder() {
+        let mut builder = QueryBuilder::new("SELECT ?fields FROM price_updates");
+        builder.and_where("pubkey = ?").bind("abc' OR 1=1 --");
+        builder
+            .and_where("has(?, quote_mint)")
+            .bind(vec!["def".to_string()]);
+        builder.and_where("is_pump = ?").bind(true);
+        builder.push(" LIMIT ?").bind(10usize);
+        assert_eq!(placeholders(&builder.sql), 4);
+
+        let query = builder.build(&Client::default());
+        assert_eq!(
+            query.sql_display().to_string(),
+            "SELECT ?fields FROM price_updates WHERE pubkey = 'abc\\' OR 1=1 --' \
+             AND has(['def'], quote_mint) AND is_pump = true LIMIT 10"
+        );
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(placeholders("SELECT 1"), 0);
+        assert_eq!(
+            placeholders("SELECT ?fields WHERE a = ? AND b LIKE '??'"),
+            1
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use super::ClickhouseDb;
+use super::{sql::QueryBuilder, ClickhouseDb};
 use anyhow::Result;
 use clickhouse::Row;
 use serde::{Deserialize, Serialize};
@@ -29,7 +29,9 @@ impl ClickhouseDb {
             .as_secs();
         let start_time = current_time - time_range;
 
-        let mut query = format!(
+        // the name and the market cap of a token come from the swaps priced
+        // with its metadata, tokens without any are not listed
+        let mut query = QueryBuilder::new(
             r#"
             WITH 
                 latest_prices AS (
@@ -41,7 +43,7 @@ impl ClickhouseDb {
                         timestamp,
                         is_pump
                     FROM price_updates
-                    WHERE timestamp >= {start_time}
+                    WHERE timestamp >= ? AND has_metadata
                     ORDER BY timestamp DESC
                     LIMIT 1 BY name, pubkey
                 ),
@@ -51,7 +53,7 @@ impl ClickhouseDb {
                         pubkey,
                         sum(swap_amount) as volume_24h
                     FROM price_updates
-                    WHERE timestamp >= {start_time}
+                    WHERE timestamp >= ?
                     GROUP BY name, pubkey
                 ),
                 price_changes AS (
@@ -60,7 +62,7 @@ impl ClickhouseDb {
                         pubkey,
                         (last_value(price) - first_value(price)) / first_value(price) * 100 as price_change_24h
                     FROM price_updates
-                    WHERE timestamp >= {start_time}
+                    WHERE timestamp >= ?
                     GROUP BY name, pubkey
                 )
             SELECT
@@ -73,35 +75,31 @@ impl ClickhouseDb {
             FROM latest_prices lp
             LEFT JOIN volumes v ON lp.name = v.name AND lp.pubkey = v.pubkey
             LEFT JOIN price_changes pc ON lp.name = pc.name AND lp.pubkey = pc.pubkey
-            "#
+            "#,
         );
-
-        let mut conditions = Vec::new();
+        query.bind(start_time).bind(start_time).bind(start_time);
 
         if let Some(min_volume) = min_volume {
-            conditions.push(format!("v.volume_24h >= {min_volume}"));
+            query.and_where("v.volume_24h >= ?").bind(min_volume);
         }
 
         if let Some(min_market_cap) = min_market_cap {
-            conditions.push(format!("lp.market_cap >= {min_market_cap}"));
+            query.and_where("lp.market_cap >= ?").bind(min_market_cap);
         }
 
         if let Some(max_market_cap) = max_market_cap {
-            conditions.push(format!("lp.market_cap <= {max_market_cap}"));
+            query.and_where("lp.market_cap <= ?").bind(max_market_cap);
         }
 
         if only_pumpfun_tokens {
-            conditions.push("is_pump = true".to_string());
-        }
-
-        if !conditions.is_empty() {
-            query.push_str(" WHERE ");
-            query.push_str(&conditions.join(" AND "));
+            query.and_where("is_pump = true");
         }
 
-        query.push_str(&format!(" ORDER BY v.volume_24h DESC LIMIT {}", limit));
+        query
+            .push(" ORDER BY v.volume_24h DESC LIMIT ?")
+            .bind(limit);
 
-        let result = self.client.query(&query).fetch_all::<TopToken>().await?;
+        let result = query.build(&self.client).fetch_all::<TopToken>().await?;
 
         Ok(result)
     }
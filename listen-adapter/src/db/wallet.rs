@@ -0,0 +1,351 @@
+use super::{sql::QueryBuilder, ClickhouseDb};
+use anyhow::Result;
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// the pnl of the most active wallets is computed from their latest trades
+/// only, sells of tokens bought before that have no cost basis
+const MAX_LEDGER_TRADES: usize = 50_000;
+
+/// a lot smaller than this share of what was bought is closed
+const DUST: f64 = 1e-6;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Row)]
+pub struct WalletTrade {
+    pub pubkey: String,
+    pub name: String,
+    pub is_buy: bool,
+    pub price: f64,
+    pub amount_usd: f64,
+    pub token_amount: f64,
+    pub multi_hop: bool,
+    pub quote_mint: String,
+    pub timestamp: u64,
+    pub slot: u64,
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize, Row)]
+struct LatestPrice {
+    pubkey: String,
+    price: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TokenPnl {
+    pub pubkey: String,
+    pub name: String,
+    pub buys: u64,
+    pub sells: u64,
+    pub bought_usd: f64,
+    pub sold_usd: f64,
+    /// tokens still held out of the tracked buys
+    pub holding: f64,
+    /// what the holding cost
+    pub cost_basis_usd: f64,
+    pub last_price: f64,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+    pub winning_sells: u64,
+    pub losing_sells: u64,
+    /// share of the sells that closed lots at a profit
+    pub win_rate: Option<f64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WalletPnl {
+    pub owner: String,
+    pub trades: u64,
+    pub realized_pnl: f64,
+    pub unrealized_pnl: f64,
+    pub total_pnl: f64,
+    /// share of the tokens sold with a positive realized pnl
+    pub win_rate: Option<f64>,
+    pub tokens: Vec<TokenPnl>,
+}
+
+struct Lot {
+    bought: f64,
+    amount: f64,
+    price: f64,
+}
+
+#[derive(Default)]
+struct TokenLedger {
+    pnl: TokenPnl,
+    lots: VecDeque<Lot>,
+}
+
+impl TokenLedger {
+    fn buy(&mut self, trade: &WalletTrade) {
+        self.pnl.buys += 1;
+        self.pnl.bought_usd += trade.amount_usd;
+        self.lots.push_back(Lot {
+            bought: trade.token_amount,
+            amount: trade.token_amount,
+            price: trade.price,
+        });
+    }
+
+    /// closes the oldest lots first, the part of the sell that is not
+    /// covered by tracked buys is left out of the realized pnl
+    fn sell(&mut self, trade: &WalletTrade) {
+        self.pnl.sells += 1;
+        self.pnl.sold_usd += trade.amount_usd;
+
+        let mut remaining = trade.token_amount;
+        let mut matched = 0.0;
+        let mut cost = 0.0;
+        while remaining > 0.0 {
+            let Some(lot) = self.lots.front_mut() else {
+                break;
+            };
+            let take = lot.amount.min(remaining);
+            lot.amount -= take;
+            remaining -= take;
+            matched += take;
+            cost += take * lot.price;
+            if lot.amount <= lot.bought * DUST {
+                self.lots.pop_front();
+            }
+        }
+
+        if matched > 0.0 {
+            let pnl = matched * trade.price - cost;
+            self.pnl.realized_pnl += pnl;
+            match pnl > 0.0 {
+                true => self.pnl.winning_sells += 1,
+                false => self.pnl.losing_sells += 1,
+            }
+        }
+    }
+
+    fn finish(mut self, last_price: f64) -> TokenPnl {
+        self.pnl.holding = self.lots.iter().map(|lot| lot.amount).sum();
+        self.pnl.cost_basis_usd = self.lots.iter().map(|lot| lot.amount * lot.price).sum();
+        self.pnl.last_price = last_price;
+        self.pnl.unrealized_pnl = self.pnl.holding * last_price - self.pnl.cost_basis_usd;
+        let closed = self.pnl.winning_sells + self.pnl.losing_sells;
+        if closed > 0 {
+            self.pnl.win_rate = Some(self.pnl.winning_sells as f64 / closed as f64);
+        }
+        self.pnl
+    }
+}
+
+/// FIFO ledger over the trades of a wallet in chronological order, open lots
+/// are valued at `last_prices` or at the price of the last trade of the token
+pub fn compute_wallet_pnl(
+    owner: &str,
+    trades: &[WalletTrade],
+    last_prices: &HashMap<String, f64>,
+) -> WalletPnl {
+    let mut ledgers: HashMap<&str, TokenLedger> = HashMap::new();
+    let mut trade_prices: HashMap<&str, f64> = HashMap::new();
+    for trade in trades {
+        let ledger = ledgers.entry(&trade.pubkey).or_default();
+        if ledger.pnl.pubkey.is_empty() {
+            ledger.pnl.pubkey = trade.pubkey.clone();
+            ledger.pnl.name = trade.name.clone();
+        }
+        match trade.is_buy {
+            true => ledger.buy(trade),
+            false => ledger.sell(trade),
+        }
+        trade_prices.insert(&trade.pubkey, trade.price);
+    }
+
+    let mut tokens: Vec<TokenPnl> = ledgers
+        .into_iter()
+        .map(|(pubkey, ledger)| {
+            let last_price = last_prices
+                .get(pubkey)
+                .copied()
+                .unwrap_or(trade_prices[pubkey]);
+            ledger.finish(last_price)
+        })
+        .collect();
+    tokens.sort_by(|a, b| {
+        (b.realized_pnl + b.unrealized_pnl).total_cmp(&(a.realized_pnl + a.unrealized_pnl))
+    });
+
+    let realized_pnl = tokens.iter().map(|t| t.realized_pnl).sum();
+    let unrealized_pnl = tokens.iter().map(|t| t.unrealized_pnl).sum();
+    let sold = tokens.iter().filter(|t| t.win_rate.is_some());
+    let (winners, sold) = sold.fold((0, 0), |(winners, sold), t| {
+        (winners + (t.realized_pnl > 0.0) as u64, sold + 1)
+    });
+
+    WalletPnl {
+        owner: owner.to_string(),
+        trades: trades.len() as u64,
+        realized_pnl,
+        unrealized_pnl,
+        total_pnl: realized_pnl + unrealized_pnl,
+        win_rate: (sold > 0).then(|| winners as f64 / sold as f64),
+        tokens,
+    }
+}
+
+impl ClickhouseDb {
+    /// trades of the wallet, latest first; the intermediate legs of routed
+    /// swaps, a token bought and sold again in the same transaction, are left
+    /// out
+    pub async fn get_wallet_trades(
+        &self,
+        owner: &str,
+        mint: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<WalletTrade>> {
+        let mut query = QueryBuilder::new("SELECT ?fields FROM wallet_trades");
+        query.and_where("owner = ?").bind(owner);
+        if let Some(mint) = mint {
+            query.and_where("pubkey = ?").bind(mint);
+        }
+        query
+            .and_where(
+                "NOT (multi_hop AND (signature, pubkey) IN (\
+                 SELECT signature, pubkey FROM wallet_trades \
+                 WHERE owner = ? AND multi_hop \
+                 GROUP BY signature, pubkey HAVING uniqExact(is_buy) = 2))",
+            )
+            .bind(owner);
+        query
+            .push(" ORDER BY timestamp DESC, slot DESC LIMIT ?")
+            .bind(limit);
+
+        let result = query.build(&self.client).fetch_all::<WalletTrade>().await?;
+
+        Ok(result)
+    }
+
+    /// the close of the last daily candle of every mint, the rollup keeps
+    /// this a lookup by key instead of a scan of all the price updates
+    pub async fn get_latest_prices(&self, mints: &[String]) -> Result<HashMap<String, f64>> {
+        let result = self
+            .client
+            .query(
+                r#"
+                SELECT pubkey, argMaxMerge(close) AS price
+                FROM candles_1d
+                WHERE has(?, pubkey)
+                GROUP BY pubkey
+                "#,
+            )
+            .bind(mints)
+            .fetch_all::<LatestPrice>()
+            .await?;
+
+        Ok(result
+            .into_iter()
+            .map(|latest| (latest.pubkey, latest.price))
+            .collect())
+    }
+
+    pub async fn get_wallet_pnl(&self, owner: &str, mint: Option<&str>) -> Result<WalletPnl> {
+        let mut trades = self
+            .get_wallet_trades(owner, mint, MAX_LEDGER_TRADES)
+            .await?;
+        trades.reverse();
+
+        let mut mints: Vec<String> = trades.iter().map(|t| t.pubkey.clone()).collect();
+        mints.sort();
+        mints.dedup();
+        let last_prices = self.get_latest_prices(&mints).await?;
+
+        Ok(compute_wallet_pnl(owner, &trades, &last_prices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::make_db;
+
+    fn trade(pubkey: &str, is_buy: bool, token_amount: f64, price: f64) -> WalletTrade {
+        WalletTrade {
+            pubkey: pubkey.to_string(),
+            name: pubkey.to_uppercase(),
+            is_buy,
+            price,
+            amount_usd: token_amount * price,
+            token_amount,
+            multi_hop: false,
+            quote_mint: "So11111111111111111111111111111111111111112".to_string(),
+            timestamp: 0,
+            slot: 0,
+            signature: String::new(),
+        }
+    }
+
+    fn approx(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_fifo_cost_basis() {
+        let trades = vec![
+            trade("abc", true, 100.0, 1.0),
+            trade("abc", true, 100.0, 2.0),
+            // closes the first lot and half of the second
+            trade("abc", false, 150.0, 3.0),
+        ];
+        let pnl = compute_wallet_pnl(
+            "wallet",
+            &trades,
+            &HashMap::from([("abc".to_string(), 4.0)]),
+        );
+
+        let token = &pnl.tokens[0];
+        assert!(approx(
+            token.realized_pnl,
+            150.0 * 3.0 - (100.0 + 50.0 * 2.0)
+        ));
+        assert!(approx(token.holding, 50.0));
+        assert!(approx(token.cost_basis_usd, 100.0));
+        assert!(approx(token.unrealized_pnl, 50.0 * 4.0 - 100.0));
+        assert_eq!(token.win_rate, Some(1.0));
+        assert!(approx(pnl.total_pnl, 250.0 + 100.0));
+    }
+
+    #[test]
+    fn test_untracked_sells_and_win_rate() {
+        let trades = vec![
+            // bought before the ledger starts, no cost basis
+            trade("old", false, 10.0, 5.0),
+            trade("win", true, 10.0, 1.0),
+            trade("win", false, 10.0, 2.0),
+            trade("loss", true, 10.0, 2.0),
+            trade("loss", false, 5.0, 1.0),
+            trade("loss", false, 5.0, 3.0),
+        ];
+        let pnl = compute_wallet_pnl("wallet", &trades, &HashMap::new());
+
+        let by_pubkey: HashMap<&str, &TokenPnl> =
+            pnl.tokens.iter().map(|t| (t.pubkey.as_str(), t)).collect();
+        assert!(approx(by_pubkey["old"].realized_pnl, 0.0));
+        assert_eq!(by_pubkey["old"].win_rate, None);
+        assert!(approx(by_pubkey["win"].realized_pnl, 10.0));
+        assert!(approx(by_pubkey["win"].holding, 0.0));
+        assert!(approx(by_pubkey["loss"].realized_pnl, -5.0 + 5.0));
+        assert_eq!(by_pubkey["loss"].win_rate, Some(0.5));
+        // the flat token is not a win
+        assert_eq!(pnl.win_rate, Some(0.5));
+        assert_eq!(pnl.trades, 6);
+    }
+
+    #[tokio::test]
+    async fn test_get_wallet_pnl() -> Result<()> {
+        let db = make_db()?;
+        let pnl = db
+            .get_wallet_pnl("5oNDL3swdJJF1g9DzJiZ4ynHXgszjAEpUkxVYejchzrY", None)
+            .await?;
+        println!(
+            "trades={} realized=${:.2} unrealized=${:.2} win_rate={:?}",
+            pnl.trades, pnl.realized_pnl, pnl.unrealized_pnl, pnl.win_rate
+        );
+
+        Ok(())
+    }
+}
@@ -1,10 +1,12 @@
 pub mod db;
 pub mod error;
+pub mod live_candles;
 pub mod redis_client;
 pub mod redis_subscriber;
 pub mod routes;
 pub mod state;
 pub mod websocket;
+pub mod ws_protocol;
 
 #[cfg(test)]
 #[ctor::ctor]
@@ -0,0 +1,506 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::db::candlesticks::{Candlestick, CandlestickInterval};
+use crate::db::PriceUpdate;
+
+/// The backfill sent once a candle subscription starts
+#[derive(Debug, Serialize)]
+pub struct CandlesMessage<'a> {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub mint: &'a str,
+    pub interval: &'static str,
+    pub candles: &'a [Candlestick],
+}
+
+impl<'a> CandlesMessage<'a> {
+    pub fn new(mint: &'a str, interval: CandlestickInterval, candles: &'a [Candlestick]) -> Self {
+        Self {
+            kind: "candles",
+            mint,
+            interval: interval.as_str(),
+            candles,
+        }
+    }
+}
+
+/// The in-progress candle after every trade and the final one once its
+/// bucket is over, with `closed` set
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CandleMessage {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub mint: String,
+    pub interval: &'static str,
+    pub closed: bool,
+    pub candle: Candlestick,
+}
+
+/// trades kept of a subscription waiting for its backfill, the oldest are
+/// dropped past it
+const MAX_PENDING_TRADES: usize = 1000;
+
+/// how long the published trades are kept, longer than the database takes to
+/// insert them; also how long a subscription skips the trades its backfill had
+pub const RECENT_TRADES_SECS: u64 = 60;
+
+const MAX_RECENT_TRADES: usize = 20_000;
+
+/// Identifies a trade, a transaction can swap the same mint more than once
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TradeKey {
+    signature: String,
+    is_buy: bool,
+    swap_amount: u64,
+}
+
+impl TradeKey {
+    pub fn new(signature: String, is_buy: bool, swap_amount: f64) -> Self {
+        Self {
+            signature,
+            is_buy,
+            swap_amount: swap_amount.to_bits(),
+        }
+    }
+
+    pub fn of(trade: &PriceUpdate) -> Self {
+        Self::new(trade.signature.clone(), trade.is_buy, trade.swap_amount)
+    }
+}
+
+/// The trades published within the last `RECENT_TRADES_SECS`, the database
+/// only has them once its insert buffer is flushed
+#[derive(Default)]
+pub struct RecentTrades {
+    trades: Mutex<VecDeque<PriceUpdate>>,
+}
+
+impl RecentTrades {
+    pub fn push(&self, trade: PriceUpdate) {
+        let mut trades = self.trades.lock().unwrap();
+        let oldest = trade.timestamp.saturating_sub(RECENT_TRADES_SECS);
+        while trades
+            .front()
+            .is_some_and(|front| front.timestamp < oldest || trades.len() >= MAX_RECENT_TRADES)
+        {
+            trades.pop_front();
+        }
+        trades.push_back(trade);
+    }
+
+    /// the trades of the mint at or after `since`, in the order they were
+    /// published
+    pub fn of_mint(&self, mint: &str, since: u64) -> Vec<PriceUpdate> {
+        self.trades
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|trade| trade.pubkey == mint && trade.timestamp >= since)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Adds the recent trades the database does not have yet to the backfilled
+/// candles, they are later than the ones it has; returns the keys of all of
+/// the trades the candles include from `inserted` and `recent`
+pub fn merge_recent_trades(
+    candles: &mut Vec<Candlestick>,
+    interval: CandlestickInterval,
+    inserted: Vec<TradeKey>,
+    recent: &[PriceUpdate],
+) -> HashSet<TradeKey> {
+    let seconds = interval.seconds();
+    let mut seen: HashSet<TradeKey> = inserted.into_iter().collect();
+    for trade in recent {
+        if !seen.insert(TradeKey::of(trade)) {
+            continue;
+        }
+        let bucket = trade.timestamp / seconds * seconds;
+        let Some(last) = candles.last() else {
+            candles.push(Candlestick {
+                timestamp: bucket,
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                volume: 0.0,
+            });
+            continue;
+        };
+        // the buckets in between are flat at the last close
+        let close = last.close;
+        let mut timestamp = last.timestamp + seconds;
+        while timestamp <= bucket {
+            candles.push(Candlestick {
+                timestamp,
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: 0.0,
+            });
+            timestamp += seconds;
+        }
+        let Some(candle) = candles
+            .iter_mut()
+            .rev()
+            .find(|candle| candle.timestamp == bucket)
+        else {
+            continue;
+        };
+        // a flat candle has not traded yet, its open is the first trade
+        if candle.volume == 0.0 {
+            candle.open = trade.price;
+            candle.high = trade.price;
+            candle.low = trade.price;
+        }
+        candle.high = candle.high.max(trade.price);
+        candle.low = candle.low.min(trade.price);
+        candle.close = trade.price;
+        candle.volume += trade.swap_amount;
+    }
+    seen
+}
+
+/// A subscription waiting for its backfill, the trades are applied once it
+/// arrives
+struct Pending {
+    request: u64,
+    trades: VecDeque<PriceUpdate>,
+}
+
+/// Builds the candles of a mint from the live price updates, the buckets are
+/// the ones of the historical candlesticks so the two line up
+pub struct LiveCandles {
+    pub mint: String,
+    pub interval: CandlestickInterval,
+    current: Option<Candlestick>,
+    /// whether the current candle has trades or is carried over flat
+    traded: bool,
+    /// the trades the backfill has, skipped until `seen_until`
+    seen: HashSet<TradeKey>,
+    seen_until: u64,
+    pending: Option<Pending>,
+}
+
+impl LiveCandles {
+    /// continues from `last`, the latest backfilled candle, it is carried over
+    /// flat if its bucket is already over at `now`
+    pub fn new(
+        mint: String,
+        interval: CandlestickInterval,
+        last: Option<&Candlestick>,
+        now: u64,
+    ) -> Self {
+        let mut live = Self {
+            mint,
+            interval,
+            current: last.cloned(),
+            traded: true,
+            seen: HashSet::new(),
+            seen_until: 0,
+            pending: None,
+        };
+        live.close_elapsed(now);
+        live
+    }
+
+    /// a subscription that keeps the trades until the backfill of `request`
+    /// starts it
+    pub fn pending(mint: String, interval: CandlestickInterval, request: u64) -> Self {
+        let mut live = Self::new(mint, interval, None, 0);
+        live.pending = Some(Pending {
+            request,
+            trades: VecDeque::new(),
+        });
+        live
+    }
+
+    pub fn is_pending(&self, request: u64) -> bool {
+        self.pending
+            .as_ref()
+            .is_some_and(|pending| pending.request == request)
+    }
+
+    /// continues from the backfill like [`LiveCandles::new`], the trades kept
+    /// meanwhile are applied right away unless `seen` says the backfill has
+    /// them
+    pub fn start(
+        &mut self,
+        last: Option<&Candlestick>,
+        seen: HashSet<TradeKey>,
+        now: u64,
+    ) -> Vec<CandleMessage> {
+        let trades = self
+            .pending
+            .take()
+            .map(|pending| pending.trades)
+            .unwrap_or_default();
+        *self = Self::new(self.mint.clone(), self.interval, last, now);
+        self.seen = seen;
+        self.seen_until = now + RECENT_TRADES_SECS;
+        trades.iter().flat_map(|trade| self.apply(trade)).collect()
+    }
+
+    fn bucket(&self, timestamp: u64) -> u64 {
+        let seconds = self.interval.seconds();
+        timestamp / seconds * seconds
+    }
+
+    fn message(&self, candle: Candlestick, closed: bool) -> CandleMessage {
+        CandleMessage {
+            kind: "candle",
+            mint: self.mint.clone(),
+            interval: self.interval.as_str(),
+            closed,
+            candle,
+        }
+    }
+
+    /// closes the current candle if its bucket is over by `now`, the bucket of
+    /// `now` starts flat at its close; buckets skipped in between are not sent
+    pub fn close_elapsed(&mut self, now: u64) -> Option<CandleMessage> {
+        if self.pending.is_some() {
+            return None;
+        }
+        if now >= self.seen_until && !self.seen.is_empty() {
+            self.seen = HashSet::new();
+        }
+        let bucket = self.bucket(now);
+        let current = self.current.take_if(|current| current.timestamp < bucket)?;
+        let close = current.close;
+        self.current = Some(Candlestick {
+            timestamp: bucket,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 0.0,
+        });
+        self.traded = false;
+        Some(self.message(current, true))
+    }
+
+    /// applies the trade to its bucket, a trade from a bucket that is already
+    /// closed goes to the current one and one the backfill has is skipped
+    pub fn apply(&mut self, trade: &PriceUpdate) -> Vec<CandleMessage> {
+        let mut messages = Vec::new();
+        if let Some(pending) = &mut self.pending {
+            if pending.trades.len() == MAX_PENDING_TRADES {
+                pending.trades.pop_front();
+            }
+            pending.trades.push_back(trade.clone());
+            return messages;
+        }
+        if !self.seen.is_empty() && self.seen.remove(&TradeKey::of(trade)) {
+            return messages;
+        }
+        messages.extend(self.close_elapsed(trade.timestamp));
+
+        let bucket = self.bucket(trade.timestamp);
+        let candle = self.current.get_or_insert(Candlestick {
+            timestamp: bucket,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: 0.0,
+        });
+        // the open of a bucket is its first trade like in the rollups
+        if !self.traded {
+            candle.open = trade.price;
+            candle.high = trade.price;
+            candle.low = trade.price;
+            self.traded = true;
+        }
+        candle.high = candle.high.max(trade.price);
+        candle.low = candle.low.min(trade.price);
+        candle.close = trade.price;
+        candle.volume += trade.swap_amount;
+
+        let candle = candle.clone();
+        messages.push(self.message(candle, false));
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(price: f64, swap_amount: f64, timestamp: u64) -> PriceUpdate {
+        PriceUpdate {
+            name: "TOKEN".to_string(),
+            pubkey: "mint".to_string(),
+            price,
+            market_cap: 0.0,
+            timestamp,
+            slot: 0,
+            swap_amount,
+            owner: String::new(),
+            signature: format!("signature{}", timestamp),
+            multi_hop: false,
+            is_buy: true,
+            is_pump: false,
+            quote_mint: String::new(),
+            has_metadata: true,
+        }
+    }
+
+    #[test]
+    fn test_live_candles() {
+        let mut live =
+            LiveCandles::new("mint".to_string(), CandlestickInterval::OneMinute, None, 0);
+
+        let messages = live.apply(&trade(1.0, 10.0, 65));
+        assert_eq!(messages.len(), 1);
+        assert!(!messages[0].closed);
+        assert_eq!(messages[0].candle.timestamp, 60);
+
+        let messages = live.apply(&trade(3.0, 5.0, 70));
+        let candle = &messages[0].candle;
+        assert_eq!(
+            (candle.open, candle.high, candle.low, candle.close),
+            (1.0, 3.0, 1.0, 3.0)
+        );
+        assert_eq!(candle.volume, 15.0);
+
+        // the next bucket closes the previous one first
+        let messages = live.apply(&trade(2.0, 1.0, 125));
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].closed);
+        assert_eq!(messages[0].candle.close, 3.0);
+        assert_eq!(messages[1].candle.timestamp, 120);
+        assert_eq!(messages[1].candle.open, 2.0);
+        assert_eq!(messages[1].candle.volume, 1.0);
+
+        assert_eq!(live.close_elapsed(170), None);
+        let closed = live.close_elapsed(180).unwrap();
+        assert!(closed.closed);
+        assert_eq!(closed.candle.timestamp, 120);
+    }
+
+    #[test]
+    fn test_live_candles_continue_backfill() {
+        let last = Candlestick {
+            timestamp: 60,
+            open: 1.0,
+            high: 2.0,
+            low: 0.5,
+            close: 1.5,
+            volume: 100.0,
+        };
+
+        // still in progress
+        let mut live = LiveCandles::new(
+            "mint".to_string(),
+            CandlestickInterval::OneMinute,
+            Some(&last),
+            90,
+        );
+        let messages = live.apply(&trade(1.8, 1.0, 95));
+        assert_eq!(messages[0].candle.open, 1.0);
+        assert_eq!(messages[0].candle.volume, 101.0);
+
+        // already over, the current bucket starts flat
+        let mut live = LiveCandles::new(
+            "mint".to_string(),
+            CandlestickInterval::OneMinute,
+            Some(&last),
+            200,
+        );
+        let closed = live.close_elapsed(240).unwrap();
+        assert_eq!(closed.candle.timestamp, 180);
+        assert_eq!(closed.candle.close, 1.5);
+        assert_eq!(closed.candle.volume, 0.0);
+    }
+
+    #[test]
+    fn test_live_candles_pending() {
+        let mut live = LiveCandles::pending("mint".to_string(), CandlestickInterval::OneMinute, 1);
+        assert!(live.is_pending(1));
+        assert!(!live.is_pending(2));
+
+        // kept until the backfill arrives
+        assert!(live.apply(&trade(2.0, 1.0, 50)).is_empty());
+        assert!(live.apply(&trade(3.0, 1.0, 95)).is_empty());
+        assert_eq!(live.close_elapsed(200), None);
+
+        let last = Candlestick {
+            timestamp: 0,
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 10.0,
+        };
+        // the trade the backfill has is skipped
+        let seen = HashSet::from([TradeKey::of(&trade(2.0, 1.0, 50))]);
+        let messages = live.start(Some(&last), seen, 90);
+        assert!(!live.is_pending(1));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].candle.timestamp, 60);
+        assert_eq!(messages[0].candle.open, 3.0);
+        assert_eq!(messages[0].candle.volume, 1.0);
+    }
+
+    #[test]
+    fn test_merge_recent_trades() {
+        let candle = |timestamp, close, volume| Candlestick {
+            timestamp,
+            open: 1.0,
+            high: close,
+            low: 1.0,
+            close,
+            volume,
+        };
+        // the last bucket was gap filled, the database has the first trade
+        let mut candles = vec![candle(0, 2.0, 10.0), candle(60, 2.0, 0.0)];
+        let inserted = vec![TradeKey::of(&trade(2.0, 10.0, 30))];
+        let recent = [
+            trade(2.0, 10.0, 30),
+            trade(3.0, 1.0, 90),
+            trade(2.5, 2.0, 100),
+            trade(4.0, 4.0, 190),
+        ];
+
+        let seen = merge_recent_trades(
+            &mut candles,
+            CandlestickInterval::OneMinute,
+            inserted,
+            &recent,
+        );
+
+        assert_eq!(seen.len(), 4);
+        let summary: Vec<_> = candles
+            .iter()
+            .map(|c| (c.timestamp, c.open, c.high, c.low, c.close, c.volume))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, 1.0, 2.0, 1.0, 2.0, 10.0),
+                (60, 3.0, 3.0, 2.5, 2.5, 3.0),
+                (120, 2.5, 2.5, 2.5, 2.5, 0.0),
+                (180, 4.0, 4.0, 4.0, 4.0, 4.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recent_trades() {
+        let recent = RecentTrades::default();
+        recent.push(trade(1.0, 1.0, 100));
+        recent.push(trade(2.0, 1.0, 150));
+        assert_eq!(recent.of_mint("mint", 120).len(), 1);
+        assert!(recent.of_mint("other", 0).is_empty());
+
+        // the trades older than the window are dropped
+        recent.push(trade(3.0, 1.0, 100 + RECENT_TRADES_SECS + 1));
+        assert_eq!(recent.of_mint("mint", 0).len(), 2);
+    }
+}
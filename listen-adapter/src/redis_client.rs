@@ -32,6 +32,21 @@ pub struct TokenMetadata {
     pub spl: SplTokenMetadata,
 }
 
+/// Latest reserves and liquidity of a pool, written by the indexer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolState {
+    pub pool: String,
+    pub dex: String,
+    pub base_mint: String,
+    pub quote_mint: String,
+    pub base_reserve: f64,
+    pub quote_reserve: f64,
+    pub liquidity_usd: f64,
+    pub timestamp: u64,
+    pub slot: u64,
+    pub signature: String,
+}
+
 impl RedisClient {
     pub async fn new(redis_url: &str) -> Result<Self> {
         let manager = RedisConnectionManager::new(redis_url)?;
@@ -99,6 +114,51 @@ impl RedisClient {
         }
     }
 
+    fn make_pool_key(&self, pool: &str) -> String {
+        format!("solana:pool:{}", pool)
+    }
+
+    fn make_mint_pools_key(&self, mint: &str) -> String {
+        format!("solana:pools:{}", mint)
+    }
+
+    /// Pools of the mint, the deepest first
+    pub async fn get_pools(&self, mint: &str) -> Result<Vec<PoolState>> {
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Failed to get Redis connection")?;
+
+        let pools: Vec<String> = cmd("SMEMBERS")
+            .arg(self.make_mint_pools_key(mint))
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to get pools for mint: {}", mint))?;
+        if pools.is_empty() {
+            debug!(mint, "No pools found");
+            return Ok(Vec::new());
+        }
+
+        let keys: Vec<String> = pools.iter().map(|p| self.make_pool_key(p)).collect();
+        let values: Vec<Option<String>> = cmd("MGET")
+            .arg(keys)
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to get pool states for mint: {}", mint))?;
+
+        let mut states = values
+            .into_iter()
+            .flatten()
+            .map(|json_str| {
+                serde_json::from_str::<PoolState>(&json_str)
+                    .with_context(|| format!("Failed to deserialize pool state for mint: {}", mint))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        states.sort_by(|a, b| b.liquidity_usd.total_cmp(&a.liquidity_usd));
+        Ok(states)
+    }
+
     fn make_chat_key(&self, chat_id: &str) -> String {
         format!("chats:shared:{}", chat_id)
     }
@@ -5,22 +5,42 @@ use futures::StreamExt;
 use tokio::sync::broadcast;
 use tracing::{debug, error};
 
+use crate::db::PriceUpdate;
+use crate::live_candles::RecentTrades;
+
+/// a payload along with the channel it was published on
+#[derive(Debug, Clone)]
+pub struct RedisMessage {
+    pub channel: String,
+    pub payload: String,
+}
+
 pub struct RedisSubscriber {
     client: redis::Client,
-    tx: broadcast::Sender<String>,
+    tx: broadcast::Sender<RedisMessage>,
+    recent_trades: Arc<RecentTrades>,
 }
 
 impl RedisSubscriber {
     pub fn new(redis_url: &str) -> Result<Self> {
         let client = redis::Client::open(redis_url)?;
         let (tx, _) = broadcast::channel(200);
-        Ok(Self { client, tx })
+        Ok(Self {
+            client,
+            tx,
+            recent_trades: Arc::default(),
+        })
     }
 
-    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+    pub fn subscribe(&self) -> broadcast::Receiver<RedisMessage> {
         self.tx.subscribe()
     }
 
+    /// the price updates published lately, kept before they are broadcast
+    pub fn recent_trades(&self) -> Arc<RecentTrades> {
+        self.recent_trades.clone()
+    }
+
     pub async fn start_listening(&self, channel: &str) -> Result<()> {
         let conn = self.client.get_async_connection().await?;
         debug!("Subscribing to Redis channel: {}", channel);
@@ -28,6 +48,7 @@ impl RedisSubscriber {
         let mut pubsub = conn.into_pubsub();
         pubsub.subscribe(channel).await?;
         let tx = self.tx.clone();
+        let recent_trades = self.recent_trades.clone();
 
         tokio::spawn(async move {
             let mut msg_stream = pubsub.on_message();
@@ -35,7 +56,13 @@ impl RedisSubscriber {
             while let Some(msg) = msg_stream.next().await {
                 match msg.get_payload::<String>() {
                     Ok(payload) => {
-                        let _ = tx.send(payload);
+                        let channel = msg.get_channel_name().to_string();
+                        if channel == "price_updates" {
+                            if let Ok(trade) = serde_json::from_str::<PriceUpdate>(&payload) {
+                                recent_trades.push(trade);
+                            }
+                        }
+                        let _ = tx.send(RedisMessage { channel, payload });
                     }
                     Err(e) => {
                         error!("Failed to get message payload: {}", e);
@@ -51,6 +78,8 @@ impl RedisSubscriber {
 pub async fn create_redis_subscriber(redis_url: &str) -> anyhow::Result<Arc<RedisSubscriber>> {
     let subscriber = RedisSubscriber::new(redis_url)?;
     subscriber.start_listening("price_updates").await?;
+    subscriber.start_listening("pool_updates").await?;
+    subscriber.start_listening("launch_events").await?;
 
     Ok(Arc::new(subscriber))
 }
@@ -68,6 +97,6 @@ mod tests {
 
         let mut sub = subscriber.subscribe();
         let msg = sub.recv().await.unwrap();
-        assert!(msg != "");
+        assert!(!msg.payload.is_empty());
     }
 }
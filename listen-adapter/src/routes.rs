@@ -1,9 +1,12 @@
+use crate::db::analytics::{AnalyticsError, ANALYTICS_LIMITS};
+use crate::db::screener::{ScreenerFilters, ScreenerSort, SortOrder};
 use crate::websocket::handle_ws_connection;
 use crate::{db::candlesticks::CandlestickInterval, state::AppState};
 use actix_web::{error::InternalError, http::StatusCode, web, Error, HttpRequest, HttpResponse};
-use regex::Regex;
+use clickhouse::error::Error::BadResponse;
 use serde::Deserialize;
 use serde_json::json;
+use std::collections::BTreeMap;
 use tracing::error;
 
 pub async fn ws_route(
@@ -18,6 +21,7 @@ pub async fn ws_route(
         session,
         msg_stream,
         state.redis_subscriber.clone(),
+        state.clickhouse_db.clone(),
     ));
 
     Ok(res)
@@ -72,25 +76,88 @@ pub async fn top_tokens(
     }
 }
 
+#[derive(Deserialize)]
+pub struct ScreenerPagination {
+    pub sort_by: Option<ScreenerSort>,
+    pub order: Option<SortOrder>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+/// Tokens ranked by `sort_by` over the last `timeframe` seconds with the
+/// filters of `ScreenerFilters`, paged with `offset`
+pub async fn screener(
+    state: web::Data<AppState>,
+    filters: web::Query<ScreenerFilters>,
+    pagination: web::Query<ScreenerPagination>,
+) -> Result<HttpResponse, Error> {
+    let page = state
+        .clickhouse_db
+        .screen_tokens(
+            &filters,
+            pagination.sort_by.unwrap_or_default(),
+            pagination.order.unwrap_or_default(),
+            pagination.limit.unwrap_or(20).min(100),
+            pagination.offset.unwrap_or(0),
+        )
+        .await;
+
+    match page {
+        Ok(page) => Ok(HttpResponse::Ok().json(page)),
+        Err(e) => {
+            error!("Error screening tokens: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct CandlestickParams {
     pub mint: String,
     pub interval: CandlestickInterval,
     pub limit: Option<usize>,
+    /// unix timestamp of the first bucket
+    pub from: Option<u64>,
+    /// unix timestamp the buckets end before
+    pub to: Option<u64>,
+    /// the `x-next-cursor` of the previous page
+    pub cursor: Option<u64>,
+    pub fill_gaps: Option<bool>,
 }
 
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// Candlesticks in chronological order, the latest ones unless `to` or
+/// `cursor` is set; `x-next-cursor` is returned while there are older ones
 pub async fn get_candlesticks(
     state: web::Data<AppState>,
     query: web::Query<CandlestickParams>,
 ) -> Result<HttpResponse, Error> {
     let params = query.into_inner();
+    let to = match (params.to, params.cursor) {
+        (Some(to), Some(cursor)) => Some(to.min(cursor)),
+        (to, cursor) => to.or(cursor),
+    };
     let candlesticks = state
         .clickhouse_db
-        .get_candlesticks(&params.mint, &params.interval.to_string(), params.limit)
+        .get_candlesticks(
+            &params.mint,
+            &params.interval,
+            params.from,
+            to,
+            Some(params.limit.unwrap_or(200).min(1000)),
+            params.fill_gaps.unwrap_or(true),
+        )
         .await;
 
     match candlesticks {
-        Ok(candlesticks) => Ok(HttpResponse::Ok().json(candlesticks)),
+        Ok(page) => {
+            let mut response = HttpResponse::Ok();
+            if let Some(next_cursor) = page.next_cursor {
+                response.insert_header((NEXT_CURSOR_HEADER, next_cursor.to_string()));
+            }
+            Ok(response.json(page.candlesticks))
+        }
         Err(e) => {
             error!("Error getting candlesticks: {}", e);
             Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
@@ -135,49 +202,110 @@ pub async fn get_price(
 }
 
 #[derive(Deserialize)]
-pub struct QueryParams {
-    pub sql: String,
+pub struct PoolsQuery {
+    pub mint: String,
 }
 
-pub async fn query_db(
+pub async fn get_pools(
     state: web::Data<AppState>,
-    query: web::Json<QueryParams>,
+    query: web::Query<PoolsQuery>,
 ) -> Result<HttpResponse, Error> {
-    // Sanitize the SQL query
-    let sql = query.sql.trim();
+    match state.redis_client.get_pools(&query.mint).await {
+        Ok(pools) => Ok(HttpResponse::Ok().json(pools)),
+        Err(e) => {
+            error!("Error getting pools: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
+    }
+}
 
-    // Only allow SELECT queries (case insensitive check)
-    if !sql.to_uppercase().starts_with("SELECT ") {
-        return Ok(HttpResponse::BadRequest().json(json!({
-            "error": "Only SELECT queries are allowed"
-        })));
+#[derive(Deserialize)]
+pub struct WalletTradesQuery {
+    pub owner: String,
+    pub mint: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub async fn get_wallet_trades(
+    state: web::Data<AppState>,
+    query: web::Query<WalletTradesQuery>,
+) -> Result<HttpResponse, Error> {
+    let trades = state
+        .clickhouse_db
+        .get_wallet_trades(
+            &query.owner,
+            query.mint.as_deref(),
+            query.limit.unwrap_or(100).min(1000),
+        )
+        .await;
+
+    match trades {
+        Ok(trades) => Ok(HttpResponse::Ok().json(trades)),
+        Err(e) => {
+            error!("Error getting wallet trades: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
+        }
     }
+}
+
+#[derive(Deserialize)]
+pub struct WalletPnlQuery {
+    pub owner: String,
+    pub mint: Option<String>,
+}
 
-    // Block dangerous SQL commands while allowing table names containing these words
-    const BLOCKED_PATTERNS: [&str; 7] = [
-        r"(?i)\bDELETE\b",
-        r"(?i)\bDROP\b",
-        r"(?i)\bUPDATE\b",
-        r"(?i)\bINSERT\b",
-        r"(?i)\bALTER\b",
-        r"(?i)\bTRUNCATE\b",
-        r"(?i)\bGRANT\b",
-    ];
-
-    for pattern in BLOCKED_PATTERNS {
-        if Regex::new(pattern).unwrap().is_match(sql) {
-            return Ok(HttpResponse::BadRequest().json(json!({
-                "error": "Query contains forbidden SQL commands"
-            })));
+pub async fn get_wallet_pnl(
+    state: web::Data<AppState>,
+    query: web::Query<WalletPnlQuery>,
+) -> Result<HttpResponse, Error> {
+    let pnl = state
+        .clickhouse_db
+        .get_wallet_pnl(&query.owner, query.mint.as_deref())
+        .await;
+
+    match pnl {
+        Ok(pnl) => Ok(HttpResponse::Ok().json(pnl)),
+        Err(e) => {
+            error!("Error getting wallet pnl: {}", e);
+            Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
         }
     }
+}
+
+#[derive(Deserialize)]
+pub struct AnalyticsRequest {
+    pub sql: String,
+    /// values of the `{name:Type}` placeholders in the sql
+    #[serde(default)]
+    pub params: BTreeMap<String, serde_json::Value>,
+}
+
+/// Read-only select over the allow-listed tables, run as the analytics user
+/// with row and time limits; rows are returned as objects of their columns
+pub async fn analytics(
+    state: web::Data<AppState>,
+    body: web::Json<AnalyticsRequest>,
+) -> Result<HttpResponse, Error> {
+    let Some(analytics_db) = &state.analytics_db else {
+        return Ok(HttpResponse::ServiceUnavailable().json(json!({
+            "error": "Analytics is disabled, its user is not read-only"
+        })));
+    };
+    let result = analytics_db
+        .analytics_query(&body.sql, &body.params, &ANALYTICS_LIMITS)
+        .await;
 
-    // Execute the validated query
-    let result = state.clickhouse_db.generic_query(sql).await;
     match result {
         Ok(result) => Ok(HttpResponse::Ok().json(result)),
+        // rejected queries and the errors clickhouse reports for them are
+        // the caller's
+        Err(e @ (AnalyticsError::Rejected(_) | AnalyticsError::Clickhouse(BadResponse(_)))) => {
+            Ok(HttpResponse::BadRequest().json(json!({
+                "error": e.to_string()
+            })))
+        }
         Err(e) => {
-            error!("Error querying database: {}", e);
+            error!("Error running analytics query: {}", e);
             Err(InternalError::new(e, StatusCode::INTERNAL_SERVER_ERROR).into())
         }
     }
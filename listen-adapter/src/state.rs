@@ -9,4 +9,6 @@ pub struct AppState {
     pub redis_subscriber: Arc<RedisSubscriber>,
     pub redis_client: Arc<RedisClient>,
     pub clickhouse_db: Arc<ClickhouseDb>,
+    /// unset if the analytics user is not read-only
+    pub analytics_db: Option<Arc<ClickhouseDb>>,
 }
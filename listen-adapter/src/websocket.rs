@@ -1,21 +1,229 @@
 use actix_ws::{Message, Session};
 use futures::{Stream, StreamExt};
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use std::collections::HashSet;
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
+use crate::db::candlesticks::{Candlestick, CandlestickInterval};
+use crate::db::ClickhouseDb;
+use crate::live_candles::{
+    merge_recent_trades, CandlesMessage, LiveCandles, RecentTrades, TradeKey, RECENT_TRADES_SECS,
+};
 use crate::redis_subscriber::RedisSubscriber;
+use crate::ws_protocol::{
+    ClientMessage, Request, ServerMessage, Subscriptions, MAX_CANDLE_SUBSCRIPTIONS,
+    PROTOCOL_VERSION,
+};
 
-#[derive(Deserialize)]
-struct SubscribeMessage {
-    action: String,
-    mints: Vec<String>,
+const MAX_CANDLE_BACKFILL: usize = 1000;
+
+/// how often the server pings the client
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+
+/// a client that sends nothing for this long, pongs included, is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// the candles of a candle subscription up to the current bucket, gap filled,
+/// with the recent trades the database does not have yet; along with the
+/// trades they include, so that the live candles skip them
+async fn candle_backfill(
+    db: &ClickhouseDb,
+    recent_trades: &RecentTrades,
+    mint: &str,
+    interval: CandlestickInterval,
+    limit: Option<usize>,
+    now: u64,
+) -> anyhow::Result<(Vec<Candlestick>, HashSet<TradeKey>)> {
+    let limit = limit.unwrap_or(200).min(MAX_CANDLE_BACKFILL);
+    let since = now.saturating_sub(RECENT_TRADES_SECS);
+    let page = db
+        .get_candlesticks(mint, &interval, None, None, Some(limit), true)
+        .await?;
+    let inserted = db
+        .get_trade_keys(mint, since)
+        .await?
+        .into_iter()
+        .map(|(signature, is_buy, swap_amount)| TradeKey::new(signature, is_buy, swap_amount))
+        .collect();
+
+    // read after the database, whatever it is missing is here by now
+    let recent = recent_trades.of_mint(mint, since);
+    let mut candles = page.candlesticks;
+    let seen = merge_recent_trades(&mut candles, interval, inserted, &recent);
+    if candles.len() > limit {
+        candles.drain(..candles.len() - limit);
+    }
+
+    Ok((candles, seen))
 }
 
-#[derive(Serialize)]
-struct ErrorMessage {
-    error: String,
+/// The result of a candle backfill, run outside of the connection loop so
+/// that the connection keeps reading and routing while it runs
+struct CandleBackfill {
+    id: Option<u64>,
+    request: u64,
+    mint: String,
+    interval: CandlestickInterval,
+    now: u64,
+    candles: anyhow::Result<(Vec<Candlestick>, HashSet<TradeKey>)>,
+}
+
+/// What a connection needs to start the backfill of a candle subscription
+struct CandleBackfills {
+    db: Arc<ClickhouseDb>,
+    recent_trades: Arc<RecentTrades>,
+    tx: mpsc::UnboundedSender<CandleBackfill>,
+    requests: u64,
+}
+
+impl CandleBackfills {
+    /// spawns the backfill, its subscription waits for it and keeps the
+    /// trades meanwhile
+    fn spawn(
+        &mut self,
+        id: Option<u64>,
+        mint: String,
+        interval: CandlestickInterval,
+        limit: Option<usize>,
+    ) -> LiveCandles {
+        self.requests += 1;
+        let request = self.requests;
+        let live = LiveCandles::pending(mint.clone(), interval, request);
+        let db = self.db.clone();
+        let recent_trades = self.recent_trades.clone();
+        let tx = self.tx.clone();
+        let now = chrono::Utc::now().timestamp() as u64;
+        tokio::spawn(async move {
+            let candles = candle_backfill(&db, &recent_trades, &mint, interval, limit, now).await;
+            // the connection is gone if nobody receives it
+            let _ = tx.send(CandleBackfill {
+                id,
+                request,
+                mint,
+                interval,
+                now,
+                candles,
+            });
+        });
+        live
+    }
+}
+
+/// starts the subscription that waits for the backfill, returns the backfill
+/// followed by the candles of the trades kept meanwhile; nothing if the
+/// subscription was dropped or replaced in the meantime
+fn start_candles(backfill: CandleBackfill, subscriptions: &mut Subscriptions) -> Vec<String> {
+    let Some(live) = subscriptions
+        .candles
+        .iter_mut()
+        .find(|live| live.is_pending(backfill.request))
+    else {
+        return vec![];
+    };
+    let (candles, seen) = match backfill.candles {
+        Ok(candles) => candles,
+        Err(e) => {
+            subscriptions.unsubscribe_candles(&backfill.mint, backfill.interval);
+            return error_reply(
+                backfill.id,
+                format!("Failed to subscribe to candles: {}", e),
+            );
+        }
+    };
+    let mut replies = vec![serde_json::to_string(&CandlesMessage::new(
+        &backfill.mint,
+        backfill.interval,
+        &candles,
+    ))
+    .unwrap()];
+    replies.extend(
+        live.start(candles.last(), seen, backfill.now)
+            .iter()
+            .map(|candle| serde_json::to_string(candle).unwrap()),
+    );
+    replies
+}
+
+fn error_reply(id: Option<u64>, error: String) -> Vec<String> {
+    vec![ServerMessage::Error { id, error }.to_text()]
+}
+
+/// applies a message of the client to its subscriptions, returns the replies;
+/// the backfill of a candle subscription follows once it is done
+fn handle_client_message(
+    text: &str,
+    subscriptions: &mut Subscriptions,
+    versioned: &mut bool,
+    backfills: &mut CandleBackfills,
+) -> Vec<String> {
+    let msg = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(msg) => msg,
+        Err(e) => return error_reply(None, format!("Invalid message format: {}", e)),
+    };
+    let id = msg.id;
+    if msg.v.is_some_and(|v| v > PROTOCOL_VERSION) {
+        return error_reply(
+            id,
+            format!("Unsupported protocol version: {}", msg.v.unwrap()),
+        );
+    }
+    let legacy = msg.is_legacy();
+    *versioned |= !legacy;
+
+    let action = msg.request.action();
+    let mut replies = Vec::new();
+    match msg.request {
+        Request::Subscribe {
+            mints,
+            topics,
+            filters,
+        } => subscriptions.subscribe(mints, topics, filters, legacy),
+        Request::Unsubscribe { mints, topics } => subscriptions.unsubscribe(&mints, &topics),
+        Request::SubscribeCandles {
+            mint,
+            interval,
+            limit,
+        } => {
+            let interval = match interval.parse::<CandlestickInterval>() {
+                Ok(interval) => interval,
+                Err(e) => return error_reply(id, e.to_string()),
+            };
+            if subscriptions.candles_full(&mint, interval) {
+                return error_reply(
+                    id,
+                    format!(
+                        "At most {} candle subscriptions per connection",
+                        MAX_CANDLE_SUBSCRIPTIONS
+                    ),
+                );
+            }
+            subscriptions.subscribe_candles(backfills.spawn(id, mint, interval, limit));
+        }
+        Request::UnsubscribeCandles { mint, interval } => {
+            let interval = match interval.parse::<CandlestickInterval>() {
+                Ok(interval) => interval,
+                Err(e) => return error_reply(id, e.to_string()),
+            };
+            subscriptions.unsubscribe_candles(&mint, interval);
+        }
+        Request::Ping => return vec![ServerMessage::Pong { id }.to_text()],
+    }
+
+    let snapshot = subscriptions.snapshot();
+    info!("Updated subscriptions: {:?}", snapshot);
+    if !legacy {
+        let ack = ServerMessage::Ack {
+            v: PROTOCOL_VERSION,
+            id,
+            action,
+            subscriptions: snapshot,
+        };
+        replies.insert(0, ack.to_text());
+    }
+    replies
 }
 
 pub struct AppState {
@@ -26,20 +234,42 @@ pub async fn handle_ws_connection(
     mut session: Session,
     mut msg_stream: impl Stream<Item = Result<Message, actix_ws::ProtocolError>> + Unpin,
     redis_subscriber: Arc<RedisSubscriber>,
+    clickhouse_db: Arc<ClickhouseDb>,
 ) {
     info!("WebSocket connection established");
 
     // Get a new broadcast receiver
     let mut redis_rx = redis_subscriber.subscribe();
 
-    // Track subscribed mints
-    let mut subscribed_mints: Vec<String> = Vec::new();
-    let mut subscribe_all = false;
+    let mut subscriptions = Subscriptions::default();
+    let (backfill_tx, mut backfill_rx) = mpsc::unbounded_channel();
+    let mut backfills = CandleBackfills {
+        db: clickhouse_db,
+        recent_trades: redis_subscriber.recent_trades(),
+        tx: backfill_tx,
+        requests: 0,
+    };
+    // clients of the versioned protocol also get acks and lag notices
+    let mut versioned = false;
+    let mut dropped = 0;
+    let mut last_seen = Instant::now();
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    // closes the candle buckets that pass without trades
+    let mut candle_ticker = tokio::time::interval(Duration::from_secs(1));
 
-    loop {
+    'connection: loop {
         tokio::select! {
             // Handle WebSocket messages
-            Some(Ok(msg)) = msg_stream.next() => {
+            msg = msg_stream.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(e)) => {
+                        error!("WebSocket protocol error: {}", e);
+                        break;
+                    }
+                    None => break,
+                };
+                last_seen = Instant::now();
                 match msg {
                     Message::Close(reason) => {
                         info!("WebSocket connection closed: {:?}", reason);
@@ -52,34 +282,12 @@ pub async fn handle_ws_connection(
                         }
                     }
                     Message::Text(text) => {
-                        match serde_json::from_str::<SubscribeMessage>(&text) {
-                            Ok(subscribe_msg) => {
-                                if subscribe_msg.action == "subscribe" {
-                                    // Check for wildcard subscription
-                                    subscribe_all = subscribe_msg.mints.iter().any(|m| m == "*");
-                                    subscribed_mints = if subscribe_all {
-                                        Vec::new() // Clear specific subscriptions if wildcard is used
-                                    } else {
-                                        subscribe_msg.mints
-                                    };
-                                    info!(
-                                        "Updated subscriptions: {}",
-                                        if subscribe_all {
-                                            "all mints (wildcard)".to_string()
-                                        } else {
-                                            format!("specific mints: {:?}", subscribed_mints)
-                                        }
-                                    );
-                                }
-                            }
-                            Err(e) => {
-                                let error_msg = ErrorMessage {
-                                    error: format!("Invalid message format: {}", e),
-                                };
-                                if let Err(e) = session.text(serde_json::to_string(&error_msg).unwrap()).await {
-                                    error!("Failed to send error message: {}", e);
-                                    break;
-                                }
+                        let replies =
+                            handle_client_message(&text, &mut subscriptions, &mut versioned, &mut backfills);
+                        for reply in replies {
+                            if let Err(e) = session.text(reply).await {
+                                error!("Failed to send reply: {}", e);
+                                break 'connection;
                             }
                         }
                     }
@@ -87,20 +295,67 @@ pub async fn handle_ws_connection(
                 }
             }
 
-            Ok(msg) = redis_rx.recv() => {
-                if let Ok(json) = serde_json::from_str::<Value>(&msg) {
-                    if let Some(mint) = json.get("pubkey").and_then(|m| m.as_str()) {
-                        if subscribe_all || subscribed_mints.iter().any(|m| m == mint) {
-                            if let Err(e) = session.text(msg).await {
+            msg = redis_rx.recv() => {
+                match msg {
+                    Ok(msg) => {
+                        for out in subscriptions.route(&msg) {
+                            if let Err(e) = session.text(out).await {
+                                error!("Failed to send message: {}", e);
+                                break 'connection;
+                            }
+                        }
+                    }
+                    // a slow client skips what it could not keep up with
+                    // instead of being disconnected
+                    Err(RecvError::Lagged(n)) => {
+                        dropped += n;
+                        warn!("WebSocket client lagging, dropped {} messages ({} total)", n, dropped);
+                        if versioned {
+                            let notice = ServerMessage::Lagged { dropped: n };
+                            if let Err(e) = session.text(notice.to_text()).await {
                                 error!("Failed to send message: {}", e);
                                 break;
                             }
                         }
                     }
+                    Err(RecvError::Closed) => break,
                 }
             }
 
-            else => break,
+            Some(backfill) = backfill_rx.recv() => {
+                for reply in start_candles(backfill, &mut subscriptions) {
+                    if let Err(e) = session.text(reply).await {
+                        error!("Failed to send candles: {}", e);
+                        break 'connection;
+                    }
+                }
+            }
+
+            _ = candle_ticker.tick(), if !subscriptions.candles.is_empty() => {
+                let now = chrono::Utc::now().timestamp() as u64;
+                let closed: Vec<_> = subscriptions
+                    .candles
+                    .iter_mut()
+                    .filter_map(|live| live.close_elapsed(now))
+                    .collect();
+                for candle in closed {
+                    if let Err(e) = session.text(serde_json::to_string(&candle).unwrap()).await {
+                        error!("Failed to send candle: {}", e);
+                        break 'connection;
+                    }
+                }
+            }
+
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() > CLIENT_TIMEOUT {
+                    info!("WebSocket client timed out");
+                    break;
+                }
+                if let Err(e) = session.ping(b"").await {
+                    error!("Failed to send ping: {}", e);
+                    break;
+                }
+            }
         }
     }
 
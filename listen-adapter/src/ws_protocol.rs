@@ -0,0 +1,518 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::candlesticks::CandlestickInterval;
+use crate::db::PriceUpdate;
+use crate::live_candles::LiveCandles;
+use crate::redis_subscriber::RedisMessage;
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// every candle subscription runs a backfill query and a live candle
+pub const MAX_CANDLE_SUBSCRIPTIONS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    /// trades of the subscribed mints
+    Prices,
+    /// pool state of the subscribed mints
+    Pools,
+    /// new pools and tokens, not tied to a mint
+    Launches,
+}
+
+impl Topic {
+    pub fn from_channel(channel: &str) -> Option<Self> {
+        match channel {
+            "price_updates" => Some(Topic::Prices),
+            "pool_updates" => Some(Topic::Pools),
+            "launch_events" => Some(Topic::Launches),
+            _ => None,
+        }
+    }
+}
+
+/// Server side filters of the price updates, unset ones let everything through
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Filters {
+    pub min_swap_usd: Option<f64>,
+    #[serde(default)]
+    pub only_buys: bool,
+    pub is_pump: Option<bool>,
+    pub min_market_cap: Option<f64>,
+}
+
+impl Filters {
+    pub fn matches(&self, update: &PriceUpdate) -> bool {
+        self.min_swap_usd
+            .is_none_or(|min| update.swap_amount >= min)
+            && (!self.only_buys || update.is_buy)
+            && self.is_pump.is_none_or(|is_pump| update.is_pump == is_pump)
+            // the market cap is not known without the token metadata
+            && self
+                .min_market_cap
+                .is_none_or(|min| update.has_metadata && update.market_cap >= min)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientMessage {
+    /// messages without a version speak the first protocol, where a subscribe
+    /// replaces the previous one and nothing is acknowledged
+    pub v: Option<u32>,
+    /// echoed back in the reply
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+impl ClientMessage {
+    pub fn is_legacy(&self) -> bool {
+        matches!(self.v, None | Some(1))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Request {
+    /// adds to the subscriptions, mints alone subscribe to their prices and
+    /// `*` to the prices of every mint
+    Subscribe {
+        #[serde(default)]
+        mints: Vec<String>,
+        #[serde(default)]
+        topics: Vec<Topic>,
+        /// replaces the filters of the connection when set
+        filters: Option<Filters>,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        mints: Vec<String>,
+        #[serde(default)]
+        topics: Vec<Topic>,
+    },
+    SubscribeCandles {
+        mint: String,
+        interval: String,
+        /// how many historical candles to send first
+        limit: Option<usize>,
+    },
+    UnsubscribeCandles {
+        mint: String,
+        interval: String,
+    },
+    Ping,
+}
+
+impl Request {
+    pub fn action(&self) -> &'static str {
+        match self {
+            Request::Subscribe { .. } => "subscribe",
+            Request::Unsubscribe { .. } => "unsubscribe",
+            Request::SubscribeCandles { .. } => "subscribe_candles",
+            Request::UnsubscribeCandles { .. } => "unsubscribe_candles",
+            Request::Ping => "ping",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Ack {
+        v: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+        action: &'static str,
+        subscriptions: SubscriptionsSnapshot,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+        error: String,
+    },
+    Pong {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<u64>,
+    },
+    /// the client did not keep up and missed this many messages
+    Lagged { dropped: u64 },
+}
+
+impl ServerMessage {
+    pub fn to_text(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CandleSubscription {
+    pub mint: String,
+    pub interval: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubscriptionsSnapshot {
+    pub mints: Vec<String>,
+    pub all_mints: bool,
+    pub topics: Vec<Topic>,
+    pub filters: Filters,
+    pub candles: Vec<CandleSubscription>,
+}
+
+/// What a connection is subscribed to
+#[derive(Default)]
+pub struct Subscriptions {
+    pub mints: HashSet<String>,
+    pub all_mints: bool,
+    pub topics: HashSet<Topic>,
+    pub filters: Filters,
+    pub candles: Vec<LiveCandles>,
+}
+
+impl Subscriptions {
+    /// a legacy subscribe replaces the mints, topics and filters and only
+    /// includes prices, the first protocol did not know any other topic
+    pub fn subscribe(
+        &mut self,
+        mints: Vec<String>,
+        topics: Vec<Topic>,
+        filters: Option<Filters>,
+        legacy: bool,
+    ) {
+        if legacy {
+            self.mints.clear();
+            self.all_mints = false;
+            self.topics = HashSet::from([Topic::Prices]);
+            self.filters = Filters::default();
+        } else if topics.is_empty() && !mints.is_empty() {
+            self.topics.insert(Topic::Prices);
+        }
+
+        for mint in mints {
+            match mint.as_str() {
+                "*" => self.all_mints = true,
+                _ => {
+                    self.mints.insert(mint);
+                }
+            }
+        }
+        self.topics.extend(topics);
+        if let Some(filters) = filters {
+            self.filters = filters;
+        }
+    }
+
+    /// `*` drops every mint
+    pub fn unsubscribe(&mut self, mints: &[String], topics: &[Topic]) {
+        for mint in mints {
+            match mint.as_str() {
+                "*" => {
+                    self.all_mints = false;
+                    self.mints.clear();
+                }
+                _ => {
+                    self.mints.remove(mint);
+                }
+            }
+        }
+        for topic in topics {
+            self.topics.remove(topic);
+        }
+    }
+
+    /// whether another candle subscription would go over the cap, replacing
+    /// the one of the same mint and interval does not
+    pub fn candles_full(&self, mint: &str, interval: CandlestickInterval) -> bool {
+        self.candles.len() >= MAX_CANDLE_SUBSCRIPTIONS
+            && !self
+                .candles
+                .iter()
+                .any(|live| live.mint == mint && live.interval == interval)
+    }
+
+    /// replaces the candles of the same mint and interval
+    pub fn subscribe_candles(&mut self, live: LiveCandles) {
+        self.unsubscribe_candles(&live.mint.clone(), live.interval);
+        self.candles.push(live);
+    }
+
+    pub fn unsubscribe_candles(&mut self, mint: &str, interval: CandlestickInterval) {
+        self.candles
+            .retain(|live| live.mint != mint || live.interval != interval);
+    }
+
+    fn wants_mint(&self, mint: &str) -> bool {
+        self.all_mints || self.mints.contains(mint)
+    }
+
+    /// what to send the client for a message from redis, the candle updates
+    /// of a trade come before the trade itself
+    pub fn route(&mut self, msg: &RedisMessage) -> Vec<String> {
+        let mut out = Vec::new();
+        let Some(topic) = Topic::from_channel(&msg.channel) else {
+            return out;
+        };
+        match topic {
+            Topic::Prices => {
+                if self.candles.is_empty() && !self.topics.contains(&Topic::Prices) {
+                    return out;
+                }
+                let Ok(update) = serde_json::from_str::<PriceUpdate>(&msg.payload) else {
+                    return out;
+                };
+                for live in self
+                    .candles
+                    .iter_mut()
+                    .filter(|live| live.mint == update.pubkey)
+                {
+                    out.extend(
+                        live.apply(&update)
+                            .iter()
+                            .map(|candle| serde_json::to_string(candle).unwrap()),
+                    );
+                }
+                if self.topics.contains(&Topic::Prices)
+                    && self.wants_mint(&update.pubkey)
+                    && self.filters.matches(&update)
+                {
+                    out.push(msg.payload.clone());
+                }
+            }
+            Topic::Pools => {
+                if !self.topics.contains(&Topic::Pools) {
+                    return out;
+                }
+                // the pool state does not tell its kind, unlike the other frames
+                let Ok(mut pool) = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(
+                    &msg.payload,
+                ) else {
+                    return out;
+                };
+                if pool
+                    .get("base_mint")
+                    .and_then(|mint| mint.as_str())
+                    .is_some_and(|mint| self.wants_mint(mint))
+                {
+                    pool.insert("type".to_string(), "pool_update".into());
+                    out.push(serde_json::Value::Object(pool).to_string());
+                }
+            }
+            Topic::Launches => {
+                if self.topics.contains(&Topic::Launches) {
+                    out.push(msg.payload.clone());
+                }
+            }
+        }
+        out
+    }
+
+    pub fn snapshot(&self) -> SubscriptionsSnapshot {
+        let mut mints: Vec<String> = self.mints.iter().cloned().collect();
+        mints.sort();
+        let mut topics: Vec<Topic> = self.topics.iter().copied().collect();
+        topics.sort();
+        SubscriptionsSnapshot {
+            mints,
+            all_mints: self.all_mints,
+            topics,
+            filters: self.filters.clone(),
+            candles: self
+                .candles
+                .iter()
+                .map(|live| CandleSubscription {
+                    mint: live.mint.clone(),
+                    interval: live.interval.as_str(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price_update(pubkey: &str, swap_amount: f64, is_buy: bool) -> RedisMessage {
+        let payload = serde_json::json!({
+            "name": "TOKEN",
+            "pubkey": pubkey,
+            "price": 1.0,
+            "market_cap": 1_000_000.0,
+            "timestamp": 65,
+            "slot": 1,
+            "swap_amount": swap_amount,
+            "owner": "owner",
+            "signature": "signature",
+            "multi_hop": false,
+            "is_buy": is_buy,
+            "is_pump": true,
+            "quote_mint": "So11111111111111111111111111111111111111112",
+        });
+        RedisMessage {
+            channel: "price_updates".to_string(),
+            payload: payload.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_client_message() {
+        let msg: ClientMessage =
+            serde_json::from_str(r#"{"action":"subscribe","mints":["abc"],"topics":["launches"]}"#)
+                .unwrap();
+        assert!(msg.is_legacy());
+        assert!(matches!(
+            msg.request,
+            Request::Subscribe { filters: None, .. }
+        ));
+
+        let msg: ClientMessage = serde_json::from_str(
+            r#"{"v":2,"id":7,"action":"subscribe","mints":["abc"],"filters":{"min_swap_usd":100,"only_buys":true}}"#,
+        )
+        .unwrap();
+        assert!(!msg.is_legacy());
+        assert_eq!(msg.id, Some(7));
+        let Request::Subscribe { filters, .. } = msg.request else {
+            panic!("expected a subscribe");
+        };
+        assert_eq!(filters.unwrap().min_swap_usd, Some(100.0));
+
+        assert!(serde_json::from_str::<ClientMessage>(
+            r#"{"v":2,"action":"subscribe","topics":["nope"]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_subscribe_and_unsubscribe() {
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.subscribe(vec!["abc".to_string()], vec![], None, false);
+        subscriptions.subscribe(vec!["def".to_string()], vec![Topic::Launches], None, false);
+        assert_eq!(subscriptions.mints.len(), 2);
+        assert_eq!(
+            subscriptions.snapshot().topics,
+            vec![Topic::Prices, Topic::Launches]
+        );
+
+        subscriptions.unsubscribe(&["abc".to_string()], &[Topic::Launches]);
+        assert_eq!(subscriptions.snapshot().mints, vec!["def".to_string()]);
+        assert_eq!(subscriptions.snapshot().topics, vec![Topic::Prices]);
+
+        // a legacy subscribe starts over
+        subscriptions.subscribe(vec!["*".to_string()], vec![], None, true);
+        assert!(subscriptions.all_mints);
+        assert!(subscriptions.mints.is_empty());
+        assert_eq!(subscriptions.snapshot().topics, vec![Topic::Prices]);
+
+        // legacy clients only know price frames
+        let pool = RedisMessage {
+            channel: "pool_updates".to_string(),
+            payload: r#"{"pool":"p","base_mint":"abc"}"#.to_string(),
+        };
+        assert!(subscriptions.route(&pool).is_empty());
+    }
+
+    #[test]
+    fn test_route_with_filters() {
+        let mut subscriptions = Subscriptions::default();
+        let filters = Filters {
+            min_swap_usd: Some(100.0),
+            only_buys: true,
+            ..Default::default()
+        };
+        subscriptions.subscribe(vec!["abc".to_string()], vec![], Some(filters), false);
+
+        assert_eq!(
+            subscriptions.route(&price_update("abc", 500.0, true)).len(),
+            1
+        );
+        assert!(subscriptions
+            .route(&price_update("abc", 50.0, true))
+            .is_empty());
+        assert!(subscriptions
+            .route(&price_update("abc", 500.0, false))
+            .is_empty());
+        assert!(subscriptions
+            .route(&price_update("def", 500.0, true))
+            .is_empty());
+
+        let pool = RedisMessage {
+            channel: "pool_updates".to_string(),
+            payload: r#"{"base_mint":"abc"}"#.to_string(),
+        };
+        assert!(subscriptions.route(&pool).is_empty());
+        subscriptions.subscribe(vec![], vec![Topic::Pools], None, false);
+        let out = subscriptions.route(&pool);
+        assert_eq!(out.len(), 1);
+        assert!(out[0].contains(r#""type":"pool_update""#));
+    }
+
+    #[test]
+    fn test_route_without_quote_mint() {
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.subscribe(vec!["abc".to_string()], vec![], None, false);
+        let mut msg = price_update("abc", 500.0, true);
+        let mut payload: serde_json::Value = serde_json::from_str(&msg.payload).unwrap();
+        payload.as_object_mut().unwrap().remove("quote_mint");
+        msg.payload = payload.to_string();
+
+        assert_eq!(subscriptions.route(&msg), vec![msg.payload.clone()]);
+    }
+
+    #[test]
+    fn test_min_market_cap_needs_metadata() {
+        let filters = Filters {
+            min_market_cap: Some(500_000.0),
+            ..Default::default()
+        };
+        let mut update: PriceUpdate =
+            serde_json::from_str(&price_update("abc", 500.0, true).payload).unwrap();
+        assert!(update.has_metadata);
+        assert!(filters.matches(&update));
+
+        update.has_metadata = false;
+        assert!(!filters.matches(&update));
+        assert!(Filters::default().matches(&update));
+    }
+
+    #[test]
+    fn test_route_candles() {
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.subscribe_candles(LiveCandles::new(
+            "abc".to_string(),
+            CandlestickInterval::OneMinute,
+            None,
+            0,
+        ));
+
+        // candles do not need the prices topic and ignore the filters
+        let out = subscriptions.route(&price_update("abc", 1.0, false));
+        assert_eq!(out.len(), 1);
+        assert!(out[0].contains(r#""type":"candle""#));
+
+        subscriptions.unsubscribe_candles("abc", CandlestickInterval::OneMinute);
+        assert!(subscriptions
+            .route(&price_update("abc", 1.0, false))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_candles_full() {
+        let mut subscriptions = Subscriptions::default();
+        for i in 0..MAX_CANDLE_SUBSCRIPTIONS {
+            assert!(!subscriptions.candles_full(&i.to_string(), CandlestickInterval::OneMinute));
+            subscriptions.subscribe_candles(LiveCandles::new(
+                i.to_string(),
+                CandlestickInterval::OneMinute,
+                None,
+                0,
+            ));
+        }
+        assert!(subscriptions.candles_full("abc", CandlestickInterval::OneMinute));
+        assert!(subscriptions.candles_full("0", CandlestickInterval::OneHour));
+        // replacing one is not another subscription
+        assert!(!subscriptions.candles_full("0", CandlestickInterval::OneMinute));
+    }
+}
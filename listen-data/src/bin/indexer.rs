@@ -3,17 +3,28 @@ use clap::Parser;
 use listen_data::{
     geyser::make_geyser_pipeline,
     metrics::SwapMetrics,
+    replay::make_replay_pipeline,
     sol_price_stream::SolPriceCache,
     util::{make_db, make_kv_store, make_message_queue},
 };
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 use tracing::{error, info};
 
 #[derive(Parser)]
-pub struct Args {}
+pub struct Args {
+    /// replay the transactions recorded in this file instead of streaming
+    /// from geyser
+    #[arg(long, conflicts_with = "record")]
+    replay: Option<PathBuf>,
+
+    /// record the streamed transactions to this file, appending if it exists
+    #[arg(long)]
+    record: Option<PathBuf>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let args = Args::parse();
     listen_tracing::setup_tracing();
     if std::env::var("IS_SYSTEMD_SERVICE").is_err() {
         dotenv::dotenv().expect("Failed to load .env file");
@@ -30,8 +41,25 @@ async fn main() -> Result<()> {
 
     info!("Solana price: {}", price_cache.get_price().await);
 
-    let mut pipeline =
-        make_geyser_pipeline(kv_store, message_queue, db, swap_metrics)?;
+    let mut pipeline = match args.replay {
+        Some(path) => {
+            info!("Replaying {}", path.display());
+            make_replay_pipeline(
+                path,
+                kv_store,
+                message_queue,
+                db,
+                swap_metrics,
+            )?
+        }
+        None => make_geyser_pipeline(
+            kv_store,
+            message_queue,
+            db,
+            swap_metrics,
+            args.record.as_deref(),
+        )?,
+    };
 
     tokio::spawn(async move {
         if let Err(e) = price_cache.start_price_stream().await {
@@ -44,7 +44,9 @@ async fn main() -> Result<()> {
     let command = Command::parse();
 
     let mut pipeline = match command {
-        Command::RaydiumAccountsRpc => make_raydium_rpc_accounts_pipeline()?,
+        Command::RaydiumAccountsRpc => {
+            make_raydium_rpc_accounts_pipeline(kv_store)?
+        }
         Command::RaydiumInstructionsRpc => {
             make_raydium_rpc_instruction_pipeline(
                 kv_store,
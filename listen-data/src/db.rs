@@ -1,55 +1,51 @@
-use std::{sync::Arc, time::Duration};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
+use crate::constants::WSOL_MINT_KEY_STR;
+use crate::events::{LaunchEvent, LaunchEventRow};
+use crate::pool::PoolState;
 use crate::price::PriceUpdate;
+use crate::quote::QUOTE_REGISTRY;
 use anyhow::{Context, Result};
 use clickhouse::inserter::Inserter;
 use clickhouse::Client;
+use solana_sdk::pubkey::Pubkey;
 use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tracing::{debug, error, info};
+
+/// buckets the candlesticks are pre-aggregated in, by name and seconds
+pub const CANDLE_ROLLUPS: [(&str, u64); 3] =
+    [("1m", 60), ("1h", 3600), ("1d", 86400)];
 
 #[async_trait::async_trait]
-pub trait Database {
-    fn new(
-        database_url: &str,
-        password: &str,
-        user: &str,
-        database: &str,
-    ) -> Self
-    where
-        Self: Sized;
+pub trait Database: Send + Sync + 'static {
     async fn initialize(&mut self) -> Result<()>;
 
     async fn health_check(&self) -> Result<()>;
 
     async fn insert_price(&self, price: &PriceUpdate) -> Result<()>;
+
+    async fn insert_pool_state(&self, pool_state: &PoolState) -> Result<()>;
+
+    async fn insert_launch_event(&self, event: &LaunchEvent) -> Result<()>;
+}
+
+/// the insert of the updates a new materialized view did not see
+struct Backfill {
+    table: String,
+    query: String,
 }
 
 pub struct ClickhouseDb {
     client: Client,
     inserter: Option<Arc<RwLock<Inserter<PriceUpdate>>>>,
+    pool_state_inserter: Option<Arc<RwLock<Inserter<PoolState>>>>,
+    launch_event_inserter: Option<Arc<RwLock<Inserter<LaunchEventRow>>>>,
     is_initialized: bool,
     max_rows: u64,
 }
 
 impl ClickhouseDb {
-    fn create_inserter(&self) -> Result<Inserter<PriceUpdate>> {
-        Ok(self
-            .client
-            .inserter::<PriceUpdate>("price_updates")
-            .context("failed to prepare price insert statement")?
-            .with_timeouts(
-                Some(Duration::from_secs(5)),
-                Some(Duration::from_secs(20)),
-            )
-            .with_max_rows(self.max_rows)
-            .with_max_bytes(1_000_000) // price update is roughly ~200 bytes
-            .with_period(Some(Duration::from_secs(15))))
-    }
-}
-
-#[async_trait::async_trait]
-impl Database for ClickhouseDb {
-    fn new(
+    pub fn new(
         database_url: &str,
         password: &str,
         user: &str,
@@ -65,11 +61,282 @@ impl Database for ClickhouseDb {
         Self {
             client,
             inserter: None,
+            pool_state_inserter: None,
+            launch_event_inserter: None,
             is_initialized: false,
             max_rows: 1000,
         }
     }
 
+    /// creates the materialized view `{table}_mv` filling `table` with
+    /// `select` from the updates from now on, and returns the backfill of
+    /// the updates before if the view is new
+    ///
+    /// the rows of an earlier start that failed before the view existed are
+    /// dropped first, a backfill that fails later is redone by dropping the
+    /// view
+    async fn create_view(
+        &self,
+        table: &str,
+        select: &str,
+        filter: Option<&str>,
+        group_by: Option<&str>,
+    ) -> Result<Option<Backfill>> {
+        let view = format!("{}_mv", table);
+        let exists = self
+            .client
+            .query(&format!("EXISTS TABLE {}", view))
+            .fetch_one::<u8>()
+            .await
+            .with_context(|| format!("Failed to check for {}", view))?
+            == 1;
+        if exists {
+            return Ok(None);
+        }
+
+        // the cutoff is part of the view so that every update lands either
+        // in the view or in the backfill
+        let cutoff = chrono::Utc::now().timestamp() as u64;
+        let query = |range: String| {
+            let filter = match filter {
+                Some(filter) => format!("({}) AND {}", filter, range),
+                None => range,
+            };
+            let group_by = group_by
+                .map(|columns| format!(" GROUP BY {}", columns))
+                .unwrap_or_default();
+            format!("{} WHERE {}{}", select, filter, group_by)
+        };
+
+        self.client
+            .query(&format!("TRUNCATE TABLE IF EXISTS {}", table))
+            .execute()
+            .await
+            .with_context(|| format!("Failed to truncate {}", table))?;
+        self.client
+            .query(&format!(
+                "CREATE MATERIALIZED VIEW {} TO {} AS {}",
+                view,
+                table,
+                query(format!("timestamp >= {}", cutoff))
+            ))
+            .execute()
+            .await
+            .with_context(|| format!("Failed to create {} view", view))?;
+
+        Ok(Some(Backfill {
+            table: table.to_string(),
+            query: format!(
+                "INSERT INTO {} {}",
+                table,
+                query(format!("timestamp < {}", cutoff))
+            ),
+        }))
+    }
+
+    /// runs the backfills of the new views one after the other in the
+    /// background, so that the indexer doesn't wait for the scans of
+    /// price_updates
+    fn spawn_backfills(&self, backfills: Vec<Backfill>) {
+        if backfills.is_empty() {
+            return;
+        }
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            for backfill in backfills {
+                info!("backfilling {}", backfill.table);
+                match client.query(&backfill.query).execute().await {
+                    Ok(()) => info!("backfilled {}", backfill.table),
+                    Err(e) => {
+                        error!("failed to backfill {}: {}", backfill.table, e)
+                    }
+                }
+            }
+        });
+    }
+
+    /// creates the `candles_{name}` rollup of the price updates in buckets of
+    /// `seconds` and its materialized view, returns the backfill of a new one
+    async fn create_candle_rollup(
+        &self,
+        name: &str,
+        seconds: u64,
+    ) -> Result<Option<Backfill>> {
+        let table = format!("candles_{}", name);
+        self.client
+            .query(&format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {table} (
+                    pubkey String,
+                    bucket UInt64,
+                    open AggregateFunction(argMin, Float64, UInt64),
+                    high SimpleAggregateFunction(max, Float64),
+                    low SimpleAggregateFunction(min, Float64),
+                    close AggregateFunction(argMax, Float64, UInt64),
+                    volume SimpleAggregateFunction(sum, Float64)
+                )
+                ENGINE = AggregatingMergeTree()
+                ORDER BY (pubkey, bucket)
+                "#
+            ))
+            .execute()
+            .await
+            .with_context(|| format!("Failed to create {} table", table))?;
+
+        let select = format!(
+            r#"
+            SELECT
+                pubkey,
+                intDiv(timestamp, {seconds}) * {seconds} AS bucket,
+                argMinState(price, timestamp) AS open,
+                max(price) AS high,
+                min(price) AS low,
+                argMaxState(price, timestamp) AS close,
+                sum(swap_amount) AS volume
+            FROM price_updates
+            "#
+        );
+        self.create_view(&table, &select, None, Some("pubkey, bucket"))
+            .await
+    }
+
+    /// the first trade of every token, the age the screener filters on
+    async fn create_token_first_seen(&self) -> Result<Option<Backfill>> {
+        self.client
+            .query(
+                r#"
+                CREATE TABLE IF NOT EXISTS token_first_seen (
+                    pubkey String,
+                    first_seen SimpleAggregateFunction(min, UInt64)
+                )
+                ENGINE = AggregatingMergeTree()
+                ORDER BY pubkey
+                "#,
+            )
+            .execute()
+            .await
+            .context("Failed to create token_first_seen table")?;
+
+        self.create_view(
+            "token_first_seen",
+            "SELECT pubkey, min(timestamp) AS first_seen FROM price_updates",
+            None,
+            Some("pubkey"),
+        )
+        .await
+    }
+
+    /// the trades of every wallet, ordered for the per-wallet ledger lookups
+    /// price_updates is not ordered for
+    async fn create_wallet_trades(&self) -> Result<Option<Backfill>> {
+        self.client
+            .query(
+                r#"
+                CREATE TABLE IF NOT EXISTS wallet_trades (
+                    owner String,
+                    pubkey String,
+                    name String,
+                    is_buy Bool,
+                    price Float64,
+                    amount_usd Float64,
+                    token_amount Float64,
+                    multi_hop Bool,
+                    quote_mint String,
+                    timestamp UInt64,
+                    slot UInt64,
+                    signature String
+                )
+                ENGINE = MergeTree()
+                ORDER BY (owner, pubkey, timestamp, slot)
+                "#,
+            )
+            .execute()
+            .await
+            .context("Failed to create wallet_trades table")?;
+
+        // a swap that prices a quote asset against another one, e.g. the
+        // SOL/USDC leg of a routed swap, is not a trade of a token; the
+        // mints end up in the view, so only valid ones are taken
+        let quote_mints = QUOTE_REGISTRY
+            .assets()
+            .iter()
+            .filter(|asset| Pubkey::from_str(&asset.mint).is_ok())
+            .map(|asset| format!("'{}'", asset.mint))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let filter = format!(
+            "price > 0 AND owner != '' AND NOT has([{}], pubkey)",
+            quote_mints
+        );
+        self.create_view(
+            "wallet_trades",
+            r#"
+            SELECT
+                owner,
+                pubkey,
+                name,
+                is_buy,
+                price,
+                swap_amount AS amount_usd,
+                swap_amount / price AS token_amount,
+                multi_hop,
+                quote_mint,
+                timestamp,
+                slot,
+                signature
+            FROM price_updates
+            "#,
+            Some(&filter),
+            None,
+        )
+        .await
+    }
+
+    fn create_inserter(&self) -> Result<Inserter<PriceUpdate>> {
+        Ok(self
+            .client
+            .inserter::<PriceUpdate>("price_updates")
+            .context("failed to prepare price insert statement")?
+            .with_timeouts(
+                Some(Duration::from_secs(5)),
+                Some(Duration::from_secs(20)),
+            )
+            .with_max_rows(self.max_rows)
+            .with_max_bytes(1_000_000) // price update is roughly ~200 bytes
+            .with_period(Some(Duration::from_secs(15))))
+    }
+
+    fn create_pool_state_inserter(&self) -> Result<Inserter<PoolState>> {
+        Ok(self
+            .client
+            .inserter::<PoolState>("pool_states")
+            .context("failed to prepare pool state insert statement")?
+            .with_timeouts(
+                Some(Duration::from_secs(5)),
+                Some(Duration::from_secs(20)),
+            )
+            .with_max_rows(self.max_rows)
+            .with_max_bytes(1_000_000)
+            .with_period(Some(Duration::from_secs(15))))
+    }
+
+    fn create_launch_event_inserter(&self) -> Result<Inserter<LaunchEventRow>> {
+        Ok(self
+            .client
+            .inserter::<LaunchEventRow>("launch_events")
+            .context("failed to prepare launch event insert statement")?
+            .with_timeouts(
+                Some(Duration::from_secs(5)),
+                Some(Duration::from_secs(20)),
+            )
+            .with_max_rows(self.max_rows)
+            .with_max_bytes(1_000_000)
+            .with_period(Some(Duration::from_secs(15))))
+    }
+}
+
+#[async_trait::async_trait]
+impl Database for ClickhouseDb {
     async fn health_check(&self) -> Result<()> {
         debug!("clickhouse healthz");
         self.client
@@ -98,6 +365,8 @@ impl Database for ClickhouseDb {
                     multi_hop Bool,
                     is_buy Bool,
                     is_pump Bool,
+                    quote_mint String,
+                    has_metadata Bool DEFAULT true,
                     INDEX idx_mints (name, pubkey) TYPE minmax GRANULARITY 1
                 ) 
                 ENGINE = MergeTree()
@@ -108,7 +377,91 @@ impl Database for ClickhouseDb {
             .await
             .context("Failed to create price_updates table")?;
 
+        // rows written before the quote asset was recorded were all priced
+        // against wSOL
+        self.client
+            .query(&format!(
+                "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS \
+                 quote_mint String DEFAULT '{}'",
+                WSOL_MINT_KEY_STR
+            ))
+            .execute()
+            .await
+            .context("Failed to add quote_mint column")?;
+
+        // swaps priced while the token metadata could not be fetched
+        self.client
+            .query(
+                "ALTER TABLE price_updates ADD COLUMN IF NOT EXISTS \
+                 has_metadata Bool DEFAULT true",
+            )
+            .execute()
+            .await
+            .context("Failed to add has_metadata column")?;
+
+        self.client
+            .query(
+                r#"
+                CREATE TABLE IF NOT EXISTS pool_states (
+                    pool String,
+                    dex String,
+                    base_mint String,
+                    quote_mint String,
+                    base_reserve Float64,
+                    quote_reserve Float64,
+                    liquidity_usd Float64,
+                    timestamp UInt64,
+                    slot UInt64,
+                    signature String,
+                    INDEX idx_pools (pool) TYPE bloom_filter GRANULARITY 1
+                )
+                ENGINE = MergeTree()
+                ORDER BY (base_mint, pool, timestamp)
+                "#,
+            )
+            .execute()
+            .await
+            .context("Failed to create pool_states table")?;
+
+        self.client
+            .query(
+                r#"
+                CREATE TABLE IF NOT EXISTS launch_events (
+                    kind LowCardinality(String),
+                    address String,
+                    dex LowCardinality(String),
+                    base_mint String,
+                    quote_mint String,
+                    creator String,
+                    name String,
+                    symbol String,
+                    uri String,
+                    timestamp UInt64,
+                    slot UInt64,
+                    signature String,
+                    INDEX idx_base_mint (base_mint) TYPE bloom_filter GRANULARITY 1
+                )
+                ENGINE = MergeTree()
+                ORDER BY (kind, timestamp)
+                "#,
+            )
+            .execute()
+            .await
+            .context("Failed to create launch_events table")?;
+
+        let mut backfills = Vec::new();
+        backfills.extend(self.create_wallet_trades().await?);
+        for (name, seconds) in CANDLE_ROLLUPS {
+            backfills.extend(self.create_candle_rollup(name, seconds).await?);
+        }
+        backfills.extend(self.create_token_first_seen().await?);
+        self.spawn_backfills(backfills);
+
         self.inserter = Some(Arc::new(RwLock::new(self.create_inserter()?)));
+        self.pool_state_inserter =
+            Some(Arc::new(RwLock::new(self.create_pool_state_inserter()?)));
+        self.launch_event_inserter =
+            Some(Arc::new(RwLock::new(self.create_launch_event_inserter()?)));
         self.is_initialized = true;
 
         Ok(())
@@ -140,6 +493,60 @@ impl Database for ClickhouseDb {
 
         Ok(())
     }
+
+    async fn insert_pool_state(&self, pool_state: &PoolState) -> Result<()> {
+        debug!("inserting pool state: {}", pool_state.pool);
+
+        let mut inserter = self
+            .pool_state_inserter
+            .as_ref()
+            .expect("pool state inserter not initialized")
+            .write()
+            .await;
+
+        inserter
+            .write(pool_state)
+            .context("Failed to write pool state to insert buffer")?;
+
+        if inserter.pending().rows >= self.max_rows {
+            let stats = inserter.commit().await?;
+            info!(
+                "Committed {} pool states ({} bytes)",
+                stats.rows, stats.bytes
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn insert_launch_event(&self, event: &LaunchEvent) -> Result<()> {
+        let row = LaunchEventRow::from(event);
+        debug!("inserting launch event: {}", row.signature);
+
+        let mut inserter = self
+            .launch_event_inserter
+            .as_ref()
+            .expect("launch event inserter not initialized")
+            .write()
+            .await;
+
+        inserter
+            .write(&row)
+            .context("Failed to write launch event to insert buffer")?;
+
+        // launches are far less frequent than swaps, commit also flushes once
+        // the period of the inserter has passed so they don't wait for
+        // max_rows to fill up
+        let stats = inserter.commit().await?;
+        if stats.rows > 0 {
+            info!(
+                "Committed {} launch events ({} bytes)",
+                stats.rows, stats.bytes
+            );
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
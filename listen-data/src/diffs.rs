@@ -1,7 +1,9 @@
 use crate::constants::{
-    RAYDIUM_AUTHORITY_MINT_KEY_STR, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID,
-    WSOL_MINT_KEY_STR,
+    RAYDIUM_AUTHORITY_MINT_KEY_STR, TOKEN_2022_PROGRAM_ID,
+    TOKEN_2022_PROGRAM_ID_STR, TOKEN_PROGRAM_ID,
 };
+use crate::metadata::TransferFeeSchedule;
+use crate::quote::{quote_side, QuotePrices, QuoteSide};
 use anyhow::Result;
 use carbon_core::{
     deserialize::ArrangeAccounts,
@@ -77,6 +79,7 @@ pub struct DiffsResult {
     pub price: f64,
     pub swap_amount: f64,
     pub coin_mint: String,
+    pub quote_mint: String,
     pub is_buy: bool,
 }
 
@@ -84,45 +87,81 @@ pub struct DiffsResult {
 pub enum DiffsError {
     #[error("Expected exactly 2 token balance diffs")]
     ExpectedExactlyTwoTokenBalanceDiffs,
-    #[error("Non-WSOL swap")]
-    NonWsolsSwap,
+    #[error("No quote asset in swap")]
+    NoQuoteAsset,
 }
 
+/// prices the coin of the swap in usd against the quote asset of the pair,
+/// if both sides are quote assets the one with the higher priority is used
 pub fn process_token_transfers(
     vaults: &HashSet<String>,
     transfers: &[TokenTransferDetails],
-    sol_price: f64,
+    quote_prices: &QuotePrices,
 ) -> Result<DiffsResult, DiffsError> {
     if transfers.len() != 2 {
         return Err(DiffsError::ExpectedExactlyTwoTokenBalanceDiffs);
     }
 
     let (token0, token1) = (&transfers[0], &transfers[1]);
-    let (sol_mint, token_mint) =
-        match (token0.mint.as_str(), token1.mint.as_str()) {
-            (WSOL_MINT_KEY_STR, _) => (token0, token1),
-            (_, WSOL_MINT_KEY_STR) => (token1, token0),
-            _ => return Err(DiffsError::NonWsolsSwap),
-        };
+    let (quote, token) = match quote_side(
+        quote_prices.rank(&token0.mint),
+        quote_prices.rank(&token1.mint),
+    ) {
+        Some(QuoteSide::First) => (token0, token1),
+        Some(QuoteSide::Second) => (token1, token0),
+        None => return Err(DiffsError::NoQuoteAsset),
+    };
+    let quote_price = quote_prices
+        .price(&quote.mint)
+        .ok_or(DiffsError::NoQuoteAsset)?;
 
-    let is_buy = vaults.contains(&sol_mint.destination)
-        || vaults.contains(&token_mint.source);
+    let is_buy =
+        vaults.contains(&quote.destination) || vaults.contains(&token.source);
 
-    let coin_mint = token_mint.mint.clone();
-    let sol_amount = sol_mint.ui_amount;
-    let token_amount = token_mint.ui_amount;
+    let quote_amount = quote.ui_amount;
+    let token_amount = token.ui_amount;
 
-    let price = (sol_amount / token_amount) * sol_price;
-    let swap_amount = sol_amount * sol_price;
+    let price = (quote_amount / token_amount) * quote_price;
+    let swap_amount = quote_amount * quote_price;
 
     Ok(DiffsResult {
         price,
         swap_amount,
-        coin_mint: coin_mint.to_string(),
+        coin_mint: token.mint.clone(),
+        quote_mint: quote.mint.clone(),
         is_buy,
     })
 }
 
+/// Token-2022 mints with the transfer fee extension withhold the fee in the
+/// destination account, so a vault only receives the amount net of the fee;
+/// transfers out of the vaults are left as is, the pool sends the full amount
+pub fn apply_transfer_fees(
+    vaults: &HashSet<String>,
+    transfers: &[TokenTransferDetails],
+    transfer_fees: &HashMap<String, TransferFeeSchedule>,
+    epoch: u64,
+) -> Vec<TokenTransferDetails> {
+    transfers
+        .iter()
+        .cloned()
+        .map(|mut transfer| {
+            if transfer.program_id != TOKEN_2022_PROGRAM_ID_STR
+                || !vaults.contains(&transfer.destination)
+            {
+                return transfer;
+            }
+            if let Some(schedule) = transfer_fees.get(&transfer.mint) {
+                let fee = schedule.fee(epoch, transfer.amount);
+                transfer.amount = transfer.amount.saturating_sub(fee);
+                transfer.ui_amount =
+                    amount_to_ui_amount(transfer.amount, transfer.decimals);
+            }
+            transfer
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct Diff {
     pub mint: String,
@@ -0,0 +1,147 @@
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::WSOL_MINT_KEY_STR;
+
+/// The pump.fun bonding curve of the token completed, the liquidity
+/// migrates to an AMM
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MigrationEvent {
+    pub mint: String,
+    pub bonding_curve: String,
+    pub user: String,
+    pub timestamp: u64,
+    pub slot: u64,
+    pub signature: String,
+}
+
+/// A new pool was initialized on one of the indexed DEXes, the base and quote
+/// are ordered like the mints of the price updates
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PoolCreated {
+    pub pool: String,
+    pub dex: String,
+    pub base_mint: String,
+    pub quote_mint: String,
+    pub creator: String,
+    pub timestamp: u64,
+    pub slot: u64,
+    pub signature: String,
+}
+
+/// A new token was created on the pump.fun bonding curve
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TokenLaunched {
+    pub mint: String,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub bonding_curve: String,
+    pub creator: String,
+    pub timestamp: u64,
+    pub slot: u64,
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum LaunchEvent {
+    PoolCreated(PoolCreated),
+    TokenLaunched(TokenLaunched),
+}
+
+impl LaunchEvent {
+    pub fn signature(&self) -> &str {
+        match self {
+            LaunchEvent::PoolCreated(pool) => &pool.signature,
+            LaunchEvent::TokenLaunched(token) => &token.signature,
+        }
+    }
+}
+
+/// Flat row of the `launch_events` table, `address` is the pool of a
+/// `PoolCreated` and the bonding curve of a `TokenLaunched`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Row)]
+pub struct LaunchEventRow {
+    pub kind: String,
+    pub address: String,
+    pub dex: String,
+    pub base_mint: String,
+    pub quote_mint: String,
+    pub creator: String,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub timestamp: u64,
+    pub slot: u64,
+    pub signature: String,
+}
+
+impl From<&LaunchEvent> for LaunchEventRow {
+    fn from(event: &LaunchEvent) -> Self {
+        match event {
+            LaunchEvent::PoolCreated(pool) => Self {
+                kind: "PoolCreated".to_string(),
+                address: pool.pool.clone(),
+                dex: pool.dex.clone(),
+                base_mint: pool.base_mint.clone(),
+                quote_mint: pool.quote_mint.clone(),
+                creator: pool.creator.clone(),
+                name: String::new(),
+                symbol: String::new(),
+                uri: String::new(),
+                timestamp: pool.timestamp,
+                slot: pool.slot,
+                signature: pool.signature.clone(),
+            },
+            LaunchEvent::TokenLaunched(token) => Self {
+                kind: "TokenLaunched".to_string(),
+                address: token.bonding_curve.clone(),
+                dex: "pump_fun".to_string(),
+                base_mint: token.mint.clone(),
+                quote_mint: WSOL_MINT_KEY_STR.to_string(),
+                creator: token.creator.clone(),
+                name: token.name.clone(),
+                symbol: token.symbol.clone(),
+                uri: token.uri.clone(),
+                timestamp: token.timestamp,
+                slot: token.slot,
+                signature: token.signature.clone(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launch_event_serialization() {
+        let event = LaunchEvent::PoolCreated(PoolCreated {
+            pool: "pool".to_string(),
+            dex: "raydium_cpmm".to_string(),
+            base_mint: "AsyfR3e5JcPqWot4H5MMhQUm7DZ4zwQrcp2zbB7vpump"
+                .to_string(),
+            quote_mint: "So11111111111111111111111111111111111111112"
+                .to_string(),
+            creator: "creator".to_string(),
+            timestamp: 1_735_000_000,
+            slot: 310_000_000,
+            signature: "5sig".to_string(),
+        });
+
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["type"], "PoolCreated");
+        assert_eq!(value["dex"], "raydium_cpmm");
+        assert_eq!(
+            serde_json::from_value::<LaunchEvent>(value).unwrap(),
+            event
+        );
+
+        let row = LaunchEventRow::from(&event);
+        assert_eq!(row.kind, "PoolCreated");
+        assert_eq!(row.address, "pool");
+        assert!(row.name.is_empty());
+    }
+}
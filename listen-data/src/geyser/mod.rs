@@ -1,58 +1,59 @@
 use anyhow::Result;
 use carbon_core::pipeline::{Pipeline, ShutdownStrategy};
 use carbon_log_metrics::LogMetrics;
-use carbon_meteora_dlmm_decoder::MeteoraDlmmDecoder;
-use carbon_orca_whirlpool_decoder::OrcaWhirlpoolDecoder;
-use carbon_pump_swap_decoder::PumpSwapDecoder;
 use carbon_raydium_amm_v4_decoder::RaydiumAmmV4Decoder;
-use carbon_raydium_clmm_decoder::RaydiumClmmDecoder;
-use carbon_raydium_cpmm_decoder::RaydiumCpmmDecoder;
 use carbon_yellowstone_grpc_datasource::YellowstoneGrpcGeyserClient;
 use std::{
     collections::{HashMap, HashSet},
+    path::Path,
     sync::Arc,
 };
 use tokio::sync::RwLock;
 use yellowstone_grpc_proto::geyser::{
-    CommitmentLevel, SubscribeRequestFilterAccounts,
+    subscribe_request_filter_accounts_filter::Filter, CommitmentLevel,
+    SubscribeRequestFilterAccounts, SubscribeRequestFilterAccountsFilter,
     SubscribeRequestFilterTransactions,
 };
 
 use crate::{
     constants::{
+        RAYDIUM_AMM_V4_PROGRAM_ID,
+        TOKEN_2022_PROGRAM_ID,
         TOKEN_PROGRAM_ID,
         // METEORA_DLMM_PROGRAM_ID, PUMP_SWAP_PROGRAM_ID,
         // RAYDIUM_AMM_V4_PROGRAM_ID, RAYDIUM_CLMM_PROGRAM_ID,
         // RAYDIUM_CPMM_PROGRAM_ID,  WHIRLPOOLS_PROGRAM_ID,
     },
-    db::ClickhouseDb,
+    db::Database,
     handler::TokenSwapHandler,
-    kv_store::RedisKVStore,
-    message_queue::RedisMessageQueue,
+    kv_store::KVStore,
+    message_queue::MessageQueue,
     metrics::SwapMetrics,
-    processor::{
-        MeteoraDlmmInstructionProcessor, OcraWhirlpoolInstructionProcessor,
-        PumpAmmInstructionProcessor, RaydiumAmmV4InstructionProcessor,
-        RaydiumClmmInstructionProcessor, RaydiumCpmmInstructionProcessor,
-    },
+    processor::{with_swap_processors, RaydiumAmmV4AccountProcessor},
+    replay::{Recorder, RecordingDatasource},
     util::must_get_env,
 };
 
+/// Size of the `AmmInfo` account of a Raydium AMM v4 pool
+const RAYDIUM_AMM_V4_AMM_INFO_SIZE: u64 = 752;
+
 pub fn make_geyser_pipeline(
-    kv_store: Arc<RedisKVStore>,
-    message_queue: Arc<RedisMessageQueue>,
-    db: Arc<ClickhouseDb>,
+    kv_store: Arc<dyn KVStore>,
+    message_queue: Arc<dyn MessageQueue>,
+    db: Arc<dyn Database>,
     metrics: Arc<SwapMetrics>,
+    record: Option<&Path>,
 ) -> Result<Pipeline> {
     let mut transaction_filters = HashMap::new();
-    // TODO support TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb (other token program)
     transaction_filters.insert(
         "swap_transaction_filter".to_string(),
         SubscribeRequestFilterTransactions {
             vote: Some(false),
             failed: Some(false),
+            // transactions that touch either of the token programs
             account_include: vec![
                 TOKEN_PROGRAM_ID.to_string(),
+                TOKEN_2022_PROGRAM_ID.to_string(),
                 // RAYDIUM_AMM_V4_PROGRAM_ID.to_string(),
                 // RAYDIUM_CLMM_PROGRAM_ID.to_string(),
                 // RAYDIUM_CPMM_PROGRAM_ID.to_string(),
@@ -66,49 +67,57 @@ pub fn make_geyser_pipeline(
         },
     );
 
-    let token_swap_handler =
-        Arc::new(TokenSwapHandler::new(kv_store, message_queue, db, metrics));
+    let token_swap_handler = Arc::new(TokenSwapHandler::new(
+        kv_store.clone(),
+        message_queue,
+        db,
+        metrics,
+    ));
 
-    // Create empty account filters since we only care about transactions
-    let account_filters: HashMap<String, SubscribeRequestFilterAccounts> =
+    // the Raydium AMM v4 pools, their pnl that was not taken yet sits in the
+    // vaults but is not part of the reserves
+    let mut account_filters: HashMap<String, SubscribeRequestFilterAccounts> =
         HashMap::new();
+    account_filters.insert(
+        "raydium_amm_v4_account_filter".to_string(),
+        SubscribeRequestFilterAccounts {
+            owner: vec![RAYDIUM_AMM_V4_PROGRAM_ID.to_string()],
+            filters: vec![SubscribeRequestFilterAccountsFilter {
+                filter: Some(Filter::Datasize(RAYDIUM_AMM_V4_AMM_INFO_SIZE)),
+            }],
+            ..Default::default()
+        },
+    );
+
+    let geyser_client = YellowstoneGrpcGeyserClient::new(
+        must_get_env("GEYSER_URL"),
+        Some(must_get_env("GEYSER_X_TOKEN")),
+        Some(CommitmentLevel::Processed),
+        account_filters,
+        transaction_filters,
+        Arc::new(RwLock::new(HashSet::new())),
+    );
+
+    // the transactions of the feed are optionally recorded for replays
+    let builder = match record {
+        Some(path) => Pipeline::builder().datasource(RecordingDatasource::new(
+            geyser_client,
+            Recorder::create(path)?,
+        )),
+        None => Pipeline::builder().datasource(geyser_client),
+    };
 
-    let pipeline = Pipeline::builder()
-        .datasource(YellowstoneGrpcGeyserClient::new(
-            must_get_env("GEYSER_URL"),
-            Some(must_get_env("GEYSER_X_TOKEN")),
-            Some(CommitmentLevel::Processed),
-            account_filters,
-            transaction_filters,
-            Arc::new(RwLock::new(HashSet::new())),
-        ))
-        .metrics(Arc::new(LogMetrics::new()))
-        .shutdown_strategy(ShutdownStrategy::Immediate)
-        .instruction(
-            RaydiumAmmV4Decoder,
-            RaydiumAmmV4InstructionProcessor::new(token_swap_handler.clone()),
-        )
-        .instruction(
-            RaydiumCpmmDecoder,
-            RaydiumCpmmInstructionProcessor::new(token_swap_handler.clone()),
-        )
-        .instruction(
-            MeteoraDlmmDecoder,
-            MeteoraDlmmInstructionProcessor::new(token_swap_handler.clone()),
-        )
-        .instruction(
-            OrcaWhirlpoolDecoder,
-            OcraWhirlpoolInstructionProcessor::new(token_swap_handler.clone()),
-        )
-        .instruction(
-            RaydiumClmmDecoder,
-            RaydiumClmmInstructionProcessor::new(token_swap_handler.clone()),
-        )
-        .instruction(
-            PumpSwapDecoder,
-            PumpAmmInstructionProcessor::new(token_swap_handler.clone()),
-        )
-        .build()?;
+    let pipeline = with_swap_processors(
+        builder
+            .account(
+                RaydiumAmmV4Decoder,
+                RaydiumAmmV4AccountProcessor::new(kv_store),
+            )
+            .metrics(Arc::new(LogMetrics::new()))
+            .shutdown_strategy(ShutdownStrategy::Immediate),
+        token_swap_handler,
+    )
+    .build()?;
 
     Ok(pipeline)
 }
@@ -1,12 +1,22 @@
 use crate::{
-    db::ClickhouseDb, kv_store::RedisKVStore, message_queue::RedisMessageQueue,
-    metrics::SwapMetrics, process_swap::process_swap,
+    db::Database,
+    events::{LaunchEvent, MigrationEvent, PoolCreated, TokenLaunched},
+    kv_store::KVStore,
+    message_queue::MessageQueue,
+    metadata::{MetadataSource, RpcMetadataSource},
+    metrics::SwapMetrics,
+    pool::Pool,
+    process_swap::{process_pump_fun_trade, process_swap},
+    pump_fun::{CompleteEvent, CreateEvent, TradeEvent},
+    quote::QUOTE_REGISTRY,
 };
 use carbon_core::instruction::{InstructionMetadata, NestedInstruction};
+use chrono::Utc;
+use solana_sdk::pubkey::Pubkey;
 use std::{collections::HashSet, sync::Arc};
 use tracing::{debug, error};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Dex {
     RaydiumAmmV4,
     RaydiumClmm,
@@ -14,33 +24,78 @@ pub enum Dex {
     MeteoraDlmm,
     Whirlpools,
     PumpSwap,
+    PumpFun,
+}
+
+impl Dex {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dex::RaydiumAmmV4 => "raydium_amm_v4",
+            Dex::RaydiumClmm => "raydium_clmm",
+            Dex::RaydiumCpmm => "raydium_cpmm",
+            Dex::MeteoraDlmm => "meteora_dlmm",
+            Dex::Whirlpools => "whirlpools",
+            Dex::PumpSwap => "pump_swap",
+            Dex::PumpFun => "pump_fun",
+        }
+    }
 }
 
 pub struct TokenSwapHandler {
-    pub kv_store: Arc<RedisKVStore>,
-    pub message_queue: Arc<RedisMessageQueue>,
-    pub db: Arc<ClickhouseDb>,
+    pub kv_store: Arc<dyn KVStore>,
+    pub metadata_source: Arc<dyn MetadataSource>,
+    pub message_queue: Arc<dyn MessageQueue>,
+    pub db: Arc<dyn Database>,
     pub metrics: Arc<SwapMetrics>,
 }
 
 impl TokenSwapHandler {
+    /// the metadata of the tokens is read over RPC
     pub fn new(
-        kv_store: Arc<RedisKVStore>,
-        message_queue: Arc<RedisMessageQueue>,
-        db: Arc<ClickhouseDb>,
+        kv_store: Arc<dyn KVStore>,
+        message_queue: Arc<dyn MessageQueue>,
+        db: Arc<dyn Database>,
         metrics: Arc<SwapMetrics>,
     ) -> Self {
         Self {
             kv_store,
+            metadata_source: Arc::new(RpcMetadataSource),
             message_queue,
             db,
             metrics,
         }
     }
 
+    pub fn with_metadata_source(
+        mut self,
+        metadata_source: Arc<dyn MetadataSource>,
+    ) -> Self {
+        self.metadata_source = metadata_source;
+        self
+    }
+
     pub fn spawn_swap_processor(
         &self,
-        vaults: &HashSet<String>,
+        pool: &Pool,
+        fee_adas: Option<&HashSet<String>>,
+        meta: &InstructionMetadata,
+        nested_instructions: &[NestedInstruction],
+        dex: Dex,
+    ) {
+        self.spawn_multi_hop_swap_processor(
+            std::slice::from_ref(pool),
+            fee_adas,
+            meta,
+            nested_instructions,
+            dex,
+        );
+    }
+
+    /// Processes a swap that goes through several pools within a single
+    /// instruction
+    pub fn spawn_multi_hop_swap_processor(
+        &self,
+        pools: &[Pool],
         fee_adas: Option<&HashSet<String>>,
         meta: &InstructionMetadata,
         nested_instructions: &[NestedInstruction],
@@ -53,10 +108,11 @@ impl TokenSwapHandler {
 
         let message_queue = self.message_queue.clone();
         let kv_store = self.kv_store.clone();
+        let metadata_source = self.metadata_source.clone();
         let db = self.db.clone();
         let metrics = self.metrics.clone();
 
-        let vaults = vaults.clone();
+        let pools = pools.to_vec();
         let fee_adas = fee_adas.cloned();
         let tx_meta = meta.transaction_metadata.clone();
         let nested_instructions = nested_instructions.to_vec();
@@ -66,12 +122,14 @@ impl TokenSwapHandler {
 
         tokio::spawn(async move {
             match process_swap(
-                &vaults,
+                &pools,
                 fee_adas.as_ref(),
+                dex,
                 &tx_meta,
                 &nested_instructions,
                 &message_queue,
                 &kv_store,
+                &metadata_source,
                 &db,
                 &metrics,
             )
@@ -95,20 +153,179 @@ impl TokenSwapHandler {
             }
         });
     }
+
+    /// Processes a trade on the pump.fun bonding curve, `meta` is the one of
+    /// the event emitted by the buy or sell instruction
+    pub fn spawn_pump_fun_trade_processor(
+        &self,
+        trade: &TradeEvent,
+        meta: &InstructionMetadata,
+    ) {
+        debug!(
+            "https://solscan.io/tx/{}",
+            meta.transaction_metadata.signature
+        );
+
+        let message_queue = self.message_queue.clone();
+        let kv_store = self.kv_store.clone();
+        let metadata_source = self.metadata_source.clone();
+        let db = self.db.clone();
+        let metrics = self.metrics.clone();
+
+        let trade = trade.clone();
+        let tx_meta = meta.transaction_metadata.clone();
+
+        metrics.increment_total_swaps();
+        metrics.increment_pending_swaps();
+
+        tokio::spawn(async move {
+            match process_pump_fun_trade(
+                &trade,
+                &tx_meta,
+                &message_queue,
+                &kv_store,
+                &metadata_source,
+                &db,
+                &metrics,
+            )
+            .await
+            {
+                Ok(_) => metrics.increment_successful_swaps(),
+                Err(e) => {
+                    metrics.increment_failed_swaps();
+                    error!(
+                        ?e,
+                        "Transaction: https://solscan.io/tx/{}",
+                        tx_meta.signature
+                    );
+                }
+            }
+        });
+    }
+
+    /// Publishes the migration of the token once its bonding curve completes
+    pub fn spawn_pump_fun_migration(
+        &self,
+        complete: &CompleteEvent,
+        meta: &InstructionMetadata,
+    ) {
+        let message_queue = self.message_queue.clone();
+        let migration = MigrationEvent {
+            mint: complete.mint.to_string(),
+            bonding_curve: complete.bonding_curve.to_string(),
+            user: complete.user.to_string(),
+            timestamp: complete.timestamp.max(0) as u64,
+            slot: meta.transaction_metadata.slot,
+            signature: meta.transaction_metadata.signature.to_string(),
+        };
+
+        self.metrics.increment_pump_fun_migrations();
+
+        tokio::spawn(async move {
+            if let Err(e) =
+                message_queue.publish_migration(migration.clone()).await
+            {
+                error!(
+                    ?e,
+                    "failed to publish migration of {}: https://solscan.io/tx/{}",
+                    migration.mint,
+                    migration.signature
+                );
+            }
+        });
+    }
+
+    /// Publishes a pool initialized on one of the DEXes, the mints are ordered
+    /// as (base, quote) with the quote registry
+    pub fn spawn_pool_created(
+        &self,
+        pool: &Pubkey,
+        dex: Dex,
+        mint_a: &Pubkey,
+        mint_b: &Pubkey,
+        creator: &Pubkey,
+        meta: &InstructionMetadata,
+    ) {
+        let (mint_a, mint_b) = (mint_a.to_string(), mint_b.to_string());
+        let (base_mint, quote_mint) =
+            QUOTE_REGISTRY.base_and_quote(&mint_a, &mint_b);
+        let event = LaunchEvent::PoolCreated(PoolCreated {
+            pool: pool.to_string(),
+            dex: dex.as_str().to_string(),
+            base_mint: base_mint.to_string(),
+            quote_mint: quote_mint.to_string(),
+            creator: creator.to_string(),
+            timestamp: Utc::now().timestamp() as u64,
+            slot: meta.transaction_metadata.slot,
+            signature: meta.transaction_metadata.signature.to_string(),
+        });
+
+        self.metrics.increment_pools_created();
+        self.spawn_launch_event(event);
+    }
+
+    /// Publishes a token created on the pump.fun bonding curve
+    pub fn spawn_token_launched(
+        &self,
+        create: &CreateEvent,
+        meta: &InstructionMetadata,
+    ) {
+        let event = LaunchEvent::TokenLaunched(TokenLaunched {
+            mint: create.mint.to_string(),
+            name: create.name.clone(),
+            symbol: create.symbol.clone(),
+            uri: create.uri.clone(),
+            bonding_curve: create.bonding_curve.to_string(),
+            creator: create.user.to_string(),
+            timestamp: Utc::now().timestamp() as u64,
+            slot: meta.transaction_metadata.slot,
+            signature: meta.transaction_metadata.signature.to_string(),
+        });
+
+        self.metrics.increment_tokens_launched();
+        self.spawn_launch_event(event);
+    }
+
+    fn spawn_launch_event(&self, event: LaunchEvent) {
+        let message_queue = self.message_queue.clone();
+        let db = self.db.clone();
+        let signature = event.signature().to_string();
+
+        // published first, the subscribers should not wait on the insert
+        tokio::spawn(async move {
+            if let Err(e) =
+                message_queue.publish_launch_event(event.clone()).await
+            {
+                error!(
+                    ?e,
+                    "failed to publish launch event: https://solscan.io/tx/{}",
+                    signature
+                );
+            }
+            if let Err(e) = db.insert_launch_event(&event).await {
+                error!(
+                    ?e,
+                    "failed to insert launch event: https://solscan.io/tx/{}",
+                    signature
+                );
+            }
+        });
+    }
 }
 
 #[cfg(test)]
 pub mod test_swaps {
     pub use crate::{
-        db::{ClickhouseDb, Database},
+        db::Database,
         diffs::{
             extra_mint_details_from_tx_metadata, DiffsError,
             TokenTransferDetails, SPL_TOKEN_TRANSFER_PROCESSOR,
         },
         handler::TokenSwapHandler,
-        kv_store::RedisKVStore,
-        message_queue::RedisMessageQueue,
+        kv_store::KVStore,
+        message_queue::MessageQueue,
         metrics::SwapMetrics,
+        replay::fetch_transaction_update,
         util::{make_db, make_kv_store, make_message_queue, make_rpc_client},
     };
     use anyhow::{anyhow, Result};
@@ -116,17 +333,10 @@ pub mod test_swaps {
         datasource::TransactionUpdate,
         instruction::{NestedInstruction, NestedInstructions},
         transaction::TransactionMetadata,
-        transformers::{
-            extract_instructions_with_metadata,
-            transaction_metadata_from_original_meta,
-        },
+        transformers::extract_instructions_with_metadata,
     };
     use dotenv::dotenv;
-    use solana_client::rpc_config::RpcTransactionConfig;
-    use solana_sdk::{
-        commitment_config::CommitmentConfig, signature::Signature,
-    };
-    use solana_transaction_status::UiTransactionEncoding;
+    use solana_sdk::signature::Signature;
     use std::{str::FromStr, sync::Arc};
 
     pub fn get_inner_token_transfers(
@@ -145,7 +355,7 @@ pub mod test_swaps {
     }
 
     pub async fn get_storages(
-    ) -> (Arc<RedisKVStore>, Arc<RedisMessageQueue>, Arc<ClickhouseDb>) {
+    ) -> (Arc<dyn KVStore>, Arc<dyn MessageQueue>, Arc<dyn Database>) {
         let kv_store = make_kv_store().await.expect("Failed to make kv store");
         let message_queue = make_message_queue()
             .await
@@ -168,51 +378,16 @@ pub mod test_swaps {
         let signature =
             Signature::from_str(tx_hash).expect("Failed to parse signature");
         let rpc_client = make_rpc_client().expect("Failed to make rpc client");
-        let encoded_transaction = rpc_client
-            .get_transaction_with_config(
-                &signature,
-                RpcTransactionConfig {
-                    encoding: Some(UiTransactionEncoding::Binary),
-                    commitment: Some(CommitmentConfig::confirmed()),
-                    max_supported_transaction_version: Some(0),
-                },
-            )
-            .await
-            .expect("Failed to get transaction");
-
-        let transaction = encoded_transaction.transaction;
-
-        let meta_original = if let Some(meta) = transaction.clone().meta {
-            meta
-        } else {
-            return Err(anyhow!(
-                "Meta is malformed for transaction: {:?}",
-                signature
-            ));
-        };
+        let transaction_update = Box::new(
+            fetch_transaction_update(&rpc_client, &signature)
+                .await
+                .expect("Failed to get transaction"),
+        );
 
-        if meta_original.status.is_err() {
+        if transaction_update.meta.status.is_err() {
             return Err(anyhow!("Transaction failed: {:?}", signature));
         }
 
-        let decoded_transaction = transaction
-            .transaction
-            .decode()
-            .ok_or_else(|| anyhow!("Failed to decode transaction"))?;
-
-        let meta_needed =
-            transaction_metadata_from_original_meta(meta_original)
-                .map_err(|e| anyhow!("Error getting metadata: {}", e))?;
-
-        let transaction_update = Box::new(TransactionUpdate {
-            signature,
-            transaction: decoded_transaction.clone(),
-            meta: meta_needed,
-            is_vote: false,
-            slot: encoded_transaction.slot,
-            block_time: encoded_transaction.block_time,
-        });
-
         let transaction_metadata: TransactionMetadata =
             (*transaction_update).clone().try_into().expect(
                 "Failed to convert transaction update to transaction metadata.",
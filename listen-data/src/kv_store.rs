@@ -1,12 +1,60 @@
+use std::{collections::HashMap, sync::Mutex};
+
 use anyhow::{Context, Result};
 use bb8_redis::{bb8, redis::cmd, RedisConnectionManager};
 use serde::{de::DeserializeOwned, Serialize};
 use tracing::{debug, info};
 
-use crate::metadata::TokenMetadata;
+use crate::metadata::{MetadataMiss, TokenMetadata};
+use crate::pool::{PoolState, VaultAdjustments};
 use crate::price::PriceUpdate;
 use crate::util::create_redis_pool;
 
+/// Latest state of the prices, pools and token metadata
+#[async_trait::async_trait]
+pub trait KVStore: Send + Sync + 'static {
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()>;
+
+    async fn get_price(&self, pubkey: &str) -> Result<Option<PriceUpdate>>;
+
+    async fn insert_metadata(&self, metadata: &TokenMetadata) -> Result<()>;
+
+    async fn get_metadata(&self, mint: &str) -> Result<Option<TokenMetadata>>;
+
+    async fn has_metadata(&self, mint: &str) -> Result<bool>;
+
+    async fn get_metadata_miss(
+        &self,
+        mint: &str,
+    ) -> Result<Option<MetadataMiss>>;
+
+    /// records a failed metadata fetch, forgotten after `expiry` seconds
+    async fn insert_metadata_miss(
+        &self,
+        mint: &str,
+        miss: &MetadataMiss,
+        expiry: u64,
+    ) -> Result<()>;
+
+    async fn delete_metadata_miss(&self, mint: &str) -> Result<()>;
+
+    /// stores the latest state of the pool and indexes it by its base mint
+    async fn insert_pool_state(&self, pool_state: &PoolState) -> Result<()>;
+
+    async fn get_pool_state(&self, pool: &str) -> Result<Option<PoolState>>;
+
+    async fn insert_vault_adjustments(
+        &self,
+        pool: &str,
+        adjustments: &VaultAdjustments,
+    ) -> Result<()>;
+
+    async fn get_vault_adjustments(
+        &self,
+        pool: &str,
+    ) -> Result<Option<VaultAdjustments>>;
+}
+
 #[derive(Debug, Clone)]
 pub struct RedisKVStore {
     pool: bb8::Pool<RedisConnectionManager>,
@@ -67,6 +115,44 @@ impl RedisKVStore {
         Ok(())
     }
 
+    /// sets the key to expire after `seconds`
+    pub async fn set_ex<T: Serialize + Send + Sync>(
+        &self,
+        key: &str,
+        value: &T,
+        seconds: u64,
+    ) -> Result<()> {
+        let mut conn = self.pool.get().await.context(format!(
+            "Failed to get Redis connection: {:#?}",
+            self.pool.state().statistics
+        ))?;
+        let json_str = serde_json::to_string(value)?;
+        let _: () = cmd("SET")
+            .arg(key)
+            .arg(json_str)
+            .arg("EX")
+            .arg(seconds)
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to set key: {}", key))?;
+        debug!(key, seconds, "redis set ex ok");
+        Ok(())
+    }
+
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        let mut conn = self.pool.get().await.context(format!(
+            "Failed to get Redis connection: {:#?}",
+            self.pool.state().statistics
+        ))?;
+        let _: () = cmd("DEL")
+            .arg(key)
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| format!("Failed to delete key: {}", key))?;
+        debug!(key, "redis del ok");
+        Ok(())
+    }
+
     pub async fn exists(&self, key: &str) -> Result<bool> {
         let mut conn = self.pool.get().await.context(format!(
             "Failed to get Redis connection: {:#?}",
@@ -91,34 +177,213 @@ impl RedisKVStore {
         format!("solana:metadata:{}", mint)
     }
 
-    pub async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
+    fn make_metadata_miss_key(&self, mint: &str) -> String {
+        format!("solana:metadata_miss:{}", mint)
+    }
+
+    fn make_pool_key(&self, pool: &str) -> String {
+        format!("solana:pool:{}", pool)
+    }
+
+    fn make_mint_pools_key(&self, mint: &str) -> String {
+        format!("solana:pools:{}", mint)
+    }
+
+    fn make_vault_adjustments_key(&self, pool: &str) -> String {
+        format!("solana:pool_adjustments:{}", pool)
+    }
+}
+
+#[async_trait::async_trait]
+impl KVStore for RedisKVStore {
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
         let key = self.make_price_key(&price.pubkey);
         self.set(&key, price).await
     }
 
-    pub async fn get_price(&self, pubkey: &str) -> Result<Option<PriceUpdate>> {
+    async fn get_price(&self, pubkey: &str) -> Result<Option<PriceUpdate>> {
         let key = self.make_price_key(pubkey);
         self.get(&key).await
     }
 
-    pub async fn insert_metadata(
-        &self,
-        metadata: &TokenMetadata,
-    ) -> Result<()> {
+    async fn insert_metadata(&self, metadata: &TokenMetadata) -> Result<()> {
         let key = self.make_metadata_key(&metadata.mint);
         self.set(&key, metadata).await
     }
 
-    pub async fn get_metadata(
+    async fn get_metadata(&self, mint: &str) -> Result<Option<TokenMetadata>> {
+        let key = self.make_metadata_key(mint);
+        self.get(&key).await
+    }
+
+    async fn get_metadata_miss(
         &self,
         mint: &str,
-    ) -> Result<Option<TokenMetadata>> {
-        let key = self.make_metadata_key(mint);
+    ) -> Result<Option<MetadataMiss>> {
+        let key = self.make_metadata_miss_key(mint);
         self.get(&key).await
     }
 
-    pub async fn has_metadata(&self, mint: &str) -> Result<bool> {
+    async fn insert_metadata_miss(
+        &self,
+        mint: &str,
+        miss: &MetadataMiss,
+        expiry: u64,
+    ) -> Result<()> {
+        let key = self.make_metadata_miss_key(mint);
+        self.set_ex(&key, miss, expiry).await
+    }
+
+    async fn delete_metadata_miss(&self, mint: &str) -> Result<()> {
+        let key = self.make_metadata_miss_key(mint);
+        self.delete(&key).await
+    }
+
+    async fn insert_pool_state(&self, pool_state: &PoolState) -> Result<()> {
+        let key = self.make_pool_key(&pool_state.pool);
+        self.set(&key, pool_state).await?;
+
+        let mut conn = self.pool.get().await.context(format!(
+            "Failed to get Redis connection: {:#?}",
+            self.pool.state().statistics
+        ))?;
+        let _: () = cmd("SADD")
+            .arg(self.make_mint_pools_key(&pool_state.base_mint))
+            .arg(&pool_state.pool)
+            .query_async(&mut *conn)
+            .await
+            .with_context(|| {
+                format!("Failed to index pool: {}", pool_state.pool)
+            })?;
+        Ok(())
+    }
+
+    async fn get_pool_state(&self, pool: &str) -> Result<Option<PoolState>> {
+        let key = self.make_pool_key(pool);
+        self.get(&key).await
+    }
+
+    async fn insert_vault_adjustments(
+        &self,
+        pool: &str,
+        adjustments: &VaultAdjustments,
+    ) -> Result<()> {
+        let key = self.make_vault_adjustments_key(pool);
+        self.set(&key, adjustments).await
+    }
+
+    async fn get_vault_adjustments(
+        &self,
+        pool: &str,
+    ) -> Result<Option<VaultAdjustments>> {
+        let key = self.make_vault_adjustments_key(pool);
+        self.get(&key).await
+    }
+
+    async fn has_metadata(&self, mint: &str) -> Result<bool> {
         let key = self.make_metadata_key(mint);
         self.exists(&key).await
     }
 }
+
+/// Keeps the state in memory, for tests and replays that should not need
+/// Redis; the expiry of the metadata misses is not kept, the retry time of a
+/// miss still applies
+#[derive(Debug, Default)]
+pub struct MemoryKVStore {
+    prices: Mutex<HashMap<String, PriceUpdate>>,
+    metadata: Mutex<HashMap<String, TokenMetadata>>,
+    metadata_misses: Mutex<HashMap<String, MetadataMiss>>,
+    pool_states: Mutex<HashMap<String, PoolState>>,
+    vault_adjustments: Mutex<HashMap<String, VaultAdjustments>>,
+}
+
+fn insert<T>(items: &Mutex<HashMap<String, T>>, key: &str, item: T) {
+    items
+        .lock()
+        .expect("memory kv store lock poisoned")
+        .insert(key.to_string(), item);
+}
+
+fn lookup<T: Clone>(items: &Mutex<HashMap<String, T>>, key: &str) -> Option<T> {
+    items
+        .lock()
+        .expect("memory kv store lock poisoned")
+        .get(key)
+        .cloned()
+}
+
+#[async_trait::async_trait]
+impl KVStore for MemoryKVStore {
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
+        insert(&self.prices, &price.pubkey, price.clone());
+        Ok(())
+    }
+
+    async fn get_price(&self, pubkey: &str) -> Result<Option<PriceUpdate>> {
+        Ok(lookup(&self.prices, pubkey))
+    }
+
+    async fn insert_metadata(&self, metadata: &TokenMetadata) -> Result<()> {
+        insert(&self.metadata, &metadata.mint, metadata.clone());
+        Ok(())
+    }
+
+    async fn get_metadata(&self, mint: &str) -> Result<Option<TokenMetadata>> {
+        Ok(lookup(&self.metadata, mint))
+    }
+
+    async fn has_metadata(&self, mint: &str) -> Result<bool> {
+        Ok(lookup(&self.metadata, mint).is_some())
+    }
+
+    async fn get_metadata_miss(
+        &self,
+        mint: &str,
+    ) -> Result<Option<MetadataMiss>> {
+        Ok(lookup(&self.metadata_misses, mint))
+    }
+
+    async fn insert_metadata_miss(
+        &self,
+        mint: &str,
+        miss: &MetadataMiss,
+        _expiry: u64,
+    ) -> Result<()> {
+        insert(&self.metadata_misses, mint, miss.clone());
+        Ok(())
+    }
+
+    async fn delete_metadata_miss(&self, mint: &str) -> Result<()> {
+        self.metadata_misses
+            .lock()
+            .expect("memory kv store lock poisoned")
+            .remove(mint);
+        Ok(())
+    }
+
+    async fn insert_pool_state(&self, pool_state: &PoolState) -> Result<()> {
+        insert(&self.pool_states, &pool_state.pool, pool_state.clone());
+        Ok(())
+    }
+
+    async fn get_pool_state(&self, pool: &str) -> Result<Option<PoolState>> {
+        Ok(lookup(&self.pool_states, pool))
+    }
+
+    async fn insert_vault_adjustments(
+        &self,
+        pool: &str,
+        adjustments: &VaultAdjustments,
+    ) -> Result<()> {
+        insert(&self.vault_adjustments, pool, adjustments.clone());
+        Ok(())
+    }
+
+    async fn get_vault_adjustments(
+        &self,
+        pool: &str,
+    ) -> Result<Option<VaultAdjustments>> {
+        Ok(lookup(&self.vault_adjustments, pool))
+    }
+}
@@ -18,12 +18,18 @@ pub mod rpc;
 pub mod geyser;
 
 pub mod db;
+pub mod events;
 pub mod kv_store;
 pub mod message_queue;
 pub mod metadata;
 pub mod metrics;
+pub mod pool;
 pub mod price;
 pub mod process_swap;
+pub mod pump_fun;
+pub mod quote;
+pub mod replay;
+pub mod sink;
 pub mod sol_price_stream;
 pub mod util;
 
@@ -4,7 +4,9 @@ fn main() {
     println!("\nAvailable commands:");
     println!("\n1. indexer");
     println!("   Geyser-based indexer for Raydium data");
-    println!("   Usage: cargo run --bin indexer");
+    println!(
+        "   Usage: cargo run --bin indexer [--record <file> | --replay <file>]"
+    );
     println!("\n2. rpc-crawler");
     println!("   RPC-based crawler for Raydium data");
     println!("   Usage: cargo run --bin rpc-crawler [COMMAND]");
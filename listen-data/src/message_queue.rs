@@ -3,16 +3,25 @@ use anyhow::{Context, Result};
 use bb8_redis::{bb8, RedisConnectionManager};
 use tracing::info;
 
+use crate::events::{LaunchEvent, MigrationEvent};
+use crate::pool::PoolState;
 use crate::price::PriceUpdate;
 
 #[async_trait::async_trait]
 pub trait MessageQueue: Send + Sync + 'static {
-    type Error: std::error::Error + Send + Sync + 'static;
-
     async fn publish_price_update(
         &self,
         price_update: PriceUpdate,
-    ) -> Result<(), Self::Error>;
+    ) -> Result<()>;
+
+    async fn publish_migration(&self, migration: MigrationEvent) -> Result<()>;
+
+    async fn publish_pool_update(&self, pool_state: PoolState) -> Result<()>;
+
+    async fn publish_launch_event(
+        &self,
+        launch_event: LaunchEvent,
+    ) -> Result<()>;
 }
 
 // Redis implementation of MessageQueue
@@ -27,16 +36,12 @@ impl RedisMessageQueue {
         info!("Connected to Redis message queue at {}", redis_url);
         Ok(Self { pool })
     }
-}
 
-#[async_trait::async_trait]
-impl MessageQueue for RedisMessageQueue {
-    type Error = redis::RedisError;
-
-    async fn publish_price_update(
+    async fn publish<T: serde::Serialize + Sync>(
         &self,
-        price_update: PriceUpdate,
-    ) -> Result<(), Self::Error> {
+        channel: &str,
+        message: &T,
+    ) -> Result<(), redis::RedisError> {
         let mut conn = self
             .pool
             .get()
@@ -52,7 +57,7 @@ impl MessageQueue for RedisMessageQueue {
                     e.to_string(),
                 ))
             })?;
-        let payload = serde_json::to_string(&price_update).map_err(|e| {
+        let payload = serde_json::to_string(message).map_err(|e| {
             redis::RedisError::from((
                 redis::ErrorKind::IoError,
                 "Serialization error",
@@ -61,9 +66,34 @@ impl MessageQueue for RedisMessageQueue {
         })?;
 
         redis::cmd("PUBLISH")
-            .arg("price_updates")
+            .arg(channel)
             .arg(payload)
             .query_async(&mut *conn)
             .await
     }
 }
+
+#[async_trait::async_trait]
+impl MessageQueue for RedisMessageQueue {
+    async fn publish_price_update(
+        &self,
+        price_update: PriceUpdate,
+    ) -> Result<()> {
+        Ok(self.publish("price_updates", &price_update).await?)
+    }
+
+    async fn publish_migration(&self, migration: MigrationEvent) -> Result<()> {
+        Ok(self.publish("migrations", &migration).await?)
+    }
+
+    async fn publish_pool_update(&self, pool_state: PoolState) -> Result<()> {
+        Ok(self.publish("pool_updates", &pool_state).await?)
+    }
+
+    async fn publish_launch_event(
+        &self,
+        launch_event: LaunchEvent,
+    ) -> Result<()> {
+        Ok(self.publish("launch_events", &launch_event).await?)
+    }
+}
@@ -1,18 +1,173 @@
 use crate::{
     constants::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID},
-    kv_store::RedisKVStore,
+    kv_store::KVStore,
     util::make_rpc_client,
 };
 use anyhow::{Context, Result};
+use chrono::Utc;
 use mpl_token_metadata::accounts::Metadata;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use solana_sdk::account::Account;
 use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::program_pack::Pack;
 use solana_sdk::pubkey::Pubkey;
-use spl_token_2022::{extension::StateWithExtensions, state::Mint};
-use std::{str::FromStr, sync::Arc};
+use spl_token_2022::{
+    extension::{
+        transfer_fee::{TransferFee, TransferFeeConfig},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+    state::Mint,
+};
+use spl_token_metadata_interface::state::TokenMetadata as TokenMetadataExtension;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use tracing::{debug, warn};
 
+pub static METADATA_CONFIG: Lazy<MetadataConfig> =
+    Lazy::new(MetadataConfig::from_env);
+
+static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(METADATA_CONFIG.fetch_timeout)
+        .build()
+        .expect("failed to build http client")
+});
+
+/// failed fetches of a mint with no metadata at all are retried in the
+/// background this many times, later ones wait for the next swap
+const BACKGROUND_RETRIES: u32 = 5;
+
+/// mints with a background retry scheduled, at most one per mint
+static RETRYING_MINTS: Lazy<Mutex<HashSet<String>>> =
+    Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Caching and fetching of the token metadata, configured with
+///
+/// - `METADATA_TTL_SECS`: age after which the supply and authorities are
+///   re-read (default 3600)
+/// - `METADATA_RETRY_SECS` and `METADATA_MAX_RETRY_SECS`: first and longest
+///   wait before a failing mint is fetched again, doubling in between
+///   (default 30 and 3600)
+/// - `IPFS_GATEWAYS` and `ARWEAVE_GATEWAYS`: comma separated gateway urls in
+///   the order they are tried
+/// - `METADATA_FETCH_TIMEOUT_MS`: timeout of a single gateway request
+///   (default 3000)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataConfig {
+    pub ttl: u64,
+    pub retry: u64,
+    pub max_retry: u64,
+    pub ipfs_gateways: Vec<String>,
+    pub arweave_gateways: Vec<String>,
+    pub fetch_timeout: Duration,
+}
+
+impl Default for MetadataConfig {
+    fn default() -> Self {
+        Self {
+            ttl: 3600,
+            retry: 30,
+            max_retry: 3600,
+            ipfs_gateways: vec![
+                "https://ipfs.io/ipfs/".to_string(),
+                "https://gateway.pinata.cloud/ipfs/".to_string(),
+                "https://dweb.link/ipfs/".to_string(),
+            ],
+            arweave_gateways: vec![
+                "https://arweave.net/".to_string(),
+                "https://ar-io.net/".to_string(),
+            ],
+            fetch_timeout: Duration::from_millis(3000),
+        }
+    }
+}
+
+fn env_u64(key: &str) -> Option<u64> {
+    let value = std::env::var(key).ok()?;
+    match value.trim().parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!("invalid {}: {}", key, value);
+            None
+        }
+    }
+}
+
+/// gateway urls with a trailing slash, `None` if the list is empty
+fn parse_gateways(value: &str) -> Option<Vec<String>> {
+    let gateways: Vec<String> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|gateway| !gateway.is_empty())
+        .map(|gateway| format!("{}/", gateway.trim_end_matches('/')))
+        .collect();
+    (!gateways.is_empty()).then_some(gateways)
+}
+
+impl MetadataConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            ttl: env_u64("METADATA_TTL_SECS").unwrap_or(default.ttl),
+            retry: env_u64("METADATA_RETRY_SECS").unwrap_or(default.retry),
+            max_retry: env_u64("METADATA_MAX_RETRY_SECS")
+                .unwrap_or(default.max_retry),
+            ipfs_gateways: std::env::var("IPFS_GATEWAYS")
+                .ok()
+                .and_then(|value| parse_gateways(&value))
+                .unwrap_or(default.ipfs_gateways),
+            arweave_gateways: std::env::var("ARWEAVE_GATEWAYS")
+                .ok()
+                .and_then(|value| parse_gateways(&value))
+                .unwrap_or(default.arweave_gateways),
+            fetch_timeout: env_u64("METADATA_FETCH_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.fetch_timeout),
+        }
+    }
+
+    /// seconds to wait before fetching a mint that failed `failures` times
+    /// in a row
+    pub fn backoff(&self, failures: u32) -> u64 {
+        let doublings = failures.saturating_sub(1).min(16);
+        self.retry
+            .saturating_mul(1 << doublings)
+            .min(self.max_retry)
+    }
+
+    /// the urls to try for the off-chain metadata at `uri`, IPFS and Arweave
+    /// uris are rewritten to every configured gateway
+    pub fn gateway_urls(&self, uri: &str) -> Vec<String> {
+        if let Some(cid) = extract_ipfs_cid(uri) {
+            return self
+                .ipfs_gateways
+                .iter()
+                .map(|gateway| format!("{}{}", gateway, cid))
+                .collect();
+        }
+        if let Some(id) = extract_arweave_id(uri) {
+            return self
+                .arweave_gateways
+                .iter()
+                .map(|gateway| format!("{}{}", gateway, id))
+                .collect();
+        }
+        vec![uri.to_string()]
+    }
+}
+
+/// A failed metadata fetch, the mint is not fetched again before `retry_at`
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct MetadataMiss {
+    pub failures: u32,
+    pub retry_at: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct MplTokenMetadata {
     pub name: String,
@@ -28,6 +183,67 @@ pub struct SplTokenMetadata {
     pub decimals: u8,
     pub is_initialized: bool,
     pub freeze_authority: Option<String>,
+    /// transfer fee of Token-2022 mints with the transfer fee extension
+    #[serde(default)]
+    pub transfer_fee: Option<TransferFeeSchedule>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq)]
+pub struct TransferFeeRate {
+    pub basis_points: u16,
+    pub maximum_fee: u64,
+}
+
+impl TransferFeeRate {
+    /// fee withheld from a transfer of `amount`, rounded up like the token
+    /// program does
+    pub fn fee(&self, amount: u64) -> u64 {
+        if self.basis_points == 0 || amount == 0 {
+            return 0;
+        }
+        let fee = (amount as u128 * self.basis_points as u128)
+            .div_ceil(10_000)
+            .min(self.maximum_fee as u128);
+        fee as u64
+    }
+}
+
+impl From<&TransferFee> for TransferFeeRate {
+    fn from(fee: &TransferFee) -> Self {
+        Self {
+            basis_points: u16::from(fee.transfer_fee_basis_points),
+            maximum_fee: u64::from(fee.maximum_fee),
+        }
+    }
+}
+
+/// The transfer fee config of a mint, a fee update only takes effect from
+/// `newer_epoch` on
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct TransferFeeSchedule {
+    pub older: TransferFeeRate,
+    pub newer: TransferFeeRate,
+    pub newer_epoch: u64,
+}
+
+impl TransferFeeSchedule {
+    pub fn fee(&self, epoch: u64, amount: u64) -> u64 {
+        if epoch >= self.newer_epoch {
+            self.newer.fee(amount)
+        } else {
+            self.older.fee(amount)
+        }
+    }
+}
+
+impl From<&TransferFeeConfig> for TransferFeeSchedule {
+    fn from(config: &TransferFeeConfig) -> Self {
+        Self {
+            older: TransferFeeRate::from(&config.older_transfer_fee),
+            newer: TransferFeeRate::from(&config.newer_transfer_fee),
+            newer_epoch: u64::from(config.newer_transfer_fee.epoch),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -35,6 +251,10 @@ pub struct TokenMetadata {
     pub mint: String,
     pub mpl: MplTokenMetadata,
     pub spl: SplTokenMetadata,
+    /// unix timestamp of the fetch, 0 for entries cached before it was
+    /// recorded
+    #[serde(default)]
+    pub fetched_at: u64,
 }
 
 fn extract_ipfs_cid(uri: &str) -> Option<String> {
@@ -47,98 +267,392 @@ fn extract_ipfs_cid(uri: &str) -> Option<String> {
     }
 }
 
+fn extract_arweave_id(uri: &str) -> Option<String> {
+    if let Some(id) = uri.strip_prefix("ar://") {
+        return Some(id.to_string());
+    }
+    let url = url::Url::parse(uri).ok()?;
+    let host = url.host_str()?;
+    if host == "arweave.net" || host.ends_with(".arweave.net") {
+        let id = url.path().trim_start_matches('/');
+        return (!id.is_empty()).then(|| id.to_string());
+    }
+    None
+}
+
+/// points IPFS uris at the first configured gateway
 fn convert_ipfs_uri(uri: &str) -> String {
-    if let Some(cid) = extract_ipfs_cid(uri) {
-        format!("https://ipfs.io/ipfs/{}", cid)
-    } else {
-        uri.to_string()
+    match extract_ipfs_cid(uri) {
+        Some(cid) => {
+            format!("{}{}", METADATA_CONFIG.ipfs_gateways[0], cid)
+        }
+        None => uri.to_string(),
+    }
+}
+
+/// Where the metadata of the mints comes from when it is not cached
+#[async_trait::async_trait]
+pub trait MetadataSource: Send + Sync + 'static {
+    async fn fetch(&self, mint: &str) -> Result<TokenMetadata>;
+
+    /// re-reads what changes over the lifetime of the mint
+    async fn refresh(&self, metadata: &TokenMetadata) -> Result<TokenMetadata>;
+}
+
+/// Reads the metadata from the chain over RPC
+#[derive(Debug, Default)]
+pub struct RpcMetadataSource;
+
+#[async_trait::async_trait]
+impl MetadataSource for RpcMetadataSource {
+    async fn fetch(&self, mint: &str) -> Result<TokenMetadata> {
+        TokenMetadata::fetch_by_mint(mint).await
+    }
+
+    async fn refresh(&self, metadata: &TokenMetadata) -> Result<TokenMetadata> {
+        metadata.refresh().await
+    }
+}
+
+/// Metadata kept in memory, for tests and replays that should not need RPC;
+/// the mints it does not know fail like a mint without metadata
+#[derive(Debug, Default)]
+pub struct MemoryMetadataSource {
+    metadata: Mutex<HashMap<String, TokenMetadata>>,
+}
+
+impl MemoryMetadataSource {
+    pub fn new(metadata: impl IntoIterator<Item = TokenMetadata>) -> Self {
+        Self {
+            metadata: Mutex::new(
+                metadata
+                    .into_iter()
+                    .map(|metadata| (metadata.mint.clone(), metadata))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn insert(&self, metadata: TokenMetadata) {
+        self.metadata
+            .lock()
+            .expect("metadata source lock poisoned")
+            .insert(metadata.mint.clone(), metadata);
+    }
+
+    /// all of the metadata, ordered by mint
+    pub fn snapshot(&self) -> Vec<TokenMetadata> {
+        let mut metadata: Vec<TokenMetadata> = self
+            .metadata
+            .lock()
+            .expect("metadata source lock poisoned")
+            .values()
+            .cloned()
+            .collect();
+        metadata.sort_by(|a, b| a.mint.cmp(&b.mint));
+        metadata
     }
 }
 
+#[async_trait::async_trait]
+impl MetadataSource for MemoryMetadataSource {
+    async fn fetch(&self, mint: &str) -> Result<TokenMetadata> {
+        self.metadata
+            .lock()
+            .expect("metadata source lock poisoned")
+            .get(mint)
+            .cloned()
+            .with_context(|| format!("no metadata for {}", mint))
+    }
+
+    async fn refresh(&self, metadata: &TokenMetadata) -> Result<TokenMetadata> {
+        self.fetch(&metadata.mint).await
+    }
+}
+
+/// The metadata of the mint from the cache, fetched if it is missing and
+/// refreshed once it is older than the configured ttl
+///
+/// Failed fetches are recorded in the kv store and not retried before the
+/// backoff passes; stale metadata is returned meanwhile, `Ok(None)` if there
+/// is none, whether the fetch failed now or is backing off. Errors are only
+/// returned for the kv store
 pub async fn get_token_metadata(
-    kv_store: &Arc<RedisKVStore>,
+    kv_store: &Arc<dyn KVStore>,
+    source: &Arc<dyn MetadataSource>,
     mint: &str,
 ) -> Result<Option<TokenMetadata>> {
-    // Try to get from cache first
-    if let Some(metadata) = kv_store.get_metadata(mint).await? {
-        debug!(mint, "metadata found in cache");
-        return Ok(Some(metadata));
+    let config = &*METADATA_CONFIG;
+    let now = Utc::now().timestamp() as u64;
+
+    let cached = kv_store.get_metadata(mint).await?;
+    if let Some(metadata) = &cached {
+        if now < metadata.fetched_at.saturating_add(config.ttl) {
+            debug!(mint, "metadata found in cache");
+            return Ok(cached);
+        }
+    }
+
+    let miss = kv_store.get_metadata_miss(mint).await?;
+    if let Some(miss) = &miss {
+        if now < miss.retry_at {
+            debug!(
+                mint,
+                retry_at = miss.retry_at,
+                "metadata fetch backing off"
+            );
+            return Ok(cached);
+        }
     }
 
-    match TokenMetadata::fetch_by_mint(mint).await {
+    let fetched = match &cached {
+        Some(metadata) => source.refresh(metadata).await,
+        None => source.fetch(mint).await,
+    };
+
+    match fetched {
         Ok(metadata) => {
             kv_store
                 .insert_metadata(&metadata)
                 .await
                 .context("failed to insert metadata")?;
+            if miss.is_some() {
+                kv_store.delete_metadata_miss(mint).await?;
+            }
 
             Ok(Some(metadata))
         }
-        Err(e) => Err(e),
+        Err(e) => {
+            let failures = miss.map_or(0, |miss| miss.failures) + 1;
+            let backoff = config.backoff(failures);
+            kv_store
+                .insert_metadata_miss(
+                    mint,
+                    &MetadataMiss {
+                        failures,
+                        retry_at: now + backoff,
+                    },
+                    backoff + config.max_retry,
+                )
+                .await?;
+
+            match cached {
+                Some(stale) => {
+                    warn!(
+                        mint,
+                        error = e.to_string(),
+                        "failed to refresh metadata, using stale"
+                    );
+                    Ok(Some(stale))
+                }
+                None => {
+                    warn!(
+                        mint,
+                        failures,
+                        error = e.to_string(),
+                        "failed to fetch metadata"
+                    );
+                    if failures <= BACKGROUND_RETRIES {
+                        retry_later(
+                            kv_store.clone(),
+                            source.clone(),
+                            mint.to_string(),
+                            backoff,
+                        );
+                    }
+                    Ok(None)
+                }
+            }
+        }
+    }
+}
+
+/// fetches the metadata of a mint that has none once the backoff passed, so
+/// it is filled in even if the mint is not traded again; false if a retry of
+/// the mint is already scheduled
+fn retry_later(
+    kv_store: Arc<dyn KVStore>,
+    source: Arc<dyn MetadataSource>,
+    mint: String,
+    backoff: u64,
+) -> bool {
+    if !RETRYING_MINTS
+        .lock()
+        .expect("retrying mints lock poisoned")
+        .insert(mint.clone())
+    {
+        return false;
+    }
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(backoff)).await;
+        // released before the fetch, a failure schedules the next retry
+        RETRYING_MINTS
+            .lock()
+            .expect("retrying mints lock poisoned")
+            .remove(&mint);
+        match get_token_metadata(&kv_store, &source, &mint).await {
+            Ok(Some(_)) => {}
+            Ok(None) => debug!(mint = mint.as_str(), "metadata retry failed"),
+            Err(e) => debug!(
+                mint = mint.as_str(),
+                error = e.to_string(),
+                "metadata retry failed"
+            ),
+        }
+    });
+    true
+}
+
+/// The mint account, its owner tells the token program of the mint
+async fn fetch_mint_account(mint: &str) -> Result<Account> {
+    let rpc_client = make_rpc_client()?;
+    let token_pubkey = Pubkey::from_str(mint)?;
+    rpc_client
+        .get_account_with_commitment(
+            &token_pubkey,
+            CommitmentConfig::processed(),
+        )
+        .await
+        .context("failed to get token account")?
+        .value
+        .context("Token account not found")
+}
+
+fn spl_metadata_from_account(
+    mint: &str,
+    account: &Account,
+) -> Result<SplTokenMetadata> {
+    let data = &account.data;
+
+    let (token_data, transfer_fee) = match account.owner {
+        TOKEN_PROGRAM_ID => {
+            (Mint::unpack(data).context("failed to unpack mint")?, None)
+        }
+        TOKEN_2022_PROGRAM_ID => {
+            let state_with_extensions =
+                StateWithExtensions::<Mint>::unpack(data)
+                    .context("failed to unpack Token-2022 mint data")?;
+            let transfer_fee = state_with_extensions
+                .get_extension::<TransferFeeConfig>()
+                .ok()
+                .map(TransferFeeSchedule::from);
+            (state_with_extensions.base, transfer_fee)
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Unknown token program owner: {}",
+                account.owner
+            ));
+        }
+    };
+
+    debug!(mint, "spl metadata fetch ok");
+
+    Ok(SplTokenMetadata {
+        mint_authority: token_data.mint_authority.map(|p| p.to_string()).into(),
+        supply: token_data.supply,
+        decimals: token_data.decimals,
+        is_initialized: token_data.is_initialized,
+        freeze_authority: token_data
+            .freeze_authority
+            .map(|p| p.to_string())
+            .into(),
+        transfer_fee,
+    })
+}
+
+/// The metadata of the token-metadata extension, `None` if the mint is not a
+/// Token-2022 mint or does not have the extension
+async fn token_2022_metadata_from_account(
+    mint: &str,
+    account: &Account,
+) -> Result<Option<MplTokenMetadata>> {
+    if account.owner != TOKEN_2022_PROGRAM_ID {
+        return Ok(None);
     }
+
+    let state_with_extensions =
+        StateWithExtensions::<Mint>::unpack(&account.data)
+            .context("failed to unpack Token-2022 mint data")?;
+    let metadata = match state_with_extensions
+        .get_variable_len_extension::<TokenMetadataExtension>()
+    {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(None),
+    };
+
+    debug!(
+        mint,
+        name = metadata.name,
+        symbol = metadata.symbol,
+        uri = metadata.uri,
+        "parsed token-metadata extension"
+    );
+
+    let uri = convert_ipfs_uri(&metadata.uri);
+    Ok(Some(MplTokenMetadata {
+        name: metadata.name,
+        symbol: metadata.symbol,
+        ipfs_metadata: fetch_uri_metadata(mint, &uri).await,
+        uri,
+    }))
 }
 
 impl TokenMetadata {
     pub async fn fetch_by_mint(mint: &str) -> Result<Self> {
-        let mpl_metadata = TokenMetadata::fetch_mpl_by_mint(mint)
-            .await
-            .unwrap_or_default();
-        let spl_metadata = TokenMetadata::fetch_spl_by_mint(mint).await?;
+        let account = fetch_mint_account(mint).await?;
+        let mpl_metadata = match TokenMetadata::fetch_mpl_by_mint(mint).await {
+            Ok(metadata) => metadata,
+            // Token-2022 mints can carry their metadata in the mint account
+            Err(_) if account.owner == TOKEN_2022_PROGRAM_ID => {
+                token_2022_metadata_from_account(mint, &account)
+                    .await
+                    .ok()
+                    .flatten()
+                    .unwrap_or_default()
+            }
+            Err(_) => MplTokenMetadata::default(),
+        };
+        let spl_metadata = spl_metadata_from_account(mint, &account)?;
 
         Ok(TokenMetadata {
             mint: mint.to_string(),
             mpl: mpl_metadata,
             spl: spl_metadata,
+            fetched_at: Utc::now().timestamp() as u64,
         })
     }
 
-    pub async fn fetch_spl_by_mint(mint: &str) -> Result<SplTokenMetadata> {
-        let rpc_client = make_rpc_client()?;
-        let token_pubkey = Pubkey::from_str(mint)?;
-        let token_account = rpc_client
-            .get_account_with_commitment(
-                &token_pubkey,
-                CommitmentConfig::processed(),
-            )
-            .await
-            .context("failed to get token account")?;
+    /// re-reads the supply and the authorities, which change with mints and
+    /// burns, and the off-chain metadata if it could not be fetched before
+    pub async fn refresh(&self) -> Result<Self> {
+        let spl = TokenMetadata::fetch_spl_by_mint(&self.mint).await?;
+        let mut mpl = self.mpl.clone();
+        if mpl.ipfs_metadata.is_none() && !mpl.uri.is_empty() {
+            mpl.ipfs_metadata = fetch_uri_metadata(&self.mint, &mpl.uri).await;
+        }
 
-        let account = token_account.value.context("Token account not found")?;
-        let data = &account.data;
+        Ok(TokenMetadata {
+            mint: self.mint.clone(),
+            mpl,
+            spl,
+            fetched_at: Utc::now().timestamp() as u64,
+        })
+    }
 
-        let token_data = match account.owner {
-            TOKEN_PROGRAM_ID => {
-                Mint::unpack(&data).expect("Failed to unpack mint")
-            }
-            TOKEN_2022_PROGRAM_ID => {
-                let state_with_extensions =
-                    StateWithExtensions::<Mint>::unpack(&data)
-                        .expect("failed to unpack Token-2022 mint data");
-                state_with_extensions.base
-            }
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "Unknown token program owner: {}",
-                    account.owner
-                ));
-            }
-        };
+    pub async fn fetch_spl_by_mint(mint: &str) -> Result<SplTokenMetadata> {
+        let account = fetch_mint_account(mint).await?;
+        spl_metadata_from_account(mint, &account)
+    }
 
-        debug!(mint, "spl metadata fetch ok");
-
-        Ok(SplTokenMetadata {
-            mint_authority: token_data
-                .mint_authority
-                .map(|p| p.to_string())
-                .into(),
-            supply: token_data.supply,
-            decimals: token_data.decimals,
-            is_initialized: token_data.is_initialized,
-            freeze_authority: token_data
-                .freeze_authority
-                .map(|p| p.to_string())
-                .into(),
-        })
+    /// Reads the metadata from the token-metadata extension of a Token-2022
+    /// mint, `None` if the mint does not have the extension
+    pub async fn fetch_token_2022_metadata_by_mint(
+        mint: &str,
+    ) -> Result<Option<MplTokenMetadata>> {
+        let account = fetch_mint_account(mint).await?;
+        token_2022_metadata_from_account(mint, &account).await
     }
 
     pub async fn fetch_mpl_by_mint(mint: &str) -> Result<MplTokenMetadata> {
@@ -201,36 +715,47 @@ impl TokenMetadata {
             .trim_matches(char::from(0))
             .to_string();
 
-        // Create base token metadata
-        let mut token_metadata = MplTokenMetadata {
+        Ok(MplTokenMetadata {
             name: metadata.name.trim_matches(char::from(0)).to_string(),
             symbol: metadata.symbol.trim_matches(char::from(0)).to_string(),
-            uri: uri.clone(),
-            ipfs_metadata: None,
-        };
+            ipfs_metadata: fetch_uri_metadata(mint, &uri).await,
+            uri,
+        })
+    }
+}
 
-        // Fetch IPFS metadata if available
-        let client = reqwest::Client::new();
-        if let Ok(response) = client.get(&uri).send().await {
-            if let Ok(ipfs_metadata) =
-                response.json::<serde_json::Value>().await
-            {
-                debug!(mint, uri, "ipfs fetch ok");
-                token_metadata.ipfs_metadata = Some(ipfs_metadata);
-            } else {
-                warn!(mint, uri, "ipfs response not json");
-            }
-        } else {
-            warn!(mint, uri, "ipfs fetch failed");
+/// Fetches the off-chain json metadata the uri points to, trying the
+/// gateways in order
+async fn fetch_uri_metadata(
+    mint: &str,
+    uri: &str,
+) -> Option<serde_json::Value> {
+    for url in METADATA_CONFIG.gateway_urls(uri) {
+        match HTTP_CLIENT.get(&url).send().await {
+            Ok(response) => match response.json::<serde_json::Value>().await {
+                Ok(ipfs_metadata) => {
+                    debug!(mint, url = url.as_str(), "ipfs fetch ok");
+                    return Some(ipfs_metadata);
+                }
+                Err(_) => {
+                    debug!(mint, url = url.as_str(), "ipfs response not json")
+                }
+            },
+            Err(e) => debug!(
+                mint,
+                url = url.as_str(),
+                error = e.to_string(),
+                "ipfs fetch failed"
+            ),
         }
-
-        Ok(token_metadata)
     }
+    warn!(mint, uri, "ipfs fetch failed on all gateways");
+    None
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::util::make_kv_store;
+    use crate::{kv_store::MemoryKVStore, util::make_kv_store};
 
     use super::*;
 
@@ -281,8 +806,10 @@ mod tests {
     #[tokio::test]
     async fn test_get_token_metadata() {
         let kv_store = make_kv_store().await.unwrap();
+        let source: Arc<dyn MetadataSource> = Arc::new(RpcMetadataSource);
         let metadata = get_token_metadata(
             &kv_store,
+            &source,
             "9BB6NFEcjBCtnNLFko2FqVQBq8HHM13kCyYcdQbgpump",
         )
         .await
@@ -290,6 +817,59 @@ mod tests {
         debug!("{:?}", metadata);
     }
 
+    #[tokio::test]
+    async fn test_get_token_metadata_from_source() {
+        let mint = "9BB6NFEcjBCtnNLFko2FqVQBq8HHM13kCyYcdQbgpump";
+        let unknown = "AsyfR3e5JcPqWot4H5MMhQUm7DZ4zwQrcp2zbB7vpump";
+        let kv_store: Arc<dyn KVStore> = Arc::new(MemoryKVStore::default());
+        let memory_source =
+            Arc::new(MemoryMetadataSource::new([TokenMetadata {
+                mint: mint.to_string(),
+                fetched_at: Utc::now().timestamp() as u64,
+                ..Default::default()
+            }]));
+        let source: Arc<dyn MetadataSource> = memory_source.clone();
+
+        let metadata = get_token_metadata(&kv_store, &source, mint)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(metadata.mint, mint);
+        assert!(kv_store.has_metadata(mint).await.unwrap());
+
+        // a failed fetch backs off, the mint is not fetched again meanwhile
+        assert!(get_token_metadata(&kv_store, &source, unknown)
+            .await
+            .unwrap()
+            .is_none());
+        let miss = kv_store.get_metadata_miss(unknown).await.unwrap().unwrap();
+        assert_eq!(miss.failures, 1);
+        memory_source.insert(TokenMetadata {
+            mint: unknown.to_string(),
+            ..Default::default()
+        });
+        assert!(get_token_metadata(&kv_store, &source, unknown)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retry_later_once_per_mint() {
+        let mint = "7GCihgDB8fe6KNjn2MYtkzZcRjQy3t9GHdC8uHYmW2hr";
+        let kv_store: Arc<dyn KVStore> = Arc::new(MemoryKVStore::default());
+        let source: Arc<dyn MetadataSource> =
+            Arc::new(MemoryMetadataSource::default());
+
+        assert!(retry_later(
+            kv_store.clone(),
+            source.clone(),
+            mint.to_string(),
+            3600
+        ));
+        assert!(!retry_later(kv_store, source, mint.to_string(), 3600));
+    }
+
     #[test]
     fn test_extract_ipfs_cid() {
         assert_eq!(
@@ -313,6 +893,70 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_gateway_urls() {
+        let config = MetadataConfig {
+            ipfs_gateways: parse_gateways(
+                "https://a.example/ipfs, https://b.example/ipfs/",
+            )
+            .unwrap(),
+            ..MetadataConfig::default()
+        };
+        assert_eq!(
+            config.gateway_urls("https://ipfs.io/ipfs/QmSomeHash"),
+            vec![
+                "https://a.example/ipfs/QmSomeHash",
+                "https://b.example/ipfs/QmSomeHash"
+            ]
+        );
+        assert_eq!(
+            config.gateway_urls("ar://SomeTx"),
+            vec!["https://arweave.net/SomeTx", "https://ar-io.net/SomeTx"]
+        );
+        assert_eq!(
+            config.gateway_urls("https://xyz.arweave.net/SomeTx")[1],
+            "https://ar-io.net/SomeTx"
+        );
+        assert_eq!(
+            config.gateway_urls("https://example.com/token.json"),
+            vec!["https://example.com/token.json"]
+        );
+        assert_eq!(parse_gateways(" , "), None);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = MetadataConfig::default();
+        assert_eq!(config.backoff(1), 30);
+        assert_eq!(config.backoff(2), 60);
+        assert_eq!(config.backoff(4), 240);
+        assert_eq!(config.backoff(10), 3600);
+        assert_eq!(config.backoff(u32::MAX), 3600);
+    }
+
+    #[test]
+    fn test_transfer_fee() {
+        let schedule = TransferFeeSchedule {
+            older: TransferFeeRate {
+                basis_points: 100,
+                maximum_fee: 5_000,
+            },
+            newer: TransferFeeRate {
+                basis_points: 250,
+                maximum_fee: u64::MAX,
+            },
+            newer_epoch: 700,
+        };
+
+        // 1% of 1001 rounds up
+        assert_eq!(schedule.fee(699, 1_001), 11);
+        // capped at the maximum fee
+        assert_eq!(schedule.fee(699, 10_000_000), 5_000);
+        assert_eq!(schedule.fee(700, 10_000_000), 250_000);
+        assert_eq!(schedule.fee(700, 0), 0);
+        assert_eq!(TransferFeeRate::default().fee(10_000), 0);
+    }
+
     #[tokio::test]
     async fn test_spl_2022_mint() {
         let metadata = TokenMetadata::fetch_spl_by_mint(
@@ -9,8 +9,9 @@ pub struct SwapMetrics {
     pub skipped_tiny_swaps: AtomicU64,
     pub skipped_zero_swaps: AtomicU64,
     pub skipped_unexpected_number_of_tokens: AtomicU64,
-    pub skipped_no_metadata: AtomicU64,
-    pub skipped_non_wsol: AtomicU64,
+    /// swaps emitted without the token metadata
+    pub missing_metadata: AtomicU64,
+    pub skipped_no_quote: AtomicU64,
     pub message_send_success: AtomicU64,
     pub message_send_failure: AtomicU64,
     pub db_insert_success: AtomicU64,
@@ -26,6 +27,10 @@ pub struct SwapMetrics {
     pub meteora_dlmm_swaps: AtomicU64,
     pub whirlpools_swaps: AtomicU64,
     pub pump_swaps: AtomicU64,
+    pub pump_fun_trades: AtomicU64,
+    pub pump_fun_migrations: AtomicU64,
+    pub pools_created: AtomicU64,
+    pub tokens_launched: AtomicU64,
 }
 
 impl SwapMetrics {
@@ -56,6 +61,22 @@ impl SwapMetrics {
         self.pump_swaps.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn increment_pump_fun_trades(&self) {
+        self.pump_fun_trades.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_pump_fun_migrations(&self) {
+        self.pump_fun_migrations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_pools_created(&self) {
+        self.pools_created.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn increment_tokens_launched(&self) {
+        self.tokens_launched.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn increment_total_swaps(&self) {
         let count = self.total_swaps_processed.fetch_add(1, Ordering::Relaxed);
         // println!("total swaps processed: {}", count);
@@ -85,12 +106,12 @@ impl SwapMetrics {
             .fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn increment_skipped_no_metadata(&self) {
-        self.skipped_no_metadata.fetch_add(1, Ordering::Relaxed);
+    pub fn increment_missing_metadata(&self) {
+        self.missing_metadata.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn increment_skipped_non_wsol(&self) {
-        self.skipped_non_wsol.fetch_add(1, Ordering::Relaxed);
+    pub fn increment_skipped_no_quote(&self) {
+        self.skipped_no_quote.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn increment_db_insert_success(&self) {
@@ -141,6 +162,10 @@ impl SwapMetrics {
         let raydium_clmm = self.raydium_clmm_swaps.load(Ordering::Relaxed);
         let whirlpools = self.whirlpools_swaps.load(Ordering::Relaxed);
         let pump = self.pump_swaps.load(Ordering::Relaxed);
+        let pump_fun = self.pump_fun_trades.load(Ordering::Relaxed);
+        let migrations = self.pump_fun_migrations.load(Ordering::Relaxed);
+        let pools_created = self.pools_created.load(Ordering::Relaxed);
+        let tokens_launched = self.tokens_launched.load(Ordering::Relaxed);
         let pending = self.pending_swaps.load(Ordering::Relaxed);
         let successful = self.successful_swaps.load(Ordering::Relaxed);
         let failed = self.failed_swaps.load(Ordering::Relaxed);
@@ -149,8 +174,8 @@ impl SwapMetrics {
         let unexpected = self
             .skipped_unexpected_number_of_tokens
             .load(Ordering::Relaxed);
-        let non_wsol = self.skipped_non_wsol.load(Ordering::Relaxed);
-        let no_metadata = self.skipped_no_metadata.load(Ordering::Relaxed);
+        let no_quote = self.skipped_no_quote.load(Ordering::Relaxed);
+        let no_metadata = self.missing_metadata.load(Ordering::Relaxed);
         let message_send_success =
             self.message_send_success.load(Ordering::Relaxed);
         let message_send_failure =
@@ -179,14 +204,18 @@ impl SwapMetrics {
              Meteora DLMM: {}\n\
              Whirlpools: {}\n\
              PumpSwap: {}\n\
+             Pump.fun: {}\n\
+             Pump.fun Migrations: {}\n\
+             Pools Created: {}\n\
+             Tokens Launched: {}\n\
              Pending: {}\n\
              Successful: {} ({:.1}%)\n\
              Failed: {}\n\
              Skipped (tiny): {}\n\
              Skipped (zero): {}\n\
              Skipped (unexpected tokens): {}\n\
-             Skipped (non-wSOL): {}\n\
-             Skipped (no metadata): {}\n\
+             Skipped (no quote asset): {}\n\
+             Missing Metadata: {}\n\
              Message Send Success: {}\n\
              Message Send Failure: {}\n\
              DB Insert Success: {}\n\
@@ -202,6 +231,10 @@ impl SwapMetrics {
             meteora_dlmm,
             whirlpools,
             pump,
+            pump_fun,
+            migrations,
+            pools_created,
+            tokens_launched,
             pending,
             successful,
             success_rate,
@@ -209,7 +242,7 @@ impl SwapMetrics {
             tiny,
             zero,
             unexpected,
-            non_wsol,
+            no_quote,
             no_metadata,
             message_send_success,
             message_send_failure,
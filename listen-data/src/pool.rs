@@ -0,0 +1,184 @@
+//! Depth of the pools the swaps go through, the reserves of a pool are the
+//! balances of its vaults after the swap as seen in the transaction metadata
+
+use std::collections::{HashMap, HashSet};
+
+use carbon_core::transaction::TransactionMetadata;
+use clickhouse::Row;
+use serde::{Deserialize, Serialize};
+use spl_token::amount_to_ui_amount;
+
+/// A pool a swap goes through, identified by its address and the token
+/// accounts (vaults) that hold its reserves
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pool {
+    pub address: String,
+    pub vaults: HashSet<String>,
+}
+
+impl Pool {
+    pub fn new(address: impl ToString, vaults: [impl ToString; 2]) -> Self {
+        Self {
+            address: address.to_string(),
+            vaults: vaults.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Row)]
+pub struct PoolState {
+    pub pool: String,
+    pub dex: String,
+    pub base_mint: String,
+    pub quote_mint: String,
+    pub base_reserve: f64,
+    pub quote_reserve: f64,
+    pub liquidity_usd: f64,
+    pub timestamp: u64,
+    pub slot: u64,
+    pub signature: String,
+}
+
+/// Balance of a token account after the transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultBalance {
+    pub mint: String,
+    pub amount: u64,
+    pub decimals: u8,
+}
+
+/// Raw amounts held by the vaults that are not part of the reserves of the
+/// pool, keyed by vault, e.g. the pnl of a Raydium AMM v4 pool that has not
+/// been taken yet
+pub type VaultAdjustments = HashMap<String, u64>;
+
+pub fn vault_balances_from_tx_metadata(
+    transaction_metadata: &TransactionMetadata,
+) -> HashMap<String, VaultBalance> {
+    let account_keys =
+        transaction_metadata.message.static_account_keys().to_vec();
+    let loaded_addresses = transaction_metadata.meta.loaded_addresses.clone();
+    let accounts_address = [
+        account_keys,
+        loaded_addresses.writable,
+        loaded_addresses.readonly,
+    ]
+    .concat();
+
+    transaction_metadata
+        .meta
+        .post_token_balances
+        .iter()
+        .flatten()
+        .filter_map(|balance| {
+            let account =
+                accounts_address.get(balance.account_index as usize)?;
+            let amount = balance.ui_token_amount.amount.parse::<u64>().ok()?;
+            Some((
+                account.to_string(),
+                VaultBalance {
+                    mint: balance.mint.clone(),
+                    amount,
+                    decimals: balance.ui_token_amount.decimals,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Reserves of the base and quote mints in the vaults of the pool, net of the
+/// adjustments; `None` if the balance of one of the vaults is missing from
+/// the transaction
+pub fn pool_reserves(
+    pool: &Pool,
+    base_mint: &str,
+    quote_mint: &str,
+    balances: &HashMap<String, VaultBalance>,
+    adjustments: &VaultAdjustments,
+) -> Option<(f64, f64)> {
+    let reserve = |mint: &str| {
+        pool.vaults.iter().find_map(|vault| {
+            let balance = balances.get(vault).filter(|b| b.mint == mint)?;
+            let adjustment = adjustments.get(vault).copied().unwrap_or(0);
+            Some(amount_to_ui_amount(
+                balance.amount.saturating_sub(adjustment),
+                balance.decimals,
+            ))
+        })
+    };
+    Some((reserve(base_mint)?, reserve(quote_mint)?))
+}
+
+/// usd value of both sides of the pool
+pub fn liquidity_usd(
+    base_reserve: f64,
+    base_price: f64,
+    quote_reserve: f64,
+    quote_price: f64,
+) -> f64 {
+    base_reserve * base_price + quote_reserve * quote_price
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{USDC_MINT_KEY_STR, WSOL_MINT_KEY_STR};
+
+    const TOKEN_MINT: &str = "AsyfR3e5JcPqWot4H5MMhQUm7DZ4zwQrcp2zbB7vpump";
+
+    fn balance(mint: &str, amount: u64, decimals: u8) -> VaultBalance {
+        VaultBalance {
+            mint: mint.to_string(),
+            amount,
+            decimals,
+        }
+    }
+
+    #[test]
+    fn test_pool_reserves() {
+        let pool = Pool::new("pool", ["coin_vault", "pc_vault"]);
+        let balances = HashMap::from([
+            (
+                "coin_vault".to_string(),
+                balance(TOKEN_MINT, 2_000_000_000_000, 6),
+            ),
+            (
+                "pc_vault".to_string(),
+                balance(WSOL_MINT_KEY_STR, 10_500_000_000, 9),
+            ),
+            // unrelated account of the transaction
+            (
+                "user_account".to_string(),
+                balance(USDC_MINT_KEY_STR, 1_000_000, 6),
+            ),
+        ]);
+
+        // half a SOL of the vault is pnl that was not taken yet
+        let adjustments =
+            VaultAdjustments::from([("pc_vault".to_string(), 500_000_000)]);
+        let (base_reserve, quote_reserve) = pool_reserves(
+            &pool,
+            TOKEN_MINT,
+            WSOL_MINT_KEY_STR,
+            &balances,
+            &adjustments,
+        )
+        .unwrap();
+        assert_eq!(base_reserve, 2_000_000.0);
+        assert_eq!(quote_reserve, 10.0);
+        assert_eq!(
+            liquidity_usd(base_reserve, 0.001, quote_reserve, 200.0),
+            4_000.0
+        );
+
+        // the vaults of the pool are not part of the transaction
+        assert!(pool_reserves(
+            &Pool::new("other", ["a", "b"]),
+            TOKEN_MINT,
+            WSOL_MINT_KEY_STR,
+            &balances,
+            &VaultAdjustments::new(),
+        )
+        .is_none());
+    }
+}
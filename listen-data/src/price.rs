@@ -24,4 +24,15 @@ pub struct PriceUpdate {
     pub multi_hop: bool,
     pub is_buy: bool,
     pub is_pump: bool,
+    /// mint of the asset the trade was priced against
+    pub quote_mint: String,
+    /// false if neither the token metadata nor an earlier price with it were
+    /// available, the name and the market cap are empty then
+    #[serde(default = "metadata_known")]
+    pub has_metadata: bool,
+}
+
+/// updates from before the flag was recorded
+fn metadata_known() -> bool {
+    true
 }
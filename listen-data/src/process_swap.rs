@@ -1,21 +1,37 @@
 use crate::diffs::{
-    extra_mint_details_from_tx_metadata, process_token_transfers, DiffsError,
-    DiffsResult, TokenTransferDetails, SPL_TOKEN_TRANSFER_PROCESSOR,
+    apply_transfer_fees, extra_mint_details_from_tx_metadata,
+    process_token_transfers, DiffsError, DiffsResult, TokenTransferDetails,
+    SPL_TOKEN_TRANSFER_PROCESSOR,
 };
 use crate::{
-    db::{ClickhouseDb, Database},
-    kv_store::RedisKVStore,
-    message_queue::{MessageQueue, RedisMessageQueue},
-    metadata::get_token_metadata,
+    constants::{
+        METEORA_DLMM_PROGRAM_ID, PUMP_FUN_PROGRAM_ID, PUMP_SWAP_PROGRAM_ID,
+        RAYDIUM_AMM_V4_PROGRAM_ID, RAYDIUM_CLMM_PROGRAM_ID,
+        RAYDIUM_CPMM_PROGRAM_ID, TOKEN_2022_PROGRAM_ID_STR,
+        WHIRLPOOLS_PROGRAM_ID, WSOL_MINT_KEY_STR,
+    },
+    db::Database,
+    handler::token_swap_handler::Dex,
+    kv_store::KVStore,
+    message_queue::MessageQueue,
+    metadata::{get_token_metadata, MetadataSource, TransferFeeSchedule},
     metrics::SwapMetrics,
+    pool::{
+        liquidity_usd, pool_reserves, vault_balances_from_tx_metadata, Pool,
+        PoolState, VaultBalance,
+    },
     price::PriceUpdate,
+    pump_fun::{bonding_curve_address, TradeEvent},
+    quote::{QuotePrices, QUOTE_REGISTRY},
     sol_price_stream::get_sol_price,
+    util::get_epoch,
 };
 use anyhow::{Context, Result};
 use carbon_core::instruction::NestedInstruction;
 use carbon_core::transaction::TransactionMetadata;
 use chrono::Utc;
-use std::collections::HashSet;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tracing::{debug, warn};
 
@@ -44,14 +60,129 @@ pub fn is_valid_vault_transfer(
     vaults.contains(&transfer.destination) || vaults.contains(&transfer.source)
 }
 
+/// Vault transfers of a single pool within a swap
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapLeg {
+    pub pool: Pool,
+    pub transfers: Vec<TokenTransferDetails>,
+}
+
+/// Pairs up the transfers with the pools they touch, one leg per pool in the
+/// order of the pools; a transfer between the vaults of two pools (the
+/// intermediate token of a route) belongs to both legs
+pub fn swap_legs(
+    pools: &[Pool],
+    fee_adas: Option<&HashSet<String>>,
+    transfers: &[TokenTransferDetails],
+) -> Vec<SwapLeg> {
+    pools
+        .iter()
+        .map(|pool| SwapLeg {
+            pool: pool.clone(),
+            transfers: transfers
+                .iter()
+                .filter(|d| is_valid_vault_transfer(d, &pool.vaults, fee_adas))
+                .cloned()
+                .collect(),
+        })
+        .collect()
+}
+
+/// Programs of the dexes the swaps are indexed from
+const DEX_PROGRAM_IDS: [Pubkey; 7] = [
+    RAYDIUM_AMM_V4_PROGRAM_ID,
+    RAYDIUM_CLMM_PROGRAM_ID,
+    RAYDIUM_CPMM_PROGRAM_ID,
+    WHIRLPOOLS_PROGRAM_ID,
+    METEORA_DLMM_PROGRAM_ID,
+    PUMP_SWAP_PROGRAM_ID,
+    PUMP_FUN_PROGRAM_ID,
+];
+
+/// Every instruction of the transaction as `(program, stack_height)`, in the
+/// order of execution
+pub fn program_invocations(
+    transaction_metadata: &TransactionMetadata,
+) -> Vec<(Pubkey, u32)> {
+    let loaded_addresses = &transaction_metadata.meta.loaded_addresses;
+    let account_keys = [
+        transaction_metadata.message.static_account_keys(),
+        loaded_addresses.writable.as_slice(),
+        loaded_addresses.readonly.as_slice(),
+    ]
+    .concat();
+    let program = |index: u8| account_keys.get(index as usize).copied();
+
+    let mut invocations = Vec::new();
+    for (index, instruction) in transaction_metadata
+        .message
+        .instructions()
+        .iter()
+        .enumerate()
+    {
+        invocations.extend(
+            program(instruction.program_id_index).map(|program| (program, 1)),
+        );
+        let inner = transaction_metadata
+            .meta
+            .inner_instructions
+            .iter()
+            .flatten()
+            .filter(|inner| inner.index as usize == index)
+            .flat_map(|inner| inner.instructions.iter());
+        for inner in inner {
+            invocations.extend(
+                program(inner.instruction.program_id_index)
+                    .map(|program| (program, inner.stack_height.unwrap_or(2))),
+            );
+        }
+    }
+    invocations
+}
+
+/// Number of swaps against the known dexes in the invocations, a dex calling
+/// into itself (e.g. the event CPI of pump.fun) is not another swap
+pub fn count_dex_swaps(invocations: &[(Pubkey, u32)]) -> usize {
+    let mut callers: Vec<Pubkey> = Vec::new();
+    let mut swaps = 0;
+    for (program, stack_height) in invocations {
+        callers.truncate(stack_height.saturating_sub(1) as usize);
+        if DEX_PROGRAM_IDS.contains(program) && callers.last() != Some(program)
+        {
+            swaps += 1;
+        }
+        callers.push(*program);
+    }
+    swaps
+}
+
+/// Number of pools the transaction went through, the legs of a single
+/// instruction that go through several pools (e.g. a Whirlpool two-hop swap)
+/// count one by one
+pub fn pools_touched(
+    transaction_metadata: &TransactionMetadata,
+    legs: &[SwapLeg],
+) -> usize {
+    let instruction_pools =
+        legs.iter().filter(|leg| !leg.transfers.is_empty()).count();
+    let dex_swaps = count_dex_swaps(&program_invocations(transaction_metadata));
+    dex_swaps + instruction_pools.saturating_sub(1)
+}
+
+/// Processes the swap through the given pools, each pool emits its own price
+/// update and pool state; the updates are flagged as multi-hop if the
+/// transaction went through more than one pool
+#[allow(clippy::too_many_arguments)]
 pub async fn process_swap(
-    vaults: &HashSet<String>,
+    pools: &[Pool],
     fee_adas: Option<&HashSet<String>>,
+    dex: Dex,
     transaction_metadata: &TransactionMetadata,
     nested_instructions: &[NestedInstruction],
-    message_queue: &RedisMessageQueue,
-    kv_store: &Arc<RedisKVStore>,
-    db: &Arc<ClickhouseDb>,
+    message_queue: &dyn MessageQueue,
+    kv_store: &Arc<dyn KVStore>,
+    metadata_source: &Arc<dyn MetadataSource>,
+    db: &dyn Database,
     metrics: &SwapMetrics,
 ) -> Result<()> {
     // Decrement pending swaps when this function exits
@@ -65,73 +196,99 @@ pub async fn process_swap(
             nested_instructions,
             &mint_details,
         );
-    let transfers = inner_transfers
-        .into_iter()
-        .filter(|d| is_valid_vault_transfer(d, vaults, fee_adas))
-        .collect::<Vec<_>>();
 
-    if transfers.iter().all(|d| d.ui_amount < 0.1) {
-        debug!("skipping tiny diffs");
-        metrics.increment_skipped_tiny_swaps();
-        return Ok(());
+    let legs = swap_legs(pools, fee_adas, &inner_transfers);
+    let multi_hop = pools_touched(transaction_metadata, &legs) > 1;
+    if multi_hop {
+        metrics.increment_multi_hop_swap();
     }
 
-    if transfers.iter().any(|d| d.ui_amount == 0.0) {
-        debug!("skipping zero diffs (arbitrage likely)");
-        metrics.increment_skipped_zero_swaps();
-        return Ok(());
-    }
+    let quote_prices = QUOTE_REGISTRY.resolve(get_sol_price().await);
+    let vault_balances = vault_balances_from_tx_metadata(transaction_metadata);
 
-    let sol_price = get_sol_price().await;
+    let mut result = Ok(());
+    for leg in legs {
+        let transfers = leg.transfers;
 
-    if transfers.len() > 3 || transfers.len() < 2 {
-        debug!(
-            "https://solscan.io/tx/{} skipping swap with unexpected number of tokens: {}",
-            transaction_metadata.signature, transfers.len()
-        );
-        metrics.increment_skipped_unexpected_number_of_tokens();
-        return Ok(());
+        if transfers.iter().all(|d| d.ui_amount < 0.1) {
+            debug!("skipping tiny diffs");
+            metrics.increment_skipped_tiny_swaps();
+            continue;
+        }
+
+        if transfers.iter().any(|d| d.ui_amount == 0.0) {
+            debug!("skipping zero diffs (arbitrage likely)");
+            metrics.increment_skipped_zero_swaps();
+            continue;
+        }
+
+        if transfers.len() > 3 || transfers.len() < 2 {
+            debug!(
+                "https://solscan.io/tx/{} skipping swap with unexpected number of tokens: {}",
+                transaction_metadata.signature, transfers.len()
+            );
+            metrics.increment_skipped_unexpected_number_of_tokens();
+            continue;
+        }
+
+        // the remaining legs are still processed if one of them fails
+        if let Err(e) = process_two_token_swap(
+            &leg.pool,
+            dex,
+            &transfers,
+            &vault_balances,
+            transaction_metadata,
+            message_queue,
+            kv_store,
+            metadata_source,
+            db,
+            metrics,
+            &quote_prices,
+            multi_hop,
+        )
+        .await
+        {
+            result = Err(e).context("failed to process two token swap");
+        }
     }
 
-    process_two_token_swap(
-        vaults,
-        &transfers,
-        transaction_metadata,
-        message_queue,
-        kv_store,
-        db,
-        metrics,
-        sol_price,
-        false,
-    )
-    .await
-    .context("failed to process two token swap")
+    result
 }
 
 // Helper function to process a single two-token swap
 #[allow(clippy::too_many_arguments)]
 async fn process_two_token_swap(
-    vaults: &HashSet<String>,
+    pool: &Pool,
+    dex: Dex,
     transfers: &[TokenTransferDetails],
+    vault_balances: &HashMap<String, VaultBalance>,
     transaction_metadata: &TransactionMetadata,
-    message_queue: &RedisMessageQueue,
-    kv_store: &Arc<RedisKVStore>,
-    db: &Arc<ClickhouseDb>,
+    message_queue: &dyn MessageQueue,
+    kv_store: &Arc<dyn KVStore>,
+    metadata_source: &Arc<dyn MetadataSource>,
+    db: &dyn Database,
     metrics: &SwapMetrics,
-    sol_price: f64,
+    quote_prices: &QuotePrices,
     multi_hop: bool,
 ) -> Result<()> {
-    let DiffsResult {
-        price,
-        swap_amount,
-        coin_mint,
-        is_buy,
-    } = match process_token_transfers(vaults, transfers, sol_price) {
+    let vaults = &pool.vaults;
+    let transfer_fees =
+        fetch_transfer_fees(vaults, transfers, kv_store, metadata_source).await;
+    let transfers = if transfer_fees.is_empty() {
+        transfers.to_vec()
+    } else {
+        let epoch = get_epoch(transaction_metadata.slot)
+            .await
+            .context("failed to get the epoch of the swap")?;
+        apply_transfer_fees(vaults, transfers, &transfer_fees, epoch)
+    };
+
+    let swap = match process_token_transfers(vaults, &transfers, quote_prices) {
         Ok(result) => result,
         Err(e) => {
             match e {
-                DiffsError::NonWsolsSwap => {
-                    metrics.increment_skipped_non_wsol();
+                DiffsError::NoQuoteAsset => {
+                    metrics.increment_skipped_no_quote();
                 }
                 DiffsError::ExpectedExactlyTwoTokenBalanceDiffs => {
                     metrics.increment_skipped_unexpected_number_of_tokens();
@@ -141,46 +298,276 @@ async fn process_two_token_swap(
         }
     };
 
-    // Get metadata and emit price update
-    let token_metadata = match get_token_metadata(kv_store, &coin_mint).await {
-        Ok(Some(metadata)) => metadata,
-        Ok(None) => {
-            debug!(
-                "https://solscan.io/tx/{} failed to get token metadata",
-                transaction_metadata.signature
-            );
-            metrics.increment_skipped_no_metadata();
-            return Ok(());
-        }
-        Err(e) => {
-            warn!(
-                "https://solscan.io/tx/{} failed to get token metadata: {}",
-                transaction_metadata.signature, e
-            );
-            metrics.increment_skipped_no_metadata();
-            return Ok(());
+    // untaken pnl of Raydium AMM v4 pools sits in the vaults, recorded by the
+    // account processor
+    let adjustments = match dex {
+        Dex::RaydiumAmmV4 => kv_store
+            .get_vault_adjustments(&pool.address)
+            .await
+            .unwrap_or_default()
+            .unwrap_or_default(),
+        _ => HashMap::new(),
+    };
+    let pool_state = pool_reserves(
+        pool,
+        &swap.coin_mint,
+        &swap.quote_mint,
+        vault_balances,
+        &adjustments,
+    )
+    .map(|(base_reserve, quote_reserve)| PoolState {
+        pool: pool.address.clone(),
+        dex: dex.as_str().to_string(),
+        base_mint: swap.coin_mint.clone(),
+        quote_mint: swap.quote_mint.clone(),
+        base_reserve,
+        quote_reserve,
+        liquidity_usd: liquidity_usd(
+            base_reserve,
+            swap.price,
+            quote_reserve,
+            quote_prices.price(&swap.quote_mint).unwrap_or_default(),
+        ),
+        timestamp: Utc::now().timestamp() as u64,
+        slot: transaction_metadata.slot,
+        signature: transaction_metadata.signature.to_string(),
+    });
+
+    publish_swap(
+        swap,
+        false,
+        multi_hop,
+        transaction_metadata,
+        message_queue,
+        kv_store,
+        metadata_source,
+        db,
+        metrics,
+    )
+    .await?;
+
+    if let Some(pool_state) = pool_state {
+        publish_pool_state(pool_state, message_queue, kv_store, db).await;
+    }
+
+    Ok(())
+}
+
+/// Transfer fee schedules of the Token-2022 mints deposited into the vaults
+async fn fetch_transfer_fees(
+    vaults: &HashSet<String>,
+    transfers: &[TokenTransferDetails],
+    kv_store: &Arc<dyn KVStore>,
+    metadata_source: &Arc<dyn MetadataSource>,
+) -> HashMap<String, TransferFeeSchedule> {
+    let mut transfer_fees = HashMap::new();
+    for transfer in transfers.iter().filter(|t| {
+        t.program_id == TOKEN_2022_PROGRAM_ID_STR
+            && vaults.contains(&t.destination)
+    }) {
+        match get_token_metadata(kv_store, metadata_source, &transfer.mint)
+            .await
+        {
+            Ok(Some(metadata)) => {
+                if let Some(schedule) = metadata.spl.transfer_fee {
+                    transfer_fees.insert(transfer.mint.clone(), schedule);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!(
+                    mint = transfer.mint,
+                    error = e.to_string(),
+                    "failed to get transfer fee"
+                );
+            }
         }
+    }
+    transfer_fees
+}
+
+/// Processes a trade on the pump.fun bonding curve, always priced against SOL
+#[allow(clippy::too_many_arguments)]
+pub async fn process_pump_fun_trade(
+    trade: &TradeEvent,
+    transaction_metadata: &TransactionMetadata,
+    message_queue: &dyn MessageQueue,
+    kv_store: &Arc<dyn KVStore>,
+    metadata_source: &Arc<dyn MetadataSource>,
+    db: &dyn Database,
+    metrics: &SwapMetrics,
+) -> Result<()> {
+    // Decrement pending swaps when this function exits
+    let _pending_guard = PendingSwapGuard(metrics);
+
+    let Some(price_in_sol) =
+        trade.price_in_sol().filter(|_| trade.sol_amount > 0)
+    else {
+        debug!("skipping zero pump.fun trade");
+        metrics.increment_skipped_zero_swaps();
+        return Ok(());
     };
 
-    // Calculate market cap if we have the metadata
-    let market_cap = {
-        let supply = token_metadata.spl.supply as f64;
-        let adjusted_supply =
-            supply / (10_f64.powi(token_metadata.spl.decimals as i32));
-        price * adjusted_supply
+    if trade.sol_amount_ui() < 0.1 && trade.token_amount_ui() < 0.1 {
+        debug!("skipping tiny diffs");
+        metrics.increment_skipped_tiny_swaps();
+        return Ok(());
+    }
+
+    let multi_hop =
+        count_dex_swaps(&program_invocations(transaction_metadata)) > 1;
+    if multi_hop {
+        metrics.increment_multi_hop_swap();
+    }
+
+    let sol_price = get_sol_price().await;
+    let swap = DiffsResult {
+        price: price_in_sol * sol_price,
+        swap_amount: trade.sol_amount_ui() * sol_price,
+        coin_mint: trade.mint.to_string(),
+        quote_mint: WSOL_MINT_KEY_STR.to_string(),
+        is_buy: trade.is_buy,
     };
 
-    let is_pump = token_metadata
-        .mpl
-        .ipfs_metadata
-        .as_ref()
-        .and_then(|metadata| metadata.get("createdOn"))
-        .is_some_and(|value| {
-            value.as_str().is_some_and(|s| s.contains("pump.fun"))
-        });
+    let base_reserve = trade.real_token_reserves_ui();
+    let quote_reserve = trade.real_sol_reserves_ui();
+    let pool_state = PoolState {
+        pool: bonding_curve_address(&trade.mint).to_string(),
+        dex: Dex::PumpFun.as_str().to_string(),
+        base_mint: swap.coin_mint.clone(),
+        quote_mint: swap.quote_mint.clone(),
+        base_reserve,
+        quote_reserve,
+        liquidity_usd: liquidity_usd(
+            base_reserve,
+            swap.price,
+            quote_reserve,
+            sol_price,
+        ),
+        timestamp: Utc::now().timestamp() as u64,
+        slot: transaction_metadata.slot,
+        signature: transaction_metadata.signature.to_string(),
+    };
+
+    publish_swap(
+        swap,
+        true,
+        multi_hop,
+        transaction_metadata,
+        message_queue,
+        kv_store,
+        metadata_source,
+        db,
+        metrics,
+    )
+    .await?;
+
+    publish_pool_state(pool_state, message_queue, kv_store, db).await;
+
+    Ok(())
+}
+
+/// Emits the state of the pool to the database, the message queue and the kv
+/// store; failures are only logged, the price update was already emitted
+async fn publish_pool_state(
+    pool_state: PoolState,
+    message_queue: &dyn MessageQueue,
+    kv_store: &Arc<dyn KVStore>,
+    db: &dyn Database,
+) {
+    let (db_result, mq_result, kv_result) = tokio::join!(
+        db.insert_pool_state(&pool_state),
+        message_queue.publish_pool_update(pool_state.clone()),
+        kv_store.insert_pool_state(&pool_state),
+    );
+
+    if let Err(e) = db_result {
+        warn!(pool = pool_state.pool, "failed to insert pool state: {}", e);
+    }
+    if let Err(e) = mq_result {
+        warn!(
+            pool = pool_state.pool,
+            "failed to publish pool state: {}", e
+        );
+    }
+    if let Err(e) = kv_result {
+        warn!(pool = pool_state.pool, "failed to store pool state: {}", e);
+    }
+}
+
+/// Emits the price update of the swap to the database, the message queue and
+/// the kv store, `pump` marks swaps that are known to be on pump.fun
+#[allow(clippy::too_many_arguments)]
+async fn publish_swap(
+    swap: DiffsResult,
+    pump: bool,
+    multi_hop: bool,
+    transaction_metadata: &TransactionMetadata,
+    message_queue: &dyn MessageQueue,
+    kv_store: &Arc<dyn KVStore>,
+    metadata_source: &Arc<dyn MetadataSource>,
+    db: &dyn Database,
+    metrics: &SwapMetrics,
+) -> Result<()> {
+    let DiffsResult {
+        price,
+        swap_amount,
+        coin_mint,
+        quote_mint,
+        is_buy,
+    } = swap;
 
+    // swaps are emitted even if the metadata cannot be fetched, the later
+    // ones get it once a fetch succeeds
+    let token_metadata =
+        match get_token_metadata(kv_store, metadata_source, &coin_mint).await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!(
+                    "https://solscan.io/tx/{} failed to get token metadata: {}",
+                    transaction_metadata.signature, e
+                );
+                None
+            }
+        };
+    if token_metadata.is_none() {
+        metrics.increment_missing_metadata();
+    }
+
+    // without the metadata the name and the supply are carried over from the
+    // last price of the token, if it had them
+    let previous = match &token_metadata {
+        Some(_) => None,
+        None => kv_store.get_price(&coin_mint).await.ok().flatten(),
+    }
+    .filter(|previous| previous.has_metadata && previous.price > 0.0);
+    let (name, market_cap) = match (&token_metadata, &previous) {
+        (Some(metadata), _) => {
+            let supply = metadata.spl.supply as f64;
+            let adjusted_supply =
+                supply / (10_f64.powi(metadata.spl.decimals as i32));
+            (metadata.mpl.name.clone(), price * adjusted_supply)
+        }
+        (None, Some(previous)) => (
+            previous.name.clone(),
+            previous.market_cap / previous.price * price,
+        ),
+        (None, None) => (String::new(), 0.0),
+    };
+
+    let is_pump = pump
+        || token_metadata
+            .as_ref()
+            .and_then(|metadata| metadata.mpl.ipfs_metadata.as_ref())
+            .and_then(|metadata| metadata.get("createdOn"))
+            .is_some_and(|value| {
+                value.as_str().is_some_and(|s| s.contains("pump.fun"))
+            })
+        || previous.as_ref().is_some_and(|previous| previous.is_pump);
+
+    let has_metadata = token_metadata.is_some() || previous.is_some();
     let price_update = PriceUpdate {
-        name: token_metadata.mpl.name,
+        name,
         pubkey: coin_mint,
         price,
         market_cap,
@@ -192,6 +579,8 @@ async fn process_two_token_swap(
         multi_hop,
         is_buy,
         is_pump,
+        quote_mint,
+        has_metadata,
     };
 
     metrics.set_latest_update_slot(transaction_metadata.slot);
@@ -209,7 +598,14 @@ async fn process_two_token_swap(
     // Run all three database operations in parallel
     let db_future = db.insert_price(&price_update);
     let mq_future = message_queue.publish_price_update(price_update.clone());
-    let kv_future = kv_store.insert_price(&price_update);
+    // the latest price is only stored with a name and a market cap, the
+    // readers would take the empty ones for real
+    let kv_future = async {
+        match price_update.has_metadata {
+            true => kv_store.insert_price(&price_update).await,
+            false => Ok(()),
+        }
+    };
 
     let (db_result, mq_result, kv_result) =
         tokio::join!(db_future, mq_future, kv_future);
@@ -227,7 +623,7 @@ async fn process_two_token_swap(
         Ok(_) => metrics.increment_message_send_success(),
         Err(e) => {
             metrics.increment_message_send_failure();
-            return Err(e.into());
+            return Err(e);
         }
     }
 
@@ -254,6 +650,8 @@ impl Drop for PendingSwapGuard<'_> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::USDC_MINT_KEY_STR;
+    use crate::quote::QuoteRegistry;
     use crate::util::{make_rpc_client, round_to_decimals};
     use carbon_core::{
         datasource::TransactionUpdate,
@@ -267,6 +665,10 @@ mod tests {
     use std::str::FromStr;
     use tracing::error;
 
+    fn quote_prices(sol_price: f64) -> QuotePrices {
+        QuoteRegistry::default().resolve(sol_price)
+    }
+
     // are all examples of transactions where both raydium and whirlpool or meteora are used simultaneously
     // https://solscan.io/tx/31pB39KowUTdDSjXhzCYi7QxVSWSM4ZijaSWAkCduWUUR6GuGrWwVBbcXLLdJnVLrWbQaV7YFL2SigBXRatGfnji
     // IQ-SOL
@@ -314,7 +716,8 @@ mod tests {
             price,
             swap_amount,
             ..
-        } = process_token_transfers(&vaults, &diffs, 201.36).unwrap();
+        } = process_token_transfers(&vaults, &diffs, &quote_prices(201.36))
+            .unwrap();
         let rounded_price = round_to_decimals(price, 4);
         assert!(!is_buy, "is_buy: {}", is_buy);
         assert!(rounded_price == 0.0062, "price: {}", rounded_price);
@@ -371,7 +774,8 @@ mod tests {
             swap_amount,
             is_buy,
             ..
-        } = process_token_transfers(&vaults, &diffs, 201.36).unwrap();
+        } = process_token_transfers(&vaults, &diffs, &quote_prices(201.36))
+            .unwrap();
         let rounded_price = round_to_decimals(price, 5);
         assert!(rounded_price == 0.06987, "price: {}", rounded_price);
         assert!(
@@ -382,6 +786,277 @@ mod tests {
         assert!(is_buy, "is_buy: {}", is_buy);
     }
 
+    // stablecoin quoted pair, the coin is priced against USDC
+    #[tokio::test]
+    async fn test_token_for_usdc() {
+        let vaults = HashSet::from([
+            "7ZKaY4nQMZPEGXjJBQKZvgnWddvKpVuQ5jL1AXgDUEZd".to_string(),
+            "H9Kcqpyj5yRTd3hA2dz6FyHYZLGWcSqYyWMHqkYjuXbC".to_string(),
+        ]);
+        let transfers = vec![
+            TokenTransferDetails {
+                program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+                    .to_string(),
+                mint: USDC_MINT_KEY_STR.to_string(),
+                source: "3oV3EFEp6GUTt8cn3swj1oQXhmeuRyKv9cEzpSVZga5K"
+                    .to_string(),
+                destination: "7ZKaY4nQMZPEGXjJBQKZvgnWddvKpVuQ5jL1AXgDUEZd"
+                    .to_string(),
+                authority: "6LXutJvKUw8Q5ue2gCgKHQdAN4suWW8awzFVC6XCguFx"
+                    .to_string(),
+                decimals: 6,
+                amount: 250000000,
+                ui_amount: 250.0,
+            },
+            TokenTransferDetails {
+                program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+                    .to_string(),
+                mint: "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN".to_string(),
+                source: "H9Kcqpyj5yRTd3hA2dz6FyHYZLGWcSqYyWMHqkYjuXbC"
+                    .to_string(),
+                destination: "BuqEDKUwyAotZuK37V4JYEykZVKY8qo1zKbpfU9gkJMo"
+                    .to_string(),
+                authority: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+                decimals: 6,
+                amount: 500000000,
+                ui_amount: 500.0,
+            },
+        ];
+
+        let DiffsResult {
+            price,
+            swap_amount,
+            coin_mint,
+            quote_mint,
+            is_buy,
+        } = process_token_transfers(&vaults, &transfers, &quote_prices(150.0))
+            .unwrap();
+        assert_eq!(price, 0.5);
+        assert_eq!(swap_amount, 250.0);
+        assert_eq!(coin_mint, "JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN");
+        assert_eq!(quote_mint, USDC_MINT_KEY_STR);
+        assert!(is_buy, "is_buy: {}", is_buy);
+    }
+
+    #[tokio::test]
+    async fn test_no_quote_asset() {
+        let transfer = |mint: &str| TokenTransferDetails {
+            program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+                .to_string(),
+            mint: mint.to_string(),
+            source: "3oV3EFEp6GUTt8cn3swj1oQXhmeuRyKv9cEzpSVZga5K".to_string(),
+            destination: "BuqEDKUwyAotZuK37V4JYEykZVKY8qo1zKbpfU9gkJMo"
+                .to_string(),
+            authority: "6LXutJvKUw8Q5ue2gCgKHQdAN4suWW8awzFVC6XCguFx"
+                .to_string(),
+            decimals: 6,
+            amount: 1000000,
+            ui_amount: 1.0,
+        };
+        let transfers = vec![
+            transfer("JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN"),
+            transfer("AsyfR3e5JcPqWot4H5MMhQUm7DZ4zwQrcp2zbB7vpump"),
+        ];
+
+        assert!(matches!(
+            process_token_transfers(
+                &HashSet::new(),
+                &transfers,
+                &quote_prices(150.0)
+            ),
+            Err(DiffsError::NoQuoteAsset)
+        ));
+    }
+
+    #[test]
+    fn test_token_2022_transfer_fee() {
+        use crate::metadata::TransferFeeRate;
+
+        let token_mint = "2zMMhcVQEXDtdE6vsFS7S7D5oUodfJHE8vd1gnBouauv";
+        let vaults = HashSet::from([
+            "7ZKaY4nQMZPEGXjJBQKZvgnWddvKpVuQ5jL1AXgDUEZd".to_string(),
+            "BuqEDKUwyAotZuK37V4JYEykZVKY8qo1zKbpfU9gkJMo".to_string(),
+        ]);
+        // sell of 1000 tokens with a 1% transfer fee for 9.9 USDC
+        let transfers = vec![
+            TokenTransferDetails {
+                program_id: TOKEN_2022_PROGRAM_ID_STR.to_string(),
+                mint: token_mint.to_string(),
+                source: "3oV3EFEp6GUTt8cn3swj1oQXhmeuRyKv9cEzpSVZga5K"
+                    .to_string(),
+                destination: "BuqEDKUwyAotZuK37V4JYEykZVKY8qo1zKbpfU9gkJMo"
+                    .to_string(),
+                authority: "6LXutJvKUw8Q5ue2gCgKHQdAN4suWW8awzFVC6XCguFx"
+                    .to_string(),
+                decimals: 6,
+                amount: 1_000_000_000,
+                ui_amount: 1000.0,
+            },
+            TokenTransferDetails {
+                program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+                    .to_string(),
+                mint: USDC_MINT_KEY_STR.to_string(),
+                source: "7ZKaY4nQMZPEGXjJBQKZvgnWddvKpVuQ5jL1AXgDUEZd"
+                    .to_string(),
+                destination: "H9Kcqpyj5yRTd3hA2dz6FyHYZLGWcSqYyWMHqkYjuXbC"
+                    .to_string(),
+                authority: "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1"
+                    .to_string(),
+                decimals: 6,
+                amount: 9_900_000,
+                ui_amount: 9.9,
+            },
+        ];
+        let transfer_fees = HashMap::from([(
+            token_mint.to_string(),
+            TransferFeeSchedule {
+                older: TransferFeeRate::default(),
+                newer: TransferFeeRate {
+                    basis_points: 100,
+                    maximum_fee: u64::MAX,
+                },
+                newer_epoch: 700,
+            },
+        )]);
+
+        let net = apply_transfer_fees(&vaults, &transfers, &transfer_fees, 750);
+        assert_eq!(net[0].amount, 990_000_000);
+        assert_eq!(net[0].ui_amount, 990.0);
+        assert_eq!(net[1], transfers[1]);
+
+        let DiffsResult { price, is_buy, .. } =
+            process_token_transfers(&vaults, &net, &quote_prices(150.0))
+                .unwrap();
+        assert_eq!(round_to_decimals(price, 4), 0.01);
+        assert!(!is_buy);
+
+        // the fee is not active yet before its epoch
+        let net = apply_transfer_fees(&vaults, &transfers, &transfer_fees, 699);
+        assert_eq!(net, transfers);
+    }
+
+    #[derive(serde::Deserialize)]
+    struct SwapFixture {
+        pools: Vec<Pool>,
+        transfers: Vec<TokenTransferDetails>,
+    }
+
+    fn load_fixture(json: &str) -> SwapFixture {
+        serde_json::from_str(json).expect("failed to parse swap fixture")
+    }
+
+    fn price_legs(fixture: &SwapFixture, sol_price: f64) -> Vec<DiffsResult> {
+        swap_legs(&fixture.pools, None, &fixture.transfers)
+            .iter()
+            .map(|leg| {
+                process_token_transfers(
+                    &leg.pool.vaults,
+                    &leg.transfers,
+                    &quote_prices(sol_price),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_whirlpool_two_hop_legs() {
+        let fixture = load_fixture(include_str!(
+            "../fixtures/whirlpool_two_hop_swap.json"
+        ));
+
+        let legs = swap_legs(&fixture.pools, None, &fixture.transfers);
+        assert_eq!(legs.len(), 2);
+        // the fee transfer does not touch any of the vaults
+        assert!(legs.iter().all(|leg| leg.transfers.len() == 2));
+
+        let results = price_legs(&fixture, 200.0);
+        assert_eq!(
+            results[0].coin_mint,
+            "Ckz3bKgNxGRpDE5rHPdBFqS1YFk1pD8rB6Z7cXqhpump"
+        );
+        assert_eq!(results[0].quote_mint, USDC_MINT_KEY_STR);
+        assert_eq!(round_to_decimals(results[0].price, 4), 0.05);
+        assert_eq!(results[0].swap_amount, 50.0);
+        assert!(!results[0].is_buy);
+
+        // the SOL leg prices SOL in USDC
+        assert_eq!(results[1].coin_mint, WSOL_MINT_KEY_STR);
+        assert_eq!(results[1].quote_mint, USDC_MINT_KEY_STR);
+        assert_eq!(round_to_decimals(results[1].price, 4), 200.0);
+        assert_eq!(results[1].swap_amount, 50.0);
+        assert!(results[1].is_buy);
+    }
+
+    #[test]
+    fn test_direct_route_legs() {
+        let fixture =
+            load_fixture(include_str!("../fixtures/direct_route_swap.json"));
+
+        // the intermediate transfer goes from one pool into the other
+        let legs = swap_legs(&fixture.pools, None, &fixture.transfers);
+        assert_eq!(legs[0].transfers[1], legs[1].transfers[0]);
+
+        let results = price_legs(&fixture, 200.0);
+        assert_eq!(results[0].coin_mint, WSOL_MINT_KEY_STR);
+        assert_eq!(results[0].quote_mint, USDC_MINT_KEY_STR);
+        assert_eq!(round_to_decimals(results[0].price, 4), 150.0);
+        assert_eq!(results[0].swap_amount, 300.0);
+        assert!(!results[0].is_buy);
+
+        assert_eq!(
+            results[1].coin_mint,
+            "Ckz3bKgNxGRpDE5rHPdBFqS1YFk1pD8rB6Z7cXqhpump"
+        );
+        assert_eq!(round_to_decimals(results[1].price, 4), 0.05);
+        assert_eq!(results[1].swap_amount, 300.0);
+        assert!(results[1].is_buy);
+    }
+
+    #[test]
+    fn test_count_dex_swaps() {
+        let jupiter =
+            Pubkey::from_str("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4")
+                .unwrap();
+        let token = crate::constants::TOKEN_PROGRAM_ID;
+
+        // a single pool through an aggregator is not a route
+        assert_eq!(
+            count_dex_swaps(&[
+                (jupiter, 1),
+                (RAYDIUM_AMM_V4_PROGRAM_ID, 2),
+                (token, 3),
+                (token, 3),
+            ]),
+            1
+        );
+
+        // the event CPI of pump.fun is not another swap
+        assert_eq!(
+            count_dex_swaps(&[
+                (PUMP_FUN_PROGRAM_ID, 1),
+                (token, 2),
+                (PUMP_FUN_PROGRAM_ID, 2),
+            ]),
+            1
+        );
+
+        // two pools of a route
+        assert_eq!(
+            count_dex_swaps(&[
+                (jupiter, 1),
+                (RAYDIUM_AMM_V4_PROGRAM_ID, 2),
+                (token, 3),
+                (token, 3),
+                (WHIRLPOOLS_PROGRAM_ID, 2),
+                (token, 3),
+                (token, 3),
+            ]),
+            2
+        );
+    }
+
     async fn get_transaction(
         signature: &str,
         outer_index: usize,
@@ -486,7 +1161,8 @@ mod tests {
             price,
             swap_amount,
             ..
-        } = process_token_transfers(&vaults, &transfers, 203.67).unwrap();
+        } = process_token_transfers(&vaults, &transfers, &quote_prices(203.67))
+            .unwrap();
         let rounded_price = round_to_decimals(price, 5);
         assert!(rounded_price == 0.00035, "price: {}", rounded_price);
         let rounded_swap_amount = round_to_decimals(swap_amount, 4);
@@ -522,7 +1198,12 @@ mod tests {
                 price,
                 swap_amount,
                 ..
-            } = process_token_transfers(&vaults, &transfers, 203.67).unwrap();
+            } = process_token_transfers(
+                &vaults,
+                &transfers,
+                &quote_prices(203.67),
+            )
+            .unwrap();
             let rounded_price = round_to_decimals(price, 5);
             assert!(rounded_price == 1.36929, "price: {}", rounded_price);
             let rounded_swap_amount = round_to_decimals(swap_amount, 4);
@@ -554,7 +1235,12 @@ mod tests {
                 price,
                 swap_amount,
                 ..
-            } = process_token_transfers(&vaults, &transfers, 203.67).unwrap();
+            } = process_token_transfers(
+                &vaults,
+                &transfers,
+                &quote_prices(203.67),
+            )
+            .unwrap();
             let rounded_price = round_to_decimals(price, 5);
             assert!(rounded_price == 1.36933, "price: {}", rounded_price);
             let rounded_swap_amount = round_to_decimals(swap_amount, 4);
@@ -591,7 +1277,12 @@ mod tests {
                 price,
                 swap_amount,
                 ..
-            } = process_token_transfers(&vaults, &transfers, 203.67).unwrap();
+            } = process_token_transfers(
+                &vaults,
+                &transfers,
+                &quote_prices(203.67),
+            )
+            .unwrap();
             let rounded_price = round_to_decimals(price, 5);
             assert!(rounded_price == 0.55458, "price: {}", rounded_price);
             let rounded_swap_amount = round_to_decimals(swap_amount, 4);
@@ -623,7 +1314,12 @@ mod tests {
                 price,
                 swap_amount,
                 ..
-            } = process_token_transfers(&vaults, &transfers, 203.67).unwrap();
+            } = process_token_transfers(
+                &vaults,
+                &transfers,
+                &quote_prices(203.67),
+            )
+            .unwrap();
             let rounded_price = round_to_decimals(price, 5);
             assert!(rounded_price == 0.55378, "price: {}", rounded_price);
             let rounded_swap_amount = round_to_decimals(swap_amount, 4);
@@ -656,7 +1352,12 @@ mod tests {
                 price,
                 swap_amount,
                 ..
-            } = process_token_transfers(&vaults, &transfers, 203.67).unwrap();
+            } = process_token_transfers(
+                &vaults,
+                &transfers,
+                &quote_prices(203.67),
+            )
+            .unwrap();
             let rounded_price = round_to_decimals(price, 5);
             assert!(rounded_price == 0.07765, "price: {}", rounded_price);
             let rounded_swap_amount = round_to_decimals(swap_amount, 4);
@@ -688,7 +1389,12 @@ mod tests {
                 price,
                 swap_amount,
                 ..
-            } = process_token_transfers(&vaults, &transfers, 203.67).unwrap();
+            } = process_token_transfers(
+                &vaults,
+                &transfers,
+                &quote_prices(203.67),
+            )
+            .unwrap();
             let rounded_price = round_to_decimals(price, 5);
             assert!(rounded_price == 0.07754, "price: {}", rounded_price);
             let rounded_swap_amount = round_to_decimals(swap_amount, 4);
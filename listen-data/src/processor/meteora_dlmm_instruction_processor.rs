@@ -1,13 +1,14 @@
 use crate::handler::{token_swap_handler::Dex, TokenSwapHandler};
+use crate::pool::Pool;
 use carbon_core::{
     deserialize::ArrangeAccounts, error::CarbonResult,
     instruction::InstructionProcessorInputType, metrics::MetricsCollection,
     processor::Processor,
 };
 use carbon_meteora_dlmm_decoder::instructions::{
-    swap::Swap, MeteoraDlmmInstruction,
+    initialize_lb_pair::InitializeLbPair, swap::Swap, MeteoraDlmmInstruction,
 };
-use std::{collections::HashSet, sync::Arc};
+use std::sync::Arc;
 
 pub struct MeteoraDlmmInstructionProcessor {
     swap_handler: Arc<TokenSwapHandler>,
@@ -30,21 +31,38 @@ impl Processor for MeteoraDlmmInstructionProcessor {
     ) -> CarbonResult<()> {
         self.swap_handler.metrics.increment_meteora_dlmm_swaps();
         let (meta, instruction, nested_instructions) = data;
-        if let MeteoraDlmmInstruction::Swap(_) = &instruction.data {
-            let accounts = Swap::arrange_accounts(&instruction.accounts);
-            if let Some(accounts) = accounts {
-                let vaults: HashSet<String> = HashSet::from([
-                    accounts.reserve_x.to_string(),
-                    accounts.reserve_y.to_string(),
-                ]);
-                self.swap_handler.spawn_swap_processor(
-                    &vaults,
-                    None,
-                    &meta,
-                    &nested_instructions,
-                    Dex::MeteoraDlmm,
-                );
+        match &instruction.data {
+            MeteoraDlmmInstruction::Swap(_) => {
+                let accounts = Swap::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    let pool = Pool::new(
+                        accounts.lb_pair,
+                        [accounts.reserve_x, accounts.reserve_y],
+                    );
+                    self.swap_handler.spawn_swap_processor(
+                        &pool,
+                        None,
+                        &meta,
+                        &nested_instructions,
+                        Dex::MeteoraDlmm,
+                    );
+                }
             }
+            MeteoraDlmmInstruction::InitializeLbPair(_) => {
+                let accounts =
+                    InitializeLbPair::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    self.swap_handler.spawn_pool_created(
+                        &accounts.lb_pair,
+                        Dex::MeteoraDlmm,
+                        &accounts.token_mint_x,
+                        &accounts.token_mint_y,
+                        &accounts.funder,
+                        &meta,
+                    );
+                }
+            }
+            _ => {}
         }
         Ok(())
     }
@@ -53,6 +71,7 @@ impl Processor for MeteoraDlmmInstructionProcessor {
 #[cfg(test)]
 mod meteora_dlmm_tests {
     use super::*;
+    use crate::replay::golden::assert_golden;
     use crate::{
         diffs::TokenTransferDetails,
         handler::token_swap_handler::test_swaps::{
@@ -165,4 +184,17 @@ mod meteora_dlmm_tests {
             .await
             .expect("Failed to process instruction");
     }
+
+    #[tokio::test]
+    async fn test_golden_price_updates() {
+        assert_golden(
+            "meteora_dlmm",
+            &[
+                "3m4LERWUekW7im8rgu8QgpSJA8a9yEYL3gDvorbd5YpkXarrL3PGoVmyFyQzd1Pw9oZiQy2LPUjaG8Xr4p433kwn",
+            ],
+            MeteoraDlmmDecoder,
+            MeteoraDlmmInstructionProcessor::new,
+        )
+        .await;
+    }
 }
@@ -1,6 +1,18 @@
+use carbon_core::pipeline::PipelineBuilder;
+use carbon_meteora_dlmm_decoder::MeteoraDlmmDecoder;
+use carbon_orca_whirlpool_decoder::OrcaWhirlpoolDecoder;
+use carbon_pump_swap_decoder::PumpSwapDecoder;
+use carbon_raydium_amm_v4_decoder::RaydiumAmmV4Decoder;
+use carbon_raydium_clmm_decoder::RaydiumClmmDecoder;
+use carbon_raydium_cpmm_decoder::RaydiumCpmmDecoder;
+use std::sync::Arc;
+
+use crate::{handler::TokenSwapHandler, pump_fun::PumpFunDecoder};
+
 mod meteora_dlmm_instruction_processor;
 mod ocra_whirlpool_instruction_processor;
 mod pump_amm_instruction_processor;
+mod pump_fun_instruction_processor;
 mod raydium_amm_v4_account_processor;
 mod raydium_amm_v4_instruction_processor;
 mod raydium_clmm_instruction_processor;
@@ -10,9 +22,46 @@ mod raydium_cpmm_instruction_processor;
 pub use meteora_dlmm_instruction_processor::MeteoraDlmmInstructionProcessor;
 pub use ocra_whirlpool_instruction_processor::OcraWhirlpoolInstructionProcessor;
 pub use pump_amm_instruction_processor::PumpAmmInstructionProcessor;
+pub use pump_fun_instruction_processor::PumpFunInstructionProcessor;
 pub use raydium_amm_v4_instruction_processor::RaydiumAmmV4InstructionProcessor;
 pub use raydium_clmm_instruction_processor::RaydiumClmmInstructionProcessor;
 pub use raydium_cpmm_instruction_processor::RaydiumCpmmInstructionProcessor;
 
 // account processor
 pub use raydium_amm_v4_account_processor::RaydiumAmmV4AccountProcessor;
+
+/// Registers the swap processors of all the indexed programs on the pipeline
+pub fn with_swap_processors(
+    builder: PipelineBuilder,
+    swap_handler: Arc<TokenSwapHandler>,
+) -> PipelineBuilder {
+    builder
+        .instruction(
+            RaydiumAmmV4Decoder,
+            RaydiumAmmV4InstructionProcessor::new(swap_handler.clone()),
+        )
+        .instruction(
+            RaydiumCpmmDecoder,
+            RaydiumCpmmInstructionProcessor::new(swap_handler.clone()),
+        )
+        .instruction(
+            MeteoraDlmmDecoder,
+            MeteoraDlmmInstructionProcessor::new(swap_handler.clone()),
+        )
+        .instruction(
+            OrcaWhirlpoolDecoder,
+            OcraWhirlpoolInstructionProcessor::new(swap_handler.clone()),
+        )
+        .instruction(
+            RaydiumClmmDecoder,
+            RaydiumClmmInstructionProcessor::new(swap_handler.clone()),
+        )
+        .instruction(
+            PumpSwapDecoder,
+            PumpAmmInstructionProcessor::new(swap_handler.clone()),
+        )
+        .instruction(
+            PumpFunDecoder,
+            PumpFunInstructionProcessor::new(swap_handler),
+        )
+}
@@ -1,13 +1,16 @@
 use crate::handler::{token_swap_handler::Dex, TokenSwapHandler};
+use crate::pool::Pool;
 use carbon_core::{
     deserialize::ArrangeAccounts, error::CarbonResult,
     instruction::InstructionProcessorInputType, metrics::MetricsCollection,
     processor::Processor,
 };
 use carbon_orca_whirlpool_decoder::instructions::{
-    swap::Swap, OrcaWhirlpoolInstruction,
+    initialize_pool::InitializePool, initialize_pool_v2::InitializePoolV2,
+    swap::Swap, two_hop_swap::TwoHopSwap, two_hop_swap_v2::TwoHopSwapV2,
+    OrcaWhirlpoolInstruction,
 };
-use std::{collections::HashSet, sync::Arc};
+use std::sync::Arc;
 
 pub struct OcraWhirlpoolInstructionProcessor {
     swap_handler: Arc<TokenSwapHandler>,
@@ -30,21 +33,110 @@ impl Processor for OcraWhirlpoolInstructionProcessor {
     ) -> CarbonResult<()> {
         self.swap_handler.metrics.increment_whirlpools_swaps();
         let (meta, instruction, nested_instructions) = data;
-        if let OrcaWhirlpoolInstruction::Swap(_) = &instruction.data {
-            let accounts = Swap::arrange_accounts(&instruction.accounts);
-            if let Some(accounts) = accounts {
-                let vaults: HashSet<String> = HashSet::from([
-                    accounts.token_vault_a.to_string(),
-                    accounts.token_vault_b.to_string(),
-                ]);
-                self.swap_handler.spawn_swap_processor(
-                    &vaults,
-                    None,
-                    &meta,
-                    &nested_instructions,
-                    Dex::Whirlpools,
-                );
+        match &instruction.data {
+            OrcaWhirlpoolInstruction::Swap(_) => {
+                let accounts = Swap::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    let pool = Pool::new(
+                        accounts.whirlpool,
+                        [accounts.token_vault_a, accounts.token_vault_b],
+                    );
+                    self.swap_handler.spawn_swap_processor(
+                        &pool,
+                        None,
+                        &meta,
+                        &nested_instructions,
+                        Dex::Whirlpools,
+                    );
+                }
             }
+            OrcaWhirlpoolInstruction::TwoHopSwap(_) => {
+                let accounts =
+                    TwoHopSwap::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    let pools = [
+                        Pool::new(
+                            accounts.whirlpool_one,
+                            [
+                                accounts.token_vault_one_a,
+                                accounts.token_vault_one_b,
+                            ],
+                        ),
+                        Pool::new(
+                            accounts.whirlpool_two,
+                            [
+                                accounts.token_vault_two_a,
+                                accounts.token_vault_two_b,
+                            ],
+                        ),
+                    ];
+                    self.swap_handler.spawn_multi_hop_swap_processor(
+                        &pools,
+                        None,
+                        &meta,
+                        &nested_instructions,
+                        Dex::Whirlpools,
+                    );
+                }
+            }
+            OrcaWhirlpoolInstruction::TwoHopSwapV2(_) => {
+                let accounts =
+                    TwoHopSwapV2::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    let pools = [
+                        Pool::new(
+                            accounts.whirlpool_one,
+                            [
+                                accounts.token_vault_one_input,
+                                accounts.token_vault_one_intermediate,
+                            ],
+                        ),
+                        Pool::new(
+                            accounts.whirlpool_two,
+                            [
+                                accounts.token_vault_two_intermediate,
+                                accounts.token_vault_two_output,
+                            ],
+                        ),
+                    ];
+                    self.swap_handler.spawn_multi_hop_swap_processor(
+                        &pools,
+                        None,
+                        &meta,
+                        &nested_instructions,
+                        Dex::Whirlpools,
+                    );
+                }
+            }
+            OrcaWhirlpoolInstruction::InitializePool(_) => {
+                let accounts =
+                    InitializePool::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    self.swap_handler.spawn_pool_created(
+                        &accounts.whirlpool,
+                        Dex::Whirlpools,
+                        &accounts.token_mint_a,
+                        &accounts.token_mint_b,
+                        &accounts.funder,
+                        &meta,
+                    );
+                }
+            }
+            OrcaWhirlpoolInstruction::InitializePoolV2(_) => {
+                let accounts =
+                    InitializePoolV2::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    self.swap_handler.spawn_pool_created(
+                        &accounts.whirlpool,
+                        Dex::Whirlpools,
+                        &accounts.token_mint_a,
+                        &accounts.token_mint_b,
+                        &accounts.funder,
+                        &meta,
+                    );
+                }
+            }
+            _ => {}
         }
 
         Ok(())
@@ -54,6 +146,7 @@ impl Processor for OcraWhirlpoolInstructionProcessor {
 #[cfg(test)]
 mod orca_whirlpool_tests {
     use super::*;
+    use crate::replay::golden::assert_golden;
     use crate::{
         diffs::TokenTransferDetails,
         handler::token_swap_handler::test_swaps::{
@@ -167,4 +260,17 @@ mod orca_whirlpool_tests {
             .await
             .expect("Failed to process instruction");
     }
+
+    #[tokio::test]
+    async fn test_golden_price_updates() {
+        assert_golden(
+            "orca_whirlpool",
+            &[
+                "3ankeujUXU4EPjcJXFdNrn4nqGVati1KpMntYfTpgGhboxywLVb2oYpG9BStMwGojjvGSfNff4Zar8tPqX9ifJMP",
+            ],
+            OrcaWhirlpoolDecoder,
+            OcraWhirlpoolInstructionProcessor::new,
+        )
+        .await;
+    }
 }
@@ -1,11 +1,12 @@
 use crate::handler::{token_swap_handler::Dex, TokenSwapHandler};
+use crate::pool::Pool;
 use carbon_core::{
     deserialize::ArrangeAccounts, error::CarbonResult,
     instruction::InstructionProcessorInputType, metrics::MetricsCollection,
     processor::Processor,
 };
 use carbon_pump_swap_decoder::instructions::{
-    buy::Buy, sell::Sell, PumpSwapInstruction,
+    buy::Buy, create_pool::CreatePool, sell::Sell, PumpSwapInstruction,
 };
 use std::{collections::HashSet, sync::Arc};
 
@@ -35,16 +36,19 @@ impl Processor for PumpAmmInstructionProcessor {
             PumpSwapInstruction::Buy(_) => {
                 let accounts = Buy::arrange_accounts(&instruction.accounts);
                 if let Some(accounts) = accounts {
-                    let vaults: HashSet<String> = HashSet::from([
-                        accounts.pool_base_token_account.to_string(),
-                        accounts.pool_quote_token_account.to_string(),
-                    ]);
+                    let pool = Pool::new(
+                        accounts.pool,
+                        [
+                            accounts.pool_base_token_account,
+                            accounts.pool_quote_token_account,
+                        ],
+                    );
                     let fee_adas = HashSet::from([accounts
                         .protocol_fee_recipient_token_account
                         .to_string()]);
 
                     self.swap_handler.spawn_swap_processor(
-                        &vaults,
+                        &pool,
                         Some(&fee_adas),
                         &meta,
                         &nested_instructions,
@@ -55,16 +59,19 @@ impl Processor for PumpAmmInstructionProcessor {
             PumpSwapInstruction::Sell(_) => {
                 let accounts = Sell::arrange_accounts(&instruction.accounts);
                 if let Some(accounts) = accounts {
-                    let vaults: HashSet<String> = HashSet::from([
-                        accounts.pool_base_token_account.to_string(),
-                        accounts.pool_quote_token_account.to_string(),
-                    ]);
+                    let pool = Pool::new(
+                        accounts.pool,
+                        [
+                            accounts.pool_base_token_account,
+                            accounts.pool_quote_token_account,
+                        ],
+                    );
                     let fee_adas = HashSet::from([accounts
                         .protocol_fee_recipient_token_account
                         .to_string()]);
 
                     self.swap_handler.spawn_swap_processor(
-                        &vaults,
+                        &pool,
                         Some(&fee_adas),
                         &meta,
                         &nested_instructions,
@@ -72,6 +79,20 @@ impl Processor for PumpAmmInstructionProcessor {
                     );
                 }
             }
+            PumpSwapInstruction::CreatePool(_) => {
+                let accounts =
+                    CreatePool::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    self.swap_handler.spawn_pool_created(
+                        &accounts.pool,
+                        Dex::PumpSwap,
+                        &accounts.base_mint,
+                        &accounts.quote_mint,
+                        &accounts.creator,
+                        &meta,
+                    );
+                }
+            }
             _ => {}
         }
 
@@ -82,6 +103,7 @@ impl Processor for PumpAmmInstructionProcessor {
 #[cfg(test)]
 mod pump_amm_tests {
     use super::*;
+    use crate::replay::golden::assert_golden;
     use crate::{
         diffs::TokenTransferDetails,
         handler::token_swap_handler::test_swaps::{
@@ -214,4 +236,17 @@ mod pump_amm_tests {
             .expect("Failed to process instruction");
         tokio::time::sleep(std::time::Duration::from_secs(10)).await;
     }
+
+    #[tokio::test]
+    async fn test_golden_price_updates() {
+        assert_golden(
+            "pump_amm",
+            &[
+                "3G7iGWpatj5vjPRmsxRsYh3N6B1WkiBX77u8yizPVcGZkqytdT6UYeCfsHan816sRH3jYpG45FRL3GLywud7CpbT",
+            ],
+            PumpSwapDecoder,
+            PumpAmmInstructionProcessor::new,
+        )
+        .await;
+    }
 }
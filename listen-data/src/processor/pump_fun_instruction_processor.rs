@@ -0,0 +1,46 @@
+use crate::{handler::TokenSwapHandler, pump_fun::PumpFunEvent};
+use carbon_core::{
+    error::CarbonResult, instruction::InstructionProcessorInputType,
+    metrics::MetricsCollection, processor::Processor,
+};
+use std::sync::Arc;
+
+/// Launches and trades on the pump.fun bonding curve, before the token
+/// migrates to an AMM
+pub struct PumpFunInstructionProcessor {
+    swap_handler: Arc<TokenSwapHandler>,
+}
+
+impl PumpFunInstructionProcessor {
+    pub fn new(swap_handler: Arc<TokenSwapHandler>) -> Self {
+        Self { swap_handler }
+    }
+}
+
+#[async_trait::async_trait]
+impl Processor for PumpFunInstructionProcessor {
+    type InputType = InstructionProcessorInputType<PumpFunEvent>;
+
+    async fn process(
+        &mut self,
+        data: Self::InputType,
+        _metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        let (meta, instruction, _) = data;
+        match &instruction.data {
+            PumpFunEvent::Create(create) => {
+                self.swap_handler.spawn_token_launched(create, &meta);
+            }
+            PumpFunEvent::Trade(trade) => {
+                self.swap_handler.metrics.increment_pump_fun_trades();
+                self.swap_handler
+                    .spawn_pump_fun_trade_processor(trade, &meta);
+            }
+            PumpFunEvent::Complete(complete) => {
+                self.swap_handler.spawn_pump_fun_migration(complete, &meta);
+            }
+        }
+
+        Ok(())
+    }
+}
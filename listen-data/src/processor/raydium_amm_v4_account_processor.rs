@@ -1,22 +1,21 @@
+use crate::{kv_store::KVStore, pool::VaultAdjustments};
 use carbon_core::{
     account::AccountProcessorInputType, error::CarbonResult,
     metrics::MetricsCollection, processor::Processor,
 };
 use carbon_raydium_amm_v4_decoder::accounts::RaydiumAmmV4Account;
 use std::sync::Arc;
-use tracing::info;
+use tracing::{debug, error};
 
-pub struct RaydiumAmmV4AccountProcessor {}
-
-impl Default for RaydiumAmmV4AccountProcessor {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Records the pnl of the pools that was not taken yet, it sits in the vaults
+/// but is not part of the reserves
+pub struct RaydiumAmmV4AccountProcessor {
+    kv_store: Arc<dyn KVStore>,
 }
 
 impl RaydiumAmmV4AccountProcessor {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(kv_store: Arc<dyn KVStore>) -> Self {
+        Self { kv_store }
     }
 }
 
@@ -29,9 +28,23 @@ impl Processor for RaydiumAmmV4AccountProcessor {
         data: Self::InputType,
         _metrics: Arc<MetricsCollection>,
     ) -> CarbonResult<()> {
-        let (_meta, account) = data;
+        let (meta, account) = data;
         if let RaydiumAmmV4Account::AmmInfo(pool) = &account.data {
-            info!("pool: {:#?}", pool);
+            let adjustments = VaultAdjustments::from([
+                (pool.token_coin.to_string(), pool.out_put.need_take_pnl_coin),
+                (pool.token_pc.to_string(), pool.out_put.need_take_pnl_pc),
+            ]);
+            debug!(pool = meta.pubkey.to_string(), ?adjustments, "amm info");
+            if let Err(e) = self
+                .kv_store
+                .insert_vault_adjustments(
+                    &meta.pubkey.to_string(),
+                    &adjustments,
+                )
+                .await
+            {
+                error!(?e, "failed to store vault adjustments");
+            }
         };
 
         Ok(())
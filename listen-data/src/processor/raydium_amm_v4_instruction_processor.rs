@@ -1,14 +1,15 @@
 use crate::handler::{token_swap_handler::Dex, TokenSwapHandler};
+use crate::pool::Pool;
 use carbon_core::{
     deserialize::ArrangeAccounts, error::CarbonResult,
     instruction::InstructionProcessorInputType, metrics::MetricsCollection,
     processor::Processor,
 };
 use carbon_raydium_amm_v4_decoder::instructions::{
-    swap_base_in::SwapBaseIn, swap_base_out::SwapBaseOut,
-    RaydiumAmmV4Instruction,
+    initialize2::Initialize2, swap_base_in::SwapBaseIn,
+    swap_base_out::SwapBaseOut, RaydiumAmmV4Instruction,
 };
-use std::{collections::HashSet, sync::Arc};
+use std::sync::Arc;
 
 pub struct RaydiumAmmV4InstructionProcessor {
     pub swap_handler: Arc<TokenSwapHandler>,
@@ -36,12 +37,15 @@ impl Processor for RaydiumAmmV4InstructionProcessor {
                 let accounts =
                     SwapBaseIn::arrange_accounts(&instruction.accounts);
                 if let Some(accounts) = accounts {
-                    let vaults: HashSet<String> = HashSet::from([
-                        accounts.pool_coin_token_account.to_string(),
-                        accounts.pool_pc_token_account.to_string(),
-                    ]);
+                    let pool = Pool::new(
+                        accounts.amm,
+                        [
+                            accounts.pool_coin_token_account,
+                            accounts.pool_pc_token_account,
+                        ],
+                    );
                     self.swap_handler.spawn_swap_processor(
-                        &vaults,
+                        &pool,
                         None,
                         &meta,
                         &nested_instructions,
@@ -53,12 +57,15 @@ impl Processor for RaydiumAmmV4InstructionProcessor {
                 let accounts =
                     SwapBaseOut::arrange_accounts(&instruction.accounts);
                 if let Some(accounts) = accounts {
-                    let vaults: HashSet<String> = HashSet::from([
-                        accounts.pool_coin_token_account.to_string(),
-                        accounts.pool_pc_token_account.to_string(),
-                    ]);
+                    let pool = Pool::new(
+                        accounts.amm,
+                        [
+                            accounts.pool_coin_token_account,
+                            accounts.pool_pc_token_account,
+                        ],
+                    );
                     self.swap_handler.spawn_swap_processor(
-                        &vaults,
+                        &pool,
                         None,
                         &meta,
                         &nested_instructions,
@@ -66,6 +73,20 @@ impl Processor for RaydiumAmmV4InstructionProcessor {
                     );
                 }
             }
+            RaydiumAmmV4Instruction::Initialize2(_) => {
+                let accounts =
+                    Initialize2::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    self.swap_handler.spawn_pool_created(
+                        &accounts.amm,
+                        Dex::RaydiumAmmV4,
+                        &accounts.coin_mint,
+                        &accounts.pc_mint,
+                        &accounts.user_wallet,
+                        &meta,
+                    );
+                }
+            }
             _ => {}
         }
         Ok(())
@@ -75,6 +96,7 @@ impl Processor for RaydiumAmmV4InstructionProcessor {
 #[cfg(test)]
 mod amm_v4_tests {
     use super::*;
+    use crate::replay::golden::assert_golden;
     use crate::{
         diffs::TokenTransferDetails,
         handler::token_swap_handler::test_swaps::{
@@ -184,4 +206,17 @@ mod amm_v4_tests {
             .await
             .expect("Failed to process instruction");
     }
+
+    #[tokio::test]
+    async fn test_golden_price_updates() {
+        assert_golden(
+            "raydium_amm_v4",
+            &[
+                "31pB39KowUTdDSjXhzCYi7QxVSWSM4ZijaSWAkCduWUUR6GuGrWwVBbcXLLdJnVLrWbQaV7YFL2SigBXRatGfnji",
+            ],
+            RaydiumAmmV4Decoder,
+            RaydiumAmmV4InstructionProcessor::new,
+        )
+        .await;
+    }
 }
@@ -1,13 +1,15 @@
 use crate::handler::{token_swap_handler::Dex, TokenSwapHandler};
+use crate::pool::Pool;
 use carbon_core::{
     deserialize::ArrangeAccounts, error::CarbonResult,
     instruction::InstructionProcessorInputType, metrics::MetricsCollection,
     processor::Processor,
 };
 use carbon_raydium_clmm_decoder::instructions::{
-    swap::Swap, swap_v2::SwapV2, RaydiumClmmInstruction,
+    create_pool::CreatePool, swap::Swap, swap_v2::SwapV2,
+    RaydiumClmmInstruction,
 };
-use std::{collections::HashSet, sync::Arc};
+use std::sync::Arc;
 
 pub struct RaydiumClmmInstructionProcessor {
     swap_handler: Arc<TokenSwapHandler>,
@@ -34,12 +36,12 @@ impl Processor for RaydiumClmmInstructionProcessor {
             RaydiumClmmInstruction::Swap(_e) => {
                 let accounts = Swap::arrange_accounts(&instruction.accounts);
                 if let Some(accounts) = accounts {
-                    let vaults = HashSet::from([
-                        accounts.input_vault.to_string(),
-                        accounts.output_vault.to_string(),
-                    ]);
+                    let pool = Pool::new(
+                        accounts.pool_state,
+                        [accounts.input_vault, accounts.output_vault],
+                    );
                     self.swap_handler.spawn_swap_processor(
-                        &vaults,
+                        &pool,
                         None,
                         &meta,
                         &nested_instructions,
@@ -50,12 +52,12 @@ impl Processor for RaydiumClmmInstructionProcessor {
             RaydiumClmmInstruction::SwapV2(_e) => {
                 let accounts = SwapV2::arrange_accounts(&instruction.accounts);
                 if let Some(accounts) = accounts {
-                    let vaults = HashSet::from([
-                        accounts.input_vault.to_string(),
-                        accounts.output_vault.to_string(),
-                    ]);
+                    let pool = Pool::new(
+                        accounts.pool_state,
+                        [accounts.input_vault, accounts.output_vault],
+                    );
                     self.swap_handler.spawn_swap_processor(
-                        &vaults,
+                        &pool,
                         None,
                         &meta,
                         &nested_instructions,
@@ -63,6 +65,20 @@ impl Processor for RaydiumClmmInstructionProcessor {
                     );
                 }
             }
+            RaydiumClmmInstruction::CreatePool(_) => {
+                let accounts =
+                    CreatePool::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    self.swap_handler.spawn_pool_created(
+                        &accounts.pool_state,
+                        Dex::RaydiumClmm,
+                        &accounts.token_mint0,
+                        &accounts.token_mint1,
+                        &accounts.pool_creator,
+                        &meta,
+                    );
+                }
+            }
             _ => {}
         }
 
@@ -73,6 +89,7 @@ impl Processor for RaydiumClmmInstructionProcessor {
 #[cfg(test)]
 mod clmm_tests {
     use super::*;
+    use crate::replay::golden::assert_golden;
     use crate::{
         diffs::TokenTransferDetails,
         handler::token_swap_handler::test_swaps::{
@@ -251,4 +268,18 @@ mod clmm_tests {
             .await
             .expect("Failed to process instruction");
     }
+
+    #[tokio::test]
+    async fn test_golden_price_updates() {
+        assert_golden(
+            "raydium_clmm",
+            &[
+                "2mV1jrKN2QMDkKdkLdNNp7iLPpW7xUG21R7NYSTYrPG6hpfQ4KC34b3XMXjc19mA9RsvFzYU5ws25E7aD24EKaf1",
+                "65coymtGUzFFxZFvcnaoAxD4MCo6RtkNA5hoZje2az93De5sfnmQP7j5t7AC84H3jFXsBzQHM7kjnZMZVtKfNEjF",
+            ],
+            RaydiumClmmDecoder,
+            RaydiumClmmInstructionProcessor::new,
+        )
+        .await;
+    }
 }
@@ -1,14 +1,15 @@
 use crate::handler::{token_swap_handler::Dex, TokenSwapHandler};
+use crate::pool::Pool;
 use carbon_core::{
     deserialize::ArrangeAccounts, error::CarbonResult,
     instruction::InstructionProcessorInputType, metrics::MetricsCollection,
     processor::Processor,
 };
 use carbon_raydium_cpmm_decoder::instructions::{
-    swap_base_input::SwapBaseInput, swap_base_output::SwapBaseOutput,
-    RaydiumCpmmInstruction,
+    initialize::Initialize, swap_base_input::SwapBaseInput,
+    swap_base_output::SwapBaseOutput, RaydiumCpmmInstruction,
 };
-use std::{collections::HashSet, sync::Arc};
+use std::sync::Arc;
 
 pub struct RaydiumCpmmInstructionProcessor {
     swap_handler: Arc<TokenSwapHandler>,
@@ -36,12 +37,15 @@ impl Processor for RaydiumCpmmInstructionProcessor {
                 let accounts =
                     SwapBaseInput::arrange_accounts(&instruction.accounts);
                 if let Some(accounts) = accounts {
-                    let vaults: HashSet<String> = HashSet::from([
-                        accounts.input_token_account.to_string(),
-                        accounts.output_token_account.to_string(),
-                    ]);
+                    let pool = Pool::new(
+                        accounts.pool_state,
+                        [
+                            accounts.input_token_account,
+                            accounts.output_token_account,
+                        ],
+                    );
                     self.swap_handler.spawn_swap_processor(
-                        &vaults,
+                        &pool,
                         None,
                         &meta,
                         &nested_instructions,
@@ -53,12 +57,15 @@ impl Processor for RaydiumCpmmInstructionProcessor {
                 let accounts =
                     SwapBaseOutput::arrange_accounts(&instruction.accounts);
                 if let Some(accounts) = accounts {
-                    let vaults: HashSet<String> = HashSet::from([
-                        accounts.input_token_account.to_string(),
-                        accounts.output_token_account.to_string(),
-                    ]);
+                    let pool = Pool::new(
+                        accounts.pool_state,
+                        [
+                            accounts.input_token_account,
+                            accounts.output_token_account,
+                        ],
+                    );
                     self.swap_handler.spawn_swap_processor(
-                        &vaults,
+                        &pool,
                         None,
                         &meta,
                         &nested_instructions,
@@ -66,6 +73,20 @@ impl Processor for RaydiumCpmmInstructionProcessor {
                     );
                 }
             }
+            RaydiumCpmmInstruction::Initialize(_) => {
+                let accounts =
+                    Initialize::arrange_accounts(&instruction.accounts);
+                if let Some(accounts) = accounts {
+                    self.swap_handler.spawn_pool_created(
+                        &accounts.pool_state,
+                        Dex::RaydiumCpmm,
+                        &accounts.token0_mint,
+                        &accounts.token1_mint,
+                        &accounts.creator,
+                        &meta,
+                    );
+                }
+            }
             _ => {}
         }
 
@@ -76,6 +97,7 @@ impl Processor for RaydiumCpmmInstructionProcessor {
 #[cfg(test)]
 mod cpmm_tests {
     use super::*;
+    use crate::replay::golden::assert_golden;
     use crate::{
         diffs::TokenTransferDetails,
         handler::token_swap_handler::test_swaps::{
@@ -258,4 +280,18 @@ mod cpmm_tests {
             .await
             .expect("Failed to process instruction");
     }
+
+    #[tokio::test]
+    async fn test_golden_price_updates() {
+        assert_golden(
+            "raydium_cpmm",
+            &[
+                "7Gr8Wtzd3T6L4YwGnH98eKbNnitbTPjTfiYy8pdnebA6sHZRYJztw76ruPGSjDe8DciEqVtyuA8VqGjxiJ7UVr5",
+                "4fzSVEeGUZGGooNYEPoEjH1wSXwRh4X5yv2SAWQ723SwncCVkgQNdC5qEMFAyDYzbAEp5xHYnqhMZLDS78xLSHt4",
+            ],
+            RaydiumCpmmDecoder,
+            RaydiumCpmmInstructionProcessor::new,
+        )
+        .await;
+    }
 }
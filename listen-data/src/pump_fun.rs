@@ -0,0 +1,321 @@
+//! Decoder for the events of the pump.fun bonding curve program, the creation
+//! of tokens, trades and the completion of the curve are emitted as anchor self-CPI events
+//! (the instruction data is the event tag followed by the event discriminator
+//! and the borsh encoded event)
+
+use carbon_core::instruction::{DecodedInstruction, InstructionDecoder};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+
+use crate::constants::PUMP_FUN_PROGRAM_ID;
+
+/// tokens of the bonding curve are minted with 6 decimals
+pub const PUMP_FUN_TOKEN_DECIMALS: i32 = 6;
+
+/// the curve starts with virtual reserves on top of the real ones, 30 SOL and
+/// 279.9M tokens
+const INITIAL_VIRTUAL_SOL_RESERVES: u64 = 30_000_000_000;
+const VIRTUAL_TOKEN_RESERVES_OFFSET: u64 = 279_900_000_000_000;
+
+const EVENT_IX_TAG: [u8; 8] = [228, 69, 165, 46, 81, 203, 154, 29];
+const TRADE_EVENT_DISCRIMINATOR: [u8; 8] =
+    [189, 219, 127, 211, 78, 230, 97, 238];
+const COMPLETE_EVENT_DISCRIMINATOR: [u8; 8] =
+    [95, 114, 97, 156, 212, 46, 152, 8];
+const CREATE_EVENT_DISCRIMINATOR: [u8; 8] =
+    [27, 114, 169, 77, 222, 235, 99, 118];
+
+/// A new token was created with its bonding curve
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateEvent {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub mint: Pubkey,
+    pub bonding_curve: Pubkey,
+    pub user: Pubkey,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeEvent {
+    pub mint: Pubkey,
+    pub sol_amount: u64,
+    pub token_amount: u64,
+    pub is_buy: bool,
+    pub user: Pubkey,
+    pub timestamp: i64,
+    pub virtual_sol_reserves: u64,
+    pub virtual_token_reserves: u64,
+}
+
+impl TradeEvent {
+    /// sol per token of the trade
+    pub fn price_in_sol(&self) -> Option<f64> {
+        if self.token_amount == 0 {
+            return None;
+        }
+        Some(self.sol_amount_ui() / self.token_amount_ui())
+    }
+
+    pub fn sol_amount_ui(&self) -> f64 {
+        self.sol_amount as f64 / 1e9
+    }
+
+    pub fn token_amount_ui(&self) -> f64 {
+        self.token_amount as f64 / 10_f64.powi(PUMP_FUN_TOKEN_DECIMALS)
+    }
+
+    /// SOL held by the bonding curve after the trade
+    pub fn real_sol_reserves_ui(&self) -> f64 {
+        self.virtual_sol_reserves
+            .saturating_sub(INITIAL_VIRTUAL_SOL_RESERVES) as f64
+            / 1e9
+    }
+
+    /// tokens left to be sold by the bonding curve after the trade
+    pub fn real_token_reserves_ui(&self) -> f64 {
+        self.virtual_token_reserves
+            .saturating_sub(VIRTUAL_TOKEN_RESERVES_OFFSET) as f64
+            / 10_f64.powi(PUMP_FUN_TOKEN_DECIMALS)
+    }
+}
+
+/// address of the bonding curve account of the mint
+pub fn bonding_curve_address(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"bonding-curve", mint.as_ref()],
+        &PUMP_FUN_PROGRAM_ID,
+    )
+    .0
+}
+
+/// The bonding curve is complete, the token migrates to an AMM
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompleteEvent {
+    pub user: Pubkey,
+    pub mint: Pubkey,
+    pub bonding_curve: Pubkey,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PumpFunEvent {
+    Create(CreateEvent),
+    Trade(TradeEvent),
+    Complete(CompleteEvent),
+}
+
+/// Reads the fields of the event in order, trailing fields added by newer
+/// versions of the program are ignored
+struct EventReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> EventReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Some(head)
+    }
+
+    fn pubkey(&mut self) -> Option<Pubkey> {
+        Pubkey::try_from(self.take(32)?).ok()
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    /// borsh string, u32 length followed by the utf-8 bytes
+    fn string(&mut self) -> Option<String> {
+        let len = u32::from_le_bytes(self.take(4)?.try_into().ok()?);
+        String::from_utf8(self.take(len as usize)?.to_vec()).ok()
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        match self.take(1)?[0] {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl PumpFunEvent {
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 16 || data[..8] != EVENT_IX_TAG {
+            return None;
+        }
+        let discriminator: [u8; 8] = data[8..16].try_into().ok()?;
+        let mut reader = EventReader { data: &data[16..] };
+        match discriminator {
+            CREATE_EVENT_DISCRIMINATOR => Some(Self::Create(CreateEvent {
+                name: reader.string()?,
+                symbol: reader.string()?,
+                uri: reader.string()?,
+                mint: reader.pubkey()?,
+                bonding_curve: reader.pubkey()?,
+                user: reader.pubkey()?,
+            })),
+            TRADE_EVENT_DISCRIMINATOR => Some(Self::Trade(TradeEvent {
+                mint: reader.pubkey()?,
+                sol_amount: reader.u64()?,
+                token_amount: reader.u64()?,
+                is_buy: reader.bool()?,
+                user: reader.pubkey()?,
+                timestamp: reader.i64()?,
+                virtual_sol_reserves: reader.u64()?,
+                virtual_token_reserves: reader.u64()?,
+            })),
+            COMPLETE_EVENT_DISCRIMINATOR => {
+                Some(Self::Complete(CompleteEvent {
+                    user: reader.pubkey()?,
+                    mint: reader.pubkey()?,
+                    bonding_curve: reader.pubkey()?,
+                    timestamp: reader.i64()?,
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct PumpFunDecoder;
+
+impl<'a> InstructionDecoder<'a> for PumpFunDecoder {
+    type InstructionType = PumpFunEvent;
+
+    fn decode_instruction(
+        &self,
+        instruction: &'a Instruction,
+    ) -> Option<DecodedInstruction<Self::InstructionType>> {
+        if instruction.program_id != PUMP_FUN_PROGRAM_ID {
+            return None;
+        }
+        Some(DecodedInstruction {
+            program_id: instruction.program_id,
+            data: PumpFunEvent::decode(&instruction.data)?,
+            accounts: instruction.accounts.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn trade_event_data(event: &TradeEvent) -> Vec<u8> {
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend(TRADE_EVENT_DISCRIMINATOR);
+        data.extend(event.mint.to_bytes());
+        data.extend(event.sol_amount.to_le_bytes());
+        data.extend(event.token_amount.to_le_bytes());
+        data.push(event.is_buy as u8);
+        data.extend(event.user.to_bytes());
+        data.extend(event.timestamp.to_le_bytes());
+        data.extend(event.virtual_sol_reserves.to_le_bytes());
+        data.extend(event.virtual_token_reserves.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_decode_trade_event() {
+        let event = TradeEvent {
+            mint: Pubkey::from_str(
+                "AsyfR3e5JcPqWot4H5MMhQUm7DZ4zwQrcp2zbB7vpump",
+            )
+            .unwrap(),
+            sol_amount: 500_000_000,
+            token_amount: 17_000_000_000_000,
+            is_buy: true,
+            user: Pubkey::new_unique(),
+            timestamp: 1_735_000_000,
+            virtual_sol_reserves: 30_500_000_000,
+            virtual_token_reserves: 1_055_000_000_000_000,
+        };
+        let mut data = trade_event_data(&event);
+        // fields of newer program versions
+        data.extend([0u8; 16]);
+
+        assert_eq!(
+            PumpFunEvent::decode(&data),
+            Some(PumpFunEvent::Trade(event.clone()))
+        );
+        let price = event.price_in_sol().unwrap();
+        assert!((price - 0.5 / 17_000_000.0).abs() < 1e-15);
+        assert_eq!(event.real_sol_reserves_ui(), 0.5);
+        assert_eq!(event.real_token_reserves_ui(), 775_100_000.0);
+
+        // truncated events are rejected
+        assert_eq!(PumpFunEvent::decode(&data[..60]), None);
+    }
+
+    #[test]
+    fn test_decode_complete_event() {
+        let event = CompleteEvent {
+            user: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            bonding_curve: Pubkey::new_unique(),
+            timestamp: 1_735_000_000,
+        };
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend(COMPLETE_EVENT_DISCRIMINATOR);
+        data.extend(event.user.to_bytes());
+        data.extend(event.mint.to_bytes());
+        data.extend(event.bonding_curve.to_bytes());
+        data.extend(event.timestamp.to_le_bytes());
+
+        assert_eq!(
+            PumpFunEvent::decode(&data),
+            Some(PumpFunEvent::Complete(event))
+        );
+    }
+
+    #[test]
+    fn test_decode_create_event() {
+        let event = CreateEvent {
+            name: "Listen".to_string(),
+            symbol: "LSN".to_string(),
+            uri: "https://ipfs.io/ipfs/Qm".to_string(),
+            mint: Pubkey::new_unique(),
+            bonding_curve: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+        };
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend(CREATE_EVENT_DISCRIMINATOR);
+        for field in [&event.name, &event.symbol, &event.uri] {
+            data.extend((field.len() as u32).to_le_bytes());
+            data.extend(field.as_bytes());
+        }
+        data.extend(event.mint.to_bytes());
+        data.extend(event.bonding_curve.to_bytes());
+        data.extend(event.user.to_bytes());
+
+        assert_eq!(
+            PumpFunEvent::decode(&data),
+            Some(PumpFunEvent::Create(event))
+        );
+
+        // the length of the name runs past the end of the data
+        let mut data = EVENT_IX_TAG.to_vec();
+        data.extend(CREATE_EVENT_DISCRIMINATOR);
+        data.extend(64u32.to_le_bytes());
+        data.extend(b"Listen");
+        assert_eq!(PumpFunEvent::decode(&data), None);
+    }
+
+    #[test]
+    fn test_decode_ignores_instructions() {
+        // buy instruction of the program, not an event
+        let mut data = vec![102, 6, 61, 18, 1, 218, 235, 234];
+        data.extend([0u8; 16]);
+        assert_eq!(PumpFunEvent::decode(&data), None);
+    }
+}
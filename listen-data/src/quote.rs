@@ -0,0 +1,277 @@
+//! Registry of the quote assets swaps are priced against, a swap is only
+//! priced if one of its sides is a quote asset with a known usd price
+//!
+//! Extra quote assets can be configured with `QUOTE_ASSETS`, a comma
+//! separated list of `SYMBOL:mint:usd_price` entries for pegged assets, e.g.
+//! `PYUSD:2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo:1.0`
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use tracing::warn;
+
+use crate::constants::{
+    USDC_MINT_KEY_STR, USDT_MINT_KEY_STR, WSOL_MINT_KEY_STR,
+};
+
+pub static QUOTE_REGISTRY: Lazy<QuoteRegistry> =
+    Lazy::new(QuoteRegistry::from_env);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PriceSource {
+    /// the live SOL price from the price stream
+    Sol,
+    /// pegged to a fixed usd price
+    Fixed(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteAsset {
+    pub symbol: String,
+    pub mint: String,
+    pub source: PriceSource,
+}
+
+impl QuoteAsset {
+    pub fn new(symbol: &str, mint: &str, source: PriceSource) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            mint: mint.to_string(),
+            source,
+        }
+    }
+
+    /// parses a `SYMBOL:mint:usd_price` entry
+    pub fn parse(entry: &str) -> Option<Self> {
+        let mut parts = entry.trim().split(':');
+        let symbol = parts.next().filter(|s| !s.is_empty())?;
+        let mint = parts.next().filter(|s| !s.is_empty())?;
+        let price = parts.next()?.parse::<f64>().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self::new(symbol, mint, PriceSource::Fixed(price)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteSide {
+    First,
+    Second,
+}
+
+/// Which side of a pair is the quote given the ranks of its mints, the quote
+/// asset with the higher priority; `None` if neither side is a quote asset
+pub fn quote_side(
+    rank_first: Option<usize>,
+    rank_second: Option<usize>,
+) -> Option<QuoteSide> {
+    match (rank_first, rank_second) {
+        (Some(first), Some(second)) if second < first => {
+            Some(QuoteSide::Second)
+        }
+        (Some(_), _) => Some(QuoteSide::First),
+        (None, Some(_)) => Some(QuoteSide::Second),
+        (None, None) => None,
+    }
+}
+
+/// Quote assets in the order of priority, if both sides of a swap are quote
+/// assets the one that comes first is the quote; the usd stables come first so
+/// that SOL/USDC prices SOL in USDC rather than USDC in SOL
+#[derive(Debug, Clone)]
+pub struct QuoteRegistry {
+    assets: Vec<QuoteAsset>,
+}
+
+impl Default for QuoteRegistry {
+    fn default() -> Self {
+        Self::new(vec![
+            QuoteAsset::new("USDC", USDC_MINT_KEY_STR, PriceSource::Fixed(1.0)),
+            QuoteAsset::new("USDT", USDT_MINT_KEY_STR, PriceSource::Fixed(1.0)),
+            QuoteAsset::new("SOL", WSOL_MINT_KEY_STR, PriceSource::Sol),
+        ])
+    }
+}
+
+impl QuoteRegistry {
+    pub fn new(assets: Vec<QuoteAsset>) -> Self {
+        Self { assets }
+    }
+
+    /// the default quote assets followed by the ones from `QUOTE_ASSETS`
+    pub fn from_env() -> Self {
+        let mut registry = Self::default();
+        if let Ok(entries) = std::env::var("QUOTE_ASSETS") {
+            for entry in entries.split(',').filter(|e| !e.trim().is_empty()) {
+                match QuoteAsset::parse(entry) {
+                    Some(asset) => registry.push(asset),
+                    None => warn!("invalid quote asset entry: {}", entry),
+                }
+            }
+        }
+        registry
+    }
+
+    /// adds the asset with the lowest priority, replaces one with the same mint
+    pub fn push(&mut self, asset: QuoteAsset) {
+        self.assets.retain(|a| a.mint != asset.mint);
+        self.assets.push(asset);
+    }
+
+    pub fn get(&self, mint: &str) -> Option<&QuoteAsset> {
+        self.assets.iter().find(|a| a.mint == mint)
+    }
+
+    /// lower is preferred as the quote of a pair
+    pub fn rank(&self, mint: &str) -> Option<usize> {
+        self.assets.iter().position(|a| a.mint == mint)
+    }
+
+    /// orders the mints of a pair as (base, quote), the quote is the quote
+    /// asset with the highest priority or the second mint if neither is one
+    pub fn base_and_quote<'a>(
+        &self,
+        mint_a: &'a str,
+        mint_b: &'a str,
+    ) -> (&'a str, &'a str) {
+        match quote_side(self.rank(mint_a), self.rank(mint_b)) {
+            Some(QuoteSide::First) => (mint_b, mint_a),
+            Some(QuoteSide::Second) | None => (mint_a, mint_b),
+        }
+    }
+
+    pub fn assets(&self) -> &[QuoteAsset] {
+        &self.assets
+    }
+
+    /// usd prices of the quote assets given the current SOL price
+    pub fn resolve(&self, sol_price: f64) -> QuotePrices {
+        QuotePrices {
+            prices: self
+                .assets
+                .iter()
+                .enumerate()
+                .map(|(rank, asset)| {
+                    let price = match asset.source {
+                        PriceSource::Sol => sol_price,
+                        PriceSource::Fixed(price) => price,
+                    };
+                    (asset.mint.clone(), (rank, price))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Snapshot of the usd prices of the quote assets, keyed by mint
+#[derive(Debug, Clone, Default)]
+pub struct QuotePrices {
+    prices: HashMap<String, (usize, f64)>,
+}
+
+impl QuotePrices {
+    pub fn price(&self, mint: &str) -> Option<f64> {
+        self.prices.get(mint).map(|(_, price)| *price)
+    }
+
+    /// lower is preferred as the quote of a swap
+    pub fn rank(&self, mint: &str) -> Option<usize> {
+        self.prices.get(mint).map(|(rank, _)| *rank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quote_asset() {
+        let asset = QuoteAsset::parse(
+            "PYUSD:2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo:1.0",
+        )
+        .unwrap();
+        assert_eq!(asset.symbol, "PYUSD");
+        assert_eq!(asset.source, PriceSource::Fixed(1.0));
+
+        assert!(QuoteAsset::parse("PYUSD:1.0").is_none());
+        assert!(QuoteAsset::parse("PYUSD:mint:abc").is_none());
+        assert!(QuoteAsset::parse(":mint:1.0").is_none());
+    }
+
+    #[test]
+    fn test_resolve_prices() {
+        let mut registry = QuoteRegistry::default();
+        registry.push(QuoteAsset::new(
+            "PYUSD",
+            "2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo",
+            PriceSource::Fixed(1.0),
+        ));
+        let prices = registry.resolve(150.0);
+
+        assert_eq!(prices.price(WSOL_MINT_KEY_STR), Some(150.0));
+        assert_eq!(prices.price(USDC_MINT_KEY_STR), Some(1.0));
+        assert!(
+            prices.rank(USDC_MINT_KEY_STR) < prices.rank(WSOL_MINT_KEY_STR)
+        );
+        assert_eq!(
+            prices.rank("2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo"),
+            Some(3)
+        );
+        assert_eq!(prices.price("unknown"), None);
+    }
+
+    #[test]
+    fn test_quote_side() {
+        assert_eq!(quote_side(Some(0), Some(2)), Some(QuoteSide::First));
+        assert_eq!(quote_side(Some(2), Some(0)), Some(QuoteSide::Second));
+        assert_eq!(quote_side(Some(2), None), Some(QuoteSide::First));
+        assert_eq!(quote_side(None, Some(2)), Some(QuoteSide::Second));
+        assert_eq!(quote_side(None, None), None);
+
+        // the swaps and the pools agree on the quote of a pair
+        let registry = QuoteRegistry::default();
+        let prices = registry.resolve(150.0);
+        for (a, b) in [
+            (USDC_MINT_KEY_STR, WSOL_MINT_KEY_STR),
+            (WSOL_MINT_KEY_STR, USDT_MINT_KEY_STR),
+            (
+                WSOL_MINT_KEY_STR,
+                "AsyfR3e5JcPqWot4H5MMhQUm7DZ4zwQrcp2zbB7vpump",
+            ),
+        ] {
+            assert_eq!(
+                quote_side(registry.rank(a), registry.rank(b)),
+                quote_side(prices.rank(a), prices.rank(b))
+            );
+        }
+    }
+
+    #[test]
+    fn test_base_and_quote() {
+        let registry = QuoteRegistry::default();
+        let token = "AsyfR3e5JcPqWot4H5MMhQUm7DZ4zwQrcp2zbB7vpump";
+
+        assert_eq!(
+            registry.base_and_quote(WSOL_MINT_KEY_STR, token),
+            (token, WSOL_MINT_KEY_STR)
+        );
+        assert_eq!(
+            registry.base_and_quote(token, USDC_MINT_KEY_STR),
+            (token, USDC_MINT_KEY_STR)
+        );
+        // SOL is priced in USDC, whichever side it is on
+        assert_eq!(
+            registry.base_and_quote(USDC_MINT_KEY_STR, WSOL_MINT_KEY_STR),
+            (WSOL_MINT_KEY_STR, USDC_MINT_KEY_STR)
+        );
+        assert_eq!(
+            registry.base_and_quote(WSOL_MINT_KEY_STR, USDC_MINT_KEY_STR),
+            (WSOL_MINT_KEY_STR, USDC_MINT_KEY_STR)
+        );
+        assert_eq!(
+            registry.base_and_quote(USDT_MINT_KEY_STR, WSOL_MINT_KEY_STR),
+            (WSOL_MINT_KEY_STR, USDT_MINT_KEY_STR)
+        );
+    }
+}
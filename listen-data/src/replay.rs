@@ -0,0 +1,600 @@
+//! Offline replay of recorded transactions through the carbon pipeline
+//!
+//! A recording is a JSON lines file of transactions in the encoding of the
+//! RPC `getTransaction` method, so it can be captured from the live feed with
+//! the `RecordingDatasource` or fetched by signature
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use carbon_core::{
+    datasource::{Datasource, TransactionUpdate, Update, UpdateType},
+    error::{CarbonResult, Error},
+    metrics::MetricsCollection,
+    pipeline::{Pipeline, ShutdownStrategy},
+    transformers::transaction_metadata_from_original_meta,
+};
+use carbon_log_metrics::LogMetrics;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use solana_transaction_status::{
+    ConfirmedTransactionWithStatusMeta,
+    EncodedConfirmedTransactionWithStatusMeta, TransactionWithStatusMeta,
+    UiTransactionEncoding, VersionedTransactionWithStatusMeta,
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::{
+    db::Database, handler::TokenSwapHandler, kv_store::KVStore,
+    message_queue::MessageQueue, metrics::SwapMetrics,
+    processor::with_swap_processors,
+};
+
+pub fn encode_transaction_update(
+    update: &TransactionUpdate,
+) -> Result<EncodedConfirmedTransactionWithStatusMeta> {
+    let transaction = ConfirmedTransactionWithStatusMeta {
+        slot: update.slot,
+        tx_with_meta: TransactionWithStatusMeta::Complete(
+            VersionedTransactionWithStatusMeta {
+                transaction: update.transaction.clone(),
+                meta: update.meta.clone(),
+            },
+        ),
+        block_time: update.block_time,
+    };
+    transaction
+        .encode(UiTransactionEncoding::Base64, Some(0))
+        .with_context(|| format!("failed to encode {}", update.signature))
+}
+
+pub fn decode_transaction_update(
+    encoded: EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<TransactionUpdate> {
+    let transaction = encoded
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| anyhow!("failed to decode transaction"))?;
+    let signature = *transaction
+        .signatures
+        .first()
+        .ok_or_else(|| anyhow!("transaction without signature"))?;
+    let meta = encoded
+        .transaction
+        .meta
+        .ok_or_else(|| anyhow!("transaction {} without meta", signature))?;
+    let meta = transaction_metadata_from_original_meta(meta)
+        .map_err(|e| anyhow!("invalid meta of {}: {}", signature, e))?;
+
+    Ok(TransactionUpdate {
+        signature,
+        transaction,
+        meta,
+        is_vote: false,
+        slot: encoded.slot,
+        block_time: encoded.block_time,
+    })
+}
+
+pub async fn fetch_transaction_update(
+    rpc_client: &RpcClient,
+    signature: &Signature,
+) -> Result<TransactionUpdate> {
+    let encoded = rpc_client
+        .get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )
+        .await
+        .with_context(|| format!("failed to get transaction {}", signature))?;
+    decode_transaction_update(encoded)
+}
+
+pub fn read_recording(
+    path: impl AsRef<Path>,
+) -> Result<Vec<TransactionUpdate>> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(l) if l.is_empty()))
+        .map(|(index, line)| {
+            let encoded = serde_json::from_str(&line?).with_context(|| {
+                format!(
+                    "invalid transaction at {}:{}",
+                    path.display(),
+                    index + 1
+                )
+            })?;
+            decode_transaction_update(encoded)
+        })
+        .collect()
+}
+
+/// Appends transactions to a recording
+pub struct Recorder {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl Recorder {
+    /// opens the recording at `path` for appending, creating it if needed
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    pub fn record(&self, update: &TransactionUpdate) -> Result<()> {
+        let line = serde_json::to_string(&encode_transaction_update(update)?)?;
+        let mut writer = self.writer.lock().expect("recorder lock poisoned");
+        writeln!(writer, "{}", line)?;
+        // flushed per transaction so the recording survives an interrupt
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Datasource that sends the transactions of a recording in order, once
+pub struct ReplayDatasource {
+    path: PathBuf,
+}
+
+impl ReplayDatasource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl Datasource for ReplayDatasource {
+    async fn consume(
+        &self,
+        sender: &UnboundedSender<Update>,
+        cancellation_token: CancellationToken,
+        _metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        let updates = read_recording(&self.path)
+            .map_err(|e| Error::Custom(format!("{:#}", e)))?;
+        info!(
+            "replaying {} transactions from {}",
+            updates.len(),
+            self.path.display()
+        );
+
+        for update in updates {
+            if cancellation_token.is_cancelled() {
+                break;
+            }
+            if sender.send(Update::Transaction(Box::new(update))).is_err() {
+                break;
+            }
+        }
+
+        info!("replay of {} finished", self.path.display());
+        Ok(())
+    }
+
+    fn update_types(&self) -> Vec<UpdateType> {
+        vec![UpdateType::Transaction]
+    }
+}
+
+/// Wraps a datasource and records the transactions it emits on their way to
+/// the pipeline
+pub struct RecordingDatasource<D: Datasource> {
+    inner: D,
+    recorder: Recorder,
+}
+
+impl<D: Datasource> RecordingDatasource<D> {
+    pub fn new(inner: D, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+#[async_trait::async_trait]
+impl<D: Datasource> Datasource for RecordingDatasource<D> {
+    async fn consume(
+        &self,
+        sender: &UnboundedSender<Update>,
+        cancellation_token: CancellationToken,
+        metrics: Arc<MetricsCollection>,
+    ) -> CarbonResult<()> {
+        let (tee_sender, mut tee_receiver) = unbounded_channel();
+
+        let consume = async move {
+            let result = self
+                .inner
+                .consume(&tee_sender, cancellation_token, metrics)
+                .await;
+            // the receiver stops once the inner datasource and the tasks it
+            // spawned are done with the sender
+            drop(tee_sender);
+            result
+        };
+
+        let forward = async {
+            while let Some(update) = tee_receiver.recv().await {
+                if let Update::Transaction(transaction) = &update {
+                    if let Err(e) = self.recorder.record(transaction) {
+                        warn!(
+                            "failed to record {}: {:#}",
+                            transaction.signature, e
+                        );
+                    }
+                }
+                if sender.send(update).is_err() {
+                    break;
+                }
+            }
+        };
+
+        let (result, _) = tokio::join!(consume, forward);
+        result
+    }
+
+    fn update_types(&self) -> Vec<UpdateType> {
+        self.inner.update_types()
+    }
+}
+
+/// Pipeline that runs the recording through the swap processors, the pending
+/// updates are processed before shutting down
+pub fn make_replay_pipeline(
+    path: impl Into<PathBuf>,
+    kv_store: Arc<dyn KVStore>,
+    message_queue: Arc<dyn MessageQueue>,
+    db: Arc<dyn Database>,
+    metrics: Arc<SwapMetrics>,
+) -> Result<Pipeline> {
+    let token_swap_handler =
+        Arc::new(TokenSwapHandler::new(kv_store, message_queue, db, metrics));
+
+    let builder = Pipeline::builder()
+        .datasource(ReplayDatasource::new(path))
+        .metrics(Arc::new(LogMetrics::new()))
+        .shutdown_strategy(ShutdownStrategy::ProcessPending);
+
+    Ok(with_swap_processors(builder, token_swap_handler).build()?)
+}
+
+/// Golden tests of the processors, the recorded transactions are run through
+/// a processor and the price updates published to an in-memory queue are
+/// compared with the expected ones
+///
+/// Missing recordings and the metadata of their tokens are fetched over RPC
+/// once, the tests run offline against the committed fixtures after that;
+/// set `UPDATE_GOLDEN` to (re)write the expected price updates after a
+/// deliberate change
+#[cfg(test)]
+pub mod golden {
+    use super::*;
+    use crate::{
+        handler::token_swap_handler::test_swaps::extract_nested_instructions,
+        kv_store::MemoryKVStore,
+        metadata::{
+            MemoryMetadataSource, MetadataSource, RpcMetadataSource,
+            TokenMetadata,
+        },
+        price::PriceUpdate,
+        sink::MemorySink,
+        sol_price_stream::SolPriceCache,
+        util::{make_rpc_client, set_epoch_schedule},
+    };
+    use carbon_core::{
+        instruction::{
+            InstructionDecoder, InstructionProcessorInputType,
+            NestedInstruction,
+        },
+        processor::Processor,
+    };
+    use solana_sdk::epoch_schedule::EpochSchedule;
+    use std::{str::FromStr, sync::atomic::Ordering};
+    use tokio::time::{sleep, Duration, Instant};
+
+    /// SOL price the golden price updates are computed with
+    pub const GOLDEN_SOL_PRICE: f64 = 200.0;
+
+    fn fixture_path(name: &str, extension: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/golden")
+            .join(format!("{}.{}", name, extension))
+    }
+
+    async fn record_missing(path: &Path, signatures: &[&str]) -> Result<()> {
+        if path.exists() {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let rpc_client = make_rpc_client()?;
+        let recorder = Recorder::create(path)?;
+        for signature in signatures {
+            let signature = Signature::from_str(signature)?;
+            let update =
+                fetch_transaction_update(&rpc_client, &signature).await?;
+            recorder.record(&update)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the metadata over RPC and keeps it, to record the metadata of
+    /// the tokens of a golden test
+    #[derive(Default)]
+    struct RecordingMetadataSource {
+        recorded: MemoryMetadataSource,
+    }
+
+    #[async_trait::async_trait]
+    impl MetadataSource for RecordingMetadataSource {
+        async fn fetch(&self, mint: &str) -> Result<TokenMetadata> {
+            let metadata = RpcMetadataSource.fetch(mint).await?;
+            self.recorded.insert(metadata.clone());
+            Ok(metadata)
+        }
+
+        async fn refresh(
+            &self,
+            metadata: &TokenMetadata,
+        ) -> Result<TokenMetadata> {
+            let metadata = RpcMetadataSource.refresh(metadata).await?;
+            self.recorded.insert(metadata.clone());
+            Ok(metadata)
+        }
+    }
+
+    fn read_metadata(path: &Path) -> Result<Vec<TokenMetadata>> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// the instructions in the order of execution, inner ones included
+    fn flatten(instructions: &[NestedInstruction]) -> Vec<NestedInstruction> {
+        instructions
+            .iter()
+            .flat_map(|instruction| {
+                std::iter::once(instruction.clone())
+                    .chain(flatten(&instruction.inner_instructions))
+            })
+            .collect()
+    }
+
+    /// compares all the fields but the timestamp, which is the time of
+    /// processing
+    fn assert_price_updates_match(
+        actual: &[PriceUpdate],
+        expected: &[PriceUpdate],
+    ) {
+        let approx = |a: f64, b: f64| (a - b).abs() <= 1e-9 * a.abs().max(1.0);
+        assert_eq!(actual.len(), expected.len(), "actual: {:#?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            let matches = a.name == e.name
+                && a.pubkey == e.pubkey
+                && approx(a.price, e.price)
+                && approx(a.market_cap, e.market_cap)
+                && a.slot == e.slot
+                && approx(a.swap_amount, e.swap_amount)
+                && a.owner == e.owner
+                && a.signature == e.signature
+                && a.multi_hop == e.multi_hop
+                && a.is_buy == e.is_buy
+                && a.is_pump == e.is_pump
+                && a.quote_mint == e.quote_mint;
+            assert!(matches, "actual: {:#?}\nexpected: {:#?}", a, e);
+        }
+    }
+
+    pub async fn assert_golden<T, D, P>(
+        name: &str,
+        signatures: &[&str],
+        decoder: D,
+        make_processor: impl FnOnce(Arc<TokenSwapHandler>) -> P,
+    ) where
+        T: Send + Sync + 'static,
+        D: for<'a> InstructionDecoder<'a, InstructionType = T>,
+        P: Processor<InputType = InstructionProcessorInputType<T>>,
+    {
+        let recording = fixture_path(name, "jsonl");
+        record_missing(&recording, signatures)
+            .await
+            .expect("failed to record transactions");
+        let updates =
+            read_recording(&recording).expect("failed to read recording");
+
+        SolPriceCache::new(None, None)
+            .set_price(GOLDEN_SOL_PRICE)
+            .await;
+        set_epoch_schedule(EpochSchedule::without_warmup());
+
+        let metadata_path = fixture_path(name, "metadata.json");
+        let recording_source = Arc::new(RecordingMetadataSource::default());
+        let metadata_source: Arc<dyn MetadataSource> =
+            match metadata_path.exists() {
+                true => Arc::new(MemoryMetadataSource::new(
+                    read_metadata(&metadata_path)
+                        .expect("failed to read token metadata"),
+                )),
+                false => recording_source.clone(),
+            };
+
+        // everything is kept in memory
+        let message_queue = Arc::new(MemorySink::default());
+        let token_swap_handler = Arc::new(
+            TokenSwapHandler::new(
+                Arc::new(MemoryKVStore::default()),
+                message_queue.clone(),
+                Arc::new(MemorySink::default()),
+                Arc::new(SwapMetrics::new()),
+            )
+            .with_metadata_source(metadata_source),
+        );
+        let mut processor = make_processor(token_swap_handler.clone());
+        for update in &updates {
+            let nested_instructions = extract_nested_instructions(update)
+                .expect("failed to extract nested instructions");
+            for instruction in flatten(&nested_instructions) {
+                let Some(decoded) =
+                    decoder.decode_instruction(&instruction.instruction)
+                else {
+                    continue;
+                };
+                processor
+                    .process(
+                        (
+                            instruction.metadata.clone(),
+                            decoded,
+                            instruction.inner_instructions.clone(),
+                        ),
+                        Arc::new(MetricsCollection::new(vec![])),
+                    )
+                    .await
+                    .expect("failed to process instruction");
+            }
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let metrics = &token_swap_handler.metrics;
+        while metrics.pending_swaps.load(Ordering::Relaxed) > 0 {
+            assert!(Instant::now() < deadline, "swaps still pending");
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        if !metadata_path.exists() {
+            std::fs::write(
+                &metadata_path,
+                serde_json::to_string_pretty(
+                    &recording_source.recorded.snapshot(),
+                )
+                .unwrap()
+                    + "\n",
+            )
+            .expect("failed to write token metadata");
+        }
+
+        let mut actual = message_queue.price_updates();
+        for price_update in &mut actual {
+            price_update.timestamp = 0;
+        }
+        actual.sort_by(|a, b| {
+            (a.slot, &a.signature, &a.pubkey, &a.quote_mint).cmp(&(
+                b.slot,
+                &b.signature,
+                &b.pubkey,
+                &b.quote_mint,
+            ))
+        });
+
+        let expected_path = fixture_path(name, "expected.json");
+        if std::env::var("UPDATE_GOLDEN").is_ok() {
+            std::fs::write(
+                &expected_path,
+                serde_json::to_string_pretty(&actual).unwrap() + "\n",
+            )
+            .expect("failed to write expected price updates");
+            return;
+        }
+
+        let expected: Vec<PriceUpdate> = serde_json::from_str(
+            &std::fs::read_to_string(&expected_path).unwrap_or_else(|_| {
+                panic!(
+                    "missing {}, run with UPDATE_GOLDEN=1 to create it",
+                    expected_path.display()
+                )
+            }),
+        )
+        .expect("invalid expected price updates");
+        assert_price_updates_match(&actual, &expected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        message::{Message, VersionedMessage},
+        transaction::VersionedTransaction,
+    };
+    use solana_transaction_status::TransactionStatusMeta;
+
+    fn transaction_update(slot: u64) -> TransactionUpdate {
+        TransactionUpdate {
+            signature: Signature::new_unique(),
+            transaction: VersionedTransaction {
+                signatures: vec![],
+                message: VersionedMessage::Legacy(Message::default()),
+            },
+            meta: TransactionStatusMeta {
+                fee: 5000,
+                pre_balances: vec![1_000_000],
+                post_balances: vec![995_000],
+                ..Default::default()
+            },
+            is_vote: false,
+            slot,
+            block_time: Some(1_735_000_000),
+        }
+    }
+
+    #[test]
+    fn test_record_and_read() {
+        let path = std::env::temp_dir()
+            .join(format!("replay-{}.jsonl", Signature::new_unique()));
+        let updates: Vec<TransactionUpdate> = (0..2)
+            .map(|slot| {
+                let mut update = transaction_update(310_000_000 + slot);
+                update.transaction.signatures = vec![update.signature];
+                update
+            })
+            .collect();
+
+        let recorder = Recorder::create(&path).unwrap();
+        for update in &updates {
+            recorder.record(update).unwrap();
+        }
+
+        let replayed = read_recording(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(replayed.len(), 2);
+        for (replayed, update) in replayed.iter().zip(&updates) {
+            assert_eq!(replayed.signature, update.signature);
+            assert_eq!(replayed.transaction, update.transaction);
+            assert_eq!(replayed.slot, update.slot);
+            assert_eq!(replayed.block_time, update.block_time);
+            assert_eq!(replayed.meta.fee, 5000);
+            assert_eq!(replayed.meta.post_balances, vec![995_000]);
+        }
+    }
+
+    #[test]
+    fn test_decode_requires_signature() {
+        let encoded =
+            encode_transaction_update(&transaction_update(310_000_000))
+                .unwrap();
+        assert!(decode_transaction_update(encoded).is_err());
+    }
+}
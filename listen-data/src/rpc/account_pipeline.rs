@@ -1,5 +1,5 @@
 use crate::{
-    constants::RAYDIUM_AMM_V4_PROGRAM_ID,
+    constants::RAYDIUM_AMM_V4_PROGRAM_ID, kv_store::KVStore,
     processor::RaydiumAmmV4AccountProcessor, util::must_get_env,
 };
 use anyhow::Result;
@@ -13,7 +13,9 @@ use solana_client::rpc_config::{
 };
 use std::sync::Arc;
 
-pub fn make_raydium_rpc_accounts_pipeline() -> Result<Pipeline> {
+pub fn make_raydium_rpc_accounts_pipeline(
+    kv_store: Arc<dyn KVStore>,
+) -> Result<Pipeline> {
     let pipeline = Pipeline::builder()
         .datasource(RpcProgramSubscribe::new(
             must_get_env("WS_URL"),
@@ -29,7 +31,10 @@ pub fn make_raydium_rpc_accounts_pipeline() -> Result<Pipeline> {
                 }),
             ),
         ))
-        .account(RaydiumAmmV4Decoder, RaydiumAmmV4AccountProcessor::new())
+        .account(
+            RaydiumAmmV4Decoder,
+            RaydiumAmmV4AccountProcessor::new(kv_store),
+        )
         .shutdown_strategy(ShutdownStrategy::Immediate)
         .metrics(Arc::new(LogMetrics::new()))
         .build()?;
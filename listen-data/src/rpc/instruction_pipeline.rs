@@ -8,16 +8,15 @@ use carbon_rpc_transaction_crawler_datasource::{
 use std::{sync::Arc, time::Duration};
 
 use crate::{
-    constants::RAYDIUM_AMM_V4_PROGRAM_ID, db::ClickhouseDb,
-    handler::TokenSwapHandler, kv_store::RedisKVStore,
-    message_queue::RedisMessageQueue, metrics::SwapMetrics,
-    processor::RaydiumAmmV4InstructionProcessor,
+    constants::RAYDIUM_AMM_V4_PROGRAM_ID, db::Database,
+    handler::TokenSwapHandler, kv_store::KVStore, message_queue::MessageQueue,
+    metrics::SwapMetrics, processor::RaydiumAmmV4InstructionProcessor,
 };
 
 pub fn make_raydium_rpc_instruction_pipeline(
-    kv_store: Arc<RedisKVStore>,
-    message_queue: Arc<RedisMessageQueue>,
-    db: Arc<ClickhouseDb>,
+    kv_store: Arc<dyn KVStore>,
+    message_queue: Arc<dyn MessageQueue>,
+    db: Arc<dyn Database>,
     metrics: Arc<SwapMetrics>,
 ) -> Result<Pipeline> {
     let token_swap_handler =
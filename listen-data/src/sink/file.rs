@@ -0,0 +1,178 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+    sync::Mutex,
+};
+
+use crate::{
+    db::Database,
+    events::{LaunchEvent, LaunchEventRow, MigrationEvent},
+    message_queue::MessageQueue,
+    pool::PoolState,
+    price::PriceUpdate,
+};
+
+/// Appends every message as a JSON line to `<dir>/<stream>.jsonl`, the
+/// streams are named after the Redis channels and the ClickHouse tables
+pub struct FileSink {
+    dir: PathBuf,
+    writers: Mutex<HashMap<&'static str, BufWriter<File>>>,
+}
+
+impl FileSink {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            writers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn append<T: Serialize>(
+        &self,
+        stream: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        let mut writers = self.writers.lock().await;
+        let writer = match writers.entry(stream) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = self.dir.join(format!("{}.jsonl", stream));
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .await
+                    .with_context(|| {
+                        format!("failed to open {}", path.display())
+                    })?;
+                entry.insert(BufWriter::new(file))
+            }
+        };
+        writer.write_all(&line).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageQueue for FileSink {
+    async fn publish_price_update(
+        &self,
+        price_update: PriceUpdate,
+    ) -> Result<()> {
+        self.append("price_updates", &price_update).await
+    }
+
+    async fn publish_migration(&self, migration: MigrationEvent) -> Result<()> {
+        self.append("migrations", &migration).await
+    }
+
+    async fn publish_pool_update(&self, pool_state: PoolState) -> Result<()> {
+        self.append("pool_updates", &pool_state).await
+    }
+
+    async fn publish_launch_event(
+        &self,
+        launch_event: LaunchEvent,
+    ) -> Result<()> {
+        self.append("launch_events", &launch_event).await
+    }
+}
+
+#[async_trait::async_trait]
+impl Database for FileSink {
+    async fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        let is_dir = tokio::fs::metadata(&self.dir)
+            .await
+            .is_ok_and(|metadata| metadata.is_dir());
+        anyhow::ensure!(is_dir, "{} is gone", self.dir.display());
+        Ok(())
+    }
+
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
+        self.append("price_updates", price).await
+    }
+
+    async fn insert_pool_state(&self, pool_state: &PoolState) -> Result<()> {
+        self.append("pool_states", pool_state).await
+    }
+
+    async fn insert_launch_event(&self, event: &LaunchEvent) -> Result<()> {
+        self.append("launch_events", &LaunchEventRow::from(event))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::TokenLaunched;
+
+    #[tokio::test]
+    async fn test_file_sink() {
+        let dir = std::env::temp_dir().join(format!(
+            "file-sink-{}",
+            solana_sdk::pubkey::Pubkey::new_unique()
+        ));
+        let message_queue = FileSink::new(dir.join("mq")).unwrap();
+        let db = FileSink::new(dir.join("db")).unwrap();
+
+        let event = LaunchEvent::TokenLaunched(TokenLaunched {
+            mint: "mint".to_string(),
+            name: "Listen".to_string(),
+            symbol: "LSN".to_string(),
+            uri: "https://ipfs.io/ipfs/Qm".to_string(),
+            bonding_curve: "bonding_curve".to_string(),
+            creator: "creator".to_string(),
+            timestamp: 1_735_000_000,
+            slot: 310_000_000,
+            signature: "5sig".to_string(),
+        });
+        for _ in 0..2 {
+            message_queue
+                .publish_launch_event(event.clone())
+                .await
+                .unwrap();
+        }
+        db.insert_launch_event(&event).await.unwrap();
+
+        let read_lines = |path: PathBuf| {
+            std::fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect::<Vec<serde_json::Value>>()
+        };
+        let published = read_lines(dir.join("mq/launch_events.jsonl"));
+        assert_eq!(published.len(), 2);
+        assert_eq!(
+            serde_json::from_value::<LaunchEvent>(published[1].clone())
+                .unwrap(),
+            event
+        );
+
+        // the database gets the flat rows of the table
+        let rows = read_lines(dir.join("db/launch_events.jsonl"));
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["kind"], "TokenLaunched");
+        assert_eq!(rows[0]["address"], "bonding_curve");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
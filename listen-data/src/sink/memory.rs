@@ -0,0 +1,102 @@
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use crate::{
+    db::Database,
+    events::{LaunchEvent, MigrationEvent},
+    message_queue::MessageQueue,
+    pool::PoolState,
+    price::PriceUpdate,
+};
+
+/// Keeps everything it is sent in memory, for tests and dry runs; use
+/// separate instances for the message queue and the database
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    price_updates: Mutex<Vec<PriceUpdate>>,
+    pool_states: Mutex<Vec<PoolState>>,
+    migrations: Mutex<Vec<MigrationEvent>>,
+    launch_events: Mutex<Vec<LaunchEvent>>,
+}
+
+fn push<T>(items: &Mutex<Vec<T>>, item: T) {
+    items.lock().expect("memory sink lock poisoned").push(item);
+}
+
+fn snapshot<T: Clone>(items: &Mutex<Vec<T>>) -> Vec<T> {
+    items.lock().expect("memory sink lock poisoned").clone()
+}
+
+impl MemorySink {
+    pub fn price_updates(&self) -> Vec<PriceUpdate> {
+        snapshot(&self.price_updates)
+    }
+
+    pub fn pool_states(&self) -> Vec<PoolState> {
+        snapshot(&self.pool_states)
+    }
+
+    pub fn migrations(&self) -> Vec<MigrationEvent> {
+        snapshot(&self.migrations)
+    }
+
+    pub fn launch_events(&self) -> Vec<LaunchEvent> {
+        snapshot(&self.launch_events)
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageQueue for MemorySink {
+    async fn publish_price_update(
+        &self,
+        price_update: PriceUpdate,
+    ) -> Result<()> {
+        push(&self.price_updates, price_update);
+        Ok(())
+    }
+
+    async fn publish_migration(&self, migration: MigrationEvent) -> Result<()> {
+        push(&self.migrations, migration);
+        Ok(())
+    }
+
+    async fn publish_pool_update(&self, pool_state: PoolState) -> Result<()> {
+        push(&self.pool_states, pool_state);
+        Ok(())
+    }
+
+    async fn publish_launch_event(
+        &self,
+        launch_event: LaunchEvent,
+    ) -> Result<()> {
+        push(&self.launch_events, launch_event);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Database for MemorySink {
+    async fn initialize(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn insert_price(&self, price: &PriceUpdate) -> Result<()> {
+        push(&self.price_updates, price.clone());
+        Ok(())
+    }
+
+    async fn insert_pool_state(&self, pool_state: &PoolState) -> Result<()> {
+        push(&self.pool_states, pool_state.clone());
+        Ok(())
+    }
+
+    async fn insert_launch_event(&self, event: &LaunchEvent) -> Result<()> {
+        push(&self.launch_events, event.clone());
+        Ok(())
+    }
+}
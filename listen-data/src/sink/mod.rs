@@ -0,0 +1,87 @@
+//! Backends of the message queue and the database besides Redis and
+//! ClickHouse, selected with the `MESSAGE_QUEUE` and `DATABASE` env vars
+//!
+//! The value is the name of the backend, `redis`, `clickhouse`, `nats`,
+//! `memory` or `file:<dir>` for JSON lines files in `dir`; `KV_STORE` picks
+//! `redis` or `memory` for the kv store the same way
+
+mod file;
+mod memory;
+#[cfg(feature = "nats")]
+mod nats;
+
+pub use file::FileSink;
+pub use memory::MemorySink;
+#[cfg(feature = "nats")]
+pub use nats::NatsMessageQueue;
+
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkConfig {
+    Redis,
+    Clickhouse,
+    Nats,
+    Memory,
+    File(PathBuf),
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SinkConfigError {
+    #[error("unknown sink: {0}")]
+    UnknownSink(String),
+    #[error("file sink without a directory, expected file:<dir>")]
+    MissingDirectory,
+}
+
+impl SinkConfig {
+    pub fn parse(value: &str) -> Result<Self, SinkConfigError> {
+        match value.trim() {
+            "redis" => Ok(Self::Redis),
+            "clickhouse" => Ok(Self::Clickhouse),
+            "nats" => Ok(Self::Nats),
+            "memory" => Ok(Self::Memory),
+            value => match value.strip_prefix("file:") {
+                Some("") => Err(SinkConfigError::MissingDirectory),
+                Some(dir) => Ok(Self::File(PathBuf::from(dir))),
+                None => Err(SinkConfigError::UnknownSink(value.to_string())),
+            },
+        }
+    }
+
+    /// the sink configured with the env var `key`, `default` if it is unset
+    pub fn from_env(
+        key: &str,
+        default: SinkConfig,
+    ) -> Result<Self, SinkConfigError> {
+        match std::env::var(key) {
+            Ok(value) if !value.trim().is_empty() => Self::parse(&value),
+            _ => Ok(default),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sink_config() {
+        assert_eq!(SinkConfig::parse("redis"), Ok(SinkConfig::Redis));
+        assert_eq!(SinkConfig::parse(" nats "), Ok(SinkConfig::Nats));
+        assert_eq!(
+            SinkConfig::parse("file:/var/lib/listen"),
+            Ok(SinkConfig::File(PathBuf::from("/var/lib/listen")))
+        );
+        assert_eq!(
+            SinkConfig::parse("file:"),
+            Err(SinkConfigError::MissingDirectory)
+        );
+        assert_eq!(
+            SinkConfig::parse("kafka"),
+            Err(SinkConfigError::UnknownSink("kafka".to_string()))
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use tracing::info;
+
+use crate::{
+    events::{LaunchEvent, MigrationEvent},
+    message_queue::MessageQueue,
+    pool::PoolState,
+    price::PriceUpdate,
+};
+
+/// Publishes on NATS subjects named like the Redis channels
+pub struct NatsMessageQueue {
+    client: async_nats::Client,
+}
+
+impl NatsMessageQueue {
+    pub async fn new(nats_url: &str) -> Result<Self> {
+        let client = async_nats::connect(nats_url)
+            .await
+            .with_context(|| format!("failed to connect to {}", nats_url))?;
+        info!("Connected to NATS at {}", nats_url);
+        Ok(Self { client })
+    }
+
+    async fn publish<T: serde::Serialize + Sync>(
+        &self,
+        subject: &'static str,
+        message: &T,
+    ) -> Result<()> {
+        let payload = serde_json::to_vec(message)?;
+        self.client
+            .publish(subject, payload.into())
+            .await
+            .with_context(|| format!("failed to publish on {}", subject))
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageQueue for NatsMessageQueue {
+    async fn publish_price_update(
+        &self,
+        price_update: PriceUpdate,
+    ) -> Result<()> {
+        self.publish("price_updates", &price_update).await
+    }
+
+    async fn publish_migration(&self, migration: MigrationEvent) -> Result<()> {
+        self.publish("migrations", &migration).await
+    }
+
+    async fn publish_pool_update(&self, pool_state: PoolState) -> Result<()> {
+        self.publish("pool_updates", &pool_state).await
+    }
+
+    async fn publish_launch_event(
+        &self,
+        launch_event: LaunchEvent,
+    ) -> Result<()> {
+        self.publish("launch_events", &launch_event).await
+    }
+}
@@ -1,7 +1,5 @@
 use crate::{
-    kv_store::RedisKVStore,
-    message_queue::{MessageQueue, RedisMessageQueue},
-    price::PriceUpdate,
+    kv_store::KVStore, message_queue::MessageQueue, price::PriceUpdate,
 };
 use anyhow::Result;
 use chrono::Utc;
@@ -32,14 +30,14 @@ struct BinancePrice {
 #[derive(Debug, Clone)]
 pub struct SolPriceCache {
     price: Arc<RwLock<f64>>,
-    message_queue: Option<Arc<RedisMessageQueue>>,
-    kv_store: Option<Arc<RedisKVStore>>,
+    message_queue: Option<Arc<dyn MessageQueue>>,
+    kv_store: Option<Arc<dyn KVStore>>,
 }
 
 impl SolPriceCache {
     pub fn new(
-        kv_store: Option<Arc<RedisKVStore>>,
-        message_queue: Option<Arc<RedisMessageQueue>>,
+        kv_store: Option<Arc<dyn KVStore>>,
+        message_queue: Option<Arc<dyn MessageQueue>>,
     ) -> Self {
         Self {
             price: SOL_PRICE_CACHE.clone(), // Use the global price cache
@@ -62,6 +60,8 @@ impl SolPriceCache {
             multi_hop: false,
             is_buy: false,
             is_pump: false,
+            quote_mint: crate::constants::USDT_MINT_KEY_STR.to_string(), // SOLUSDT
+            has_metadata: true,
         };
         if let Some(kv_store) = &self.kv_store {
             kv_store.insert_price(&price_update).await?;
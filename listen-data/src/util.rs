@@ -1,12 +1,16 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bb8_redis::{bb8, RedisConnectionManager};
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::epoch_schedule::EpochSchedule;
 use std::{fs::File, io::BufWriter, sync::Arc};
 
+#[cfg(feature = "nats")]
+use crate::sink::NatsMessageQueue;
 use crate::{
     db::{ClickhouseDb, Database},
-    kv_store::RedisKVStore,
-    message_queue::RedisMessageQueue,
+    kv_store::{KVStore, MemoryKVStore, RedisKVStore},
+    message_queue::{MessageQueue, RedisMessageQueue},
+    sink::{FileSink, MemorySink, SinkConfig},
 };
 
 pub fn is_local() -> bool {
@@ -18,53 +22,113 @@ pub fn make_rpc_client() -> Result<RpcClient> {
     Ok(rpc_client)
 }
 
-pub async fn make_kv_store() -> Result<Arc<RedisKVStore>> {
-    match is_local() {
-        true => {
-            let kv_store = RedisKVStore::new("redis://localhost:6379").await?;
-            Ok(Arc::new(kv_store))
-        }
-        false => {
-            let kv_store =
-                RedisKVStore::new(must_get_env("REDIS_URL").as_str()).await?;
-            Ok(Arc::new(kv_store))
-        }
+static EPOCH_SCHEDULE: tokio::sync::OnceCell<EpochSchedule> =
+    tokio::sync::OnceCell::const_new();
+
+/// Epoch of the slot by the epoch schedule of the cluster, the schedule is
+/// fetched once
+pub async fn get_epoch(slot: u64) -> Result<u64> {
+    let schedule = EPOCH_SCHEDULE
+        .get_or_try_init(|| async {
+            Ok::<_, anyhow::Error>(
+                make_rpc_client()?.get_epoch_schedule().await?,
+            )
+        })
+        .await?;
+    Ok(schedule.get_epoch(slot))
+}
+
+/// Sets the epoch schedule instead of fetching it, e.g. for replays that
+/// should not need RPC; a no-op once the schedule is known
+pub fn set_epoch_schedule(schedule: EpochSchedule) {
+    let _ = EPOCH_SCHEDULE.set(schedule);
+}
+
+/// the kv store configured with `KV_STORE`, `redis` or `memory`; by default
+/// it is kept in memory when the message queue and the database are
+pub async fn make_kv_store() -> Result<Arc<dyn KVStore>> {
+    let default = default_kv_store(
+        &SinkConfig::from_env("MESSAGE_QUEUE", SinkConfig::Redis)?,
+        &SinkConfig::from_env("DATABASE", SinkConfig::Clickhouse)?,
+    );
+    let kv_store: Arc<dyn KVStore> =
+        match SinkConfig::from_env("KV_STORE", default)? {
+            SinkConfig::Redis => {
+                Arc::new(RedisKVStore::new(&redis_url()).await?)
+            }
+            SinkConfig::Memory => Arc::new(MemoryKVStore::default()),
+            sink => bail!("{:?} is not a kv store", sink),
+        };
+    Ok(kv_store)
+}
+
+/// Redis, unless neither the message queue nor the database leaves the
+/// process, so that the memory and file sinks run without Redis
+fn default_kv_store(message_queue: &SinkConfig, db: &SinkConfig) -> SinkConfig {
+    let is_local = |sink: &SinkConfig| {
+        matches!(sink, SinkConfig::Memory | SinkConfig::File(_))
+    };
+    match is_local(message_queue) && is_local(db) {
+        true => SinkConfig::Memory,
+        false => SinkConfig::Redis,
     }
 }
 
-pub async fn make_message_queue() -> Result<Arc<RedisMessageQueue>> {
+fn redis_url() -> String {
     match is_local() {
-        true => {
-            let message_queue =
-                RedisMessageQueue::new("redis://localhost:6379").await?;
-            Ok(Arc::new(message_queue))
-        }
-        false => {
-            let message_queue =
-                RedisMessageQueue::new(must_get_env("REDIS_URL").as_str())
-                    .await?;
-            Ok(Arc::new(message_queue))
-        }
+        true => "redis://localhost:6379".to_string(),
+        false => must_get_env("REDIS_URL"),
     }
 }
 
-pub async fn make_db() -> Result<Arc<ClickhouseDb>> {
-    let mut db = match is_local() {
-        true => ClickhouseDb::new(
-            "http://localhost:8123",
-            "default",
-            "default",
-            "default",
-        ),
-        false => ClickhouseDb::new(
-            must_get_env("CLICKHOUSE_URL").as_str(),
-            must_get_env("CLICKHOUSE_PASSWORD").as_str(),
-            must_get_env("CLICKHOUSE_USER").as_str(),
-            must_get_env("CLICKHOUSE_DATABASE").as_str(),
-        ),
-    };
+/// the message queue configured with `MESSAGE_QUEUE`, Redis by default
+pub async fn make_message_queue() -> Result<Arc<dyn MessageQueue>> {
+    let message_queue: Arc<dyn MessageQueue> =
+        match SinkConfig::from_env("MESSAGE_QUEUE", SinkConfig::Redis)? {
+            SinkConfig::Redis => {
+                Arc::new(RedisMessageQueue::new(&redis_url()).await?)
+            }
+            #[cfg(feature = "nats")]
+            SinkConfig::Nats => Arc::new(
+                NatsMessageQueue::new(&must_get_env("NATS_URL")).await?,
+            ),
+            #[cfg(not(feature = "nats"))]
+            SinkConfig::Nats => {
+                bail!("the nats message queue needs the nats feature")
+            }
+            SinkConfig::Memory => Arc::new(MemorySink::default()),
+            SinkConfig::File(dir) => Arc::new(FileSink::new(dir)?),
+            SinkConfig::Clickhouse => {
+                bail!("clickhouse is not a message queue")
+            }
+        };
+    Ok(message_queue)
+}
+
+/// the database configured with `DATABASE`, ClickHouse by default
+pub async fn make_db() -> Result<Arc<dyn Database>> {
+    let mut db: Box<dyn Database> =
+        match SinkConfig::from_env("DATABASE", SinkConfig::Clickhouse)? {
+            SinkConfig::Clickhouse => Box::new(match is_local() {
+                true => ClickhouseDb::new(
+                    "http://localhost:8123",
+                    "default",
+                    "default",
+                    "default",
+                ),
+                false => ClickhouseDb::new(
+                    must_get_env("CLICKHOUSE_URL").as_str(),
+                    must_get_env("CLICKHOUSE_PASSWORD").as_str(),
+                    must_get_env("CLICKHOUSE_USER").as_str(),
+                    must_get_env("CLICKHOUSE_DATABASE").as_str(),
+                ),
+            }),
+            SinkConfig::Memory => Box::new(MemorySink::default()),
+            SinkConfig::File(dir) => Box::new(FileSink::new(dir)?),
+            sink => bail!("{:?} is not a database", sink),
+        };
     db.initialize().await?;
-    Ok(Arc::new(db))
+    Ok(Arc::from(db))
 }
 
 pub fn write_json(data: &str, file_name: &str) -> Result<()> {
@@ -132,4 +196,21 @@ mod tests {
         assert!(price.is_ok());
         assert!(price.unwrap() > 0.0);
     }
+
+    #[test]
+    fn test_default_kv_store() {
+        let file = SinkConfig::File("/tmp/listen".into());
+        assert_eq!(
+            default_kv_store(&SinkConfig::Memory, &file),
+            SinkConfig::Memory
+        );
+        assert_eq!(
+            default_kv_store(&SinkConfig::Redis, &file),
+            SinkConfig::Redis
+        );
+        assert_eq!(
+            default_kv_store(&SinkConfig::Memory, &SinkConfig::Clickhouse),
+            SinkConfig::Redis
+        );
+    }
 }
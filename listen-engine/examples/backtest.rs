@@ -0,0 +1,53 @@
+use anyhow::Result;
+
+use listen_engine::engine::{
+    api::{PipelineParams, WirePipeline},
+    backtest::{run_backtest, BacktestConfig, ClickhouseSource, PriceSource},
+    paper::PaperConfig,
+    pipeline::Pipeline,
+};
+
+// cargo run --example backtest -- <pipeline.json> <prices.jsonl>
+// cargo run --example backtest -- <pipeline.json> <from> <to>  (ClickHouse)
+#[tokio::main]
+async fn main() -> Result<()> {
+    std::env::set_var("RUST_LOG", "info");
+    tracing_subscriber::fmt::init();
+    dotenv::dotenv().ok();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let wire: WirePipeline = serde_json::from_str(&std::fs::read_to_string(&args[0])?)?;
+    if let Err(e) = wire.validate() {
+        anyhow::bail!("Invalid pipeline: {}", e);
+    }
+    let pipeline: Pipeline = (
+        wire,
+        PipelineParams {
+            user_id: "backtest".to_string(),
+            wallet_address: None,
+            pubkey: None,
+        },
+    )
+        .into();
+
+    let source = match args.len() {
+        2 => PriceSource::Jsonl(args[1].clone().into()),
+        _ => PriceSource::Clickhouse(ClickhouseSource {
+            url: std::env::var("CLICKHOUSE_URL")?,
+            user: std::env::var("CLICKHOUSE_USER")?,
+            password: std::env::var("CLICKHOUSE_PASSWORD")?,
+            database: std::env::var("CLICKHOUSE_DATABASE")?,
+            from: args[1].parse()?,
+            to: args[2].parse()?,
+        }),
+    };
+
+    let config = BacktestConfig {
+        paper: PaperConfig::from_env(),
+        ..Default::default()
+    };
+    let report = run_backtest(pipeline, &source, config).await?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
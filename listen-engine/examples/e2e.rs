@@ -61,6 +61,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             status: Status::Pending,
             transaction_hash: None,
             error: None,
+            expires_at: None,
+            completed_at: None,
+            fills: vec![],
+            pending: None,
         },
     );
     let mut pipeline = Pipeline {
@@ -72,6 +76,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         steps,
         status: Status::Pending,
         created_at: chrono::Utc::now(),
+        child_executions: vec![],
+        paper: false,
+        notifications: None,
     };
     engine.evaluate_pipeline(&mut pipeline).await.unwrap();
     Ok(())
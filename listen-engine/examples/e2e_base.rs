@@ -45,6 +45,10 @@ async fn main() -> Result<()> {
             status: Status::Pending,
             transaction_hash: None,
             error: None,
+            expires_at: None,
+            completed_at: None,
+            fills: vec![],
+            pending: None,
         },
     );
 
@@ -57,6 +61,9 @@ async fn main() -> Result<()> {
         steps,
         status: Status::Pending,
         created_at: chrono::Utc::now(),
+        child_executions: vec![],
+        paper: false,
+        notifications: None,
     };
 
     engine.evaluate_pipeline(&mut pipeline).await.unwrap();
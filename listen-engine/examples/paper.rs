@@ -0,0 +1,99 @@
+use anyhow::Result;
+use std::collections::HashMap;
+
+use listen_engine::{
+    engine::{
+        order::SwapOrder,
+        paper::{SOL_MINT, USDC_MINT},
+        pipeline::{Action, Condition, ConditionType, Pipeline, PipelineStep, Status},
+    },
+    redis::subscriber::PriceUpdate,
+    Engine,
+};
+use uuid::Uuid;
+
+// paper trading against a local Redis, no Privy, LiFi or Jupiter needed
+#[tokio::main]
+async fn main() -> Result<()> {
+    std::env::set_var("RUST_LOG", "info");
+    std::env::set_var("PAPER_TRADING_ONLY", "true");
+    tracing_subscriber::fmt::init();
+    dotenv::dotenv().ok();
+
+    let (engine, _) = Engine::from_env().await?;
+    let user_id = "paper-user".to_string();
+
+    engine
+        .redis
+        .set(
+            &format!("solana:price:{}", SOL_MINT),
+            &PriceUpdate {
+                name: "SOL".to_string(),
+                pubkey: SOL_MINT.to_string(),
+                price: 200.0,
+                market_cap: 0.0,
+                timestamp: chrono::Utc::now().timestamp() as u64,
+                slot: 0,
+                swap_amount: 0.0,
+                owner: String::new(),
+                signature: String::new(),
+                multi_hop: false,
+                is_buy: true,
+                is_pump: false,
+                has_metadata: true,
+            },
+        )
+        .await?;
+
+    let step_id = Uuid::new_v4();
+    let mut steps = HashMap::new();
+    steps.insert(
+        step_id,
+        PipelineStep {
+            id: step_id,
+            action: Action::Order(SwapOrder {
+                input_token: USDC_MINT.to_string(),
+                output_token: SOL_MINT.to_string(),
+                amount: "100000000".to_string(), // 100 USDC
+                from_chain_caip2: "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp".to_string(),
+                to_chain_caip2: "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp".to_string(),
+            }),
+            conditions: vec![Condition {
+                condition_type: ConditionType::Now {
+                    asset: "".to_string(),
+                },
+                triggered: false,
+                last_evaluated: None,
+            }],
+            next_steps: vec![],
+            status: Status::Pending,
+            transaction_hash: None,
+            error: None,
+            expires_at: None,
+            completed_at: None,
+            fills: vec![],
+            pending: None,
+        },
+    );
+    let mut pipeline = Pipeline {
+        id: Uuid::new_v4(),
+        user_id: user_id.clone(),
+        wallet_address: None,
+        pubkey: None,
+        current_steps: vec![step_id],
+        steps,
+        status: Status::Pending,
+        created_at: chrono::Utc::now(),
+        child_executions: vec![],
+        paper: true,
+        notifications: None,
+    };
+    engine.evaluate_pipeline(&mut pipeline).await?;
+
+    tracing::info!("Step: {:#?}", pipeline.steps[&step_id]);
+    tracing::info!(
+        "Balances: {:#?}",
+        engine.get_paper_balances(&user_id).await?
+    );
+    Ok(())
+}
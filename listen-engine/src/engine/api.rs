@@ -1,12 +1,17 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use std::collections::HashMap;
 use uuid::Uuid;
 
+use super::events::StepNotifications;
+use super::market::MAX_WINDOW_SECS;
+use super::notifications::ChannelKind;
 use super::order::SwapOrder;
 use super::pipeline::{
     Action, Condition, ConditionType, Notification, Pipeline, PipelineStep, Status,
+    TakeProfitTarget,
 };
+use super::recurring::{PriceGuard, RecurringOrder};
 
 #[derive(Debug, Deserialize)]
 pub enum WireActionType {
@@ -14,6 +19,8 @@ pub enum WireActionType {
     SwapOrder,
     #[serde(rename = "Notification")]
     Notification,
+    #[serde(rename = "RecurringSwapOrder")]
+    RecurringSwapOrder,
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,6 +31,24 @@ pub enum WireConditionType {
     PriceBelow,
     #[serde(rename = "Now")]
     Now,
+    #[serde(rename = "TrailingStop")]
+    TrailingStop,
+    #[serde(rename = "TakeProfitLadder")]
+    TakeProfitLadder,
+    #[serde(rename = "At")]
+    At,
+    #[serde(rename = "After")]
+    After,
+    #[serde(rename = "MarketCapAbove")]
+    MarketCapAbove,
+    #[serde(rename = "MarketCapBelow")]
+    MarketCapBelow,
+    #[serde(rename = "VolumeInWindowAbove")]
+    VolumeInWindowAbove,
+    #[serde(rename = "PercentChange")]
+    PercentChange,
+    #[serde(rename = "BuySellRatioAbove")]
+    BuySellRatioAbove,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -43,14 +68,55 @@ pub enum WireAction {
     Notification {
         input_token: String,
         message: String,
+        #[serde(default)]
+        channels: Vec<ChannelKind>,
+    },
+    /// DCA / TWAP, `amount` is the total amount split between the slices
+    #[serde(rename = "RecurringSwapOrder")]
+    RecurringSwapOrder {
+        input_token: String,
+        output_token: String,
+        amount: String,
+        #[serde(default)]
+        from_chain_caip2: Option<String>,
+        #[serde(default)]
+        to_chain_caip2: Option<String>,
+        slices: u32,
+        interval_secs: u64,
+        /// slices are skipped while the price of this asset is outside of the bounds
+        #[serde(default)]
+        guard_asset: Option<String>,
+        #[serde(default)]
+        min_price: Option<f64>,
+        #[serde(default)]
+        max_price: Option<f64>,
     },
 }
 
 #[derive(Debug, Deserialize)]
 pub struct WireCondition {
     pub r#type: WireConditionType,
+    #[serde(default)]
     pub asset: String,
+    /// price for PriceAbove/PriceBelow, trail percentage for TrailingStop,
+    /// percentage for PercentChange, usd for the market cap and volume conditions
+    #[serde(default)]
     pub value: f64,
+    /// target prices for TakeProfitLadder
+    #[serde(default)]
+    pub targets: Vec<f64>,
+    /// time for At
+    #[serde(default)]
+    pub at: Option<DateTime<Utc>>,
+    /// index of the previous step in the pipeline for After
+    #[serde(default)]
+    pub step: Option<usize>,
+    /// delay after the completion of `step` for After
+    #[serde(default)]
+    pub delay_secs: u64,
+    /// lookback for VolumeInWindowAbove, PercentChange and BuySellRatioAbove
+    #[serde(default)]
+    pub window_secs: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -58,11 +124,97 @@ pub struct WireStep {
     pub action: WireAction,
     #[serde(default)]
     pub conditions: Vec<WireCondition>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct WirePipeline {
     pub steps: Vec<WireStep>,
+    /// paper trading, no funds are spent
+    #[serde(default)]
+    pub paper: bool,
+    /// notify when a step completes or fails
+    #[serde(default)]
+    pub notifications: Option<StepNotifications>,
+}
+
+pub const MAX_RECURRING_SLICES: u32 = 1000;
+
+impl WirePipeline {
+    /// Checks the condition parameters that the conversion into `Pipeline` relies on
+    pub fn validate(&self) -> Result<(), String> {
+        for (idx, step) in self.steps.iter().enumerate() {
+            if let WireAction::RecurringSwapOrder {
+                slices,
+                interval_secs,
+                guard_asset,
+                min_price,
+                max_price,
+                ..
+            } = &step.action
+            {
+                if *slices == 0 || *slices > MAX_RECURRING_SLICES {
+                    return Err(format!(
+                        "step {}: slices must be between 1 and {}",
+                        idx, MAX_RECURRING_SLICES
+                    ));
+                }
+                if *slices > 1 && *interval_secs == 0 {
+                    return Err(format!("step {}: interval_secs must be positive", idx));
+                }
+                if guard_asset.is_none() && (min_price.is_some() || max_price.is_some()) {
+                    return Err(format!(
+                        "step {}: guard_asset is required for min_price/max_price",
+                        idx
+                    ));
+                }
+            }
+            for condition in &step.conditions {
+                match condition.r#type {
+                    WireConditionType::TrailingStop
+                        if !(condition.value > 0.0 && condition.value < 100.0) =>
+                    {
+                        return Err(format!(
+                            "step {}: TrailingStop value must be a percentage between 0 and 100",
+                            idx
+                        ));
+                    }
+                    WireConditionType::TakeProfitLadder if condition.targets.is_empty() => {
+                        return Err(format!(
+                            "step {}: TakeProfitLadder requires at least one target",
+                            idx
+                        ));
+                    }
+                    WireConditionType::At if condition.at.is_none() => {
+                        return Err(format!("step {}: At requires `at`", idx));
+                    }
+                    WireConditionType::VolumeInWindowAbove
+                    | WireConditionType::PercentChange
+                    | WireConditionType::BuySellRatioAbove
+                        if condition.window_secs == 0
+                            || condition.window_secs > MAX_WINDOW_SECS =>
+                    {
+                        return Err(format!(
+                            "step {}: window_secs must be between 1 and {}",
+                            idx, MAX_WINDOW_SECS
+                        ));
+                    }
+                    WireConditionType::After => match condition.step {
+                        Some(previous) if previous < idx => {}
+                        _ => {
+                            return Err(format!(
+                                "step {}: After requires `step` pointing to a previous step",
+                                idx
+                            ));
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 pub struct PipelineParams {
@@ -81,7 +233,8 @@ impl From<(WirePipeline, PipelineParams)> for Pipeline {
         let current_steps = step_ids.clone();
 
         for (step, id) in wire.steps.iter().zip(step_ids.iter()) {
-            let pipeline_step: PipelineStep = step.into();
+            let mut pipeline_step = PipelineStep::from((step, step_ids.as_slice()));
+            pipeline_step.id = *id;
             steps.insert(*id, pipeline_step);
         }
 
@@ -94,12 +247,23 @@ impl From<(WirePipeline, PipelineParams)> for Pipeline {
             steps,
             status: Status::Pending,
             created_at: Utc::now(),
+            child_executions: Vec::new(),
+            paper: wire.paper,
+            notifications: wire.notifications,
         }
     }
 }
 
 impl From<&WireStep> for PipelineStep {
     fn from(wire: &WireStep) -> Self {
+        Self::from((wire, &[] as &[Uuid]))
+    }
+}
+
+/// `step_ids` are the ids of the steps of the pipeline, in the wire order,
+/// used to resolve the step references of `After` conditions
+impl From<(&WireStep, &[Uuid])> for PipelineStep {
+    fn from((wire, step_ids): (&WireStep, &[Uuid])) -> Self {
         let conditions = if wire.conditions.is_empty() {
             vec![Condition {
                 condition_type: ConditionType::Now {
@@ -109,7 +273,10 @@ impl From<&WireStep> for PipelineStep {
                 last_evaluated: None,
             }]
         } else {
-            wire.conditions.iter().map(Into::into).collect()
+            wire.conditions
+                .iter()
+                .map(|condition| Condition::from((condition, step_ids)))
+                .collect()
         };
 
         PipelineStep {
@@ -120,6 +287,10 @@ impl From<&WireStep> for PipelineStep {
             status: Status::Pending,
             transaction_hash: None,
             error: None,
+            expires_at: wire.expires_at,
+            completed_at: None,
+            fills: vec![],
+            pending: None,
         }
     }
 }
@@ -133,35 +304,69 @@ impl From<&WireAction> for Action {
                 amount,
                 from_chain_caip2,
                 to_chain_caip2,
-            } => {
-                const DEFAULT_SOLANA_CHAIN: &str = "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp";
-                const SOLANA_NUMERIC_ID: &str = "1151111081099710";
-
-                let convert_chain_id = |chain_id: &Option<String>| -> String {
-                    match chain_id {
-                        Some(id) if id == SOLANA_NUMERIC_ID => DEFAULT_SOLANA_CHAIN.to_string(),
-                        Some(id) => id.clone(),
-                        None => DEFAULT_SOLANA_CHAIN.to_string(),
-                    }
-                };
-
-                Action::Order(SwapOrder {
+            } => Action::Order(SwapOrder {
+                input_token: input_token.clone(),
+                output_token: output_token.clone(),
+                amount: amount.clone(),
+                from_chain_caip2: convert_chain_id(from_chain_caip2),
+                to_chain_caip2: convert_chain_id(to_chain_caip2),
+            }),
+            WireAction::Notification {
+                message, channels, ..
+            } => Action::Notification(Notification {
+                message: message.clone(),
+                channels: channels.clone(),
+            }),
+            WireAction::RecurringSwapOrder {
+                input_token,
+                output_token,
+                amount,
+                from_chain_caip2,
+                to_chain_caip2,
+                slices,
+                interval_secs,
+                guard_asset,
+                min_price,
+                max_price,
+            } => Action::Recurring(RecurringOrder {
+                order: SwapOrder {
                     input_token: input_token.clone(),
                     output_token: output_token.clone(),
                     amount: amount.clone(),
                     from_chain_caip2: convert_chain_id(from_chain_caip2),
                     to_chain_caip2: convert_chain_id(to_chain_caip2),
-                })
-            }
-            WireAction::Notification { message, .. } => Action::Notification(Notification {
-                message: message.clone(),
+                },
+                slices: *slices,
+                interval_secs: *interval_secs,
+                price_guard: guard_asset.as_ref().map(|asset| PriceGuard {
+                    asset: asset.clone(),
+                    min_price: *min_price,
+                    max_price: *max_price,
+                }),
             }),
         }
     }
 }
 
+fn convert_chain_id(chain_id: &Option<String>) -> String {
+    const DEFAULT_SOLANA_CHAIN: &str = "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp";
+    const SOLANA_NUMERIC_ID: &str = "1151111081099710";
+
+    match chain_id {
+        Some(id) if id == SOLANA_NUMERIC_ID => DEFAULT_SOLANA_CHAIN.to_string(),
+        Some(id) => id.clone(),
+        None => DEFAULT_SOLANA_CHAIN.to_string(),
+    }
+}
+
 impl From<&WireCondition> for Condition {
     fn from(wire: &WireCondition) -> Self {
+        Self::from((wire, &[] as &[Uuid]))
+    }
+}
+
+impl From<(&WireCondition, &[Uuid])> for Condition {
+    fn from((wire, step_ids): (&WireCondition, &[Uuid])) -> Self {
         let condition_type = match wire.r#type {
             WireConditionType::PriceAbove => ConditionType::PriceAbove {
                 asset: wire.asset.clone(),
@@ -174,6 +379,55 @@ impl From<&WireCondition> for Condition {
             WireConditionType::Now => ConditionType::Now {
                 asset: wire.asset.clone(),
             },
+            WireConditionType::TrailingStop => ConditionType::TrailingStop {
+                asset: wire.asset.clone(),
+                trail_pct: wire.value,
+                peak: None,
+            },
+            WireConditionType::TakeProfitLadder => ConditionType::TakeProfitLadder {
+                asset: wire.asset.clone(),
+                targets: wire
+                    .targets
+                    .iter()
+                    .map(|price| TakeProfitTarget {
+                        price: *price,
+                        hit: false,
+                    })
+                    .collect(),
+            },
+            // validated beforehand, never triggers if not set
+            WireConditionType::At => ConditionType::At(wire.at.unwrap_or(DateTime::<Utc>::MAX_UTC)),
+            // unresolved step references fail on evaluation
+            WireConditionType::After => ConditionType::After {
+                step: wire
+                    .step
+                    .and_then(|idx| step_ids.get(idx).copied())
+                    .unwrap_or_default(),
+                delay_secs: wire.delay_secs,
+            },
+            WireConditionType::MarketCapAbove => ConditionType::MarketCapAbove {
+                asset: wire.asset.clone(),
+                value: wire.value,
+            },
+            WireConditionType::MarketCapBelow => ConditionType::MarketCapBelow {
+                asset: wire.asset.clone(),
+                value: wire.value,
+            },
+            WireConditionType::VolumeInWindowAbove => ConditionType::VolumeInWindowAbove {
+                asset: wire.asset.clone(),
+                window_secs: wire.window_secs,
+                value: wire.value,
+            },
+            WireConditionType::PercentChange => ConditionType::PercentChange {
+                asset: wire.asset.clone(),
+                window_secs: wire.window_secs,
+                pct: wire.value,
+            },
+            WireConditionType::BuySellRatioAbove => ConditionType::BuySellRatioAbove {
+                asset: wire.asset.clone(),
+                window_secs: wire.window_secs,
+                value: wire.value,
+            },
         };
 
         Condition {
@@ -307,4 +561,272 @@ mod tests {
             panic!("Expected SwapOrder action");
         }
     }
+
+    #[test]
+    fn test_wire_trailing_stop_and_ladder_conditions() {
+        let json = json!({
+            "action": {
+                "type": "SwapOrder",
+                "input_token": "5mbK36SZ7J19An8jFochhQS4of8g6BwUjbeCSxBSoWdp",
+                "output_token": "So11111111111111111111111111111111111111112",
+                "amount": "1000000"
+            },
+            "conditions": [
+                {
+                    "type": "TrailingStop",
+                    "asset": "5mbK36SZ7J19An8jFochhQS4of8g6BwUjbeCSxBSoWdp",
+                    "value": 15.0
+                },
+                {
+                    "type": "TakeProfitLadder",
+                    "asset": "5mbK36SZ7J19An8jFochhQS4of8g6BwUjbeCSxBSoWdp",
+                    "targets": [0.1, 0.2]
+                }
+            ]
+        });
+
+        let wire_step: WireStep = serde_json::from_value(json).unwrap();
+        let pipeline_step: PipelineStep = (&wire_step).into();
+
+        match &pipeline_step.conditions[0].condition_type {
+            ConditionType::TrailingStop {
+                trail_pct, peak, ..
+            } => {
+                assert_eq!(*trail_pct, 15.0);
+                assert!(peak.is_none());
+            }
+            _ => panic!("Expected TrailingStop condition type"),
+        }
+
+        match &pipeline_step.conditions[1].condition_type {
+            ConditionType::TakeProfitLadder { targets, .. } => {
+                assert_eq!(targets.len(), 2);
+                assert!(targets.iter().all(|t| !t.hit));
+            }
+            _ => panic!("Expected TakeProfitLadder condition type"),
+        }
+    }
+
+    #[test]
+    fn test_wire_pipeline_time_conditions() {
+        let json = json!({
+            "steps": [
+                {
+                    "action": {
+                        "type": "SwapOrder",
+                        "input_token": "So11111111111111111111111111111111111111112",
+                        "output_token": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                        "amount": "1000000"
+                    },
+                    "conditions": [
+                        { "type": "At", "at": "2030-01-01T14:00:00Z" }
+                    ],
+                    "expires_at": "2030-01-02T14:00:00Z"
+                },
+                {
+                    "action": {
+                        "type": "SwapOrder",
+                        "input_token": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                        "output_token": "So11111111111111111111111111111111111111112",
+                        "amount": "1000000"
+                    },
+                    "conditions": [
+                        { "type": "After", "step": 0, "delay_secs": 1800 }
+                    ]
+                }
+            ]
+        });
+
+        let wire: WirePipeline = serde_json::from_value(json).unwrap();
+        assert!(wire.validate().is_ok());
+
+        let pipeline: Pipeline = (
+            wire,
+            PipelineParams {
+                user_id: "user".to_string(),
+                wallet_address: None,
+                pubkey: None,
+            },
+        )
+            .into();
+
+        let first = pipeline
+            .steps
+            .values()
+            .find(|step| matches!(step.conditions[0].condition_type, ConditionType::At(_)))
+            .unwrap();
+        assert!(first.expires_at.is_some());
+
+        let second = pipeline
+            .steps
+            .values()
+            .find(|step| step.id != first.id)
+            .unwrap();
+        match &second.conditions[0].condition_type {
+            ConditionType::After { step, delay_secs } => {
+                assert_eq!(*step, first.id);
+                assert_eq!(*delay_secs, 1800);
+            }
+            _ => panic!("Expected After condition type"),
+        }
+    }
+
+    #[test]
+    fn test_wire_pipeline_validate_after_references_previous_step() {
+        let json = json!({
+            "steps": [
+                {
+                    "action": {
+                        "type": "Notification",
+                        "input_token": "So11111111111111111111111111111111111111112",
+                        "message": "hello"
+                    },
+                    "conditions": [
+                        { "type": "After", "step": 0, "delay_secs": 60 }
+                    ]
+                }
+            ]
+        });
+
+        let wire: WirePipeline = serde_json::from_value(json).unwrap();
+        assert!(wire.validate().is_err());
+    }
+
+    #[test]
+    fn test_wire_market_conditions() {
+        let json = json!({
+            "steps": [
+                {
+                    "action": {
+                        "type": "SwapOrder",
+                        "input_token": "So11111111111111111111111111111111111111112",
+                        "output_token": "5mbK36SZ7J19An8jFochhQS4of8g6BwUjbeCSxBSoWdp",
+                        "amount": "1000000"
+                    },
+                    "conditions": [
+                        {
+                            "type": "MarketCapBelow",
+                            "asset": "5mbK36SZ7J19An8jFochhQS4of8g6BwUjbeCSxBSoWdp",
+                            "value": 1000000.0
+                        },
+                        {
+                            "type": "PercentChange",
+                            "asset": "5mbK36SZ7J19An8jFochhQS4of8g6BwUjbeCSxBSoWdp",
+                            "window_secs": 3600,
+                            "value": -20.0
+                        }
+                    ]
+                }
+            ]
+        });
+
+        let wire: WirePipeline = serde_json::from_value(json).unwrap();
+        assert!(wire.validate().is_ok());
+
+        let step: PipelineStep = (&wire.steps[0]).into();
+        assert!(matches!(
+            step.conditions[0].condition_type,
+            ConditionType::MarketCapBelow { value, .. } if value == 1000000.0
+        ));
+        match &step.conditions[1].condition_type {
+            ConditionType::PercentChange {
+                window_secs, pct, ..
+            } => {
+                assert_eq!(*window_secs, 3600);
+                assert_eq!(*pct, -20.0);
+            }
+            _ => panic!("Expected PercentChange condition type"),
+        }
+    }
+
+    #[test]
+    fn test_wire_windowed_condition_requires_window() {
+        let json = json!({
+            "steps": [
+                {
+                    "action": {
+                        "type": "Notification",
+                        "input_token": "So11111111111111111111111111111111111111112",
+                        "message": "volume spike"
+                    },
+                    "conditions": [
+                        {
+                            "type": "VolumeInWindowAbove",
+                            "asset": "So11111111111111111111111111111111111111112",
+                            "value": 50000.0
+                        }
+                    ]
+                }
+            ]
+        });
+
+        let wire: WirePipeline = serde_json::from_value(json).unwrap();
+        assert!(wire.validate().is_err());
+    }
+
+    #[test]
+    fn test_wire_pipeline_paper_flag() {
+        let json = json!({
+            "paper": true,
+            "notifications": { "on_failed": true, "channels": ["Telegram"] },
+            "steps": [
+                {
+                    "action": {
+                        "type": "SwapOrder",
+                        "input_token": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                        "output_token": "So11111111111111111111111111111111111111112",
+                        "amount": "1000000"
+                    },
+                    "conditions": [{ "type": "Now" }]
+                }
+            ]
+        });
+
+        let wire: WirePipeline = serde_json::from_value(json).unwrap();
+        let pipeline: Pipeline = (
+            wire,
+            PipelineParams {
+                user_id: "user".to_string(),
+                wallet_address: None,
+                pubkey: None,
+            },
+        )
+            .into();
+        assert!(pipeline.paper);
+        let notifications = pipeline.notifications.unwrap();
+        assert!(notifications.on_failed && !notifications.on_completed);
+        assert_eq!(notifications.channels, vec![ChannelKind::Telegram]);
+    }
+
+    #[test]
+    fn test_recurring_swap_order_deserialize() {
+        let json = json!({
+            "type": "RecurringSwapOrder",
+            "input_token": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            "output_token": "So11111111111111111111111111111111111111112",
+            "amount": "500000000",
+            "slices": 10,
+            "interval_secs": 3600,
+            "guard_asset": "So11111111111111111111111111111111111111112",
+            "max_price": 250.0
+        });
+
+        let wire_action: WireAction = serde_json::from_value(json).unwrap();
+        let action: Action = (&wire_action).into();
+
+        if let Action::Recurring(recurring) = action {
+            assert_eq!(recurring.slices, 10);
+            assert_eq!(recurring.interval_secs, 3600);
+            assert_eq!(recurring.order.amount, "500000000");
+            assert_eq!(
+                recurring.order.from_chain_caip2,
+                "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp"
+            );
+            let guard = recurring.price_guard.unwrap();
+            assert_eq!(guard.max_price, Some(250.0));
+            assert!(guard.min_price.is_none());
+        } else {
+            panic!("Expected Recurring action");
+        }
+    }
 }
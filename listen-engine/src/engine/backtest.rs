@@ -0,0 +1,584 @@
+//! Replays a pipeline against recorded price updates, either from the
+//! `price_updates` table in ClickHouse or from a JSONL fixture; the steps go
+//! through the regular `process_all_steps` with the orders filled in memory
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::{mpsc, RwLock};
+use uuid::Uuid;
+
+use crate::engine::{
+    confirm::{ConfirmationConfig, ConfirmationTracker},
+    market::MarketState,
+    order::SwapOrder,
+    paper::{
+        known_decimals, price_or_peg, simulate_fill, PaperConfig, PaperQuote, PaperTradingError,
+    },
+    pipeline::{OrderFill, Pipeline, Status},
+    Engine, EngineError,
+};
+use crate::redis::{
+    client::RedisClient,
+    subscriber::{PriceUpdate, RedisSubscriber},
+};
+use privy::{config::PrivyConfig, Privy};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BacktestError {
+    #[error("[Backtest] Failed to read fixture: {0}")]
+    ReadFixtureError(std::io::Error),
+
+    #[error("[Backtest] Failed to parse price update on line {0}: {1}")]
+    ParseError(usize, serde_json::Error),
+
+    #[error("[Backtest] ClickHouse error: {0}")]
+    ClickhouseError(reqwest::Error),
+
+    #[error("[Backtest] Engine error: {0}")]
+    EngineError(EngineError),
+}
+
+#[derive(Debug, Clone)]
+pub struct ClickhouseSource {
+    pub url: String,
+    pub user: String,
+    pub password: String,
+    pub database: String,
+    /// unix timestamps, inclusive
+    pub from: u64,
+    pub to: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum PriceSource {
+    Clickhouse(ClickhouseSource),
+    /// one `PriceUpdate` per line, as stored in `price_updates`
+    Jsonl(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    /// slippage, fees and the starting balances
+    pub paper: PaperConfig,
+    /// decimals of the tokens that are not known upfront
+    pub decimals: HashMap<String, u8>,
+    pub default_decimals: u8,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            paper: PaperConfig::default(),
+            decimals: HashMap::new(),
+            default_decimals: 6, // pump.fun and most of the spl tokens
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestFill {
+    pub timestamp: DateTime<Utc>,
+    pub transaction_hash: String,
+    pub input_token: String,
+    pub output_token: String,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub fee_usd: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StepTrigger {
+    pub step_id: Uuid,
+    pub status: Status,
+    pub at: DateTime<Utc>,
+    pub transaction_hash: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestReport {
+    pub pipeline: Pipeline,
+    pub triggers: Vec<StepTrigger>,
+    pub fills: Vec<BacktestFill>,
+    pub balances: HashMap<String, u64>,
+    pub updates: usize,
+    /// usd value of the balances with a known price
+    pub initial_value: f64,
+    pub final_value: f64,
+    pub pnl: f64,
+    pub pnl_pct: f64,
+    pub max_drawdown_pct: f64,
+}
+
+/// In-memory ledger of a replay, takes the place of Privy and Redis
+pub struct Backtest {
+    config: BacktestConfig,
+    clock: Mutex<DateTime<Utc>>,
+    balances: Mutex<HashMap<String, u64>>,
+    fills: Mutex<Vec<BacktestFill>>,
+}
+
+impl Backtest {
+    pub fn new(config: BacktestConfig) -> Self {
+        let balances = config.paper.initial_balances.iter().cloned().collect();
+        Self {
+            config,
+            clock: Mutex::new(DateTime::<Utc>::MIN_UTC),
+            balances: Mutex::new(balances),
+            fills: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn decimals(&self, mint: &str) -> u8 {
+        known_decimals(mint)
+            .or_else(|| self.config.decimals.get(mint).copied())
+            .unwrap_or(self.config.default_decimals)
+    }
+
+    fn quote(&self, mint: &str, market: &MarketState) -> Result<PaperQuote, PaperTradingError> {
+        Ok(PaperQuote {
+            price: price_or_peg(mint, market.price(mint))
+                .ok_or_else(|| PaperTradingError::MissingPrice(mint.to_string()))?,
+            decimals: self.decimals(mint),
+        })
+    }
+
+    pub fn fill(&self, order: &SwapOrder, market: &MarketState) -> Result<OrderFill, EngineError> {
+        let amount = order
+            .amount
+            .parse::<u64>()
+            .map_err(|_| PaperTradingError::InvalidAmount(order.amount.clone()))?;
+        let input = self.quote(&order.input_token, market)?;
+        let output = self.quote(&order.output_token, market)?;
+        let (output_amount, fee_usd) = simulate_fill(amount, input, output, &self.config.paper);
+
+        {
+            let mut balances = self.balances.lock();
+            let balance = balances.entry(order.input_token.clone()).or_default();
+            if *balance < amount {
+                return Err(
+                    PaperTradingError::InsufficientBalance(order.input_token.clone()).into(),
+                );
+            }
+            *balance -= amount;
+            *balances.entry(order.output_token.clone()).or_default() += output_amount;
+        }
+
+        let transaction_hash = format!("backtest-{}", Uuid::new_v4());
+        self.fills.lock().push(BacktestFill {
+            timestamp: *self.clock.lock(),
+            transaction_hash: transaction_hash.clone(),
+            input_token: order.input_token.clone(),
+            output_token: order.output_token.clone(),
+            input_amount: amount,
+            output_amount,
+            fee_usd,
+        });
+
+        Ok(OrderFill {
+            transaction_hash,
+            input_amount: amount.to_string(),
+            output_amount: Some(output_amount.to_string()),
+        })
+    }
+
+    /// USD value of the balances, the ones without a price are skipped
+    pub fn value(&self, market: &MarketState) -> f64 {
+        self.balances
+            .lock()
+            .iter()
+            .filter_map(|(mint, amount)| {
+                let price = price_or_peg(mint, market.price(mint))?;
+                Some(*amount as f64 / 10f64.powi(self.decimals(mint) as i32) * price)
+            })
+            .sum()
+    }
+}
+
+impl Engine {
+    /// Engine that never leaves the process, the orders are filled by the
+    /// backtest ledger and nothing is saved
+    pub async fn for_backtest(backtest: Arc<Backtest>) -> Result<Self, EngineError> {
+        let (tx, _) = mpsc::channel(1);
+        Ok(Self {
+            // the pool connects lazily, it is never used during a replay
+            redis: Arc::new(
+                RedisClient::new("redis://127.0.0.1/")
+                    .await
+                    .map_err(EngineError::RedisClientError)?,
+            ),
+            redis_sub: Arc::new(
+                RedisSubscriber::new("redis://127.0.0.1/", tx)
+                    .map_err(EngineError::RedisSubscriberError)?,
+            ),
+            privy: Arc::new(Privy::new(PrivyConfig {
+                app_id: String::new(),
+                app_secret: String::new(),
+                verification_key: String::new(),
+            })),
+            paper_config: Arc::new(backtest.config.paper.clone()),
+            confirmations: Arc::new(ConfirmationTracker::new(
+                ConfirmationConfig::disabled(),
+                None,
+            )),
+            backtest: Some(backtest),
+            market_state: Arc::new(RwLock::new(MarketState::default())),
+            processing_pipelines: Default::default(),
+            active_pipelines: Default::default(),
+            shutdown_signal: Default::default(),
+            pending_tasks: Default::default(),
+            evaluation_samples: Default::default(),
+            evm_chain_locks: Default::default(),
+        })
+    }
+}
+
+pub async fn load_price_updates(
+    source: &PriceSource,
+    assets: &HashSet<String>,
+) -> Result<Vec<PriceUpdate>, BacktestError> {
+    let content = match source {
+        PriceSource::Jsonl(path) => {
+            std::fs::read_to_string(path).map_err(BacktestError::ReadFixtureError)?
+        }
+        PriceSource::Clickhouse(clickhouse) => fetch_from_clickhouse(clickhouse, assets).await?,
+    };
+
+    let mut updates = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let update: PriceUpdate =
+            serde_json::from_str(line).map_err(|e| BacktestError::ParseError(idx + 1, e))?;
+        if assets.contains(&update.pubkey) {
+            updates.push(update);
+        }
+    }
+    updates.sort_by_key(|u| (u.timestamp, u.slot));
+
+    Ok(updates)
+}
+
+/// Rows of `price_updates` as JSONEachRow, which is the fixture format as well
+async fn fetch_from_clickhouse(
+    source: &ClickhouseSource,
+    assets: &HashSet<String>,
+) -> Result<String, BacktestError> {
+    let assets = format!(
+        "[{}]",
+        assets
+            .iter()
+            .map(|asset| format!("'{}'", asset.replace('\'', "")))
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let from = source.from.to_string();
+    let to = source.to.to_string();
+
+    reqwest::Client::new()
+        .post(&source.url)
+        .header("X-ClickHouse-User", &source.user)
+        .header("X-ClickHouse-Key", &source.password)
+        .query(&[
+            ("database", source.database.as_str()),
+            ("output_format_json_quote_64bit_integers", "0"),
+            ("param_assets", assets.as_str()),
+            ("param_from", from.as_str()),
+            ("param_to", to.as_str()),
+        ])
+        .body(
+            r#"
+            SELECT *
+            FROM price_updates
+            WHERE pubkey IN {assets:Array(String)}
+              AND timestamp BETWEEN {from:UInt64} AND {to:UInt64}
+            ORDER BY timestamp, slot
+            FORMAT JSONEachRow
+            "#,
+        )
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(BacktestError::ClickhouseError)?
+        .text()
+        .await
+        .map_err(BacktestError::ClickhouseError)
+}
+
+pub async fn run_backtest(
+    mut pipeline: Pipeline,
+    source: &PriceSource,
+    config: BacktestConfig,
+) -> Result<BacktestReport, BacktestError> {
+    let backtest = Arc::new(Backtest::new(config));
+    let engine = Engine::for_backtest(backtest.clone())
+        .await
+        .map_err(BacktestError::EngineError)?;
+
+    let assets = engine.extract_assets(&pipeline);
+    let updates = load_price_updates(source, &assets.iter().cloned().collect()).await?;
+    engine.watch_market_windows(&pipeline).await;
+    let active = HashSet::from([format!("{}:{}", pipeline.user_id, pipeline.id)]);
+
+    if pipeline.current_steps.is_empty() {
+        pipeline.current_steps = pipeline
+            .steps
+            .iter()
+            .filter(|(_, step)| matches!(step.status, Status::Pending))
+            .map(|(id, _)| *id)
+            .collect();
+    }
+
+    let mut pipeline_hash = pipeline.hash();
+    let mut triggers = Vec::new();
+    let mut initial_value = None;
+    let mut final_value = 0.0;
+    let mut peak_value: f64 = 0.0;
+    let mut max_drawdown_pct: f64 = 0.0;
+    let mut replayed = 0;
+
+    for update in &updates {
+        replayed += 1;
+        let now = DateTime::from_timestamp(update.timestamp as i64, 0).unwrap_or_default();
+        *backtest.clock.lock() = now;
+
+        let market = {
+            let mut market_state = engine.market_state.write().await;
+            market_state.record(update);
+            market_state.prune(update.timestamp, &active);
+            market_state.snapshot(&assets, update.timestamp)
+        };
+
+        let pending: Vec<Uuid> = pipeline
+            .steps
+            .iter()
+            .filter(|(_, step)| matches!(step.status, Status::Pending))
+            .map(|(id, _)| *id)
+            .collect();
+
+        engine
+            .process_all_steps(&mut pipeline, &market, now, &mut pipeline_hash)
+            .await
+            .map_err(BacktestError::EngineError)?;
+
+        for step_id in pending {
+            let step = &pipeline.steps[&step_id];
+            if !matches!(step.status, Status::Pending) {
+                triggers.push(StepTrigger {
+                    step_id,
+                    status: step.status.clone(),
+                    at: now,
+                    transaction_hash: step.transaction_hash.clone(),
+                    error: step.error.clone(),
+                });
+            }
+        }
+
+        let value = backtest.value(&market);
+        initial_value.get_or_insert(value);
+        final_value = value;
+        peak_value = peak_value.max(value);
+        if peak_value > 0.0 {
+            max_drawdown_pct = max_drawdown_pct.max((peak_value - value) / peak_value * 100.0);
+        }
+
+        if engine.collect_step_results(&mut pipeline) {
+            break;
+        }
+    }
+
+    let initial_value = initial_value.unwrap_or_default();
+    let pnl = final_value - initial_value;
+    let fills = backtest.fills.lock().clone();
+    let balances = backtest.balances.lock().clone();
+
+    Ok(BacktestReport {
+        pipeline,
+        triggers,
+        fills,
+        balances,
+        updates: replayed,
+        initial_value,
+        final_value,
+        pnl,
+        pnl_pct: if initial_value > 0.0 {
+            pnl / initial_value * 100.0
+        } else {
+            0.0
+        },
+        max_drawdown_pct,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{
+        api::{PipelineParams, WirePipeline},
+        paper::USDC_MINT,
+    };
+
+    const TOKEN: &str = "5mbK36SZ7J19An8jFochhQS4of8g6BwUjbeCSxBSoWdp";
+
+    fn fixture() -> PriceSource {
+        PriceSource::Jsonl(PathBuf::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/backtest_prices.jsonl"
+        )))
+    }
+
+    fn pipeline(wire: serde_json::Value) -> Pipeline {
+        let wire: WirePipeline = serde_json::from_value(wire).unwrap();
+        (
+            wire,
+            PipelineParams {
+                user_id: "backtest".to_string(),
+                wallet_address: None,
+                pubkey: None,
+            },
+        )
+            .into()
+    }
+
+    fn config() -> BacktestConfig {
+        BacktestConfig {
+            paper: PaperConfig {
+                slippage_bps: 0,
+                fee_bps: 0,
+                initial_balances: vec![(USDC_MINT.to_string(), 10_000_000_000)],
+                only: true,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn swap(input: &str, output: &str, amount: &str) -> serde_json::Value {
+        serde_json::json!({
+            "type": "SwapOrder",
+            "input_token": input,
+            "output_token": output,
+            "amount": amount
+        })
+    }
+
+    #[tokio::test]
+    async fn test_backtest_buy_the_dip_and_take_profit() {
+        let pipeline = pipeline(serde_json::json!({
+            "steps": [
+                {
+                    "action": swap(USDC_MINT, TOKEN, "100000000"),
+                    "conditions": [{ "type": "PriceBelow", "asset": TOKEN, "value": 0.6 }]
+                },
+                {
+                    "action": swap(TOKEN, USDC_MINT, "100000000"),
+                    "conditions": [{ "type": "PriceAbove", "asset": TOKEN, "value": 1.2 }]
+                }
+            ]
+        }));
+
+        let report = run_backtest(pipeline, &fixture(), config()).await.unwrap();
+
+        assert_eq!(report.triggers.len(), 2);
+        assert_eq!(report.triggers[0].at.timestamp(), 1_700_000_060);
+        assert_eq!(report.triggers[1].at.timestamp(), 1_700_000_180);
+        assert!(report
+            .triggers
+            .iter()
+            .all(|t| matches!(t.status, Status::Completed)));
+
+        assert_eq!(report.fills.len(), 2);
+        assert_eq!(report.fills[0].output_amount, 200_000_000);
+        assert_eq!(report.fills[1].output_amount, 150_000_000);
+        assert_eq!(report.balances[TOKEN], 100_000_000);
+
+        // bought 200 at 0.5, 0.4 dip, sold half at 1.5
+        assert!((report.initial_value - 10_000.0).abs() < 1e-6);
+        assert!((report.pnl - 200.0).abs() < 1e-6);
+        assert!((report.max_drawdown_pct - 0.2).abs() < 1e-6);
+        assert!(matches!(report.pipeline.status, Status::Completed));
+    }
+
+    #[tokio::test]
+    async fn test_backtest_insufficient_balance_fails_step() {
+        let pipeline = pipeline(serde_json::json!({
+            "steps": [
+                {
+                    "action": swap(TOKEN, USDC_MINT, "100000000"),
+                    "conditions": [{ "type": "PriceAbove", "asset": TOKEN, "value": 0.9 }]
+                }
+            ]
+        }));
+
+        let report = run_backtest(pipeline, &fixture(), config()).await.unwrap();
+
+        assert_eq!(report.triggers.len(), 1);
+        assert!(matches!(report.triggers[0].status, Status::Failed));
+        assert!(report.fills.is_empty());
+        assert_eq!(report.updates, 1);
+    }
+
+    #[tokio::test]
+    async fn test_backtest_recurring_order_runs_past_expiry() {
+        let pipeline = pipeline(serde_json::json!({
+            "steps": [
+                {
+                    "action": {
+                        "type": "RecurringSwapOrder",
+                        "input_token": USDC_MINT,
+                        "output_token": TOKEN,
+                        "amount": "300000000",
+                        "slices": 3,
+                        "interval_secs": 60
+                    },
+                    "conditions": [{ "type": "PriceAbove", "asset": TOKEN, "value": 0.1 }],
+                    "expires_at": "2023-11-14T22:15:00Z"
+                }
+            ]
+        }));
+
+        let report = run_backtest(pipeline, &fixture(), config()).await.unwrap();
+
+        // the slices due after the expiry of the step are still executed
+        assert_eq!(report.fills.len(), 3);
+        assert!(matches!(report.pipeline.status, Status::Completed));
+    }
+
+    #[tokio::test]
+    async fn test_backtest_partially_filled_ladder_runs_past_expiry() {
+        let pipeline = pipeline(serde_json::json!({
+            "steps": [
+                {
+                    "action": swap(TOKEN, USDC_MINT, "100000000"),
+                    "conditions": [{
+                        "type": "TakeProfitLadder",
+                        "asset": TOKEN,
+                        "targets": [0.9, 1.2]
+                    }],
+                    "expires_at": "2023-11-14T22:15:00Z"
+                }
+            ]
+        }));
+        let mut config = config();
+        config
+            .paper
+            .initial_balances
+            .push((TOKEN.to_string(), 100_000_000));
+
+        let report = run_backtest(pipeline, &fixture(), config).await.unwrap();
+
+        // half filled at 1.0 before the expiry, the rest at 1.5 after it
+        assert_eq!(report.fills.len(), 2);
+        assert_eq!(report.fills[0].output_amount, 50_000_000);
+        assert_eq!(report.fills[1].output_amount, 75_000_000);
+        assert_eq!(report.balances[TOKEN], 0);
+        assert!(matches!(report.pipeline.status, Status::Completed));
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::HashSet;
 
+use chrono::Utc;
+
 use crate::engine::{
+    history::{PipelineEvent, PipelineEventKind},
     pipeline::{PipelineStep, Status},
     Engine, EngineError, Pipeline,
 };
@@ -68,6 +71,13 @@ impl Engine {
             }
         }
 
+        // Stop the remaining slices of the recurring orders
+        for execution in pipeline.child_executions.iter_mut() {
+            if matches!(execution.status, Status::Pending) {
+                execution.status = Status::Cancelled;
+            }
+        }
+
         let assets_mentioned = self.extract_assets(&pipeline);
 
         for asset in assets_mentioned {
@@ -86,6 +96,17 @@ impl Engine {
             return Err(EngineError::RedisClientError(e));
         }
 
+        let event = PipelineEvent::new(
+            pipeline.id,
+            None,
+            PipelineEventKind::Cancelled {
+                reason: Some("Cancelled by the user".to_string()),
+            },
+            self.pipeline_prices(&pipeline).await,
+            Utc::now(),
+        );
+        self.record_events(&pipeline, vec![event]).await;
+
         Ok(())
     }
 
@@ -135,6 +156,14 @@ impl Engine {
                     }
                 }
 
+                for execution in pipeline.child_executions.iter_mut() {
+                    if matches!(execution.status, Status::Pending)
+                        && to_cancel.iter().any(|step| step.id == execution.step_id)
+                    {
+                        execution.status = Status::Cancelled;
+                    }
+                }
+
                 let mut assets_mentioned: HashSet<String> = HashSet::new();
                 for step in to_cancel.iter() {
                     self.collect_assets_from_condition(&step.conditions, &mut assets_mentioned);
@@ -156,12 +185,23 @@ impl Engine {
                     return Err(EngineError::RedisClientError(e));
                 }
 
-                return Ok(());
+                let event = PipelineEvent::new(
+                    pipeline.id,
+                    Some(step_id),
+                    PipelineEventKind::Cancelled {
+                        reason: Some("Cancelled by the user".to_string()),
+                    },
+                    self.pipeline_prices(&pipeline).await,
+                    Utc::now(),
+                );
+                self.record_events(&pipeline, vec![event]).await;
+
+                Ok(())
             } else {
-                return Err(EngineError::StepNotCancellable);
+                Err(EngineError::StepNotCancellable)
             }
         } else {
-            return Err(EngineError::StepNotFound(step_id.to_string()));
+            Err(EngineError::StepNotFound(step_id.to_string()))
         }
     }
 }
@@ -1,17 +1,87 @@
-use crate::engine::pipeline::{Condition, ConditionType};
+use crate::engine::pipeline::{Action, Condition, ConditionType};
 use crate::engine::{Engine, Pipeline};
 use std::collections::HashSet;
 
+/// Pseudo-asset for pipelines that have to be re-evaluated on the timer tick
+/// rather than on price updates (time conditions, step expiry, confirmations)
+pub const TIMER_ASSET: &str = "TIMER";
+
 impl Engine {
     /// Extract all unique assets mentioned in pipeline conditions
     pub fn extract_assets(&self, pipeline: &Pipeline) -> Vec<String> {
         let mut assets = HashSet::new();
         for step in pipeline.steps.values() {
             self.collect_assets_from_condition(&step.conditions, &mut assets);
+            // expiring steps and the ones waiting for a confirmation are settled on the timer
+            if step.expires_at.is_some() || step.pending.is_some() {
+                assets.insert(TIMER_ASSET.to_string());
+            }
+            // slices of recurring orders are due on the timer
+            if let Action::Recurring(recurring) = &step.action {
+                assets.insert(TIMER_ASSET.to_string());
+                if let Some(guard) = &recurring.price_guard {
+                    assets.insert(guard.asset.clone());
+                }
+            }
         }
         assets.into_iter().collect()
     }
 
+    /// The market windows of the volume and momentum conditions, as
+    /// `(asset, window_secs)`
+    pub fn extract_windows(&self, pipeline: &Pipeline) -> HashSet<(String, u64)> {
+        let mut windows = HashSet::new();
+        let mut stack: Vec<&Condition> = pipeline
+            .steps
+            .values()
+            .flat_map(|step| step.conditions.iter())
+            .collect();
+        while let Some(condition) = stack.pop() {
+            match &condition.condition_type {
+                ConditionType::VolumeInWindowAbove {
+                    asset, window_secs, ..
+                }
+                | ConditionType::PercentChange {
+                    asset, window_secs, ..
+                }
+                | ConditionType::BuySellRatioAbove {
+                    asset, window_secs, ..
+                } => {
+                    windows.insert((asset.clone(), *window_secs));
+                }
+                ConditionType::And(sub_conditions) | ConditionType::Or(sub_conditions) => {
+                    stack.extend(sub_conditions.iter());
+                }
+                _ => {}
+            }
+        }
+        windows
+    }
+
+    /// Starts keeping the market windows the conditions of the pipeline need
+    pub async fn watch_market_windows(&self, pipeline: &Pipeline) {
+        let windows = self.extract_windows(pipeline);
+        if windows.is_empty() {
+            return;
+        }
+        let pipeline_id = format!("{}:{}", pipeline.user_id, pipeline.id);
+        let mut market_state = self.market_state.write().await;
+        for (asset, window_secs) in windows {
+            market_state.watch(&pipeline_id, &asset, window_secs);
+        }
+    }
+
+    /// Drops the market windows and trades the active pipelines do not need
+    pub async fn prune_market_state(&self) {
+        let active: HashSet<String> = self
+            .active_pipelines
+            .iter()
+            .flat_map(|entry| entry.value().iter().cloned().collect::<Vec<_>>())
+            .collect();
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        self.market_state.write().await.prune(now, &active);
+    }
+
     pub fn collect_assets_from_condition(
         &self,
         conditions: &[Condition],
@@ -28,12 +98,26 @@ impl Engine {
                 ConditionType::PriceBelow { asset, .. } => {
                     assets.insert(asset.clone());
                 }
+                ConditionType::TrailingStop { asset, .. } => {
+                    assets.insert(asset.clone());
+                }
+                ConditionType::TakeProfitLadder { asset, .. }
+                | ConditionType::MarketCapAbove { asset, .. }
+                | ConditionType::MarketCapBelow { asset, .. }
+                | ConditionType::VolumeInWindowAbove { asset, .. }
+                | ConditionType::PercentChange { asset, .. }
+                | ConditionType::BuySellRatioAbove { asset, .. } => {
+                    assets.insert(asset.clone());
+                }
                 ConditionType::And(sub_conditions) | ConditionType::Or(sub_conditions) => {
                     stack.extend(sub_conditions.iter());
                 }
                 ConditionType::Now { .. } => {
                     assets.insert("NOW".to_string());
                 }
+                ConditionType::At(_) | ConditionType::After { .. } => {
+                    assets.insert(TIMER_ASSET.to_string());
+                }
             }
         }
     }
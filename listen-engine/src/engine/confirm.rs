@@ -0,0 +1,1011 @@
+//! Confirmation of the submitted transactions, Solana signatures are polled with
+//! `getSignatureStatuses` and EVM transactions through their receipt; the
+//! confirmations are tracked in the background and the steps waiting for them
+//! are settled on their next evaluation. A Solana transaction that did not land
+//! before its `lastValidBlockHeight` is re-submitted with a fresh blockhash
+
+use std::time::Duration;
+
+use blockhash_cache::{inject_blockhash_into_encoded_tx, BLOCKHASH_CACHE};
+use chrono::{DateTime, Utc};
+use dashmap::{mapref::entry::Entry, DashMap};
+use evm_approvals::caip2_to_ethereum_rpc_url;
+use privy::tx::PrivyTransaction;
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::engine::{
+    evaluate::cancel_downstream_steps,
+    execute::execute_solana_transaction_with_retry,
+    history::{PipelineEvent, PipelineEventKind},
+    market::MarketState,
+    order::SwapOrder,
+    pipeline::{ConditionType, PendingTransaction, Pipeline, Status},
+    Engine, EngineError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfirmationError {
+    #[error("[Confirmation] Request failed: {0}")]
+    RequestError(reqwest::Error),
+
+    #[error("[Confirmation] RPC error: {0}")]
+    RpcError(String),
+
+    #[error("[Confirmation] Missing RPC url: {0}")]
+    MissingRpcUrl(String),
+
+    #[error("[Confirmation] Transaction {0} failed: {1}")]
+    TransactionFailed(String, String),
+
+    #[error("[Confirmation] Transaction {0} reverted")]
+    Reverted(String),
+
+    #[error("[Confirmation] Transaction {0} dropped, its blockhash expired")]
+    BlockhashExpired(String),
+
+    #[error("[Confirmation] Transaction {0} not confirmed within {1}s")]
+    Timeout(String, u64),
+}
+
+impl ConfirmationError {
+    /// failed requests and RPC errors, the transaction might still land
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ConfirmationError::RequestError(_) | ConfirmationError::RpcError(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfirmationConfig {
+    /// orders complete on submission if disabled
+    pub enabled: bool,
+    pub poll_interval: Duration,
+    /// how long an EVM receipt is waited for, and a Solana transaction whose
+    /// last valid block height is not known
+    pub timeout: Duration,
+    /// times a Solana transaction is sent again after its blockhash expired
+    pub max_resubmits: u32,
+}
+
+impl Default for ConfirmationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(90),
+            max_resubmits: 2,
+        }
+    }
+}
+
+impl ConfirmationConfig {
+    /// CONFIRMATION_ENABLED, CONFIRMATION_POLL_INTERVAL_MS, CONFIRMATION_TIMEOUT_SECS
+    /// and CONFIRMATION_MAX_RESUBMITS
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+
+        Self {
+            enabled: std::env::var("CONFIRMATION_ENABLED")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(default.enabled),
+            poll_interval: var("CONFIRMATION_POLL_INTERVAL_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.poll_interval),
+            timeout: var("CONFIRMATION_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(default.timeout),
+            max_resubmits: var("CONFIRMATION_MAX_RESUBMITS")
+                .map(|v| v as u32)
+                .unwrap_or(default.max_resubmits),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignatureStatus {
+    /// not seen by the node
+    Pending,
+    /// processed, it can still be dropped with its fork but must not be sent again
+    Landed,
+    Confirmed,
+    Failed(String),
+}
+
+/// Outcome of a confirmation tracked in the background
+#[derive(Debug, Clone, PartialEq)]
+pub enum Confirmation {
+    Tracking,
+    /// the hash of the transaction that landed, a re-submitted Solana
+    /// transaction has a new signature
+    Confirmed(String),
+    Failed(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse<T> {
+    #[serde(default = "Option::default")]
+    result: Option<T>,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcValue<T> {
+    value: T,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcSignatureStatus {
+    #[serde(default)]
+    confirmation_status: Option<String>,
+    #[serde(default)]
+    err: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcBlockhash {
+    blockhash: String,
+    last_valid_block_height: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct EvmReceipt {
+    status: String,
+}
+
+pub struct ConfirmationTracker {
+    pub config: ConfirmationConfig,
+    client: reqwest::Client,
+    solana_rpc_url: Option<String>,
+    /// transaction hash -> outcome, taken once the step is settled
+    outcomes: DashMap<String, Confirmation>,
+}
+
+impl ConfirmationTracker {
+    pub fn new(config: ConfirmationConfig, solana_rpc_url: Option<String>) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+            solana_rpc_url,
+            outcomes: DashMap::new(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            ConfirmationConfig::from_env(),
+            std::env::var("SOLANA_RPC_URL").ok(),
+        )
+    }
+
+    async fn rpc<T: DeserializeOwned>(
+        &self,
+        rpc_url: &str,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<Option<T>, ConfirmationError> {
+        let response: RpcResponse<T> = self
+            .client
+            .post(rpc_url)
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .map_err(ConfirmationError::RequestError)?
+            .json()
+            .await
+            .map_err(ConfirmationError::RequestError)?;
+
+        match response.error {
+            Some(error) => Err(ConfirmationError::RpcError(error.to_string())),
+            None => Ok(response.result),
+        }
+    }
+
+    pub async fn signature_status(
+        &self,
+        rpc_url: &str,
+        signature: &str,
+    ) -> Result<SignatureStatus, ConfirmationError> {
+        let statuses = self
+            .rpc::<RpcValue<Vec<Option<RpcSignatureStatus>>>>(
+                rpc_url,
+                "getSignatureStatuses",
+                serde_json::json!([[signature], { "searchTransactionHistory": true }]),
+            )
+            .await?
+            .ok_or_else(|| ConfirmationError::RpcError("missing result".to_string()))?;
+
+        Ok(match statuses.value.into_iter().next().flatten() {
+            None => SignatureStatus::Pending,
+            Some(RpcSignatureStatus { err: Some(err), .. }) if !err.is_null() => {
+                SignatureStatus::Failed(err.to_string())
+            }
+            Some(RpcSignatureStatus {
+                confirmation_status: Some(status),
+                ..
+            }) if status == "confirmed" || status == "finalized" => SignatureStatus::Confirmed,
+            Some(_) => SignatureStatus::Landed,
+        })
+    }
+
+    /// Latest blockhash with the block height it is valid until
+    pub async fn latest_blockhash(
+        &self,
+        rpc_url: &str,
+    ) -> Result<(String, u64), ConfirmationError> {
+        self.rpc::<RpcValue<RpcBlockhash>>(
+            rpc_url,
+            "getLatestBlockhash",
+            serde_json::json!([{ "commitment": "confirmed" }]),
+        )
+        .await?
+        .map(|latest| (latest.value.blockhash, latest.value.last_valid_block_height))
+        .ok_or_else(|| ConfirmationError::RpcError("missing result".to_string()))
+    }
+
+    pub async fn block_height(&self, rpc_url: &str) -> Result<u64, ConfirmationError> {
+        self.rpc::<u64>(
+            rpc_url,
+            "getBlockHeight",
+            serde_json::json!([{ "commitment": "confirmed" }]),
+        )
+        .await?
+        .ok_or_else(|| ConfirmationError::RpcError("missing result".to_string()))
+    }
+
+    /// Status of the signature, `BlockhashExpired` once it is still not seen
+    /// after the block height passed `last_valid_block_height`
+    async fn poll_solana(
+        &self,
+        rpc_url: &str,
+        signature: &str,
+        last_valid_block_height: Option<u64>,
+    ) -> Result<SignatureStatus, ConfirmationError> {
+        let status = self.signature_status(rpc_url, signature).await?;
+        let Some(last_valid) =
+            last_valid_block_height.filter(|_| status == SignatureStatus::Pending)
+        else {
+            return Ok(status);
+        };
+        if self.block_height(rpc_url).await? <= last_valid {
+            return Ok(status);
+        }
+
+        // it might have landed right before the blockhash expired
+        match self.signature_status(rpc_url, signature).await? {
+            SignatureStatus::Pending => {
+                Err(ConfirmationError::BlockhashExpired(signature.to_string()))
+            }
+            status => Ok(status),
+        }
+    }
+
+    /// Waits until the signature is confirmed; once the transaction landed it is
+    /// waited for until it is confirmed or fails, otherwise it fails with
+    /// `BlockhashExpired` once the block height passed `last_valid_block_height`
+    /// (or with `Timeout` if that is not known). Failed requests are retried
+    /// until the RPC did not answer for the whole timeout
+    pub async fn confirm_solana(
+        &self,
+        rpc_url: &str,
+        signature: &str,
+        last_valid_block_height: Option<u64>,
+    ) -> Result<(), ConfirmationError> {
+        let deadline = Instant::now() + self.config.timeout;
+        let mut answered_at = Instant::now();
+        loop {
+            match self
+                .poll_solana(rpc_url, signature, last_valid_block_height)
+                .await
+            {
+                Ok(SignatureStatus::Confirmed) => return Ok(()),
+                Ok(SignatureStatus::Failed(err)) => {
+                    return Err(ConfirmationError::TransactionFailed(
+                        signature.to_string(),
+                        err,
+                    ))
+                }
+                Ok(SignatureStatus::Pending)
+                    if last_valid_block_height.is_none() && Instant::now() >= deadline =>
+                {
+                    return Err(self.timeout(signature))
+                }
+                Ok(_) => answered_at = Instant::now(),
+                Err(e) if e.is_transient() && answered_at.elapsed() < self.config.timeout => {
+                    tracing::warn!(%signature, error = %e, "Failed to poll the signature status");
+                }
+                Err(e) => return Err(e),
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Waits for the receipt of the transaction, failed requests are retried
+    /// until the timeout
+    pub async fn confirm_evm(&self, rpc_url: &str, hash: &str) -> Result<(), ConfirmationError> {
+        let deadline = Instant::now() + self.config.timeout;
+        loop {
+            let receipt = self
+                .rpc::<EvmReceipt>(
+                    rpc_url,
+                    "eth_getTransactionReceipt",
+                    serde_json::json!([hash]),
+                )
+                .await;
+            match receipt {
+                Ok(Some(receipt)) if receipt.status == "0x1" => return Ok(()),
+                Ok(Some(_)) => return Err(ConfirmationError::Reverted(hash.to_string())),
+                Ok(None) => {}
+                Err(e) if e.is_transient() => {
+                    tracing::warn!(%hash, error = %e, "Failed to fetch the transaction receipt");
+                }
+                Err(e) => return Err(e),
+            }
+
+            if Instant::now() >= deadline {
+                return Err(self.timeout(hash));
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Marks the transaction as tracked, false if it already is
+    pub fn begin(&self, hash: &str) -> bool {
+        match self.outcomes.entry(hash.to_string()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(Confirmation::Tracking);
+                true
+            }
+        }
+    }
+
+    pub fn finish(&self, hash: &str, outcome: Result<String, String>) {
+        let outcome = match outcome {
+            Ok(hash) => Confirmation::Confirmed(hash),
+            Err(error) => Confirmation::Failed(error),
+        };
+        self.outcomes.insert(hash.to_string(), outcome);
+    }
+
+    /// The outcome of the tracked transaction, removed once final; `None` if
+    /// the transaction is not tracked
+    pub fn take(&self, hash: &str) -> Option<Confirmation> {
+        let outcome = self.outcomes.get(hash)?.clone();
+        if outcome != Confirmation::Tracking {
+            self.outcomes.remove(hash);
+        }
+        Some(outcome)
+    }
+
+    fn timeout(&self, hash: &str) -> ConfirmationError {
+        ConfirmationError::Timeout(hash.to_string(), self.config.timeout.as_secs())
+    }
+
+    fn solana_rpc_url(&self) -> Result<&str, ConfirmationError> {
+        self.solana_rpc_url
+            .as_deref()
+            .ok_or_else(|| ConfirmationError::MissingRpcUrl("SOLANA_RPC_URL".to_string()))
+    }
+}
+
+impl Engine {
+    /// Whether the orders of the pipeline are only complete once confirmed
+    pub fn tracks_confirmations(&self, paper: bool) -> bool {
+        self.confirmations.config.enabled
+            && !paper
+            && !self.paper_config.only
+            && self.backtest.is_none()
+    }
+
+    /// Sends the transaction with a fresh blockhash, its confirmation is tracked
+    /// in the background if enabled
+    pub async fn submit_solana_transaction(
+        &self,
+        mut privy_transaction: PrivyTransaction,
+        transaction: &str,
+        order: &SwapOrder,
+    ) -> Result<(String, Option<u64>), EngineError> {
+        if !self.confirmations.config.enabled {
+            let blockhash = BLOCKHASH_CACHE
+                .get_blockhash()
+                .await
+                .map_err(EngineError::BlockhashCacheError)?
+                .to_string();
+            privy_transaction.solana_transaction = Some(
+                inject_blockhash_into_encoded_tx(transaction, &blockhash)
+                    .map_err(EngineError::InjectBlockhashError)?,
+            );
+            let signature = execute_solana_transaction_with_retry(
+                &privy_transaction,
+                self.privy.clone(),
+                order,
+            )
+            .await?;
+            return Ok((signature, None));
+        }
+
+        let (signature, last_valid_block_height) = self
+            .send_solana_transaction(&mut privy_transaction, transaction, order)
+            .await?;
+        self.confirmations.begin(&signature);
+
+        let engine = self.clone();
+        let transaction = transaction.to_string();
+        let order = order.clone();
+        let tracked = signature.clone();
+        tokio::spawn(async move {
+            let outcome = engine
+                .confirm_with_resubmits(
+                    privy_transaction,
+                    &transaction,
+                    &order,
+                    tracked.clone(),
+                    last_valid_block_height,
+                )
+                .await;
+            engine.confirmations.finish(&tracked, outcome);
+        });
+
+        Ok((signature, Some(last_valid_block_height)))
+    }
+
+    async fn send_solana_transaction(
+        &self,
+        privy_transaction: &mut PrivyTransaction,
+        transaction: &str,
+        order: &SwapOrder,
+    ) -> Result<(String, u64), EngineError> {
+        let rpc_url = self
+            .confirmations
+            .solana_rpc_url()
+            .map_err(EngineError::ConfirmationError)?;
+        let (blockhash, last_valid_block_height) = self
+            .confirmations
+            .latest_blockhash(rpc_url)
+            .await
+            .map_err(EngineError::ConfirmationError)?;
+        privy_transaction.solana_transaction = Some(
+            inject_blockhash_into_encoded_tx(transaction, &blockhash)
+                .map_err(EngineError::InjectBlockhashError)?,
+        );
+        let signature =
+            execute_solana_transaction_with_retry(privy_transaction, self.privy.clone(), order)
+                .await?;
+        Ok((signature, last_valid_block_height))
+    }
+
+    /// Waits for the transaction, it is only sent again if it did not land
+    /// before its blockhash expired; returns the signature that was confirmed
+    async fn confirm_with_resubmits(
+        &self,
+        mut privy_transaction: PrivyTransaction,
+        transaction: &str,
+        order: &SwapOrder,
+        mut signature: String,
+        mut last_valid_block_height: u64,
+    ) -> Result<String, String> {
+        let rpc_url = self
+            .confirmations
+            .solana_rpc_url()
+            .map_err(|e| e.to_string())?;
+        let mut resubmits = 0;
+        loop {
+            match self
+                .confirmations
+                .confirm_solana(rpc_url, &signature, Some(last_valid_block_height))
+                .await
+            {
+                Ok(()) => {
+                    metrics::counter!("transactions_confirmed", 1, "chain" => "solana");
+                    return Ok(signature);
+                }
+                Err(ConfirmationError::BlockhashExpired(_))
+                    if resubmits < self.confirmations.config.max_resubmits =>
+                {
+                    resubmits += 1;
+                    tracing::warn!(%signature, resubmits, "Blockhash expired, re-submitting");
+                    metrics::counter!("transactions_resubmitted", 1);
+                    (signature, last_valid_block_height) = self
+                        .send_solana_transaction(&mut privy_transaction, transaction, order)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                Err(e) => {
+                    metrics::counter!("transactions_not_confirmed", 1, "chain" => "solana");
+                    return Err(e.to_string());
+                }
+            }
+        }
+    }
+
+    /// Tracks the receipt on the source chain of the order in the background,
+    /// bridged orders complete once the source transaction is mined
+    pub fn track_evm_transaction(&self, chain_caip2: &str, hash: &str) {
+        if !self.confirmations.begin(hash) {
+            return;
+        }
+        let engine = self.clone();
+        let chain_caip2 = chain_caip2.to_string();
+        let hash = hash.to_string();
+        tokio::spawn(async move {
+            let outcome = engine.confirm_evm_transaction(&chain_caip2, &hash).await;
+            engine
+                .confirmations
+                .finish(&hash, outcome.map(|()| hash.clone()));
+        });
+    }
+
+    async fn confirm_evm_transaction(&self, chain_caip2: &str, hash: &str) -> Result<(), String> {
+        let rpc_url = caip2_to_ethereum_rpc_url(chain_caip2).map_err(|e| e.to_string())?;
+        match self.confirmations.confirm_evm(&rpc_url, hash).await {
+            Ok(()) => {
+                metrics::counter!("transactions_confirmed", 1, "chain" => "evm");
+                Ok(())
+            }
+            Err(e) => {
+                metrics::counter!("transactions_not_confirmed", 1, "chain" => "evm");
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Tracks a transaction submitted before a restart, it is not sent again
+    fn track_pending_transaction(&self, pending: &PendingTransaction) {
+        if pending.chain_caip2.starts_with("eip155:") {
+            return self.track_evm_transaction(&pending.chain_caip2, &pending.transaction_hash);
+        }
+        if !self.confirmations.begin(&pending.transaction_hash) {
+            return;
+        }
+        let engine = self.clone();
+        let pending = pending.clone();
+        tokio::spawn(async move {
+            let outcome = match engine.confirmations.solana_rpc_url() {
+                Ok(rpc_url) => engine
+                    .confirmations
+                    .confirm_solana(
+                        rpc_url,
+                        &pending.transaction_hash,
+                        pending.last_valid_block_height,
+                    )
+                    .await
+                    .map(|()| pending.transaction_hash.clone())
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            engine
+                .confirmations
+                .finish(&pending.transaction_hash, outcome);
+        });
+    }
+
+    /// The final outcome of the transaction, `None` while it is tracked
+    fn confirmation_outcome(&self, pending: &PendingTransaction) -> Option<Result<String, String>> {
+        match self.confirmations.take(&pending.transaction_hash) {
+            Some(Confirmation::Confirmed(hash)) => Some(Ok(hash)),
+            Some(Confirmation::Failed(error)) => Some(Err(error)),
+            Some(Confirmation::Tracking) => None,
+            None => {
+                self.track_pending_transaction(pending);
+                None
+            }
+        }
+    }
+
+    /// Settles the order steps and recurring slices whose transactions were
+    /// confirmed or failed since the last evaluation
+    pub async fn settle_pending_transactions(
+        &self,
+        pipeline: &mut Pipeline,
+        market: &MarketState,
+        now: DateTime<Utc>,
+        pipeline_hash: &mut String,
+    ) -> Result<(), EngineError> {
+        let mut events = Vec::new();
+        let mut finished = Vec::new();
+
+        let step_ids: Vec<Uuid> = pipeline
+            .steps
+            .values()
+            .filter(|step| step.pending.is_some() && matches!(step.status, Status::Pending))
+            .map(|step| step.id)
+            .collect();
+        for step_id in step_ids {
+            let Some(step) = pipeline.steps.get_mut(&step_id) else {
+                continue;
+            };
+            let Some(outcome) = step
+                .pending
+                .as_ref()
+                .and_then(|pending| self.confirmation_outcome(&pending.transaction))
+            else {
+                continue;
+            };
+            let Some(pending) = step.pending.take() else {
+                continue;
+            };
+            let prices = self.step_prices(step, market);
+            let pipeline_id = pipeline.id;
+            let event = |kind| PipelineEvent::new(pipeline_id, Some(step_id), kind, prices, now);
+
+            match outcome {
+                Ok(transaction_hash) => {
+                    events.push(event(PipelineEventKind::TxConfirmed {
+                        transaction_hash: transaction_hash.clone(),
+                    }));
+                    step.transaction_hash = Some(transaction_hash.clone());
+                    let mut fill = pending.fill;
+                    fill.transaction_hash = transaction_hash;
+                    step.fills.push(fill);
+                    if pending.completes_step {
+                        step.status = Status::Completed;
+                        step.completed_at = Some(now);
+                        finished.push(step_id);
+                    }
+                }
+                Err(error) => {
+                    events.push(event(PipelineEventKind::Failed {
+                        error: error.clone(),
+                    }));
+                    step.status = Status::Failed;
+                    step.error = Some(error);
+                    finished.push(step_id);
+
+                    // Only cancel downstream steps if this is not a "Now" condition
+                    if !step
+                        .conditions
+                        .iter()
+                        .any(|c| matches!(c.condition_type, ConditionType::Now { .. }))
+                    {
+                        let next_steps = step.next_steps.clone();
+                        cancel_downstream_steps(pipeline, next_steps);
+                    }
+                }
+            }
+        }
+
+        for slice in pipeline.child_executions.iter_mut() {
+            if !matches!(slice.status, Status::Pending) {
+                continue;
+            }
+            let Some(outcome) = slice
+                .pending
+                .as_ref()
+                .and_then(|pending| self.confirmation_outcome(pending))
+            else {
+                continue;
+            };
+            slice.pending = None;
+            let kind = match outcome {
+                Ok(transaction_hash) => {
+                    slice.status = Status::Completed;
+                    slice.transaction_hash = Some(transaction_hash.clone());
+                    PipelineEventKind::TxConfirmed { transaction_hash }
+                }
+                Err(error) => {
+                    slice.status = Status::Failed;
+                    slice.error = Some(error.clone());
+                    PipelineEventKind::Failed { error }
+                }
+            };
+            events.push(PipelineEvent::new(
+                pipeline.id,
+                Some(slice.step_id),
+                kind,
+                Default::default(),
+                now,
+            ));
+        }
+
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.record_events(pipeline, events).await;
+        self.save_pipeline(pipeline, pipeline_hash).await?;
+        for step_id in finished {
+            self.on_step_finished(pipeline, step_id).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::engine::{
+        api::{PipelineParams, WirePipeline},
+        backtest::{Backtest, BacktestConfig},
+        mock::MockServer,
+        pipeline::{OrderFill, PendingOrder},
+    };
+
+    const SIGNATURE: &str =
+        "5VERv8NMvzbJMEkV8xnrLkEaWRtSz9CosKDYjCJjBRnbJLgp8uirBgmQpjKhoR4tjF3ZpRzrFmBV6UjKdiSZkQUW";
+    const LAST_VALID_BLOCK_HEIGHT: u64 = 1000;
+
+    fn tracker() -> ConfirmationTracker {
+        ConfirmationTracker::new(
+            ConfirmationConfig {
+                enabled: true,
+                poll_interval: Duration::from_millis(10),
+                timeout: Duration::from_millis(200),
+                max_resubmits: 1,
+            },
+            None,
+        )
+    }
+
+    fn status(status: &str) -> String {
+        serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": { "context": { "slot": 1 }, "value": [status_value(status)] }
+        })
+        .to_string()
+    }
+
+    fn status_value(status: &str) -> serde_json::Value {
+        match status {
+            "pending" => serde_json::Value::Null,
+            "failed" => serde_json::json!({
+                "confirmationStatus": "processed",
+                "err": { "InstructionError": [0, { "Custom": 6001 }] }
+            }),
+            status => serde_json::json!({ "confirmationStatus": status, "err": null }),
+        }
+    }
+
+    fn block_height(height: u64) -> String {
+        serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": height }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_solana_confirmed() {
+        let pending = status("pending");
+        let height = block_height(LAST_VALID_BLOCK_HEIGHT - 10);
+        let confirmed = status("confirmed");
+        let server =
+            MockServer::start(vec![(200, &pending), (200, &height), (200, &confirmed)]).await;
+
+        tracker()
+            .confirm_solana(&server.url, SIGNATURE, Some(LAST_VALID_BLOCK_HEIGHT))
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].body.contains("getSignatureStatuses"));
+        assert!(requests[1].body.contains("getBlockHeight"));
+    }
+
+    #[tokio::test]
+    async fn test_solana_landed_waits_past_expiry() {
+        let processed = status("processed");
+        let confirmed = status("confirmed");
+        let server = MockServer::start(vec![
+            (200, &processed),
+            (200, &processed),
+            (200, &confirmed),
+        ])
+        .await;
+
+        // the block height is never checked once the transaction landed
+        tracker()
+            .confirm_solana(&server.url, SIGNATURE, Some(0))
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests
+            .iter()
+            .all(|r| r.body.contains("getSignatureStatuses")));
+    }
+
+    #[tokio::test]
+    async fn test_solana_retries_failed_requests() {
+        let error =
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"Node is behind"}}"#;
+        let confirmed = status("confirmed");
+        let server =
+            MockServer::start(vec![(503, "unavailable"), (200, error), (200, &confirmed)]).await;
+
+        tracker()
+            .confirm_solana(&server.url, SIGNATURE, Some(LAST_VALID_BLOCK_HEIGHT))
+            .await
+            .unwrap();
+        assert_eq!(server.requests().len(), 3);
+
+        // gives up once the RPC did not answer for the whole timeout
+        let server = MockServer::start(vec![(503, "unavailable")]).await;
+        let err = tracker()
+            .confirm_solana(&server.url, SIGNATURE, Some(LAST_VALID_BLOCK_HEIGHT))
+            .await
+            .unwrap_err();
+        assert!(err.is_transient());
+        assert!(server.requests().len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_solana_failed() {
+        let failed = status("failed");
+        let server = MockServer::start(vec![(200, &failed)]).await;
+
+        let err = tracker()
+            .confirm_solana(&server.url, SIGNATURE, Some(LAST_VALID_BLOCK_HEIGHT))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ConfirmationError::TransactionFailed(_, e) if e.contains("6001")));
+    }
+
+    #[tokio::test]
+    async fn test_solana_blockhash_expired() {
+        let pending = status("pending");
+        let expired = block_height(LAST_VALID_BLOCK_HEIGHT + 1);
+        let server =
+            MockServer::start(vec![(200, &pending), (200, &expired), (200, &pending)]).await;
+
+        let err = tracker()
+            .confirm_solana(&server.url, SIGNATURE, Some(LAST_VALID_BLOCK_HEIGHT))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ConfirmationError::BlockhashExpired(_)));
+    }
+
+    #[test]
+    fn test_tracker_outcomes() {
+        let tracker = tracker();
+        assert_eq!(tracker.take(SIGNATURE), None);
+
+        assert!(tracker.begin(SIGNATURE));
+        assert!(!tracker.begin(SIGNATURE));
+        assert_eq!(tracker.take(SIGNATURE), Some(Confirmation::Tracking));
+
+        tracker.finish(SIGNATURE, Ok("resubmitted".to_string()));
+        assert_eq!(
+            tracker.take(SIGNATURE),
+            Some(Confirmation::Confirmed("resubmitted".to_string()))
+        );
+        assert_eq!(tracker.take(SIGNATURE), None);
+    }
+
+    #[tokio::test]
+    async fn test_settle_pending_order() {
+        let engine = Engine::for_backtest(Arc::new(Backtest::new(BacktestConfig::default())))
+            .await
+            .unwrap();
+        let wire: WirePipeline = serde_json::from_value(serde_json::json!({
+            "steps": [{
+                "action": {
+                    "type": "SwapOrder",
+                    "input_token": "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                    "output_token": "So11111111111111111111111111111111111111112",
+                    "amount": "1000000"
+                },
+                "conditions": [{ "type": "Now", "asset": "" }]
+            }]
+        }))
+        .unwrap();
+        let mut pipeline: Pipeline = (
+            wire,
+            PipelineParams {
+                user_id: "user".to_string(),
+                wallet_address: None,
+                pubkey: None,
+            },
+        )
+            .into();
+        let step_id = pipeline.current_steps[0];
+        let step = pipeline.steps.get_mut(&step_id).unwrap();
+        step.transaction_hash = Some(SIGNATURE.to_string());
+        step.pending = Some(PendingOrder {
+            fill: OrderFill {
+                transaction_hash: SIGNATURE.to_string(),
+                input_amount: "1000000".to_string(),
+                output_amount: Some("5000".to_string()),
+            },
+            transaction: PendingTransaction {
+                transaction_hash: SIGNATURE.to_string(),
+                chain_caip2: "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp".to_string(),
+                last_valid_block_height: Some(LAST_VALID_BLOCK_HEIGHT),
+            },
+            completes_step: true,
+        });
+        let mut hash = pipeline.hash();
+        let market = MarketState::default();
+
+        // still tracked, nothing to settle
+        engine.confirmations.begin(SIGNATURE);
+        engine
+            .settle_pending_transactions(&mut pipeline, &market, Utc::now(), &mut hash)
+            .await
+            .unwrap();
+        assert!(pipeline.steps[&step_id].pending.is_some());
+
+        engine
+            .confirmations
+            .finish(SIGNATURE, Ok("resubmitted".to_string()));
+        engine
+            .settle_pending_transactions(&mut pipeline, &market, Utc::now(), &mut hash)
+            .await
+            .unwrap();
+        let step = &pipeline.steps[&step_id];
+        assert!(step.pending.is_none());
+        assert!(matches!(step.status, Status::Completed));
+        assert_eq!(step.transaction_hash.as_deref(), Some("resubmitted"));
+        assert_eq!(step.fills[0].transaction_hash, "resubmitted");
+    }
+
+    #[tokio::test]
+    async fn test_evm_receipt() {
+        let server = MockServer::start(vec![
+            (200, r#"{"jsonrpc":"2.0","id":1,"result":null}"#),
+            (502, "bad gateway"),
+            (200, r#"{"jsonrpc":"2.0","id":1,"result":{"status":"0x1"}}"#),
+        ])
+        .await;
+        tracker().confirm_evm(&server.url, "0xabc").await.unwrap();
+        assert_eq!(server.requests().len(), 3);
+
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"jsonrpc":"2.0","id":1,"result":{"status":"0x0"}}"#,
+        )])
+        .await;
+        let err = tracker()
+            .confirm_evm(&server.url, "0xabc")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ConfirmationError::Reverted(_)));
+    }
+
+    #[tokio::test]
+    async fn test_evm_timeout() {
+        let server =
+            MockServer::start(vec![(200, r#"{"jsonrpc":"2.0","id":1,"result":null}"#)]).await;
+
+        let err = tracker()
+            .confirm_evm(&server.url, "0xabc")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ConfirmationError::Timeout(..)));
+        assert!(server.requests().len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_error() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32602,"message":"Invalid param"}}"#,
+        )])
+        .await;
+
+        let err = tracker()
+            .signature_status(&server.url, SIGNATURE)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ConfirmationError::RpcError(e) if e.contains("Invalid param")));
+    }
+}
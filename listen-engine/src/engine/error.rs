@@ -1,5 +1,8 @@
+use crate::engine::confirm::ConfirmationError;
 use crate::engine::evaluator::EvaluatorError;
+use crate::engine::notifications::NotificationError;
 use crate::engine::order::SwapOrderError;
+use crate::engine::paper::PaperTradingError;
 use crate::redis::client::RedisClientError;
 use crate::redis::subscriber::RedisSubscriberError;
 use privy::config::PrivyConfigError;
@@ -72,4 +75,13 @@ pub enum EngineError {
 
     #[error("[Engine] Unauthorized")]
     Unauthorized,
+
+    #[error("[Engine] Paper trading error: {0}")]
+    PaperTradingError(PaperTradingError),
+
+    #[error("[Engine] Notification error: {0}")]
+    NotificationError(NotificationError),
+
+    #[error("[Engine] Confirmation error: {0}")]
+    ConfirmationError(ConfirmationError),
 }
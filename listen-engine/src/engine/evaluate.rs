@@ -10,7 +10,7 @@ use std::{
     time::Instant,
 };
 
-use dashmap::DashMap;
+use chrono::{DateTime, Utc};
 use metrics::{counter, histogram};
 use solana_sdk::pubkey::Pubkey;
 use tokio::sync::Mutex;
@@ -18,9 +18,13 @@ use uuid::Uuid;
 
 use crate::{
     engine::{
+        collect::TIMER_ASSET,
         error::EngineError,
-        evaluator::Evaluator,
-        pipeline::{Action, ConditionType, Pipeline, PipelineStep, Status},
+        evaluator::{EvaluationContext, Evaluator},
+        execute::Execution,
+        history::{sample_evaluation, slice_events, PipelineEvent, PipelineEventKind},
+        market::MarketState,
+        pipeline::{Action, ConditionType, PendingOrder, Pipeline, PipelineStep, Status},
     },
     Engine,
 };
@@ -36,21 +40,23 @@ impl Engine {
         &self,
         pipeline: &mut Pipeline,
     ) -> Result<bool, EngineError> {
-        // Get the initial price cache
-        let mut price_cache = self.price_cache.read().await.clone();
-
         // Extract all assets needed for this pipeline
         let needed_assets = self.extract_assets(pipeline);
 
         // Check for missing prices and try to fetch them from Redis
-        let missing_assets: Vec<_> = needed_assets
-            .iter()
-            .filter(|asset| !price_cache.contains_key(*asset) && *asset != "NOW")
-            .collect();
+        let missing_assets: Vec<_> = {
+            let market_state = self.market_state.read().await;
+            needed_assets
+                .iter()
+                .filter(|asset| {
+                    !market_state.contains(asset) && *asset != "NOW" && *asset != TIMER_ASSET
+                })
+                .collect()
+        };
 
         // Validate that all assets are valid Solana pubkeys
         for asset in &needed_assets {
-            if *asset != "NOW" && !self.is_valid_solana_asset(asset) {
+            if *asset != "NOW" && *asset != TIMER_ASSET && !self.is_valid_solana_asset(asset) {
                 // First identify which steps use the invalid asset
                 let mut failed_steps = Vec::new();
                 let mut steps_to_cancel = Vec::new();
@@ -81,22 +87,45 @@ impl Engine {
                 }
 
                 // Now update all the steps
-                for step_id in failed_steps {
-                    if let Some(step) = pipeline.steps.get_mut(&step_id) {
+                let error = "Only Solana assets with specific mints supported".to_string();
+                let now = Utc::now();
+                let mut events = Vec::new();
+                for step_id in &failed_steps {
+                    if let Some(step) = pipeline.steps.get_mut(step_id) {
                         step.status = Status::Failed;
-                        step.error =
-                            Some("Only Solana assets with specific mints supported".to_string());
+                        step.error = Some(error.clone());
+                        events.push(PipelineEvent::new(
+                            pipeline.id,
+                            Some(*step_id),
+                            PipelineEventKind::Failed {
+                                error: error.clone(),
+                            },
+                            HashMap::new(),
+                            now,
+                        ));
                     }
                 }
 
                 for step_id in all_cancelled {
                     if let Some(step) = pipeline.steps.get_mut(&step_id) {
                         step.status = Status::Cancelled;
+                        events.push(PipelineEvent::new(
+                            pipeline.id,
+                            Some(step_id),
+                            PipelineEventKind::Cancelled { reason: None },
+                            HashMap::new(),
+                            now,
+                        ));
                     }
                 }
 
                 // Mark the pipeline as failed
                 pipeline.status = Status::Failed;
+
+                self.record_events(pipeline, events).await;
+                for step_id in failed_steps {
+                    self.on_step_finished(pipeline, step_id).await;
+                }
                 return Ok(true);
             }
         }
@@ -109,7 +138,6 @@ impl Engine {
             for asset in missing_assets {
                 if let Some(price) = self.fetch_price_from_redis(asset).await {
                     tracing::debug!("Found price for {} in Redis: {}", asset, price);
-                    price_cache.insert(asset.clone(), price);
                 }
             }
         }
@@ -168,9 +196,14 @@ impl Engine {
     pub async fn process_all_steps(
         &self,
         pipeline: &mut Pipeline,
-        price_cache: &HashMap<String, f64>,
+        market_state: &MarketState,
+        now: DateTime<Utc>,
         pipeline_hash: &mut String,
     ) -> Result<(), EngineError> {
+        self.settle_pending_transactions(pipeline, market_state, now, pipeline_hash)
+            .await?;
+        let ctx = EvaluationContext::new(pipeline, market_state, now);
+
         // Collect indexes of steps to remove after processing
         let mut steps_to_remove = Vec::new();
         let mut steps_to_add = Vec::new();
@@ -180,6 +213,7 @@ impl Engine {
         while i < pipeline.current_steps.len() {
             let current_step_id = pipeline.current_steps[i];
             let mut step_status_changed = false;
+            let mut events = Vec::new();
 
             if let Some(step) = pipeline.steps.get_mut(&current_step_id) {
                 match step.status {
@@ -190,18 +224,105 @@ impl Engine {
                             steps_to_add.extend(step.next_steps.clone());
                         }
                     }
+                    Status::Pending if step.pending.is_some() => {
+                        // Submitted order waiting for its confirmation
+                    }
+                    Status::Pending
+                        if pipeline
+                            .child_executions
+                            .iter()
+                            .any(|e| e.step_id == current_step_id) =>
+                    {
+                        // Recurring order already started, its conditions are not evaluated again
+                        if let Action::Recurring(recurring) = step.action.clone() {
+                            let prices = self.step_prices(step, ctx.market);
+                            step_status_changed = self
+                                .process_recurring_step(
+                                    pipeline,
+                                    current_step_id,
+                                    &recurring,
+                                    ctx.market,
+                                    now,
+                                )
+                                .await;
+                            events.extend(slice_events(pipeline, current_step_id, &prices, now));
+                        }
+                    }
+                    // Recurring orders that already started run all of their slices and
+                    // partially filled ladders keep their remaining targets
+                    Status::Pending
+                        if step.fills.is_empty() && step.expires_at.is_some_and(|t| t <= now) =>
+                    {
+                        tracing::info!(%current_step_id, "Step expired");
+                        step.status = Status::Cancelled;
+                        step.error =
+                            Some("Step expired before its conditions were met".to_string());
+                        step_status_changed = true;
+                        events.push(PipelineEvent::new(
+                            pipeline.id,
+                            Some(current_step_id),
+                            PipelineEventKind::Cancelled {
+                                reason: step.error.clone(),
+                            },
+                            self.step_prices(step, ctx.market),
+                            now,
+                        ));
+
+                        let next_steps = step.next_steps.clone();
+                        cancel_downstream_steps(pipeline, next_steps);
+                        steps_to_remove.push(i);
+                    }
                     Status::Pending => {
-                        match Evaluator::evaluate_conditions(&step.conditions, price_cache) {
+                        let result = Evaluator::evaluate_conditions(&mut step.conditions, &ctx);
+                        let prices = self.step_prices(step, ctx.market);
+                        let event = |kind| {
+                            PipelineEvent::new(
+                                pipeline.id,
+                                Some(current_step_id),
+                                kind,
+                                prices.clone(),
+                                now,
+                            )
+                        };
+                        match &result {
+                            Ok(true) => events.push(event(PipelineEventKind::Triggered)),
+                            Ok(false) => {
+                                if sample_evaluation(&self.evaluation_samples, step, now) {
+                                    events.push(event(PipelineEventKind::ConditionEvaluated {
+                                        result: false,
+                                    }))
+                                }
+                            }
+                            Err(_) => {}
+                        }
+
+                        match result {
                             Ok(true) => match &step.action {
                                 Action::Order(order) => {
-                                    let order = order.clone();
+                                    let mut order = order.clone();
+
+                                    // Take profit ladders swap a slice of the remaining amount
+                                    // for every target hit, the rest stays on the step
+                                    let mut remaining_amount = None;
+                                    let split_result = match Evaluator::ladder_progress(
+                                        &step.conditions,
+                                        ctx.market,
+                                    ) {
+                                        Some((crossed, unhit)) => order
+                                            .split_amount(crossed, unhit)
+                                            .map(|(slice, rest)| {
+                                                order.amount = slice;
+                                                remaining_amount = Some(rest);
+                                            }),
+                                        None => Ok(()),
+                                    };
 
                                     // For EVM transactions, we need to serialize execution for the same wallet on the same chain
-                                    if order.is_evm() && pipeline.wallet_address.is_some() {
+                                    if let Some(wallet_address) =
+                                        pipeline.wallet_address.clone().filter(|_| order.is_evm())
+                                    {
                                         // Extract chain ID from the order's from_chain_caip2
                                         let chain_id = order.from_chain_caip2.clone();
-                                        let wallet_address =
-                                            pipeline.wallet_address.as_ref().unwrap().clone();
                                         let user_wallet_key =
                                             format!("{}:{}", pipeline.user_id, wallet_address);
 
@@ -212,10 +333,8 @@ impl Engine {
                                         );
 
                                         // Get or create the chain's wallet locks map
-                                        let chain_locks = self
-                                            .evm_chain_locks
-                                            .entry(chain_id)
-                                            .or_insert_with(|| DashMap::new());
+                                        let chain_locks =
+                                            self.evm_chain_locks.entry(chain_id).or_default();
 
                                         // Get or create a mutex for this wallet on this chain
                                         let wallet_lock = chain_locks
@@ -228,26 +347,74 @@ impl Engine {
                                     }
 
                                     // Execute order regardless of EVM status (lock is held if needed)
-                                    let result = self
-                                        .execute_order(
-                                            &order,
-                                            &pipeline.user_id,
-                                            pipeline.wallet_address.clone(),
-                                            pipeline.pubkey.clone(),
-                                        )
-                                        .await;
+                                    let result = match split_result {
+                                        Ok(()) => {
+                                            self.execute_order(
+                                                &order,
+                                                &pipeline.user_id,
+                                                pipeline.wallet_address.clone(),
+                                                pipeline.pubkey.clone(),
+                                                pipeline.paper,
+                                            )
+                                            .await
+                                        }
+                                        Err(e) => Err(EngineError::SwapOrderError(e)),
+                                    };
 
                                     match result {
-                                        Ok(transaction_hash) => {
-                                            step.status = Status::Completed;
-                                            step.transaction_hash = Some(transaction_hash);
+                                        Ok(Execution { fill, pending }) => {
+                                            events.push(event(PipelineEventKind::TxSubmitted {
+                                                transaction_hash: fill.transaction_hash.clone(),
+                                            }));
+                                            step.transaction_hash =
+                                                Some(fill.transaction_hash.clone());
                                             step_status_changed = true;
+
+                                            let ladder_done = remaining_amount.is_none()
+                                                || Evaluator::mark_ladder_targets_hit(
+                                                    &mut step.conditions,
+                                                    ctx.market,
+                                                );
+                                            if let (false, Action::Order(step_order), Some(rest)) =
+                                                (ladder_done, &mut step.action, remaining_amount)
+                                            {
+                                                step_order.amount = rest;
+                                            }
+
+                                            // the step settles once the transaction is confirmed
+                                            match pending {
+                                                Some(transaction) => {
+                                                    // settled on the timer tick
+                                                    self.active_pipelines
+                                                        .entry(TIMER_ASSET.to_string())
+                                                        .or_default()
+                                                        .insert(format!(
+                                                            "{}:{}",
+                                                            pipeline.user_id, pipeline.id
+                                                        ));
+                                                    step.pending = Some(PendingOrder {
+                                                        fill,
+                                                        transaction,
+                                                        completes_step: ladder_done,
+                                                    });
+                                                }
+                                                None => {
+                                                    step.fills.push(fill);
+                                                    if ladder_done {
+                                                        step.status = Status::Completed;
+                                                        step.completed_at = Some(now);
+                                                    }
+                                                }
+                                            }
                                         }
                                         Err(e) => {
                                             step.status = Status::Failed;
                                             step.transaction_hash = None;
                                             step.error = Some(e.to_string());
                                             step_status_changed = true;
+                                            events.push(event(PipelineEventKind::Failed {
+                                                error: e.to_string(),
+                                            }));
 
                                             // Only cancel downstream steps if this is not a "Now" condition
                                             if !step.conditions.iter().any(|c| {
@@ -305,7 +472,9 @@ impl Engine {
                                                 res
                                             );
                                             step.status = Status::Completed;
+                                            step.completed_at = Some(now);
                                             step_status_changed = true;
+                                            events.push(event(PipelineEventKind::Completed));
                                         }
                                         Err(e) => {
                                             tracing::error!(
@@ -316,9 +485,30 @@ impl Engine {
                                             step.status = Status::Failed;
                                             step.error = Some(e.to_string());
                                             step_status_changed = true;
+                                            events.push(event(PipelineEventKind::Failed {
+                                                error: e.to_string(),
+                                            }));
                                         }
                                     }
                                 }
+                                Action::Recurring(recurring) => {
+                                    let recurring = recurring.clone();
+                                    step_status_changed = self
+                                        .process_recurring_step(
+                                            pipeline,
+                                            current_step_id,
+                                            &recurring,
+                                            ctx.market,
+                                            now,
+                                        )
+                                        .await;
+                                    events.extend(slice_events(
+                                        pipeline,
+                                        current_step_id,
+                                        &prices,
+                                        now,
+                                    ));
+                                }
                             },
                             Ok(false) => {
                                 // Conditions not met yet, keep step in current_steps
@@ -329,6 +519,9 @@ impl Engine {
                                 step.status = Status::Failed;
                                 step.error = Some(e.to_string());
                                 step_status_changed = true;
+                                events.push(event(PipelineEventKind::Failed {
+                                    error: e.to_string(),
+                                }));
 
                                 // Only cancel downstream steps if this is not a "Now" condition
                                 if !step
@@ -372,9 +565,19 @@ impl Engine {
                 steps_to_remove.push(i);
             }
 
+            self.record_events(pipeline, events).await;
+
             // Save the pipeline if the step's status changed
             if step_status_changed {
                 self.save_pipeline(pipeline, pipeline_hash).await?;
+
+                if pipeline
+                    .steps
+                    .get(&current_step_id)
+                    .is_some_and(|step| matches!(step.status, Status::Completed | Status::Failed))
+                {
+                    self.on_step_finished(pipeline, current_step_id).await;
+                }
             }
 
             i += 1;
@@ -461,8 +664,12 @@ impl Engine {
             Ok(false) => {} // false means keep going
         }
 
-        // Clone the price cache after ensuring prices are available
-        let price_cache = self.price_cache.read().await.clone();
+        // Snapshot the market state after ensuring prices are available
+        let now = Utc::now();
+        let market_state = self.market_state.read().await.snapshot(
+            &self.extract_assets(pipeline),
+            now.timestamp().max(0) as u64,
+        );
 
         let mut pipeline_hash = pipeline.hash();
 
@@ -481,7 +688,7 @@ impl Engine {
         self.save_pipeline(pipeline, &mut pipeline_hash).await?;
 
         if !pipeline.current_steps.is_empty() {
-            self.process_all_steps(pipeline, &price_cache, &mut pipeline_hash)
+            self.process_all_steps(pipeline, &market_state, now, &mut pipeline_hash)
                 .await?;
             self.save_pipeline(pipeline, &mut pipeline_hash).await?;
         }
@@ -495,17 +702,17 @@ impl Engine {
         Ok(pipeline_done)
     }
 
-    async fn fetch_price_from_redis(&self, asset: &str) -> Option<f64> {
-        if let Ok(price) = self.redis.get_price(asset).await {
+    pub(crate) async fn fetch_price_from_redis(&self, asset: &str) -> Option<f64> {
+        if let Ok(price_update) = self.redis.get_price_update(asset).await {
             metrics::counter!("redis_price_fallback_hits", 1);
 
-            // Update the shared in-memory cache for future lookups
+            // Update the shared in-memory state for future lookups
             {
-                let mut cache = self.price_cache.write().await;
-                cache.insert(asset.to_string(), price);
+                let mut market_state = self.market_state.write().await;
+                market_state.set_latest(&price_update);
             }
 
-            return Some(price);
+            return Some(price_update.price);
         }
 
         metrics::counter!("redis_price_fallback_misses", 1);
@@ -529,6 +736,10 @@ impl Engine {
         pipeline: &Pipeline,
         pipeline_hash: &mut String,
     ) -> Result<(), EngineError> {
+        if self.backtest.is_some() {
+            *pipeline_hash = pipeline.hash();
+            return Ok(());
+        }
         if pipeline.hash() != *pipeline_hash {
             tracing::info!("Saving pipeline: {}", pipeline.id);
             *pipeline_hash = pipeline.hash();
@@ -541,3 +752,16 @@ impl Engine {
         }
     }
 }
+
+/// Cancels the given steps and everything downstream of them that has not
+/// finished yet
+pub fn cancel_downstream_steps(pipeline: &mut Pipeline, mut to_cancel: Vec<Uuid>) {
+    while let Some(step_id) = to_cancel.pop() {
+        if let Some(step) = pipeline.steps.get_mut(&step_id) {
+            if !matches!(step.status, Status::Failed | Status::Cancelled) {
+                step.status = Status::Cancelled;
+                to_cancel.extend(step.next_steps.clone());
+            }
+        }
+    }
+}
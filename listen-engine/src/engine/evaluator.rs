@@ -1,9 +1,36 @@
-use super::pipeline::{Condition, ConditionType};
+use super::market::MarketState;
+use super::pipeline::{Condition, ConditionType, Pipeline, Status, TakeProfitTarget};
 use crate::engine::EngineError;
+use chrono::{DateTime, Duration, Utc};
 use std::collections::HashMap;
+use uuid::Uuid;
 
 pub struct Evaluator;
 
+/// State the conditions of a pipeline are evaluated against
+pub struct EvaluationContext<'a> {
+    pub market: &'a MarketState,
+    pub now: DateTime<Utc>,
+    /// status and completion time of every step of the pipeline
+    pub steps: HashMap<Uuid, (Status, Option<DateTime<Utc>>)>,
+}
+
+impl<'a> EvaluationContext<'a> {
+    pub fn new(pipeline: &Pipeline, market: &'a MarketState, now: DateTime<Utc>) -> Self {
+        let steps = pipeline
+            .steps
+            .iter()
+            .map(|(id, step)| (*id, (step.status.clone(), step.completed_at)))
+            .collect();
+        Self { market, now, steps }
+    }
+
+    /// `now` as unix seconds, the unit of the price update timestamps
+    pub fn timestamp(&self) -> u64 {
+        self.now.timestamp().max(0) as u64
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum EvaluatorError {
     #[error("[Evaluator] Failed to evaluate conditions: {0}")]
@@ -17,6 +44,12 @@ pub enum EvaluatorError {
 
     #[error("[Evaluator] Invalid condition type: {0}")]
     InvalidConditionType(String),
+
+    #[error("[Evaluator] Missing market cap data for asset: {0}")]
+    MissingMarketCapData(String),
+
+    #[error("[Evaluator] Step this step depends on did not complete: {0}")]
+    DependencyNotCompleted(String),
 }
 
 impl From<EvaluatorError> for EngineError {
@@ -26,39 +59,429 @@ impl From<EvaluatorError> for EngineError {
 }
 
 impl Evaluator {
+    /// Evaluates all of the conditions, updating the state of the stateful ones
+    /// (trailing stop peak) on the way, hence every condition is always visited
     pub fn evaluate_conditions(
-        conditions: &[Condition],
-        prices: &HashMap<String, f64>,
+        conditions: &mut [Condition],
+        ctx: &EvaluationContext,
     ) -> Result<bool, EvaluatorError> {
-        conditions.iter().try_fold(true, |acc, c| {
-            Ok(acc && Self::evaluate_condition(c, prices)?)
+        conditions.iter_mut().try_fold(true, |acc, c| {
+            let result = Self::evaluate_condition(c, ctx)?;
+            Ok(acc && result)
         })
     }
 
     fn evaluate_condition(
-        condition: &Condition,
-        prices: &HashMap<String, f64>,
+        condition: &mut Condition,
+        ctx: &EvaluationContext,
     ) -> Result<bool, EvaluatorError> {
-        match &condition.condition_type {
+        match &mut condition.condition_type {
             ConditionType::PriceAbove { asset, value } => {
-                let price = prices
-                    .get(asset)
-                    .ok_or_else(|| EvaluatorError::MissingPriceData(asset.clone()))?;
-                Ok(price >= value)
+                let price = Self::get_price(ctx.market, asset)?;
+                Ok(price >= *value)
             }
             ConditionType::PriceBelow { asset, value } => {
-                let price = prices
-                    .get(asset)
-                    .ok_or_else(|| EvaluatorError::MissingPriceData(asset.clone()))?;
-                Ok(price <= value)
+                let price = Self::get_price(ctx.market, asset)?;
+                Ok(price <= *value)
             }
-            ConditionType::And(sub) => sub.iter().try_fold(true, |acc, c| {
-                Ok(acc && Self::evaluate_condition(c, prices)?)
+            ConditionType::And(sub) => sub.iter_mut().try_fold(true, |acc, c| {
+                let result = Self::evaluate_condition(c, ctx)?;
+                Ok(acc && result)
             }),
-            ConditionType::Or(sub) => sub.iter().try_fold(false, |acc, c| {
-                Ok(acc || Self::evaluate_condition(c, prices)?)
+            ConditionType::Or(sub) => sub.iter_mut().try_fold(false, |acc, c| {
+                let result = Self::evaluate_condition(c, ctx)?;
+                Ok(acc || result)
             }),
             ConditionType::Now { .. } => Ok(true),
+            ConditionType::TrailingStop {
+                asset,
+                trail_pct,
+                peak,
+            } => {
+                if !(*trail_pct > 0.0 && *trail_pct < 100.0) {
+                    return Err(EvaluatorError::InvalidConditionType(format!(
+                        "trail_pct must be between 0 and 100, got {}",
+                        trail_pct
+                    )));
+                }
+                let price = Self::get_price(ctx.market, asset)?;
+                let new_peak = peak.map_or(price, |p| p.max(price));
+                *peak = Some(new_peak);
+                Ok(price <= new_peak * (1.0 - *trail_pct / 100.0))
+            }
+            ConditionType::TakeProfitLadder { asset, targets } => {
+                if targets.is_empty() {
+                    return Err(EvaluatorError::InvalidConditionType(
+                        "take profit ladder requires at least one target".to_string(),
+                    ));
+                }
+                let price = Self::get_price(ctx.market, asset)?;
+                Ok(targets.iter().any(|t| !t.hit && price >= t.price))
+            }
+            ConditionType::MarketCapAbove { asset, value } => {
+                let market_cap = Self::get_market_cap(ctx.market, asset)?;
+                Ok(market_cap >= *value)
+            }
+            ConditionType::MarketCapBelow { asset, value } => {
+                let market_cap = Self::get_market_cap(ctx.market, asset)?;
+                Ok(market_cap <= *value)
+            }
+            // windowed conditions are not met until there are trades in the window
+            ConditionType::VolumeInWindowAbove {
+                asset,
+                window_secs,
+                value,
+            } => Ok(ctx
+                .market
+                .volume(asset, *window_secs, ctx.timestamp())
+                .is_some_and(|volume| volume >= *value)),
+            ConditionType::PercentChange {
+                asset,
+                window_secs,
+                pct,
+            } => Ok(ctx
+                .market
+                .percent_change(asset, *window_secs, ctx.timestamp())
+                .is_some_and(|change| {
+                    if *pct >= 0.0 {
+                        change >= *pct
+                    } else {
+                        change <= *pct
+                    }
+                })),
+            ConditionType::BuySellRatioAbove {
+                asset,
+                window_secs,
+                value,
+            } => Ok(ctx
+                .market
+                .buy_sell_ratio(asset, *window_secs, ctx.timestamp())
+                .is_some_and(|ratio| ratio >= *value)),
+            ConditionType::At(at) => Ok(ctx.now >= *at),
+            ConditionType::After { step, delay_secs } => match ctx.steps.get(step) {
+                Some((Status::Completed, completed_at)) => Ok(completed_at
+                    .is_none_or(|t| t + Duration::seconds(*delay_secs as i64) <= ctx.now)),
+                Some((Status::Pending, _)) => Ok(false),
+                Some((Status::Failed | Status::Cancelled, _)) => {
+                    Err(EvaluatorError::DependencyNotCompleted(step.to_string()))
+                }
+                None => Err(EvaluatorError::InvalidConditionType(format!(
+                    "After references unknown step {}",
+                    step
+                ))),
+            },
+        }
+    }
+
+    /// For steps with a take profit ladder, returns the number of targets the
+    /// current price crossed that were not hit yet, alongside the number of
+    /// all of the targets that were not hit yet; `None` if no target was crossed
+    pub fn ladder_progress(
+        conditions: &[Condition],
+        market: &MarketState,
+    ) -> Option<(usize, usize)> {
+        let (asset, targets) = Self::find_ladder(conditions)?;
+        let price = market.price(asset)?;
+        let unhit = targets.iter().filter(|t| !t.hit).count();
+        let crossed = targets
+            .iter()
+            .filter(|t| !t.hit && price >= t.price)
+            .count();
+        // the ladder did not cause the trigger (e.g. other branch of an Or)
+        if crossed == 0 {
+            return None;
+        }
+        Some((crossed, unhit))
+    }
+
+    /// Marks the ladder targets crossed by the current price as hit,
+    /// returns true if all of the targets of the ladder have been hit
+    pub fn mark_ladder_targets_hit(conditions: &mut [Condition], market: &MarketState) -> bool {
+        for condition in conditions.iter_mut() {
+            match &mut condition.condition_type {
+                ConditionType::TakeProfitLadder { asset, targets } => {
+                    if let Some(price) = market.price(asset) {
+                        for target in targets.iter_mut() {
+                            if price >= target.price {
+                                target.hit = true;
+                            }
+                        }
+                    }
+                    return targets.iter().all(|t| t.hit);
+                }
+                ConditionType::And(sub) | ConditionType::Or(sub)
+                    if Self::find_ladder(sub).is_some() =>
+                {
+                    return Self::mark_ladder_targets_hit(sub, market);
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    fn find_ladder(conditions: &[Condition]) -> Option<(&String, &Vec<TakeProfitTarget>)> {
+        conditions
+            .iter()
+            .find_map(|condition| match &condition.condition_type {
+                ConditionType::TakeProfitLadder { asset, targets } => Some((asset, targets)),
+                ConditionType::And(sub) | ConditionType::Or(sub) => Self::find_ladder(sub),
+                _ => None,
+            })
+    }
+
+    fn get_price(market: &MarketState, asset: &str) -> Result<f64, EvaluatorError> {
+        market
+            .price(asset)
+            .ok_or_else(|| EvaluatorError::MissingPriceData(asset.to_string()))
+    }
+
+    fn get_market_cap(market: &MarketState, asset: &str) -> Result<f64, EvaluatorError> {
+        market
+            .market_cap(asset)
+            .ok_or_else(|| EvaluatorError::MissingMarketCapData(asset.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redis::subscriber::PriceUpdate;
+
+    const ASSET: &str = "So11111111111111111111111111111111111111112";
+
+    fn condition(condition_type: ConditionType) -> Condition {
+        Condition {
+            condition_type,
+            triggered: false,
+            last_evaluated: None,
+        }
+    }
+
+    fn prices(price: f64) -> MarketState {
+        HashMap::from([(ASSET.to_string(), price)]).into()
+    }
+
+    fn evaluate(conditions: &mut [Condition], price: f64) -> Result<bool, EvaluatorError> {
+        let market = prices(price);
+        let ctx = EvaluationContext {
+            market: &market,
+            now: Utc::now(),
+            steps: HashMap::new(),
+        };
+        Evaluator::evaluate_conditions(conditions, &ctx)
+    }
+
+    #[test]
+    fn test_trailing_stop_tracks_peak() {
+        let mut conditions = vec![condition(ConditionType::TrailingStop {
+            asset: ASSET.to_string(),
+            trail_pct: 10.0,
+            peak: None,
+        })];
+
+        assert!(!evaluate(&mut conditions, 100.0).unwrap());
+        assert!(!evaluate(&mut conditions, 120.0).unwrap());
+        // 10% below the 100 entry, but not 10% below the 120 peak
+        assert!(!evaluate(&mut conditions, 110.0).unwrap());
+        assert!(evaluate(&mut conditions, 108.0).unwrap());
+
+        match &conditions[0].condition_type {
+            ConditionType::TrailingStop { peak, .. } => assert_eq!(*peak, Some(120.0)),
+            _ => panic!("Expected TrailingStop condition type"),
+        }
+    }
+
+    #[test]
+    fn test_trailing_stop_peak_updated_inside_and() {
+        let mut conditions = vec![condition(ConditionType::And(vec![
+            condition(ConditionType::PriceAbove {
+                asset: ASSET.to_string(),
+                value: 1000.0,
+            }),
+            condition(ConditionType::TrailingStop {
+                asset: ASSET.to_string(),
+                trail_pct: 5.0,
+                peak: None,
+            }),
+        ]))];
+
+        assert!(!evaluate(&mut conditions, 150.0).unwrap());
+
+        let ConditionType::And(sub) = &conditions[0].condition_type else {
+            panic!("Expected And condition type");
+        };
+        match &sub[1].condition_type {
+            ConditionType::TrailingStop { peak, .. } => assert_eq!(*peak, Some(150.0)),
+            _ => panic!("Expected TrailingStop condition type"),
+        }
+    }
+
+    #[test]
+    fn test_take_profit_ladder() {
+        let mut conditions = vec![condition(ConditionType::TakeProfitLadder {
+            asset: ASSET.to_string(),
+            targets: [110.0, 120.0, 130.0]
+                .into_iter()
+                .map(|price| TakeProfitTarget { price, hit: false })
+                .collect(),
+        })];
+
+        assert!(!evaluate(&mut conditions, 105.0).unwrap());
+        assert!(evaluate(&mut conditions, 121.0).unwrap());
+        assert_eq!(
+            Evaluator::ladder_progress(&conditions, &prices(121.0)),
+            Some((2, 3))
+        );
+
+        assert!(!Evaluator::mark_ladder_targets_hit(
+            &mut conditions,
+            &prices(121.0)
+        ));
+        assert!(!evaluate(&mut conditions, 125.0).unwrap());
+        assert_eq!(
+            Evaluator::ladder_progress(&conditions, &prices(131.0)),
+            Some((1, 1))
+        );
+        assert!(Evaluator::mark_ladder_targets_hit(
+            &mut conditions,
+            &prices(131.0)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_trail_pct() {
+        let mut conditions = vec![condition(ConditionType::TrailingStop {
+            asset: ASSET.to_string(),
+            trail_pct: 150.0,
+            peak: None,
+        })];
+
+        assert!(matches!(
+            evaluate(&mut conditions, 100.0),
+            Err(EvaluatorError::InvalidConditionType(_))
+        ));
+    }
+
+    #[test]
+    fn test_at() {
+        let market = MarketState::default();
+        let now = Utc::now();
+        let ctx = EvaluationContext {
+            market: &market,
+            now,
+            steps: HashMap::new(),
+        };
+
+        let mut conditions = vec![condition(ConditionType::At(now + Duration::minutes(1)))];
+        assert!(!Evaluator::evaluate_conditions(&mut conditions, &ctx).unwrap());
+
+        let mut conditions = vec![condition(ConditionType::At(now - Duration::minutes(1)))];
+        assert!(Evaluator::evaluate_conditions(&mut conditions, &ctx).unwrap());
+    }
+
+    #[test]
+    fn test_after() {
+        let market = MarketState::default();
+        let now = Utc::now();
+        let step = Uuid::new_v4();
+        let mut conditions = vec![condition(ConditionType::After {
+            step,
+            delay_secs: 30 * 60,
+        })];
+
+        let mut ctx = EvaluationContext {
+            market: &market,
+            now,
+            steps: HashMap::from([(step, (Status::Pending, None))]),
+        };
+        assert!(!Evaluator::evaluate_conditions(&mut conditions, &ctx).unwrap());
+
+        ctx.steps
+            .insert(step, (Status::Completed, Some(now - Duration::minutes(10))));
+        assert!(!Evaluator::evaluate_conditions(&mut conditions, &ctx).unwrap());
+
+        ctx.steps
+            .insert(step, (Status::Completed, Some(now - Duration::minutes(30))));
+        assert!(Evaluator::evaluate_conditions(&mut conditions, &ctx).unwrap());
+
+        ctx.steps.insert(step, (Status::Failed, None));
+        assert!(matches!(
+            Evaluator::evaluate_conditions(&mut conditions, &ctx),
+            Err(EvaluatorError::DependencyNotCompleted(_))
+        ));
+    }
+
+    #[test]
+    fn test_market_conditions() {
+        let mut market = MarketState::default();
+        for window_secs in [90, 600] {
+            market.watch("user:pipeline", ASSET, window_secs);
         }
+        let now = Utc::now();
+        for (secs_ago, price, swap_amount, is_buy) in [
+            (300, 1.0, 1000.0, true),
+            (120, 1.2, 500.0, false),
+            (60, 1.5, 2000.0, true),
+        ] {
+            market.record(&PriceUpdate {
+                name: "TEST".to_string(),
+                pubkey: ASSET.to_string(),
+                price,
+                market_cap: price * 1_000_000.0,
+                timestamp: (now.timestamp() - secs_ago) as u64,
+                slot: 0,
+                swap_amount,
+                owner: String::new(),
+                signature: String::new(),
+                multi_hop: false,
+                is_buy,
+                is_pump: false,
+                has_metadata: true,
+            });
+        }
+        let ctx = EvaluationContext {
+            market: &market,
+            now,
+            steps: HashMap::new(),
+        };
+        let asset = ASSET.to_string();
+        let check = |condition_type: ConditionType| {
+            Evaluator::evaluate_conditions(&mut [condition(condition_type)], &ctx).unwrap()
+        };
+
+        assert!(check(ConditionType::MarketCapAbove {
+            asset: asset.clone(),
+            value: 1_000_000.0,
+        }));
+        assert!(!check(ConditionType::MarketCapBelow {
+            asset: asset.clone(),
+            value: 1_000_000.0,
+        }));
+        assert!(check(ConditionType::VolumeInWindowAbove {
+            asset: asset.clone(),
+            window_secs: 600,
+            value: 3500.0,
+        }));
+        assert!(!check(ConditionType::VolumeInWindowAbove {
+            asset: asset.clone(),
+            window_secs: 90,
+            value: 3500.0,
+        }));
+        assert!(check(ConditionType::PercentChange {
+            asset: asset.clone(),
+            window_secs: 600,
+            pct: 50.0,
+        }));
+        assert!(!check(ConditionType::PercentChange {
+            asset: asset.clone(),
+            window_secs: 600,
+            pct: -10.0,
+        }));
+        assert!(check(ConditionType::BuySellRatioAbove {
+            asset: asset.clone(),
+            window_secs: 600,
+            value: 6.0,
+        }));
     }
 }
@@ -0,0 +1,324 @@
+//! Step completion and failure events, published to a Redis stream and
+//! optionally delivered to the user as a templated notification
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::engine::{
+    notifications::ChannelKind,
+    pipeline::{Action, Notification, Pipeline, Status},
+    Engine,
+};
+
+pub const PIPELINE_EVENTS_STREAM: &str = "pipeline_events";
+pub const PIPELINE_EVENTS_MAXLEN: usize = 100_000;
+
+pub const DEFAULT_COMPLETED_TEMPLATE: &str =
+    "Step {step_id} completed: swapped {amount} of {input_token} for {output_token}, transaction {transaction_hash}";
+pub const DEFAULT_FAILED_TEMPLATE: &str = "Step {step_id} failed: {error}";
+
+/// Per-pipeline configuration of the automatic notifications; the templates
+/// take {pipeline_id}, {step_id}, {status}, {transaction_hash}, {input_token},
+/// {output_token}, {amount}, {output_amount} and {error}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StepNotifications {
+    #[serde(default)]
+    pub on_completed: bool,
+    #[serde(default)]
+    pub on_failed: bool,
+    /// the configured channels of the user if empty
+    #[serde(default)]
+    pub channels: Vec<ChannelKind>,
+    #[serde(default)]
+    pub completed_template: Option<String>,
+    #[serde(default)]
+    pub failed_template: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepEvent {
+    pub pipeline_id: Uuid,
+    pub user_id: String,
+    pub step_id: Uuid,
+    pub status: Status,
+    pub transaction_hash: Option<String>,
+    pub error: Option<String>,
+    pub input_token: Option<String>,
+    pub output_token: Option<String>,
+    /// executed input amount, the sum of the fills of ladders and recurring
+    /// orders; the requested amount if nothing was executed
+    pub amount: Option<String>,
+    /// executed (quoted for on-chain swaps) output amount
+    pub output_amount: Option<String>,
+    pub paper: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl StepEvent {
+    pub fn new(pipeline: &Pipeline, step_id: Uuid, timestamp: DateTime<Utc>) -> Option<Self> {
+        let step = pipeline.steps.get(&step_id)?;
+        let (input_token, output_token, amount, output_amount) = match &step.action {
+            Action::Order(order) if step.fills.is_empty() => (
+                Some(order.input_token.clone()),
+                Some(order.output_token.clone()),
+                Some(order.amount.clone()),
+                None,
+            ),
+            Action::Order(order) => {
+                let (amount, output_amount) = sum_fills(
+                    step.fills
+                        .iter()
+                        .map(|f| (f.input_amount.as_str(), f.output_amount.as_deref())),
+                );
+                (
+                    Some(order.input_token.clone()),
+                    Some(order.output_token.clone()),
+                    Some(amount),
+                    output_amount,
+                )
+            }
+            Action::Recurring(recurring) => {
+                let (amount, output_amount) = sum_fills(
+                    pipeline
+                        .child_executions
+                        .iter()
+                        .filter(|e| e.step_id == step_id && matches!(e.status, Status::Completed))
+                        .map(|e| (e.amount.as_str(), e.output_amount.as_deref())),
+                );
+                (
+                    Some(recurring.order.input_token.clone()),
+                    Some(recurring.order.output_token.clone()),
+                    Some(amount),
+                    output_amount,
+                )
+            }
+            Action::Notification(_) => (None, None, None, None),
+        };
+
+        Some(Self {
+            pipeline_id: pipeline.id,
+            user_id: pipeline.user_id.clone(),
+            step_id,
+            status: step.status.clone(),
+            transaction_hash: step.transaction_hash.clone(),
+            error: step.error.clone(),
+            input_token,
+            output_token,
+            amount,
+            output_amount,
+            paper: pipeline.paper,
+            timestamp,
+        })
+    }
+
+    pub fn render(&self, template: &str) -> String {
+        let or_empty = |value: &Option<String>| value.clone().unwrap_or_default();
+        template
+            .replace("{pipeline_id}", &self.pipeline_id.to_string())
+            .replace("{step_id}", &self.step_id.to_string())
+            .replace("{status}", &format!("{:?}", self.status))
+            .replace("{transaction_hash}", &or_empty(&self.transaction_hash))
+            .replace("{input_token}", &or_empty(&self.input_token))
+            .replace("{output_token}", &or_empty(&self.output_token))
+            .replace("{amount}", &or_empty(&self.amount))
+            .replace("{output_amount}", &or_empty(&self.output_amount))
+            .replace("{error}", &or_empty(&self.error))
+    }
+}
+
+/// Total input and output amounts of the fills, the output only if every fill
+/// has one
+fn sum_fills<'a>(
+    fills: impl Iterator<Item = (&'a str, Option<&'a str>)>,
+) -> (String, Option<String>) {
+    let mut input: u128 = 0;
+    let mut output: Option<u128> = Some(0);
+    for (input_amount, output_amount) in fills {
+        input += input_amount.parse::<u128>().unwrap_or_default();
+        output = output
+            .zip(output_amount.and_then(|a| a.parse::<u128>().ok()))
+            .map(|(total, amount)| total + amount);
+    }
+    (input.to_string(), output.map(|total| total.to_string()))
+}
+
+impl StepNotifications {
+    /// The notification for the event, if it should be sent
+    pub fn notification_for(&self, event: &StepEvent) -> Option<Notification> {
+        let template = match event.status {
+            Status::Completed if self.on_completed => self
+                .completed_template
+                .as_deref()
+                .unwrap_or(DEFAULT_COMPLETED_TEMPLATE),
+            Status::Failed if self.on_failed => self
+                .failed_template
+                .as_deref()
+                .unwrap_or(DEFAULT_FAILED_TEMPLATE),
+            _ => return None,
+        };
+        Some(Notification {
+            message: event.render(template),
+            channels: self.channels.clone(),
+        })
+    }
+}
+
+impl Engine {
+    /// Publishes the event of the finished step and sends the notification
+    /// configured on the pipeline, failures are only logged
+    pub async fn on_step_finished(&self, pipeline: &Pipeline, step_id: Uuid) {
+        if self.backtest.is_some() {
+            return;
+        }
+        let Some(event) = StepEvent::new(pipeline, step_id, Utc::now()) else {
+            return;
+        };
+
+        if let Err(e) = self
+            .redis
+            .xadd(PIPELINE_EVENTS_STREAM, PIPELINE_EVENTS_MAXLEN, &event)
+            .await
+        {
+            tracing::error!(%step_id, error = %e, "Failed to publish step event");
+        }
+
+        // notification steps would notify about themselves
+        let is_notification_step = pipeline
+            .steps
+            .get(&step_id)
+            .is_some_and(|step| matches!(step.action, Action::Notification(_)));
+        let Some(notification) = pipeline
+            .notifications
+            .as_ref()
+            .filter(|_| !is_notification_step)
+            .and_then(|config| config.notification_for(&event))
+        else {
+            return;
+        };
+
+        if let Err(e) = self
+            .send_notification(&pipeline.user_id, &notification)
+            .await
+        {
+            tracing::warn!(%step_id, error = %e, "Failed to send step notification");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::engine::{
+        order::SwapOrder,
+        pipeline::{OrderFill, PipelineStep},
+    };
+
+    fn pipeline(status: Status, error: Option<&str>) -> (Pipeline, Uuid) {
+        let step_id = Uuid::new_v4();
+        let step = PipelineStep {
+            id: step_id,
+            action: Action::Order(SwapOrder {
+                input_token: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
+                output_token: "So11111111111111111111111111111111111111112".to_string(),
+                amount: "1000000".to_string(),
+                from_chain_caip2: "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp".to_string(),
+                to_chain_caip2: "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp".to_string(),
+            }),
+            conditions: vec![],
+            next_steps: vec![],
+            status,
+            transaction_hash: error.is_none().then(|| "5sig".to_string()),
+            error: error.map(str::to_string),
+            expires_at: None,
+            completed_at: None,
+            fills: vec![],
+            pending: None,
+        };
+        let pipeline = Pipeline {
+            id: Uuid::new_v4(),
+            user_id: "user".to_string(),
+            wallet_address: None,
+            pubkey: None,
+            current_steps: vec![step_id],
+            steps: HashMap::from([(step_id, step)]),
+            status: Status::Pending,
+            created_at: Utc::now(),
+            child_executions: vec![],
+            paper: false,
+            notifications: None,
+        };
+        (pipeline, step_id)
+    }
+
+    #[test]
+    fn test_completed_notification() {
+        let (pipeline, step_id) = pipeline(Status::Completed, None);
+        let event = StepEvent::new(&pipeline, step_id, Utc::now()).unwrap();
+        let config = StepNotifications {
+            on_completed: true,
+            completed_template: Some("{amount} {input_token} -> {transaction_hash}".to_string()),
+            ..Default::default()
+        };
+
+        let notification = config.notification_for(&event).unwrap();
+        assert_eq!(
+            notification.message,
+            "1000000 EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v -> 5sig"
+        );
+    }
+
+    #[test]
+    fn test_event_reports_fills() {
+        let (mut pipeline, step_id) = pipeline(Status::Completed, None);
+        let step = pipeline.steps.get_mut(&step_id).unwrap();
+        // the ladder leaves the remainder of its last slice on the order
+        if let Action::Order(order) = &mut step.action {
+            order.amount = "400000".to_string();
+        }
+        step.fills = vec![
+            OrderFill {
+                transaction_hash: "4sig".to_string(),
+                input_amount: "600000".to_string(),
+                output_amount: Some("5000".to_string()),
+            },
+            OrderFill {
+                transaction_hash: "5sig".to_string(),
+                input_amount: "400000".to_string(),
+                output_amount: Some("3000".to_string()),
+            },
+        ];
+
+        let event = StepEvent::new(&pipeline, step_id, Utc::now()).unwrap();
+        assert_eq!(event.amount.as_deref(), Some("1000000"));
+        assert_eq!(event.output_amount.as_deref(), Some("8000"));
+        assert_eq!(
+            event.render("{amount} -> {output_amount}"),
+            "1000000 -> 8000"
+        );
+    }
+
+    #[test]
+    fn test_failed_notification() {
+        let (pipeline, step_id) = pipeline(Status::Failed, Some("insufficient funds"));
+        let event = StepEvent::new(&pipeline, step_id, Utc::now()).unwrap();
+
+        let config = StepNotifications {
+            on_completed: true,
+            ..Default::default()
+        };
+        assert!(config.notification_for(&event).is_none());
+
+        let config = StepNotifications {
+            on_failed: true,
+            ..Default::default()
+        };
+        let notification = config.notification_for(&event).unwrap();
+        assert_eq!(
+            notification.message,
+            format!("Step {} failed: insufficient funds", step_id)
+        );
+    }
+}
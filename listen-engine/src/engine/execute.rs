@@ -1,22 +1,41 @@
 use std::sync::Arc;
 
 use crate::engine::{
-    order::{swap_order_to_transaction, SwapOrder, SwapOrderTransaction},
+    order::{swap_order_to_transaction, QuotedSwap, SwapOrder, SwapOrderTransaction},
+    pipeline::{OrderFill, PendingTransaction},
     retry::retry_with_backoff,
     Engine, EngineError,
 };
-use blockhash_cache::{inject_blockhash_into_encoded_tx, BLOCKHASH_CACHE};
 use evm_approvals::{caip2_to_chain_id, create_approval_transaction, get_allowance};
 use privy::{tx::PrivyTransaction, Privy};
 
+/// Executed order, the transaction is pending while its confirmation is
+/// tracked in the background
+pub struct Execution {
+    pub fill: OrderFill,
+    pub pending: Option<PendingTransaction>,
+}
+
 impl Engine {
+    /// Executes the order, the fill of on-chain swaps carries the quoted output
     pub async fn execute_order(
         &self,
         order: &SwapOrder,
         user_id: &str,
         wallet_address: Option<String>,
         pubkey: Option<String>,
-    ) -> Result<String, EngineError> {
+        paper: bool,
+    ) -> Result<Execution, EngineError> {
+        if let Some(backtest) = &self.backtest {
+            let market_state = self.market_state.read().await;
+            return backtest.fill(order, &market_state).map(Execution::filled);
+        }
+        if paper || self.paper_config.only {
+            return self
+                .execute_paper_order(order, user_id)
+                .await
+                .map(Execution::filled);
+        }
         if wallet_address.is_none() && order.is_evm() {
             return Err(EngineError::EVMWalletNotAvailable);
         }
@@ -35,20 +54,21 @@ impl Engine {
             evm_transaction: None,
             solana_transaction: None,
         };
-        let lifi_api_key: Option<String> = match std::env::var("LIFI_API_KEY") {
-            Ok(val) => Some(val),
-            Err(_) => None,
-        };
+        let lifi_api_key: Option<String> = std::env::var("LIFI_API_KEY").ok();
 
-        match swap_order_to_transaction(
+        let QuotedSwap {
+            transaction,
+            out_amount,
+        } = swap_order_to_transaction(
             order,
             &lifi::LiFi::new(lifi_api_key, Some("listen".to_string())),
             wallet_address.clone(),
             pubkey.clone(),
         )
         .await
-        .map_err(EngineError::SwapOrderError)?
-        {
+        .map_err(EngineError::SwapOrderError)?;
+
+        let (transaction_hash, last_valid_block_height) = match transaction {
             SwapOrderTransaction::Evm(transaction) => {
                 ensure_approvals(order, &privy_transaction, self.privy.clone()).await?;
                 privy_transaction.evm_transaction = Some(transaction);
@@ -57,27 +77,49 @@ impl Engine {
                     .execute_transaction(privy_transaction.clone())
                     .await
                 {
-                    Ok(transaction_hash) => Ok(transaction_hash),
+                    Ok(transaction_hash) => {
+                        if self.confirmations.config.enabled {
+                            self.track_evm_transaction(&order.from_chain_caip2, &transaction_hash);
+                        }
+                        (transaction_hash, None)
+                    }
                     Err(e) => {
                         tracing::error!(transaction = ?privy_transaction, ?order, error = %e, "Failed to execute evm order");
-                        Err(EngineError::TransactionError(e))
+                        return Err(EngineError::TransactionError(e));
                     }
                 }
             }
             SwapOrderTransaction::Solana(transaction) => {
-                let latest_blockhash = BLOCKHASH_CACHE
-                    .get_blockhash()
-                    .await
-                    .map_err(EngineError::BlockhashCacheError)?;
-                let fresh_blockhash_tx =
-                    inject_blockhash_into_encoded_tx(&transaction, &latest_blockhash.to_string())
-                        .map_err(EngineError::InjectBlockhashError)?;
-                privy_transaction.solana_transaction = Some(fresh_blockhash_tx);
-
-                // Execute Solana transaction with retry
-                execute_solana_transaction_with_retry(&privy_transaction, self.privy.clone(), order)
-                    .await
+                self.submit_solana_transaction(privy_transaction, &transaction, order)
+                    .await?
             }
+        };
+
+        let pending = self
+            .confirmations
+            .config
+            .enabled
+            .then(|| PendingTransaction {
+                transaction_hash: transaction_hash.clone(),
+                chain_caip2: order.from_chain_caip2.clone(),
+                last_valid_block_height,
+            });
+        Ok(Execution {
+            fill: OrderFill {
+                transaction_hash,
+                input_amount: order.amount.clone(),
+                output_amount: Some(out_amount),
+            },
+            pending,
+        })
+    }
+}
+
+impl Execution {
+    fn filled(fill: OrderFill) -> Self {
+        Self {
+            fill,
+            pending: None,
         }
     }
 }
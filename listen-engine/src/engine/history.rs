@@ -0,0 +1,334 @@
+//! Append-only audit log of a pipeline, every evaluation, trigger, transaction
+//! and status change is recorded to a per-pipeline Redis stream with the prices
+//! the engine saw at the time
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::engine::{
+    market::MarketState,
+    pipeline::{Action, Pipeline, PipelineStep, Status},
+    Engine, EngineError,
+};
+
+pub const PIPELINE_HISTORY_MAXLEN: usize = 10_000;
+pub const DEFAULT_EVENTS_PAGE: usize = 100;
+pub const MAX_EVENTS_PAGE: usize = 1_000;
+
+/// Evaluations that did not trigger are only logged once per interval per step
+pub const EVALUATION_LOG_INTERVAL_SECS: i64 = 60;
+
+pub fn pipeline_history_key(user_id: &str, pipeline_id: &Uuid) -> String {
+    format!("pipeline_history:{}:{}", user_id, pipeline_id)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PipelineEventKind {
+    Created,
+    ConditionEvaluated { result: bool },
+    Triggered,
+    TxSubmitted { transaction_hash: String },
+    TxConfirmed { transaction_hash: String },
+    Completed,
+    Failed { error: String },
+    Cancelled { reason: Option<String> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineEvent {
+    /// id of the stream entry, set when read back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub pipeline_id: Uuid,
+    pub step_id: Option<Uuid>,
+    #[serde(flatten)]
+    pub kind: PipelineEventKind,
+    /// prices of the assets of the step (or pipeline) when the event happened
+    #[serde(default)]
+    pub prices: HashMap<String, f64>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl PipelineEvent {
+    pub fn new(
+        pipeline_id: Uuid,
+        step_id: Option<Uuid>,
+        kind: PipelineEventKind,
+        prices: HashMap<String, f64>,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id: None,
+            pipeline_id,
+            step_id,
+            kind,
+            prices,
+            timestamp,
+        }
+    }
+}
+
+/// Whether a non-triggering evaluation of the step should be logged, marks the
+/// conditions as evaluated if so; the pipelines are read from Redis on every
+/// evaluation and the sampling time is not part of their change hash, so the
+/// last logged evaluation per step is kept in `samples`
+pub fn sample_evaluation(
+    samples: &DashMap<Uuid, DateTime<Utc>>,
+    step: &mut PipelineStep,
+    now: DateTime<Utc>,
+) -> bool {
+    let last = samples.get(&step.id).map(|t| *t).or_else(|| {
+        step.conditions
+            .iter()
+            .filter_map(|c| c.last_evaluated)
+            .max()
+    });
+    if last.is_some_and(|t| now - t < Duration::seconds(EVALUATION_LOG_INTERVAL_SECS)) {
+        return false;
+    }
+    samples.insert(step.id, now);
+    for condition in step.conditions.iter_mut() {
+        condition.last_evaluated = Some(now);
+    }
+    true
+}
+
+/// Events of the slices of a recurring step that were executed at `now`, their
+/// confirmations are recorded once settled
+pub fn slice_events(
+    pipeline: &Pipeline,
+    step_id: Uuid,
+    prices: &HashMap<String, f64>,
+    now: DateTime<Utc>,
+) -> Vec<PipelineEvent> {
+    pipeline
+        .child_executions
+        .iter()
+        .filter(|e| e.step_id == step_id && e.executed_at == Some(now))
+        .filter_map(|e| {
+            let kind = match (&e.status, &e.transaction_hash, &e.error) {
+                (Status::Completed | Status::Pending, Some(hash), _) => {
+                    PipelineEventKind::TxSubmitted {
+                        transaction_hash: hash.clone(),
+                    }
+                }
+                (Status::Failed, _, error) => PipelineEventKind::Failed {
+                    error: error.clone().unwrap_or_default(),
+                },
+                (Status::Cancelled, _, error) => PipelineEventKind::Cancelled {
+                    reason: error.clone(),
+                },
+                _ => return None,
+            };
+            Some(PipelineEvent::new(
+                pipeline.id,
+                Some(step_id),
+                kind,
+                prices.clone(),
+                now,
+            ))
+        })
+        .collect()
+}
+
+impl Engine {
+    /// Prices of the assets the step depends on, including the tokens it swaps
+    pub fn step_prices(&self, step: &PipelineStep, market: &MarketState) -> HashMap<String, f64> {
+        let mut assets = HashSet::new();
+        self.collect_assets_from_condition(&step.conditions, &mut assets);
+        match &step.action {
+            Action::Order(order) => {
+                assets.insert(order.input_token.clone());
+                assets.insert(order.output_token.clone());
+            }
+            Action::Recurring(recurring) => {
+                assets.insert(recurring.order.input_token.clone());
+                assets.insert(recurring.order.output_token.clone());
+            }
+            Action::Notification(_) => {}
+        }
+        assets
+            .into_iter()
+            .filter_map(|asset| market.price(&asset).map(|price| (asset, price)))
+            .collect()
+    }
+
+    pub async fn pipeline_prices(&self, pipeline: &Pipeline) -> HashMap<String, f64> {
+        let market = self.market_state.read().await;
+        pipeline
+            .steps
+            .values()
+            .flat_map(|step| self.step_prices(step, &market))
+            .collect()
+    }
+
+    /// Drops the samples that no longer hold back a log
+    pub fn prune_evaluation_samples(&self, now: DateTime<Utc>) {
+        self.evaluation_samples
+            .retain(|_, t| *t > now - Duration::seconds(EVALUATION_LOG_INTERVAL_SECS));
+    }
+
+    /// Appends the events to the history of the pipeline, failures are only logged
+    pub async fn record_events(&self, pipeline: &Pipeline, events: Vec<PipelineEvent>) {
+        if self.backtest.is_some() {
+            return;
+        }
+        let key = pipeline_history_key(&pipeline.user_id, &pipeline.id);
+        for event in events {
+            if let Err(e) = self.redis.xadd(&key, PIPELINE_HISTORY_MAXLEN, &event).await {
+                tracing::error!(pipeline_id = %pipeline.id, error = %e, "Failed to record pipeline event");
+            }
+        }
+    }
+
+    /// A page of the history, `start` is the id of the last event of the
+    /// previous page
+    pub async fn get_pipeline_events(
+        &self,
+        user_id: &str,
+        pipeline_id: Uuid,
+        start: Option<String>,
+        count: usize,
+    ) -> Result<Vec<PipelineEvent>, EngineError> {
+        // the history is keyed by the user, the lookup only distinguishes a
+        // missing pipeline from one without events
+        if self
+            .redis
+            .get_pipeline(user_id, &pipeline_id.to_string())
+            .await
+            .map_err(EngineError::RedisClientError)?
+            .is_none()
+        {
+            return Err(EngineError::PipelineNotFound(pipeline_id.to_string()));
+        }
+
+        let entries = self
+            .redis
+            .xrange::<PipelineEvent>(
+                &pipeline_history_key(user_id, &pipeline_id),
+                start.as_deref(),
+                count.min(MAX_EVENTS_PAGE),
+            )
+            .await
+            .map_err(EngineError::RedisClientError)?;
+
+        Ok(entries
+            .into_iter()
+            .map(|(id, mut event)| {
+                event.id = Some(id);
+                event
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::pipeline::{Condition, ConditionType, Notification};
+
+    #[test]
+    fn test_event_serialization() {
+        let event = PipelineEvent::new(
+            Uuid::new_v4(),
+            Some(Uuid::new_v4()),
+            PipelineEventKind::TxSubmitted {
+                transaction_hash: "5sig".to_string(),
+            },
+            HashMap::from([(
+                "So11111111111111111111111111111111111111112".to_string(),
+                150.0,
+            )]),
+            Utc::now(),
+        );
+
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["type"], "TxSubmitted");
+        assert_eq!(value["transaction_hash"], "5sig");
+        assert!(value.get("id").is_none());
+
+        let decoded: PipelineEvent = serde_json::from_value(value).unwrap();
+        assert_eq!(decoded.kind, event.kind);
+        assert_eq!(decoded.prices, event.prices);
+    }
+
+    #[test]
+    fn test_sample_evaluation() {
+        let mut step = PipelineStep {
+            id: Uuid::new_v4(),
+            action: Action::Notification(Notification {
+                message: "hello".to_string(),
+                channels: vec![],
+            }),
+            conditions: vec![Condition {
+                condition_type: ConditionType::PriceAbove {
+                    asset: "So11111111111111111111111111111111111111112".to_string(),
+                    value: 200.0,
+                },
+                triggered: false,
+                last_evaluated: None,
+            }],
+            next_steps: vec![],
+            status: Status::Pending,
+            transaction_hash: None,
+            error: None,
+            expires_at: None,
+            completed_at: None,
+            fills: vec![],
+            pending: None,
+        };
+        let now = Utc::now();
+        let samples = DashMap::new();
+
+        assert!(sample_evaluation(&samples, &mut step, now));
+        assert!(!sample_evaluation(
+            &samples,
+            &mut step,
+            now + Duration::seconds(30)
+        ));
+        assert!(sample_evaluation(
+            &samples,
+            &mut step,
+            now + Duration::seconds(EVALUATION_LOG_INTERVAL_SECS)
+        ));
+
+        // a copy re-read from Redis is still sampled
+        let mut reloaded = step.clone();
+        for condition in reloaded.conditions.iter_mut() {
+            condition.last_evaluated = None;
+        }
+        assert!(!sample_evaluation(
+            &samples,
+            &mut reloaded,
+            now + Duration::seconds(EVALUATION_LOG_INTERVAL_SECS + 30)
+        ));
+
+        // sampling alone does not make the pipeline dirty
+        let mut pipeline = Pipeline {
+            id: Uuid::new_v4(),
+            user_id: "user".to_string(),
+            wallet_address: None,
+            pubkey: None,
+            current_steps: vec![step.id],
+            steps: HashMap::from([(step.id, step.clone())]),
+            status: Status::Pending,
+            created_at: now,
+            child_executions: vec![],
+            paper: false,
+            notifications: None,
+        };
+        let hash = pipeline.hash();
+        let step = pipeline.steps.get_mut(&step.id).unwrap();
+        assert!(sample_evaluation(
+            &samples,
+            step,
+            now + Duration::seconds(600)
+        ));
+        assert_eq!(pipeline.hash(), hash);
+    }
+}
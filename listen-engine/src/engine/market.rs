@@ -0,0 +1,398 @@
+//! Rolling per-asset market state built from the price update stream,
+//! the source for the price, market cap, volume and momentum conditions
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::redis::subscriber::PriceUpdate;
+
+/// The longest window a condition can look back
+pub const MAX_WINDOW_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone)]
+pub struct Trade {
+    pub timestamp: u64,
+    pub price: f64,
+    pub swap_amount: f64, // denoted as usd
+    pub is_buy: bool,
+}
+
+/// Running totals of the trades within the last `window_secs`, kept for the
+/// windows the conditions of the active pipelines reference
+#[derive(Debug, Clone)]
+pub struct WindowStats {
+    pub window_secs: u64,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    pub trades: u64,
+    /// price of the first trade in the window
+    pub first_price: Option<f64>,
+    /// sequence number of the first trade in the window
+    start_seq: u64,
+    /// the pipelines referencing the window
+    watchers: HashSet<String>,
+}
+
+impl WindowStats {
+    fn add(&mut self, trade: &Trade) {
+        if self.trades == 0 {
+            self.first_price = Some(trade.price);
+        }
+        self.trades += 1;
+        if trade.is_buy {
+            self.buy_volume += trade.swap_amount;
+        } else {
+            self.sell_volume += trade.swap_amount;
+        }
+    }
+
+    /// drops the trades that fell out of the window at `now`
+    fn advance(&mut self, trades: &VecDeque<Trade>, first_seq: u64, now: u64) {
+        let since = now.saturating_sub(self.window_secs);
+        let mut expired = false;
+        while let Some(trade) = trades.get((self.start_seq - first_seq) as usize) {
+            if trade.timestamp >= since {
+                break;
+            }
+            self.trades -= 1;
+            if trade.is_buy {
+                self.buy_volume -= trade.swap_amount;
+            } else {
+                self.sell_volume -= trade.swap_amount;
+            }
+            self.start_seq += 1;
+            expired = true;
+        }
+        if !expired {
+            return;
+        }
+        match trades.get((self.start_seq - first_seq) as usize) {
+            Some(trade) => self.first_price = Some(trade.price),
+            // resets the float drift of the sums too
+            None => {
+                self.first_price = None;
+                self.buy_volume = 0.0;
+                self.sell_volume = 0.0;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AssetWindow {
+    pub price: Option<f64>,
+    pub market_cap: Option<f64>,
+    /// trades of the longest watched window, empty for the unwatched assets
+    trades: VecDeque<Trade>,
+    /// sequence number of the front trade
+    first_seq: u64,
+    windows: HashMap<u64, WindowStats>,
+}
+
+impl AssetWindow {
+    fn next_seq(&self) -> u64 {
+        self.first_seq + self.trades.len() as u64
+    }
+
+    /// the window as of `now`, without modifying the state
+    fn stats(&self, window_secs: u64, now: u64) -> Option<WindowStats> {
+        let mut stats = self.windows.get(&window_secs)?.clone();
+        stats.advance(&self.trades, self.first_seq, now);
+        Some(stats)
+    }
+}
+
+/// Latest price and market cap of every asset, alongside the volume and
+/// momentum windows of the assets the active pipelines watch
+#[derive(Debug, Clone, Default)]
+pub struct MarketState {
+    assets: HashMap<String, AssetWindow>,
+}
+
+impl MarketState {
+    pub fn record(&mut self, update: &PriceUpdate) {
+        self.set_latest(update);
+        let window = self.assets.entry(update.pubkey.clone()).or_default();
+        if window.windows.is_empty() {
+            return;
+        }
+
+        let trade = Trade {
+            timestamp: update.timestamp,
+            price: update.price,
+            swap_amount: update.swap_amount,
+            is_buy: update.is_buy,
+        };
+        for stats in window.windows.values_mut() {
+            stats.add(&trade);
+        }
+        window.trades.push_back(trade);
+    }
+
+    /// Starts keeping the `window_secs` window of the asset for the pipeline,
+    /// the window starts out with the trades kept for the other windows
+    pub fn watch(&mut self, pipeline_id: &str, asset: &str, window_secs: u64) {
+        let window = self.assets.entry(asset.to_string()).or_default();
+        let first_seq = window.first_seq;
+        let stats = window.windows.entry(window_secs).or_insert_with(|| {
+            let mut stats = WindowStats {
+                window_secs,
+                buy_volume: 0.0,
+                sell_volume: 0.0,
+                trades: 0,
+                first_price: None,
+                start_seq: first_seq,
+                watchers: HashSet::new(),
+            };
+            for trade in &window.trades {
+                stats.add(trade);
+            }
+            stats
+        });
+        stats.watchers.insert(pipeline_id.to_string());
+    }
+
+    /// Drops the windows no active pipeline references anymore and the trades
+    /// that fell out of every window, run periodically
+    pub fn prune(&mut self, now: u64, active_pipelines: &HashSet<String>) {
+        for window in self.assets.values_mut() {
+            if window.windows.is_empty() && window.trades.is_empty() {
+                continue;
+            }
+            window.windows.retain(|_, stats| {
+                stats
+                    .watchers
+                    .retain(|pipeline_id| active_pipelines.contains(pipeline_id));
+                !stats.watchers.is_empty()
+            });
+
+            let first_seq = window.first_seq;
+            for stats in window.windows.values_mut() {
+                stats.advance(&window.trades, first_seq, now);
+            }
+            let keep_from = window
+                .windows
+                .values()
+                .map(|stats| stats.start_seq)
+                .min()
+                .unwrap_or_else(|| window.next_seq());
+            while window.first_seq < keep_from {
+                window.trades.pop_front();
+                window.first_seq += 1;
+            }
+        }
+    }
+
+    /// Sets the latest price and market cap without recording a trade,
+    /// used for the updates fetched from Redis; the market cap of updates
+    /// without the token metadata is not known and keeps the last one
+    pub fn set_latest(&mut self, update: &PriceUpdate) {
+        let window = self.assets.entry(update.pubkey.clone()).or_default();
+        window.price = Some(update.price);
+        if update.has_metadata {
+            window.market_cap = Some(update.market_cap);
+        }
+    }
+
+    pub fn set_price(&mut self, asset: &str, price: f64) {
+        self.assets.entry(asset.to_string()).or_default().price = Some(price);
+    }
+
+    pub fn contains(&self, asset: &str) -> bool {
+        self.price(asset).is_some()
+    }
+
+    pub fn price(&self, asset: &str) -> Option<f64> {
+        self.assets.get(asset)?.price
+    }
+
+    pub fn market_cap(&self, asset: &str) -> Option<f64> {
+        self.assets.get(asset)?.market_cap
+    }
+
+    /// USD volume traded within the last `window_secs`,
+    /// `None` unless the window is watched
+    pub fn volume(&self, asset: &str, window_secs: u64, now: u64) -> Option<f64> {
+        let stats = self.assets.get(asset)?.stats(window_secs, now)?;
+        Some(stats.buy_volume + stats.sell_volume)
+    }
+
+    /// Change of the price within the last `window_secs` in percent, compared
+    /// to the first trade of the window; `None` without trades in the window
+    pub fn percent_change(&self, asset: &str, window_secs: u64, now: u64) -> Option<f64> {
+        let window = self.assets.get(asset)?;
+        let first = window.stats(window_secs, now)?.first_price?;
+        let last = window.price?;
+        if first == 0.0 {
+            return None;
+        }
+        Some((last - first) / first * 100.0)
+    }
+
+    /// Ratio of the buy to the sell USD volume within the last `window_secs`,
+    /// infinite if there were only buys, `None` without trades in the window
+    pub fn buy_sell_ratio(&self, asset: &str, window_secs: u64, now: u64) -> Option<f64> {
+        let stats = self.assets.get(asset)?.stats(window_secs, now)?;
+        match (stats.buy_volume, stats.sell_volume) {
+            (buys, sells) if sells > 0.0 => Some(buys / sells),
+            (buys, _) if buys > 0.0 => Some(f64::INFINITY),
+            _ => None,
+        }
+    }
+
+    /// The given assets with their windows as of `now`, the trades are not
+    /// copied so it is cheap enough to be taken for every pipeline evaluation
+    pub fn snapshot<'a>(
+        &self,
+        assets: impl IntoIterator<Item = &'a String>,
+        now: u64,
+    ) -> MarketState {
+        let mut snapshot = MarketState::default();
+        for asset in assets {
+            if let Some(window) = self.assets.get(asset) {
+                let windows = window
+                    .windows
+                    .keys()
+                    .filter_map(|window_secs| {
+                        Some((*window_secs, window.stats(*window_secs, now)?))
+                    })
+                    .collect();
+                snapshot.assets.insert(
+                    asset.clone(),
+                    AssetWindow {
+                        price: window.price,
+                        market_cap: window.market_cap,
+                        trades: VecDeque::new(),
+                        first_seq: window.first_seq,
+                        windows,
+                    },
+                );
+            }
+        }
+        snapshot
+    }
+}
+
+impl From<HashMap<String, f64>> for MarketState {
+    fn from(prices: HashMap<String, f64>) -> Self {
+        let mut state = MarketState::default();
+        for (asset, price) in prices {
+            state.set_price(&asset, price);
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSET: &str = "So11111111111111111111111111111111111111112";
+
+    fn update(timestamp: u64, price: f64, swap_amount: f64, is_buy: bool) -> PriceUpdate {
+        PriceUpdate {
+            name: "SOL".to_string(),
+            pubkey: ASSET.to_string(),
+            price,
+            market_cap: price * 1_000_000.0,
+            timestamp,
+            slot: timestamp,
+            swap_amount,
+            owner: String::new(),
+            signature: String::new(),
+            multi_hop: false,
+            is_buy,
+            is_pump: false,
+            has_metadata: true,
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        let mut state = MarketState::default();
+        for window_secs in [60, 200, 600, 3600] {
+            state.watch("user:pipeline", ASSET, window_secs);
+        }
+        state.record(&update(1000, 100.0, 50.0, true));
+        state.record(&update(1500, 110.0, 30.0, false));
+        state.record(&update(1900, 120.0, 10.0, true));
+
+        assert_eq!(state.price(ASSET), Some(120.0));
+        assert_eq!(state.market_cap(ASSET), Some(120_000_000.0));
+        assert_eq!(state.volume(ASSET, 600, 2000), Some(40.0));
+        assert_eq!(state.volume(ASSET, 3600, 2000), Some(90.0));
+        assert_eq!(state.percent_change(ASSET, 3600, 2000), Some(20.0));
+        assert_eq!(state.buy_sell_ratio(ASSET, 3600, 2000), Some(2.0));
+        assert_eq!(state.buy_sell_ratio(ASSET, 200, 2000), Some(f64::INFINITY));
+        assert_eq!(state.buy_sell_ratio(ASSET, 60, 2000), None);
+        // only the watched windows are kept
+        assert_eq!(state.volume(ASSET, 300, 2000), None);
+    }
+
+    #[test]
+    fn test_update_without_metadata_keeps_market_cap() {
+        let mut state = MarketState::default();
+        state.record(&update(1000, 100.0, 50.0, true));
+        state.record(&PriceUpdate {
+            name: String::new(),
+            market_cap: 0.0,
+            has_metadata: false,
+            ..update(1100, 110.0, 30.0, false)
+        });
+
+        assert_eq!(state.price(ASSET), Some(110.0));
+        assert_eq!(state.market_cap(ASSET), Some(100_000_000.0));
+
+        state.set_latest(&PriceUpdate {
+            pubkey: "unknown".to_string(),
+            market_cap: 0.0,
+            has_metadata: false,
+            ..update(1200, 1.0, 0.0, true)
+        });
+        assert_eq!(state.price("unknown"), Some(1.0));
+        assert_eq!(state.market_cap("unknown"), None);
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut state = MarketState::default();
+        state.record(&update(900, 90.0, 70.0, true));
+        state.watch("user:a", ASSET, 100);
+        state.watch("user:b", ASSET, 1000);
+        state.record(&update(1000, 100.0, 50.0, true));
+        state.record(&update(1200, 110.0, 30.0, false));
+
+        // trades of unwatched assets are not kept
+        assert_eq!(state.assets[ASSET].trades.len(), 2);
+
+        let active = HashSet::from(["user:a".to_string(), "user:b".to_string()]);
+        state.prune(1250, &active);
+        assert_eq!(state.volume(ASSET, 100, 1250), Some(30.0));
+        assert_eq!(state.volume(ASSET, 1000, 1250), Some(80.0));
+        assert_eq!(state.assets[ASSET].trades.len(), 2);
+
+        // the longer window went away with its pipeline
+        state.prune(1250, &HashSet::from(["user:a".to_string()]));
+        assert_eq!(state.volume(ASSET, 1000, 1250), None);
+        assert_eq!(state.assets[ASSET].trades.len(), 1);
+
+        state.prune(1400, &HashSet::new());
+        assert!(state.assets[ASSET].trades.is_empty());
+        assert_eq!(state.price(ASSET), Some(110.0));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut state = MarketState::default();
+        state.watch("user:pipeline", ASSET, 600);
+        state.record(&update(1000, 100.0, 50.0, true));
+        state.record(&update(1500, 110.0, 30.0, false));
+        state.set_price("other", 1.0);
+
+        let snapshot = state.snapshot(&[ASSET.to_string()], 1700);
+        assert_eq!(snapshot.price(ASSET), Some(110.0));
+        assert_eq!(snapshot.volume(ASSET, 600, 1700), Some(30.0));
+        assert_eq!(snapshot.percent_change(ASSET, 600, 1700), Some(0.0));
+        assert!(snapshot.assets[ASSET].trades.is_empty());
+        assert!(!snapshot.contains("other"));
+    }
+}
@@ -1,24 +1,33 @@
 pub mod api;
+pub mod backtest;
 pub mod bridge;
 pub mod collect;
+pub mod confirm;
 pub mod constants;
 pub mod error;
 pub mod evaluate;
 pub mod evaluator;
+pub mod events;
 pub mod execute;
+pub mod history;
+pub mod market;
+#[cfg(test)]
+pub mod mock;
 pub mod notifications;
 pub mod order;
+pub mod paper;
 pub mod pipeline;
+pub mod recurring;
 pub mod retry;
 use crate::engine::error::EngineError;
 use crate::redis::client::{make_redis_client, RedisClient};
 use crate::redis::subscriber::{make_redis_subscriber, PriceUpdate, RedisSubscriber};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use metrics::{counter, histogram};
 use privy::config::PrivyConfig;
 use privy::Privy;
-use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
@@ -28,25 +37,39 @@ use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 use tokio::sync::Notify;
 use tokio::sync::RwLock;
-
+use uuid::Uuid;
+
+use self::backtest::Backtest;
+use self::collect::TIMER_ASSET;
+use self::confirm::ConfirmationTracker;
+use self::history::{PipelineEvent, PipelineEventKind};
+use self::market::MarketState;
+use self::paper::PaperConfig;
 use self::pipeline::{Pipeline, Status};
 use crate::server::state::EngineMessage;
 
+type EvmChainLocks = DashMap<String, DashMap<String, Arc<Mutex<()>>>>;
+
 pub struct Engine {
     pub redis: Arc<RedisClient>,
     pub redis_sub: Arc<RedisSubscriber>,
     pub privy: Arc<Privy>,
+    pub paper_config: Arc<PaperConfig>,
+    pub confirmations: Arc<ConfirmationTracker>,
+    /// set for the replays, orders are filled in memory and nothing is persisted
+    backtest: Option<Arc<Backtest>>,
 
     // Current market state
-    price_cache: Arc<RwLock<HashMap<String, f64>>>,
+    market_state: Arc<RwLock<MarketState>>,
     processing_pipelines: Arc<Mutex<HashSet<String>>>,
     active_pipelines: Arc<DashMap<String, HashSet<String>>>, // asset -> pipeline ids
     shutdown_signal: Arc<Notify>,                            // Used to signal shutdown
     pending_tasks: Arc<AtomicUsize>, // Track number of running pipeline evaluations
+    evaluation_samples: Arc<DashMap<Uuid, DateTime<Utc>>>, // step id -> last logged evaluation
 
     // Locks for EVM transactions to prevent nonce conflicts
     // Map of chain_id -> user_wallet -> Mutex
-    evm_chain_locks: Arc<DashMap<String, DashMap<String, Arc<Mutex<()>>>>>,
+    evm_chain_locks: Arc<EvmChainLocks>,
 }
 
 impl Clone for Engine {
@@ -55,11 +78,15 @@ impl Clone for Engine {
             redis: self.redis.clone(),
             redis_sub: self.redis_sub.clone(),
             privy: self.privy.clone(),
-            price_cache: self.price_cache.clone(),
+            paper_config: self.paper_config.clone(),
+            confirmations: self.confirmations.clone(),
+            backtest: self.backtest.clone(),
+            market_state: self.market_state.clone(),
             processing_pipelines: self.processing_pipelines.clone(),
             active_pipelines: self.active_pipelines.clone(),
             shutdown_signal: self.shutdown_signal.clone(),
             pending_tasks: self.pending_tasks.clone(),
+            evaluation_samples: self.evaluation_samples.clone(),
             evm_chain_locks: self.evm_chain_locks.clone(),
         }
     }
@@ -68,21 +95,35 @@ impl Clone for Engine {
 impl Engine {
     pub async fn from_env() -> Result<(Self, mpsc::Receiver<PriceUpdate>), EngineError> {
         let (tx, rx) = mpsc::channel(1000);
+        let paper_config = PaperConfig::from_env();
+
+        // a paper-only engine never talks to Privy
+        let privy_config = match PrivyConfig::from_env() {
+            Ok(config) => config,
+            Err(_) if paper_config.only => PrivyConfig {
+                app_id: String::new(),
+                app_secret: String::new(),
+                verification_key: String::new(),
+            },
+            Err(e) => return Err(EngineError::PrivyConfigError(e)),
+        };
 
         Ok((
             Self {
-                privy: Arc::new(Privy::new(
-                    PrivyConfig::from_env().map_err(EngineError::PrivyConfigError)?,
-                )),
+                privy: Arc::new(Privy::new(privy_config)),
+                paper_config: Arc::new(paper_config),
+                confirmations: Arc::new(ConfirmationTracker::from_env()),
+                backtest: None,
                 redis: make_redis_client()
                     .await
                     .map_err(EngineError::RedisClientError)?,
                 redis_sub: make_redis_subscriber(tx).map_err(EngineError::RedisSubscriberError)?,
-                price_cache: Arc::new(RwLock::new(HashMap::new())),
+                market_state: Arc::new(RwLock::new(MarketState::default())),
                 processing_pipelines: Arc::new(Mutex::new(HashSet::new())),
                 active_pipelines: Arc::new(DashMap::new()),
                 shutdown_signal: Arc::new(Notify::new()),
                 pending_tasks: Arc::new(AtomicUsize::new(0)),
+                evaluation_samples: Arc::new(DashMap::new()),
                 evm_chain_locks: Arc::new(DashMap::new()),
             },
             rx,
@@ -100,6 +141,14 @@ impl Engine {
         let mut health_check_interval = tokio::time::interval(Duration::from_secs(60));
         let mut last_price_update = Instant::now();
 
+        // Pipelines with time conditions or expiring steps are re-evaluated on
+        // every tick, as those might not receive any price updates
+        let mut timer_interval = tokio::time::interval(Duration::from_secs(1));
+        timer_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        // Trades are kept for the windows of the active pipelines only
+        let mut market_prune_interval = tokio::time::interval(Duration::from_secs(10));
+
         let existing_pipelines = match engine.redis.get_all_pipelines().await {
             Ok(p) => {
                 tracing::info!("{} pipelines from Redis", p.len());
@@ -113,6 +162,7 @@ impl Engine {
 
         // load existing pipelines into active pipelines
         for pipeline in existing_pipelines {
+            engine.watch_market_windows(&pipeline).await;
             let asset_ids = engine.extract_assets(&pipeline);
             for asset_id in asset_ids {
                 engine
@@ -161,6 +211,15 @@ impl Engine {
 
                             // Save the pipeline to Redis first
                             engine.redis.save_pipeline(&pipeline).await?;
+                            engine.watch_market_windows(&pipeline).await;
+                            let created = PipelineEvent::new(
+                                pipeline.id,
+                                None,
+                                PipelineEventKind::Created,
+                                engine.pipeline_prices(&pipeline).await,
+                                chrono::Utc::now(),
+                            );
+                            engine.record_events(&pipeline, vec![created]).await;
 
                             // If it's a NOW pipeline, evaluate it immediately instead of waiting for price updates
                             if has_now_condition {
@@ -251,16 +310,57 @@ impl Engine {
                                 tracing::error!("Failed to send response - channel closed");
                             }
                         },
+                        EngineMessage::GetPipelineEvents { user_id, pipeline_id, start, count, response_tx } => {
+                            let result = engine.get_pipeline_events(&user_id, pipeline_id, start, count).await;
+                            if response_tx.send(result).is_err() {
+                                tracing::error!("Failed to send response - channel closed");
+                            }
+                        },
+                        EngineMessage::GetPaperBalances { user_id, response_tx } => {
+                            let result = engine.get_paper_balances(&user_id).await;
+                            if response_tx.send(result).is_err() {
+                                tracing::error!("Failed to send response - channel closed");
+                            }
+                        },
+                        EngineMessage::GetNotificationSettings { user_id, response_tx } => {
+                            let result = engine
+                                .get_notification_settings(&user_id)
+                                .await
+                                .map(|settings| settings.redacted())
+                                .map_err(EngineError::NotificationError);
+                            if response_tx.send(result).is_err() {
+                                tracing::error!("Failed to send response - channel closed");
+                            }
+                        },
+                        EngineMessage::SetNotificationSettings { user_id, settings, response_tx } => {
+                            let result = engine
+                                .set_notification_settings(&user_id, &settings)
+                                .await
+                                .map_err(EngineError::NotificationError);
+                            if response_tx.send(result).is_err() {
+                                tracing::error!("Failed to send response - channel closed");
+                            }
+                        },
                     }
                 }
                 Some(price_update) = receiver.recv() => {
                     last_price_update = Instant::now();
                     metrics::counter!("engine_price_updates_received", 1);
-                    if let Err(e) = engine.handle_price_update(&price_update.pubkey, price_update.price, price_update.slot).await {
+                    if let Err(e) = engine.handle_price_update(&price_update).await {
                         tracing::error!("Error handling price update: {}", e);
                         metrics::counter!("engine_price_update_errors", 1);
                     }
                 }
+                _ = market_prune_interval.tick() => {
+                    engine.prune_market_state().await;
+                    engine.prune_evaluation_samples(Utc::now());
+                }
+                _ = timer_interval.tick() => {
+                    if let Err(e) = engine.handle_timer_tick().await {
+                        tracing::error!("Error handling timer tick: {}", e);
+                        metrics::counter!("engine_timer_tick_errors", 1);
+                    }
+                }
                 else => break
             }
         }
@@ -268,7 +368,8 @@ impl Engine {
         Ok(())
     }
 
-    pub async fn handle_price_update(&self, asset: &str, price: f64, slot: u64) -> Result<()> {
+    pub async fn handle_price_update(&self, price_update: &PriceUpdate) -> Result<()> {
+        let asset = price_update.pubkey.as_str();
         let start = Instant::now();
         counter!("price_updates_processed", 1);
 
@@ -283,12 +384,38 @@ impl Engine {
             res
         };
 
-        // Update price cache after getting pipeline IDs
+        // Update market state after getting pipeline IDs
         {
-            let mut cache = self.price_cache.write().await;
-            cache.insert(asset.to_string(), price);
+            let mut market_state = self.market_state.write().await;
+            market_state.record(price_update);
         }
 
+        self.evaluate_active_pipelines(asset, &pipeline_ids).await?;
+
+        histogram!("price_update_duration", start.elapsed());
+        tracing::debug!(
+            "{}: {} {} took {:?}",
+            asset,
+            price_update.price,
+            price_update.slot,
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    pub async fn handle_timer_tick(&self) -> Result<()> {
+        let pipeline_ids: Vec<String> = match self.active_pipelines.get(TIMER_ASSET) {
+            Some(pipeline_ids) => pipeline_ids.iter().cloned().collect(),
+            None => return Ok(()),
+        };
+
+        self.evaluate_active_pipelines(TIMER_ASSET, &pipeline_ids)
+            .await
+    }
+
+    /// Spawns evaluation of the given pipelines, `asset` is the active pipelines
+    /// entry the pipelines are removed from once complete
+    async fn evaluate_active_pipelines(&self, asset: &str, pipeline_ids: &[String]) -> Result<()> {
         // Process in chunks to limit concurrent Redis connections
         for chunk in pipeline_ids.chunks(10) {
             let asset = asset.to_string();
@@ -310,6 +437,10 @@ impl Engine {
                     let asset = asset.clone();
 
                     if !matches!(pipeline.status, Status::Pending) {
+                        // Finished through another entry, e.g. a price update of other asset
+                        if let Some(mut pipelines) = self_clone.active_pipelines.get_mut(&asset) {
+                            pipelines.remove(&pipeline_id);
+                        }
                         continue;
                     }
 
@@ -373,8 +504,6 @@ impl Engine {
             }
         }
 
-        histogram!("price_update_duration", start.elapsed());
-        tracing::debug!("{}: {} {} took {:?}", asset, price, slot, start.elapsed());
         Ok(())
     }
 
@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    http_client, ChannelKind, NotificationChannel, NotificationError, NotificationMessage,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscordConfig {
+    pub webhook_url: String,
+}
+
+pub struct DiscordChannel {
+    client: reqwest::Client,
+    config: DiscordConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscordMessage {
+    id: String,
+}
+
+impl DiscordChannel {
+    pub fn new(config: DiscordConfig) -> Self {
+        Self {
+            client: http_client(reqwest::Client::builder()),
+            config,
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for DiscordChannel {
+    fn kind(&self) -> ChannelKind {
+        ChannelKind::Discord
+    }
+
+    async fn send(&self, message: &NotificationMessage) -> Result<String, NotificationError> {
+        // wait=true makes discord return the created message instead of 204
+        let res = self
+            .client
+            .post(&self.config.webhook_url)
+            .query(&[("wait", "true")])
+            .json(&serde_json::json!({ "content": message.message }))
+            .send()
+            .await
+            .map_err(NotificationError::RequestError)?;
+
+        let status = res.status();
+        if !status.is_success() {
+            let text = res.text().await.unwrap_or_default();
+            return Err(NotificationError::UnexpectedResponse(status.as_u16(), text));
+        }
+        let body: DiscordMessage = res.json().await.map_err(NotificationError::RequestError)?;
+        Ok(format!("discord:{}", body.id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::mock::MockServer;
+
+    #[tokio::test]
+    async fn test_discord_webhook() {
+        let server = MockServer::start(vec![(200, r#"{"id":"1234567890","type":0}"#)]).await;
+        let channel = DiscordChannel::new(DiscordConfig {
+            webhook_url: format!("{}/api/webhooks/1/token", server.url),
+        });
+        let message = NotificationMessage {
+            user_id: "user".to_string(),
+            message: "pipeline completed".to_string(),
+            timestamp: 0,
+        };
+
+        assert_eq!(channel.send(&message).await.unwrap(), "discord:1234567890");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/api/webhooks/1/token?wait=true");
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["content"], "pipeline completed");
+    }
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use privy::Privy;
+use resend_rs::types::CreateEmailBaseOptions;
+use resend_rs::Resend;
+
+use super::{ChannelKind, NotificationChannel, NotificationError, NotificationMessage};
+
+pub const EMAIL_FROM: &str = "listen@app.listen-rs.com";
+
+/// Emails the address linked to the Privy account of the user through Resend
+pub struct EmailChannel {
+    privy: Arc<Privy>,
+    resend: Resend,
+    user_id: String,
+}
+
+impl EmailChannel {
+    pub fn from_env(privy: Arc<Privy>, user_id: &str) -> Result<Self, NotificationError> {
+        let api_key = std::env::var("RESEND_API_KEY")
+            .map_err(|_| NotificationError::MissingEnvVar("RESEND_API_KEY"))?;
+        Ok(Self {
+            privy,
+            resend: Resend::new(&api_key),
+            user_id: user_id.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for EmailChannel {
+    fn kind(&self) -> ChannelKind {
+        ChannelKind::Email
+    }
+
+    async fn send(&self, message: &NotificationMessage) -> Result<String, NotificationError> {
+        let recipient_email = self
+            .privy
+            .get_email_by_user_id(&self.user_id)
+            .await
+            .map_err(NotificationError::EmailError)?;
+        let to = [recipient_email.as_str()];
+
+        let email = CreateEmailBaseOptions::new(EMAIL_FROM, to, &message.message)
+            .with_text(&message.message);
+
+        let result = self
+            .resend
+            .emails
+            .send(email)
+            .await
+            .map_err(|e| NotificationError::EmailError(anyhow::anyhow!("{}", e)))?;
+
+        tracing::info!("Email sent with ID: {:?}", result.id);
+
+        Ok(result.id.to_string())
+    }
+}
@@ -0,0 +1,162 @@
+//! The notification urls are set by the users, the requests must not reach
+//! the internal network (loopback, metadata endpoint, private ranges)
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use reqwest::Url;
+
+use super::NotificationError;
+
+pub const DISCORD_WEBHOOK_PREFIX: &str = "https://discord.com/api/webhooks/";
+
+pub fn validate_discord_url(url: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed)
+            if parsed.as_str().starts_with(DISCORD_WEBHOOK_PREFIX)
+                && parsed.port().is_none()
+                && parsed.username().is_empty() =>
+        {
+            Ok(())
+        }
+        _ => Err(format!(
+            "discord webhook url must start with {}",
+            DISCORD_WEBHOOK_PREFIX
+        )),
+    }
+}
+
+/// An https url, the host is only resolved when sending
+pub fn validate_webhook_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|_| format!("invalid url: {}", url))?;
+    if parsed.scheme() != "https" {
+        return Err("webhook url must use https".to_string());
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("invalid url: {}", url))?;
+    let forbidden = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => !is_public_ip(ip),
+        Err(_) => host.eq_ignore_ascii_case("localhost"),
+    };
+    if forbidden {
+        return Err(format!("webhook host is not allowed: {}", host));
+    }
+    Ok(parsed)
+}
+
+/// Resolves the host of the url, failing if any of its addresses is not
+/// public; the request is then pinned to these addresses so that the host
+/// can not be re-resolved elsewhere in between
+pub async fn resolve_public(url: &Url) -> Result<Vec<SocketAddr>, NotificationError> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| NotificationError::ForbiddenHost(url.to_string()))?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.trim_matches(['[', ']']), port))
+        .await
+        .map_err(|e| NotificationError::ForbiddenHost(format!("{}: {}", host, e)))?
+        .collect();
+
+    if addrs.is_empty() {
+        return Err(NotificationError::ForbiddenHost(host.to_string()));
+    }
+    if let Some(addr) = addrs.iter().find(|addr| !is_public_ip(addr.ip())) {
+        return Err(NotificationError::ForbiddenHost(format!(
+            "{} resolves to {}",
+            host,
+            addr.ip()
+        )));
+    }
+    Ok(addrs)
+}
+
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8, 100.64.0.0/10 (carrier-grade NAT), 198.18.0.0/15 (benchmarking)
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 198 && (18..20).contains(&b)))
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 unique local, fe80::/10 link-local
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_discord_url() {
+        assert!(validate_discord_url("https://discord.com/api/webhooks/1/token").is_ok());
+        assert!(validate_discord_url("http://discord.com/api/webhooks/1/token").is_err());
+        assert!(validate_discord_url("https://discord.com.evil.io/api/webhooks/1").is_err());
+        assert!(validate_discord_url("https://discord.com:8443/api/webhooks/1").is_err());
+        assert!(validate_discord_url("https://169.254.169.254/latest/meta-data").is_err());
+    }
+
+    #[test]
+    fn test_validate_webhook_url() {
+        assert!(validate_webhook_url("https://example.com/hook").is_ok());
+        assert!(validate_webhook_url("http://example.com/hook").is_err());
+        assert!(validate_webhook_url("https://localhost/hook").is_err());
+        assert!(validate_webhook_url("https://127.0.0.1/hook").is_err());
+        assert!(validate_webhook_url("https://169.254.169.254/latest").is_err());
+        assert!(validate_webhook_url("https://10.0.0.8/hook").is_err());
+        assert!(validate_webhook_url("https://[::1]/hook").is_err());
+        assert!(validate_webhook_url("https://[::ffff:192.168.1.1]/hook").is_err());
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        assert!(is_public_ip("1.1.1.1".parse().unwrap()));
+        assert!(is_public_ip("2606:4700:4700::1111".parse().unwrap()));
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_public() {
+        let url = Url::parse("https://localhost:8443/hook").unwrap();
+        assert!(matches!(
+            resolve_public(&url).await,
+            Err(NotificationError::ForbiddenHost(_))
+        ));
+    }
+}
@@ -0,0 +1,444 @@
+//! Delivery of the Notification actions, every channel (email, signed webhook,
+//! Telegram, Discord) implements `NotificationChannel`; the channels of a user
+//! are configured in `NotificationSettings`
+
+pub mod discord;
+pub mod email;
+pub mod guard;
+pub mod telegram;
+pub mod webhook;
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::pipeline::Notification;
+use crate::engine::retry::retry_with_backoff_if;
+use crate::redis::client::RedisClientError;
+use crate::redis::rate_limits::RateLimitType;
+use crate::Engine;
+use anyhow::Result;
+
+use self::discord::{DiscordChannel, DiscordConfig};
+use self::email::EmailChannel;
+use self::guard::{resolve_public, validate_discord_url, validate_webhook_url};
+use self::telegram::{TelegramChannel, TelegramConfig};
+use self::webhook::{WebhookChannel, WebhookConfig};
+
+#[derive(Debug, thiserror::Error)]
+pub enum NotificationError {
+    #[error("[Notification] Channel not configured: {0:?}")]
+    NotConfigured(ChannelKind),
+
+    #[error("[Notification] Rate limit exceeded: {0:?}")]
+    RateLimitExceeded(ChannelKind),
+
+    #[error("[Notification] Missing environment variable: {0}")]
+    MissingEnvVar(&'static str),
+
+    #[error("[Notification] Failed to serialize: {0}")]
+    SerializeError(serde_json::Error),
+
+    #[error("[Notification] Request failed: {0}")]
+    RequestError(reqwest::Error),
+
+    #[error("[Notification] Unexpected response {0}: {1}")]
+    UnexpectedResponse(u16, String),
+
+    #[error("[Notification] Email error: {0}")]
+    EmailError(anyhow::Error),
+
+    #[error("[Notification] Redis error: {0}")]
+    RedisError(RedisClientError),
+
+    #[error("[Notification] Host not allowed: {0}")]
+    ForbiddenHost(String),
+}
+
+impl NotificationError {
+    /// server errors, timeouts and failed connections, anything else fails
+    /// the same way again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            NotificationError::RequestError(e) => e.is_connect() || e.is_timeout(),
+            NotificationError::UnexpectedResponse(status, _) => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+/// How long a channel gets to deliver a message
+pub const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client of the channels, the redirects are not followed as they could
+/// lead anywhere
+pub fn http_client(builder: reqwest::ClientBuilder) -> reqwest::Client {
+    builder
+        .timeout(NOTIFICATION_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("notification client config is valid")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChannelKind {
+    Email,
+    Webhook,
+    Telegram,
+    Discord,
+}
+
+impl ChannelKind {
+    pub fn rate_limit(&self) -> RateLimitType {
+        match self {
+            ChannelKind::Email => RateLimitType::EmailNotifications,
+            ChannelKind::Webhook => RateLimitType::WebhookNotifications,
+            ChannelKind::Telegram => RateLimitType::TelegramNotifications,
+            ChannelKind::Discord => RateLimitType::DiscordNotifications,
+        }
+    }
+}
+
+/// What gets delivered, the webhook receives it as the JSON body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationMessage {
+    pub user_id: String,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+#[async_trait]
+pub trait NotificationChannel: Send + Sync {
+    fn kind(&self) -> ChannelKind;
+
+    /// returns the id of the delivered message
+    async fn send(&self, message: &NotificationMessage) -> Result<String, NotificationError>;
+}
+
+/// Per-user channel configuration, email is always available
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    #[serde(default)]
+    pub webhook: Option<WebhookConfig>,
+    #[serde(default)]
+    pub telegram: Option<TelegramConfig>,
+    #[serde(default)]
+    pub discord: Option<DiscordConfig>,
+}
+
+impl NotificationSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(webhook) = &self.webhook {
+            validate_webhook_url(&webhook.url)?;
+            if webhook.secret.len() < 16 {
+                return Err("webhook secret must be at least 16 characters".to_string());
+            }
+        }
+        if let Some(telegram) = &self.telegram {
+            if telegram.chat_id.trim().is_empty() {
+                return Err("telegram chat_id is required".to_string());
+            }
+        }
+        if let Some(discord) = &self.discord {
+            validate_discord_url(&discord.webhook_url)?;
+        }
+        Ok(())
+    }
+
+    /// Copy that is safe to return to the user
+    pub fn redacted(mut self) -> Self {
+        if let Some(webhook) = &mut self.webhook {
+            webhook.secret = "********".to_string();
+        }
+        self
+    }
+
+    /// The configured channels, email only if nothing else is set up
+    pub fn default_channels(&self) -> Vec<ChannelKind> {
+        let mut channels = Vec::new();
+        if self.webhook.is_some() {
+            channels.push(ChannelKind::Webhook);
+        }
+        if self.telegram.is_some() {
+            channels.push(ChannelKind::Telegram);
+        }
+        if self.discord.is_some() {
+            channels.push(ChannelKind::Discord);
+        }
+        if channels.is_empty() {
+            channels.push(ChannelKind::Email);
+        }
+        channels
+    }
+}
+
+pub fn notification_settings_key(user_id: &str) -> String {
+    format!("notification_settings:{}", user_id)
+}
+
+/// Sends through the channel, retrying server errors, timeouts and failed
+/// connections with backoff
+pub async fn deliver(
+    channel: &dyn NotificationChannel,
+    message: &NotificationMessage,
+) -> Result<String, NotificationError> {
+    retry_with_backoff_if(
+        &format!("send_notification_{:?}", channel.kind()),
+        || channel.send(message),
+        NotificationError::is_retryable,
+    )
+    .await
+}
+
+impl Engine {
+    pub async fn get_notification_settings(
+        &self,
+        user_id: &str,
+    ) -> Result<NotificationSettings, NotificationError> {
+        self.redis
+            .get(&notification_settings_key(user_id))
+            .await
+            .map(|settings| settings.unwrap_or_default())
+            .map_err(NotificationError::RedisError)
+    }
+
+    pub async fn set_notification_settings(
+        &self,
+        user_id: &str,
+        settings: &NotificationSettings,
+    ) -> Result<(), NotificationError> {
+        self.redis
+            .set(&notification_settings_key(user_id), settings)
+            .await
+            .map_err(NotificationError::RedisError)
+    }
+
+    /// The urls are checked again as the settings might predate the checks,
+    /// the webhook host has to resolve to public addresses only
+    async fn make_channel(
+        &self,
+        kind: ChannelKind,
+        user_id: &str,
+        settings: &NotificationSettings,
+    ) -> Result<Box<dyn NotificationChannel>, NotificationError> {
+        let not_configured = || NotificationError::NotConfigured(kind);
+        Ok(match kind {
+            ChannelKind::Email => Box::new(EmailChannel::from_env(self.privy.clone(), user_id)?),
+            ChannelKind::Webhook => {
+                let config = settings.webhook.clone().ok_or_else(not_configured)?;
+                let url =
+                    validate_webhook_url(&config.url).map_err(NotificationError::ForbiddenHost)?;
+                let addrs = resolve_public(&url).await?;
+                let host = url.host_str().unwrap_or_default().to_string();
+                Box::new(WebhookChannel::pinned(config, &host, &addrs))
+            }
+            ChannelKind::Telegram => Box::new(TelegramChannel::from_env(
+                settings.telegram.clone().ok_or_else(not_configured)?,
+            )?),
+            ChannelKind::Discord => {
+                let config = settings.discord.clone().ok_or_else(not_configured)?;
+                validate_discord_url(&config.webhook_url)
+                    .map_err(NotificationError::ForbiddenHost)?;
+                Box::new(DiscordChannel::new(config))
+            }
+        })
+    }
+
+    /// Sets the channel up and reserves a message of its rate limit, the
+    /// reserved message counts even if the delivery fails
+    async fn deliver_to_channel(
+        &self,
+        kind: ChannelKind,
+        settings: &NotificationSettings,
+        message: &NotificationMessage,
+    ) -> Result<String, NotificationError> {
+        let channel = self.make_channel(kind, &message.user_id, settings).await?;
+        let reserved = self
+            .redis
+            .reserve_rate_limit(&message.user_id, &kind.rate_limit())
+            .await
+            .map_err(NotificationError::RedisError)?;
+        if !reserved {
+            return Err(NotificationError::RateLimitExceeded(kind));
+        }
+
+        let id = deliver(channel.as_ref(), message).await?;
+        metrics::counter!("notifications_sent", 1, "channel" => format!("{:?}", kind));
+
+        Ok(id)
+    }
+
+    /// Delivers to the channels of the notification, or to the configured channels
+    /// of the user if none are given; the channels are sent to concurrently and
+    /// the notification succeeds if any of them delivered it
+    pub async fn send_notification(
+        &self,
+        user_id: &str,
+        notification: &Notification,
+    ) -> Result<String> {
+        if self.backtest.is_some() {
+            return Ok("backtest".to_string());
+        }
+
+        let settings = self.get_notification_settings(user_id).await?;
+        let channels = match notification.channels.is_empty() {
+            true => settings.default_channels(),
+            false => notification.channels.clone(),
+        };
+        let message = NotificationMessage {
+            user_id: user_id.to_string(),
+            message: notification.message.clone(),
+            timestamp: chrono::Utc::now().timestamp() as u64,
+        };
+
+        let results = join_all(
+            channels
+                .iter()
+                .map(|kind| self.deliver_to_channel(*kind, &settings, &message)),
+        )
+        .await;
+
+        let mut sent = Vec::new();
+        let mut errors = Vec::new();
+        for (kind, result) in channels.into_iter().zip(results) {
+            match result {
+                Ok(id) => {
+                    tracing::info!(?kind, %id, "Notification sent");
+                    sent.push(format!("{:?}", kind).to_lowercase());
+                }
+                Err(e) => {
+                    tracing::warn!(?kind, error = %e, "Failed to send notification");
+                    metrics::counter!("notification_errors", 1, "channel" => format!("{:?}", kind));
+                    errors.push(e.to_string());
+                }
+            }
+        }
+
+        if sent.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Failed to send notification: {}",
+                errors.join(", ")
+            ));
+        }
+
+        Ok(format!("sent:{}", sent.join(",")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::mock::MockServer;
+
+    struct FlakyChannel {
+        url: String,
+    }
+
+    #[async_trait]
+    impl NotificationChannel for FlakyChannel {
+        fn kind(&self) -> ChannelKind {
+            ChannelKind::Webhook
+        }
+
+        async fn send(&self, _: &NotificationMessage) -> Result<String, NotificationError> {
+            let res = reqwest::get(&self.url)
+                .await
+                .map_err(NotificationError::RequestError)?;
+            match res.status().is_success() {
+                true => Ok("ok".to_string()),
+                false => Err(NotificationError::UnexpectedResponse(
+                    res.status().as_u16(),
+                    String::new(),
+                )),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deliver_retries() {
+        let server = MockServer::start(vec![(500, "{}"), (502, "{}"), (200, "{}")]).await;
+        let channel = FlakyChannel {
+            url: server.url.clone(),
+        };
+        let message = NotificationMessage {
+            user_id: "user".to_string(),
+            message: "hello".to_string(),
+            timestamp: 0,
+        };
+
+        assert_eq!(deliver(&channel, &message).await.unwrap(), "ok");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_does_not_retry_client_errors() {
+        let server = MockServer::start(vec![(404, "{}"), (200, "{}")]).await;
+        let channel = FlakyChannel {
+            url: server.url.clone(),
+        };
+        let message = NotificationMessage {
+            user_id: "user".to_string(),
+            message: "hello".to_string(),
+            timestamp: 0,
+        };
+
+        assert!(matches!(
+            deliver(&channel, &message).await,
+            Err(NotificationError::UnexpectedResponse(404, _))
+        ));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_are_retryable() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let e = reqwest::get(&url).await.unwrap_err();
+        assert!(NotificationError::RequestError(e).is_retryable());
+        assert!(!NotificationError::NotConfigured(ChannelKind::Webhook).is_retryable());
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings: NotificationSettings = serde_json::from_value(serde_json::json!({
+            "webhook": { "url": "https://example.com/hook", "secret": "0123456789abcdef" },
+            "telegram": { "chat_id": "12345" }
+        }))
+        .unwrap();
+
+        assert!(settings.validate().is_ok());
+        assert_eq!(
+            settings.default_channels(),
+            vec![ChannelKind::Webhook, ChannelKind::Telegram]
+        );
+        assert_eq!(
+            settings.clone().redacted().webhook.unwrap().secret,
+            "********"
+        );
+        assert_eq!(
+            NotificationSettings::default().default_channels(),
+            vec![ChannelKind::Email]
+        );
+
+        let invalid = NotificationSettings {
+            discord: Some(DiscordConfig {
+                webhook_url: "http://169.254.169.254/latest/meta-data".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+
+        let invalid = NotificationSettings {
+            webhook: Some(WebhookConfig {
+                url: "https://192.168.1.10/hook".to_string(),
+                secret: "0123456789abcdef".to_string(),
+            }),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    http_client, ChannelKind, NotificationChannel, NotificationError, NotificationMessage,
+};
+
+pub const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TelegramConfig {
+    pub chat_id: String,
+}
+
+/// Sends through the bot of TELEGRAM_BOT_TOKEN, the user has to start a chat with it
+pub struct TelegramChannel {
+    client: reqwest::Client,
+    api_url: String,
+    bot_token: String,
+    config: TelegramConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramResponse {
+    ok: bool,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    result: Option<TelegramMessage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TelegramMessage {
+    message_id: i64,
+}
+
+impl TelegramChannel {
+    pub fn new(api_url: &str, bot_token: &str, config: TelegramConfig) -> Self {
+        Self {
+            client: http_client(reqwest::Client::builder()),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            bot_token: bot_token.to_string(),
+            config,
+        }
+    }
+
+    pub fn from_env(config: TelegramConfig) -> Result<Self, NotificationError> {
+        let bot_token = std::env::var("TELEGRAM_BOT_TOKEN")
+            .map_err(|_| NotificationError::MissingEnvVar("TELEGRAM_BOT_TOKEN"))?;
+        let api_url =
+            std::env::var("TELEGRAM_API_URL").unwrap_or_else(|_| TELEGRAM_API_URL.to_string());
+        Ok(Self::new(&api_url, &bot_token, config))
+    }
+}
+
+#[async_trait]
+impl NotificationChannel for TelegramChannel {
+    fn kind(&self) -> ChannelKind {
+        ChannelKind::Telegram
+    }
+
+    async fn send(&self, message: &NotificationMessage) -> Result<String, NotificationError> {
+        let res = self
+            .client
+            .post(format!(
+                "{}/bot{}/sendMessage",
+                self.api_url, self.bot_token
+            ))
+            .json(&serde_json::json!({
+                "chat_id": self.config.chat_id,
+                "text": message.message,
+            }))
+            .send()
+            .await
+            .map_err(NotificationError::RequestError)?;
+
+        let status = res.status().as_u16();
+        let body: TelegramResponse = res.json().await.map_err(NotificationError::RequestError)?;
+        match (body.ok, body.result) {
+            (true, Some(result)) => Ok(format!("telegram:{}", result.message_id)),
+            _ => Err(NotificationError::UnexpectedResponse(
+                status,
+                body.description.unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::mock::MockServer;
+
+    #[tokio::test]
+    async fn test_telegram_send_message() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"ok":true,"result":{"message_id":42,"chat":{"id":12345}}}"#,
+        )])
+        .await;
+        let channel = TelegramChannel::new(
+            &server.url,
+            "123:token",
+            TelegramConfig {
+                chat_id: "12345".to_string(),
+            },
+        );
+        let message = NotificationMessage {
+            user_id: "user".to_string(),
+            message: "order filled".to_string(),
+            timestamp: 0,
+        };
+
+        assert_eq!(channel.send(&message).await.unwrap(), "telegram:42");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/bot123:token/sendMessage");
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["chat_id"], "12345");
+        assert_eq!(body["text"], "order filled");
+    }
+
+    #[tokio::test]
+    async fn test_telegram_error() {
+        let server = MockServer::start(vec![(
+            400,
+            r#"{"ok":false,"error_code":400,"description":"Bad Request: chat not found"}"#,
+        )])
+        .await;
+        let channel = TelegramChannel::new(
+            &server.url,
+            "123:token",
+            TelegramConfig {
+                chat_id: "1".to_string(),
+            },
+        );
+        let message = NotificationMessage {
+            user_id: "user".to_string(),
+            message: "hello".to_string(),
+            timestamp: 0,
+        };
+
+        match channel.send(&message).await {
+            Err(NotificationError::UnexpectedResponse(400, description)) => {
+                assert!(description.contains("chat not found"))
+            }
+            other => panic!("Expected error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::{
+    http_client, ChannelKind, NotificationChannel, NotificationError, NotificationMessage,
+};
+
+pub const SIGNATURE_HEADER: &str = "X-Listen-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Listen-Timestamp";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// key of the HMAC-SHA256 signature of the requests
+    pub secret: String,
+}
+
+/// Posts the message as JSON, signed with `sha256=hex(hmac(secret, "{timestamp}.{body}"))`
+pub struct WebhookChannel {
+    client: reqwest::Client,
+    config: WebhookConfig,
+}
+
+impl WebhookChannel {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            client: http_client(reqwest::Client::builder()),
+            config,
+        }
+    }
+
+    /// Connects to the given addresses of the host only, see `guard::resolve_public`
+    pub fn pinned(config: WebhookConfig, host: &str, addrs: &[SocketAddr]) -> Self {
+        Self {
+            client: http_client(reqwest::Client::builder().resolve_to_addrs(host, addrs)),
+            config,
+        }
+    }
+}
+
+pub fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[async_trait]
+impl NotificationChannel for WebhookChannel {
+    fn kind(&self) -> ChannelKind {
+        ChannelKind::Webhook
+    }
+
+    async fn send(&self, message: &NotificationMessage) -> Result<String, NotificationError> {
+        let body = serde_json::to_string(message).map_err(NotificationError::SerializeError)?;
+        let res = self
+            .client
+            .post(&self.config.url)
+            .header("Content-Type", "application/json")
+            .header(TIMESTAMP_HEADER, message.timestamp.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                sign(&self.config.secret, message.timestamp, &body),
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(NotificationError::RequestError)?;
+
+        let status = res.status();
+        if !status.is_success() {
+            let text = res.text().await.unwrap_or_default();
+            return Err(NotificationError::UnexpectedResponse(status.as_u16(), text));
+        }
+        Ok(format!("webhook:{}", status.as_u16()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::mock::MockServer;
+
+    #[tokio::test]
+    async fn test_webhook_is_signed() {
+        let server = MockServer::start(vec![(200, "{}")]).await;
+        let secret = "0123456789abcdef";
+        let channel = WebhookChannel::new(WebhookConfig {
+            url: format!("{}/hook", server.url),
+            secret: secret.to_string(),
+        });
+        let message = NotificationMessage {
+            user_id: "user".to_string(),
+            message: "SOL above 200".to_string(),
+            timestamp: 1_700_000_000,
+        };
+
+        channel.send(&message).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.path, "/hook");
+        assert_eq!(
+            request.header(SIGNATURE_HEADER),
+            Some(sign(secret, 1_700_000_000, &request.body).as_str())
+        );
+        let body: NotificationMessage = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(body.message, "SOL above 200");
+    }
+
+    #[tokio::test]
+    async fn test_webhook_error_status() {
+        let server = MockServer::start(vec![(404, "not found")]).await;
+        let channel = WebhookChannel::new(WebhookConfig {
+            url: server.url.clone(),
+            secret: "0123456789abcdef".to_string(),
+        });
+        let message = NotificationMessage {
+            user_id: "user".to_string(),
+            message: "hello".to_string(),
+            timestamp: 0,
+        };
+
+        assert!(matches!(
+            channel.send(&message).await,
+            Err(NotificationError::UnexpectedResponse(404, _))
+        ));
+    }
+}
@@ -67,6 +67,24 @@ impl SwapOrder {
     pub fn is_solana(&self) -> bool {
         is_solana(&self.from_chain_caip2)
     }
+
+    /// Splits the amount into `parts` out of `total_parts`, returns the slice
+    /// and the rest; the rounding remainder is kept in the rest
+    pub fn split_amount(
+        &self,
+        parts: usize,
+        total_parts: usize,
+    ) -> Result<(String, String), SwapOrderError> {
+        let amount = self
+            .amount
+            .parse::<u128>()
+            .map_err(|e| SwapOrderError::InvalidAmount(e.into()))?;
+        if total_parts == 0 || parts >= total_parts {
+            return Ok((amount.to_string(), "0".to_string()));
+        }
+        let slice = amount / total_parts as u128 * parts as u128;
+        Ok((slice.to_string(), (amount - slice).to_string()))
+    }
 }
 
 // Map of CAIP2 identifiers to LiFi chain IDs
@@ -117,12 +135,18 @@ pub enum SwapOrderTransaction {
     Solana(String),
 }
 
+/// The transaction of the order with the output amount it was quoted for
+pub struct QuotedSwap {
+    pub transaction: SwapOrderTransaction,
+    pub out_amount: String,
+}
+
 pub async fn swap_order_to_transaction(
     order: &SwapOrder,
     lifi: &lifi::LiFi,
     wallet_address: Option<String>, // evm output
     pubkey: Option<String>,         // solana output
-) -> Result<SwapOrderTransaction, SwapOrderError> {
+) -> Result<QuotedSwap, SwapOrderError> {
     let from_chain_id =
         caip2_to_chain_id(&order.from_chain_caip2).ok_or(SwapOrderError::InvalidCaip2)?;
     let to_chain_id =
@@ -162,7 +186,7 @@ async fn try_lifi_swap_order_to_transaction(
     lifi: &lifi::LiFi,
     wallet_address: &str,
     pubkey: &str,
-) -> Result<SwapOrderTransaction, SwapOrderError> {
+) -> Result<QuotedSwap, SwapOrderError> {
     let from_chain_id =
         caip2_to_chain_id(&order.from_chain_caip2).ok_or(SwapOrderError::InvalidCaip2)?;
     let to_chain_id =
@@ -207,27 +231,32 @@ async fn try_lifi_swap_order_to_transaction(
 
     tracing::info!("Quote: {:#?}", quote);
 
-    match quote.transaction_request {
+    let transaction = match quote.transaction_request {
         Some(transaction_request) => {
             if transaction_request.is_solana() {
-                Ok(SwapOrderTransaction::Solana(transaction_request.data))
+                SwapOrderTransaction::Solana(transaction_request.data)
             } else {
-                Ok(SwapOrderTransaction::Evm(
+                SwapOrderTransaction::Evm(
                     transaction_request
                         .to_json_rpc()
                         .map_err(SwapOrderError::SerializeError)?,
-                ))
+                )
             }
         }
-        None => Err(SwapOrderError::NoTransactionRequest),
-    }
+        None => return Err(SwapOrderError::NoTransactionRequest),
+    };
+
+    Ok(QuotedSwap {
+        transaction,
+        out_amount: quote.estimate.to_amount,
+    })
 }
 
 // Helper function that actually performs the swap operation
 async fn try_solana_swap_order_to_transaction(
     order: &SwapOrder,
     pubkey: &str,
-) -> Result<SwapOrderTransaction, SwapOrderError> {
+) -> Result<QuotedSwap, SwapOrderError> {
     let quote = Jupiter::fetch_quote(
         &order.input_token,
         &order.output_token,
@@ -238,6 +267,7 @@ async fn try_solana_swap_order_to_transaction(
     )
     .await
     .map_err(SwapOrderError::JupiterError)?;
+    let out_amount = quote.out_amount.clone();
 
     let tx = Jupiter::swap(
         quote,
@@ -246,7 +276,10 @@ async fn try_solana_swap_order_to_transaction(
     .await
     .map_err(SwapOrderError::JupiterError)?;
 
-    Ok(SwapOrderTransaction::Solana(transaction_to_base64(&tx)?))
+    Ok(QuotedSwap {
+        transaction: SwapOrderTransaction::Solana(transaction_to_base64(&tx)?),
+        out_amount,
+    })
 }
 
 pub fn transaction_to_base64<T: Serialize>(transaction: &T) -> Result<String, SwapOrderError> {
@@ -297,10 +330,7 @@ mod tests {
             to_chain_caip2: to_chain_caip2.to_string(),
         };
 
-        let lifi_api_key: Option<String> = match std::env::var("LIFI_API_KEY") {
-            Ok(val) => Some(val),
-            Err(_) => None,
-        };
+        let lifi_api_key: Option<String> = std::env::var("LIFI_API_KEY").ok();
 
         let lifi = lifi::LiFi::new(lifi_api_key, Some("listen".to_string()));
         let transaction = swap_order_to_transaction(
@@ -310,7 +340,8 @@ mod tests {
             Some(TEST_ADDRESS_SOL.to_string()),
         )
         .await
-        .unwrap();
+        .unwrap()
+        .transaction;
 
         let privy = std::sync::Arc::new(privy::Privy::new(
             privy::config::PrivyConfig::from_env().unwrap(),
@@ -508,12 +539,9 @@ mod tests {
 
         let privy = privy::Privy::new(privy::config::PrivyConfig::from_env().unwrap());
 
-        let value = format!("0x{}", hex::encode((10e8 as u64).to_le_bytes().to_vec()));
-        let gas_limit = format!("0x{}", hex::encode((1000000 as u64).to_le_bytes().to_vec()));
-        let gas_price = format!(
-            "0x{}",
-            hex::encode((1000000000 as u64).to_le_bytes().to_vec())
-        );
+        let value = format!("0x{}", hex::encode((10e8 as u64).to_le_bytes()));
+        let gas_limit = format!("0x{}", hex::encode(1000000_u64.to_le_bytes()));
+        let gas_price = format!("0x{}", hex::encode(1000000000_u64.to_le_bytes()));
         println!("Value: {:#?}", value);
 
         // Execute the transaction
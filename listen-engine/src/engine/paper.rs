@@ -0,0 +1,300 @@
+//! Paper trading, the orders of paper pipelines are filled from the current
+//! price against a virtual per-user ledger in Redis instead of going on-chain
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::engine::{order::SwapOrder, pipeline::OrderFill, Engine, EngineError};
+use crate::redis::client::RedisClientError;
+
+pub const SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+pub const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+pub const USDT_MINT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+
+#[derive(Debug, thiserror::Error)]
+pub enum PaperTradingError {
+    #[error("[Paper] Unsupported chain: {0}")]
+    UnsupportedChain(String),
+
+    #[error("[Paper] Invalid amount: {0}")]
+    InvalidAmount(String),
+
+    #[error("[Paper] Missing price for {0}")]
+    MissingPrice(String),
+
+    #[error("[Paper] Missing decimals for {0}")]
+    MissingDecimals(String),
+
+    #[error("[Paper] Insufficient balance of {0}")]
+    InsufficientBalance(String),
+
+    #[error("[Paper] Redis error: {0}")]
+    RedisError(RedisClientError),
+}
+
+#[derive(Debug, Clone)]
+pub struct PaperConfig {
+    pub slippage_bps: u32,
+    pub fee_bps: u32,
+    /// raw amounts credited to the ledger of a user on the first paper order
+    pub initial_balances: Vec<(String, u64)>,
+    /// every order is simulated, Privy is not required
+    pub only: bool,
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        Self {
+            slippage_bps: 50,
+            fee_bps: 30,
+            initial_balances: vec![
+                (USDC_MINT.to_string(), 10_000 * 10u64.pow(6)),
+                (SOL_MINT.to_string(), 10 * 10u64.pow(9)),
+            ],
+            only: false,
+        }
+    }
+}
+
+impl PaperConfig {
+    /// PAPER_SLIPPAGE_BPS, PAPER_FEE_BPS, PAPER_INITIAL_USDC (ui amount),
+    /// PAPER_INITIAL_SOL (ui amount) and PAPER_TRADING_ONLY
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+
+        let usdc = var("PAPER_INITIAL_USDC").unwrap_or(10_000);
+        let sol = var("PAPER_INITIAL_SOL").unwrap_or(10);
+
+        Self {
+            slippage_bps: var("PAPER_SLIPPAGE_BPS")
+                .map(|v| v as u32)
+                .unwrap_or(default.slippage_bps),
+            fee_bps: var("PAPER_FEE_BPS")
+                .map(|v| v as u32)
+                .unwrap_or(default.fee_bps),
+            initial_balances: vec![
+                (USDC_MINT.to_string(), usdc * 10u64.pow(6)),
+                (SOL_MINT.to_string(), sol * 10u64.pow(9)),
+            ],
+            only: std::env::var("PAPER_TRADING_ONLY").is_ok_and(|v| v == "true" || v == "1"),
+        }
+    }
+}
+
+/// Price in USD and decimals of a token
+#[derive(Debug, Clone, Copy)]
+pub struct PaperQuote {
+    pub price: f64,
+    pub decimals: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaperFill {
+    pub transaction_hash: String,
+    pub input_token: String,
+    pub output_token: String,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub fee_usd: f64,
+    pub timestamp: u64,
+}
+
+/// Fills `amount` of the input token at the given quotes, the fee is taken from
+/// the USD value of the order and the slippage is applied to the output
+pub fn simulate_fill(
+    amount: u64,
+    input: PaperQuote,
+    output: PaperQuote,
+    config: &PaperConfig,
+) -> (u64, f64) {
+    let value_usd = amount as f64 / 10f64.powi(input.decimals as i32) * input.price;
+    let fee_usd = value_usd * config.fee_bps as f64 / 10_000.0;
+    let output_ui =
+        (value_usd - fee_usd) * (1.0 - config.slippage_bps as f64 / 10_000.0) / output.price;
+    let output_amount = (output_ui * 10f64.powi(output.decimals as i32)).floor() as u64;
+    (output_amount, fee_usd)
+}
+
+/// Minimal view of the token metadata written by listen-data
+#[derive(Debug, Deserialize)]
+struct MetadataDecimals {
+    spl: SplDecimals,
+}
+
+#[derive(Debug, Deserialize)]
+struct SplDecimals {
+    decimals: u8,
+}
+
+pub fn known_decimals(mint: &str) -> Option<u8> {
+    match mint {
+        SOL_MINT => Some(9),
+        USDC_MINT | USDT_MINT => Some(6),
+        _ => None,
+    }
+}
+
+/// Stablecoins are assumed to be at peg when there is no price for them
+pub fn price_or_peg(mint: &str, price: Option<f64>) -> Option<f64> {
+    match (price, mint) {
+        (Some(price), _) if price > 0.0 => Some(price),
+        (_, USDC_MINT | USDT_MINT) => Some(1.0),
+        _ => None,
+    }
+}
+
+impl Engine {
+    pub async fn execute_paper_order(
+        &self,
+        order: &SwapOrder,
+        user_id: &str,
+    ) -> Result<OrderFill, EngineError> {
+        if !order.is_solana() || order.from_chain_caip2 != order.to_chain_caip2 {
+            return Err(PaperTradingError::UnsupportedChain(order.to_chain_caip2.clone()).into());
+        }
+        let amount = order
+            .amount
+            .parse::<u64>()
+            .map_err(|_| PaperTradingError::InvalidAmount(order.amount.clone()))?;
+
+        let input = self.paper_quote(&order.input_token).await?;
+        let output = self.paper_quote(&order.output_token).await?;
+        let (output_amount, fee_usd) = simulate_fill(amount, input, output, &self.paper_config);
+
+        self.redis
+            .init_paper_ledger(user_id, &self.paper_config.initial_balances)
+            .await
+            .map_err(PaperTradingError::RedisError)?;
+        let swapped = self
+            .redis
+            .paper_swap(
+                user_id,
+                &order.input_token,
+                amount,
+                &order.output_token,
+                output_amount,
+            )
+            .await
+            .map_err(PaperTradingError::RedisError)?;
+        if !swapped {
+            return Err(PaperTradingError::InsufficientBalance(order.input_token.clone()).into());
+        }
+
+        let fill = PaperFill {
+            transaction_hash: format!("paper-{}", Uuid::new_v4()),
+            input_token: order.input_token.clone(),
+            output_token: order.output_token.clone(),
+            input_amount: amount,
+            output_amount,
+            fee_usd,
+            timestamp: chrono::Utc::now().timestamp() as u64,
+        };
+        if let Err(e) = self.redis.push_paper_fill(user_id, &fill).await {
+            tracing::warn!(error = %e, "Failed to record paper fill");
+        }
+
+        metrics::counter!("paper_orders_filled", 1);
+        tracing::info!(?fill, "Paper order filled");
+
+        Ok(OrderFill {
+            transaction_hash: fill.transaction_hash,
+            input_amount: amount.to_string(),
+            output_amount: Some(output_amount.to_string()),
+        })
+    }
+
+    async fn paper_quote(&self, mint: &str) -> Result<PaperQuote, PaperTradingError> {
+        // the read guard has to be dropped before the Redis fallback writes the state
+        let cached = self.market_state.read().await.price(mint);
+        let price = match cached {
+            Some(price) => Some(price),
+            None => self.fetch_price_from_redis(mint).await,
+        };
+        let price = price_or_peg(mint, price)
+            .ok_or_else(|| PaperTradingError::MissingPrice(mint.to_string()))?;
+
+        let decimals = match known_decimals(mint) {
+            Some(decimals) => decimals,
+            None => self
+                .redis
+                .get::<MetadataDecimals>(&format!("solana:metadata:{}", mint))
+                .await
+                .map_err(PaperTradingError::RedisError)?
+                .map(|metadata| metadata.spl.decimals)
+                .ok_or_else(|| PaperTradingError::MissingDecimals(mint.to_string()))?,
+        };
+
+        Ok(PaperQuote { price, decimals })
+    }
+
+    pub async fn get_paper_balances(
+        &self,
+        user_id: &str,
+    ) -> Result<HashMap<String, i64>, EngineError> {
+        self.redis
+            .init_paper_ledger(user_id, &self.paper_config.initial_balances)
+            .await
+            .map_err(EngineError::RedisClientError)?;
+        self.redis
+            .get_paper_balances(user_id)
+            .await
+            .map_err(EngineError::RedisClientError)
+    }
+}
+
+impl From<PaperTradingError> for EngineError {
+    fn from(e: PaperTradingError) -> Self {
+        EngineError::PaperTradingError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simulate_fill() {
+        let config = PaperConfig {
+            slippage_bps: 100,
+            fee_bps: 50,
+            ..Default::default()
+        };
+        let sol = PaperQuote {
+            price: 200.0,
+            decimals: 9,
+        };
+        let usdc = PaperQuote {
+            price: 1.0,
+            decimals: 6,
+        };
+
+        // 1 SOL -> 200 USD, 1 USD fee, 1% slippage on the rest
+        let (output, fee_usd) = simulate_fill(1_000_000_000, sol, usdc, &config);
+        assert_eq!(fee_usd, 1.0);
+        assert_eq!(output, 197_010_000);
+    }
+
+    #[test]
+    fn test_simulate_fill_without_costs() {
+        let config = PaperConfig {
+            slippage_bps: 0,
+            fee_bps: 0,
+            ..Default::default()
+        };
+        let usdc = PaperQuote {
+            price: 1.0,
+            decimals: 6,
+        };
+        let token = PaperQuote {
+            price: 0.5,
+            decimals: 6,
+        };
+
+        let (output, fee_usd) = simulate_fill(10_000_000, usdc, token, &config);
+        assert_eq!(fee_usd, 0.0);
+        assert_eq!(output, 20_000_000);
+    }
+}
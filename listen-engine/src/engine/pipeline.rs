@@ -5,15 +5,80 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::engine::events::StepNotifications;
+use crate::engine::notifications::ChannelKind;
 use crate::engine::order::SwapOrder;
+use crate::engine::recurring::RecurringOrder;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConditionType {
-    PriceAbove { asset: String, value: f64 },
-    PriceBelow { asset: String, value: f64 },
-    Now { asset: String },
+    PriceAbove {
+        asset: String,
+        value: f64,
+    },
+    PriceBelow {
+        asset: String,
+        value: f64,
+    },
+    Now {
+        asset: String,
+    },
     And(Vec<Condition>),
     Or(Vec<Condition>),
+    /// Fires once the price drops `trail_pct` percent below the highest price
+    /// seen since the step became active; `peak` is persisted with the pipeline
+    TrailingStop {
+        asset: String,
+        trail_pct: f64,
+        #[serde(default)]
+        peak: Option<f64>,
+    },
+    /// Fires every time the price crosses one of the not-yet-hit targets,
+    /// the step stays pending until all of the targets are hit
+    TakeProfitLadder {
+        asset: String,
+        targets: Vec<TakeProfitTarget>,
+    },
+    MarketCapAbove {
+        asset: String,
+        value: f64,
+    },
+    MarketCapBelow {
+        asset: String,
+        value: f64,
+    },
+    /// USD volume traded within the last `window_secs` is at least `value`
+    VolumeInWindowAbove {
+        asset: String,
+        window_secs: u64,
+        value: f64,
+    },
+    /// Price moved by at least `pct` percent within the last `window_secs`,
+    /// negative `pct` for drops
+    PercentChange {
+        asset: String,
+        window_secs: u64,
+        pct: f64,
+    },
+    /// Buy to sell USD volume ratio within the last `window_secs` is at least `value`
+    BuySellRatioAbove {
+        asset: String,
+        window_secs: u64,
+        value: f64,
+    },
+    /// Fires once the given time is reached
+    At(DateTime<Utc>),
+    /// Fires `delay_secs` after the given step of the pipeline completed
+    After {
+        step: Uuid,
+        delay_secs: u64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TakeProfitTarget {
+    pub price: f64,
+    pub hit: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,12 +91,16 @@ pub struct Condition {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Notification {
     pub message: String,
+    /// the configured channels of the user if empty
+    #[serde(default)]
+    pub channels: Vec<ChannelKind>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Action {
     Order(SwapOrder),
     Notification(Notification),
+    Recurring(RecurringOrder),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +112,44 @@ pub struct PipelineStep {
     pub status: Status,
     pub transaction_hash: Option<String>,
     pub error: Option<String>,
+    /// The step is cancelled if its conditions are not met before this time
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// swaps executed by the order step, ladders fill once per target hit
+    #[serde(default)]
+    pub fills: Vec<OrderFill>,
+    /// submitted swap of the order step waiting for its confirmation
+    #[serde(default)]
+    pub pending: Option<PendingOrder>,
+}
+
+/// Executed swap of an order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderFill {
+    pub transaction_hash: String,
+    pub input_amount: String,
+    /// the quoted amount for on-chain swaps, the simulated one otherwise
+    pub output_amount: Option<String>,
+}
+
+/// Submitted transaction whose confirmation is tracked in the background
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingTransaction {
+    pub transaction_hash: String,
+    /// chain the transaction was sent to
+    pub chain_caip2: String,
+    /// block height after which a Solana transaction can no longer land
+    pub last_valid_block_height: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingOrder {
+    pub fill: OrderFill,
+    pub transaction: PendingTransaction,
+    /// ladders with targets left stay pending once confirmed
+    pub completes_step: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,6 +162,34 @@ pub struct Pipeline {
     pub steps: HashMap<Uuid, PipelineStep>,
     pub status: Status,
     pub created_at: DateTime<Utc>,
+    /// slices of the recurring steps
+    #[serde(default)]
+    pub child_executions: Vec<ChildExecution>,
+    /// orders are simulated against the paper ledger instead of being executed
+    #[serde(default)]
+    pub paper: bool,
+    /// notifications sent when a step completes or fails
+    #[serde(default)]
+    pub notifications: Option<StepNotifications>,
+}
+
+/// Single execution (slice) of a recurring step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChildExecution {
+    pub id: Uuid,
+    pub step_id: Uuid,
+    pub index: u32,
+    pub amount: String,
+    pub scheduled_at: DateTime<Utc>,
+    pub executed_at: Option<DateTime<Utc>>,
+    pub status: Status,
+    pub transaction_hash: Option<String>,
+    #[serde(default)]
+    pub output_amount: Option<String>,
+    pub error: Option<String>,
+    /// the slice is executed once its transaction is confirmed
+    #[serde(default)]
+    pub pending: Option<PendingTransaction>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -93,9 +228,20 @@ impl Pipeline {
             value.id.hash(&mut hasher);
             // For PipelineStep, hash important fields
             value.next_steps.hash(&mut hasher);
+            hash_conditions_state(&value.conditions, &mut hasher);
             value.status.hash(&mut hasher);
             value.transaction_hash.hash(&mut hasher);
             value.error.hash(&mut hasher);
+            value.fills.len().hash(&mut hasher);
+            value.pending.is_some().hash(&mut hasher);
+        }
+
+        for execution in &self.child_executions {
+            execution.id.hash(&mut hasher);
+            execution.status.hash(&mut hasher);
+            execution.transaction_hash.hash(&mut hasher);
+            execution.error.hash(&mut hasher);
+            execution.pending.is_some().hash(&mut hasher);
         }
 
         self.status.hash(&mut hasher);
@@ -105,3 +251,26 @@ impl Pipeline {
         format!("{:x}", hasher.finish())
     }
 }
+
+/// Stateful conditions (trailing stop peak, ladder targets) have to be part of
+/// the hash, otherwise the updated state would never be persisted to Redis; the
+/// sampling time of the evaluations is left out so that it does not save the
+/// pipeline every minute, it is persisted with the next change
+fn hash_conditions_state<H: Hasher>(conditions: &[Condition], state: &mut H) {
+    for condition in conditions {
+        match &condition.condition_type {
+            ConditionType::TrailingStop { peak, .. } => {
+                peak.map(f64::to_bits).hash(state);
+            }
+            ConditionType::TakeProfitLadder { targets, .. } => {
+                for target in targets {
+                    target.hit.hash(state);
+                }
+            }
+            ConditionType::And(sub) | ConditionType::Or(sub) => {
+                hash_conditions_state(sub, state);
+            }
+            _ => {}
+        }
+    }
+}
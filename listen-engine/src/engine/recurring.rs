@@ -0,0 +1,340 @@
+//! Recurring (DCA / TWAP) orders, the order amount is split into slices that
+//! are executed on a fixed interval once the conditions of the step are met
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::engine::{
+    evaluate::cancel_downstream_steps,
+    market::MarketState,
+    order::{SwapOrder, SwapOrderError},
+    pipeline::{ChildExecution, Pipeline, Status},
+    Engine,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringOrder {
+    /// the order with the total amount, split equally between the slices
+    pub order: SwapOrder,
+    pub slices: u32,
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub price_guard: Option<PriceGuard>,
+}
+
+/// A slice is skipped if the price of the asset is outside of the bounds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceGuard {
+    pub asset: String,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+}
+
+impl PriceGuard {
+    /// `None` if the price of the asset is not known
+    pub fn allows(&self, market: &MarketState) -> Option<bool> {
+        let price = market.price(&self.asset)?;
+        Some(
+            self.min_price.is_none_or(|min| price >= min)
+                && self.max_price.is_none_or(|max| price <= max),
+        )
+    }
+}
+
+impl RecurringOrder {
+    /// Splits the order into the slices, first one is due at `start`,
+    /// the rounding remainder of the amount goes into the last slice
+    pub fn schedule(
+        &self,
+        step_id: Uuid,
+        start: DateTime<Utc>,
+    ) -> Result<Vec<ChildExecution>, SwapOrderError> {
+        let amount = self
+            .order
+            .amount
+            .parse::<u128>()
+            .map_err(|e| SwapOrderError::InvalidAmount(e.into()))?;
+        let slices = self.slices.max(1);
+        let slice_amount = amount / slices as u128;
+        if slice_amount == 0 {
+            return Err(SwapOrderError::InvalidAmount(anyhow::anyhow!(
+                "amount {} too small to split into {} slices",
+                amount,
+                slices
+            )));
+        }
+
+        Ok((0..slices)
+            .map(|index| {
+                let amount = if index + 1 == slices {
+                    amount - slice_amount * (slices as u128 - 1)
+                } else {
+                    slice_amount
+                };
+                ChildExecution {
+                    id: Uuid::new_v4(),
+                    step_id,
+                    index,
+                    amount: amount.to_string(),
+                    scheduled_at: start
+                        + Duration::seconds((self.interval_secs * index as u64) as i64),
+                    executed_at: None,
+                    status: Status::Pending,
+                    transaction_hash: None,
+                    output_amount: None,
+                    error: None,
+                    pending: None,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Final status of the recurring step, `None` while some of its slices are pending;
+/// the step is completed if at least one of the slices went through
+pub fn recurring_step_status(step_id: Uuid, executions: &[ChildExecution]) -> Option<Status> {
+    let mut slices = executions.iter().filter(|e| e.step_id == step_id);
+    if slices.clone().any(|e| matches!(e.status, Status::Pending)) {
+        return None;
+    }
+    if slices.any(|e| matches!(e.status, Status::Completed)) {
+        Some(Status::Completed)
+    } else {
+        Some(Status::Failed)
+    }
+}
+
+impl Engine {
+    /// Schedules the slices of the recurring step on the first run, executes the
+    /// slice that is due and settles the step once all of the slices are done;
+    /// returns true if the pipeline changed
+    pub async fn process_recurring_step(
+        &self,
+        pipeline: &mut Pipeline,
+        step_id: Uuid,
+        recurring: &RecurringOrder,
+        market: &MarketState,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let mut changed = false;
+
+        if !pipeline
+            .child_executions
+            .iter()
+            .any(|e| e.step_id == step_id)
+        {
+            match recurring.schedule(step_id, now) {
+                Ok(executions) => {
+                    tracing::info!(%step_id, slices = executions.len(), "Starting recurring order");
+                    pipeline.child_executions.extend(executions);
+                    changed = true;
+                }
+                Err(e) => {
+                    if let Some(step) = pipeline.steps.get_mut(&step_id) {
+                        step.status = Status::Failed;
+                        step.error = Some(e.to_string());
+                        let next_steps = step.next_steps.clone();
+                        cancel_downstream_steps(pipeline, next_steps);
+                    }
+                    return true;
+                }
+            }
+        }
+
+        changed |= self
+            .execute_due_slice(
+                step_id,
+                recurring,
+                &mut pipeline.child_executions,
+                &pipeline.user_id,
+                pipeline.wallet_address.clone(),
+                pipeline.pubkey.clone(),
+                pipeline.paper,
+                market,
+                now,
+            )
+            .await;
+
+        let status = recurring_step_status(step_id, &pipeline.child_executions);
+        let last_transaction_hash = pipeline
+            .child_executions
+            .iter()
+            .filter(|e| e.step_id == step_id && e.transaction_hash.is_some())
+            .max_by_key(|e| e.index)
+            .and_then(|e| e.transaction_hash.clone());
+
+        let Some(step) = pipeline.steps.get_mut(&step_id) else {
+            return changed;
+        };
+        match status {
+            Some(Status::Completed) => {
+                step.status = Status::Completed;
+                step.completed_at = Some(now);
+                step.transaction_hash = last_transaction_hash;
+                true
+            }
+            Some(_) => {
+                step.status = Status::Failed;
+                step.error =
+                    Some("None of the slices of the recurring order went through".to_string());
+                let next_steps = step.next_steps.clone();
+                cancel_downstream_steps(pipeline, next_steps);
+                true
+            }
+            None => changed,
+        }
+    }
+
+    /// Executes the earliest slice of the step that is due,
+    /// returns true if any of the slices changed
+    #[allow(clippy::too_many_arguments)]
+    pub async fn execute_due_slice(
+        &self,
+        step_id: Uuid,
+        recurring: &RecurringOrder,
+        executions: &mut [ChildExecution],
+        user_id: &str,
+        wallet_address: Option<String>,
+        pubkey: Option<String>,
+        paper: bool,
+        market: &MarketState,
+        now: DateTime<Utc>,
+    ) -> bool {
+        // the slices are executed one after another, the next one waits for
+        // the confirmation of the previous
+        if executions
+            .iter()
+            .any(|e| e.step_id == step_id && e.pending.is_some())
+        {
+            return false;
+        }
+        let Some(slice) = executions
+            .iter_mut()
+            .filter(|e| e.step_id == step_id && matches!(e.status, Status::Pending))
+            .filter(|e| e.scheduled_at <= now)
+            .min_by_key(|e| e.index)
+        else {
+            return false;
+        };
+
+        slice.executed_at = Some(now);
+
+        if let Some(guard) = &recurring.price_guard {
+            match guard.allows(market) {
+                Some(true) => {}
+                Some(false) => {
+                    tracing::info!(%step_id, index = slice.index, "Slice skipped by price guard");
+                    slice.status = Status::Cancelled;
+                    slice.error = Some(format!("Price guard not met for {}", guard.asset));
+                    return true;
+                }
+                None => {
+                    slice.status = Status::Failed;
+                    slice.error = Some(format!("Missing price data for {}", guard.asset));
+                    return true;
+                }
+            }
+        }
+
+        let mut order = recurring.order.clone();
+        order.amount = slice.amount.clone();
+
+        match self
+            .execute_order(&order, user_id, wallet_address, pubkey, paper)
+            .await
+        {
+            Ok(execution) => {
+                slice.transaction_hash = Some(execution.fill.transaction_hash);
+                slice.output_amount = execution.fill.output_amount;
+                match execution.pending {
+                    Some(pending) => slice.pending = Some(pending),
+                    None => slice.status = Status::Completed,
+                }
+            }
+            Err(e) => {
+                tracing::error!(%step_id, index = slice.index, error = %e, "Slice failed");
+                slice.status = Status::Failed;
+                slice.error = Some(e.to_string());
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn recurring(amount: &str, slices: u32) -> RecurringOrder {
+        RecurringOrder {
+            order: SwapOrder {
+                input_token: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
+                output_token: "So11111111111111111111111111111111111111112".to_string(),
+                amount: amount.to_string(),
+                from_chain_caip2: "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp".to_string(),
+                to_chain_caip2: "solana:5eykt4UsFv8P8NJdTREpY1vzqKqZKvdp".to_string(),
+            },
+            slices,
+            interval_secs: 3600,
+            price_guard: None,
+        }
+    }
+
+    #[test]
+    fn test_schedule_splits_amount() {
+        let start = Utc::now();
+        let executions = recurring("1000", 3)
+            .schedule(Uuid::new_v4(), start)
+            .unwrap();
+
+        let amounts: Vec<&str> = executions.iter().map(|e| e.amount.as_str()).collect();
+        assert_eq!(amounts, vec!["333", "333", "334"]);
+        assert_eq!(executions[0].scheduled_at, start);
+        assert_eq!(executions[2].scheduled_at, start + Duration::hours(2));
+    }
+
+    #[test]
+    fn test_schedule_amount_too_small() {
+        assert!(recurring("2", 3)
+            .schedule(Uuid::new_v4(), Utc::now())
+            .is_err());
+    }
+
+    #[test]
+    fn test_recurring_step_status() {
+        let step_id = Uuid::new_v4();
+        let mut executions = recurring("1000", 2).schedule(step_id, Utc::now()).unwrap();
+        assert!(recurring_step_status(step_id, &executions).is_none());
+
+        executions[0].status = Status::Failed;
+        executions[1].status = Status::Completed;
+        assert!(matches!(
+            recurring_step_status(step_id, &executions),
+            Some(Status::Completed)
+        ));
+
+        executions[1].status = Status::Cancelled;
+        assert!(matches!(
+            recurring_step_status(step_id, &executions),
+            Some(Status::Failed)
+        ));
+    }
+
+    #[test]
+    fn test_price_guard() {
+        let guard = PriceGuard {
+            asset: "So11111111111111111111111111111111111111112".to_string(),
+            min_price: Some(100.0),
+            max_price: Some(200.0),
+        };
+        let prices = |price: f64| MarketState::from(HashMap::from([(guard.asset.clone(), price)]));
+
+        assert_eq!(guard.allows(&prices(150.0)), Some(true));
+        assert_eq!(guard.allows(&prices(250.0)), Some(false));
+        assert_eq!(guard.allows(&MarketState::default()), None);
+    }
+}
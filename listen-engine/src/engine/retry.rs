@@ -4,6 +4,21 @@ where
     F: Fn() -> Fut,
     Fut: std::future::Future<Output = Result<T, E>>,
     E: std::fmt::Debug,
+{
+    retry_with_backoff_if(operation_name, f, |_| true).await
+}
+
+/// Like `retry_with_backoff`, the errors `retryable` rejects are returned at once
+pub async fn retry_with_backoff_if<F, Fut, T, E, R>(
+    operation_name: &str,
+    f: F,
+    retryable: R,
+) -> Result<T, E>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+    E: std::fmt::Debug,
+    R: Fn(&E) -> bool,
 {
     const MAX_RETRIES: u32 = 4;
 
@@ -24,6 +39,10 @@ where
         match f().await {
             Ok(result) => return Ok(result),
             Err(err) => {
+                if !retryable(&err) {
+                    return Err(err);
+                }
+
                 // On last attempt, return the error
                 if attempt == MAX_RETRIES {
                     tracing::error!(
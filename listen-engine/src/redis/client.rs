@@ -1,16 +1,41 @@
 // TODO! this should be a listen-redis create (the base) and each tenant can add
 // their own commands to proc
-use crate::{engine::pipeline::Pipeline, redis::subscriber::PriceUpdate};
+use crate::{
+    engine::{paper::PaperFill, pipeline::Pipeline},
+    redis::subscriber::PriceUpdate,
+};
 use anyhow::Result;
 use bb8_redis::{
     bb8::{self, PooledConnection},
-    redis::{cmd, pipe},
+    redis::{cmd, pipe, Script},
     RedisConnectionManager,
 };
 use serde::{de::DeserializeOwned, Serialize};
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
 use tracing::warn;
 
+/// Debits the input and credits the output of a paper swap if the balance
+/// covers it, loaded once and run through EVALSHA
+static PAPER_SWAP_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local balance = tonumber(redis.call('HGET', KEYS[1], ARGV[1]) or '0')
+        if balance < tonumber(ARGV[2]) then
+            return 0
+        end
+        redis.call('HINCRBY', KEYS[1], ARGV[1], -tonumber(ARGV[2]))
+        redis.call('HINCRBY', KEYS[1], ARGV[3], ARGV[4])
+        return 1
+        "#,
+    )
+});
+
+/// The most recent paper fills kept per user
+const PAPER_FILLS_MAXLEN: isize = 1000;
+
 pub struct RedisClient {
     pool: bb8::Pool<RedisConnectionManager>,
 }
@@ -222,14 +247,144 @@ impl RedisClient {
     }
 
     pub async fn get_price(&self, asset: &str) -> Result<f64, RedisClientError> {
+        self.get_price_update(asset)
+            .await
+            .map(|update| update.price)
+    }
+
+    pub async fn get_price_update(&self, asset: &str) -> Result<PriceUpdate, RedisClientError> {
         let price_key = format!("solana:price:{}", asset);
         let price: Option<PriceUpdate> = self.get(&price_key).await?;
         match price {
-            Some(price) => Ok(price.price),
+            Some(price) => Ok(price),
             None => Err(RedisClientError::KeyNotFound(price_key)),
         }
     }
 
+    /// Credits the initial balances, only for the mints the ledger has never seen
+    pub async fn init_paper_ledger(
+        &self,
+        user_id: &str,
+        initial_balances: &[(String, u64)],
+    ) -> Result<(), RedisClientError> {
+        let mut conn = self.pool.get().await?;
+        let key = format!("paper:ledger:{}", user_id);
+        let mut pipe = pipe();
+        for (mint, amount) in initial_balances {
+            pipe.cmd("HSETNX").arg(&key).arg(mint).arg(amount).ignore();
+        }
+        let _: () = pipe.query_async(&mut *conn).await?;
+        Ok(())
+    }
+
+    /// Atomically debits `input_amount` and credits `output_amount`,
+    /// returns false without changes if the input balance is too low
+    pub async fn paper_swap(
+        &self,
+        user_id: &str,
+        input_mint: &str,
+        input_amount: u64,
+        output_mint: &str,
+        output_amount: u64,
+    ) -> Result<bool, RedisClientError> {
+        let mut conn = self.pool.get().await?;
+        let swapped: i64 = PAPER_SWAP_SCRIPT
+            .key(format!("paper:ledger:{}", user_id))
+            .arg(input_mint)
+            .arg(input_amount)
+            .arg(output_mint)
+            .arg(output_amount)
+            .invoke_async(&mut *conn)
+            .await?;
+        Ok(swapped == 1)
+    }
+
+    pub async fn get_paper_balances(
+        &self,
+        user_id: &str,
+    ) -> Result<HashMap<String, i64>, RedisClientError> {
+        let mut conn = self.pool.get().await?;
+        let balances: HashMap<String, i64> = cmd("HGETALL")
+            .arg(format!("paper:ledger:{}", user_id))
+            .query_async(&mut *conn)
+            .await?;
+        Ok(balances)
+    }
+
+    pub async fn push_paper_fill(
+        &self,
+        user_id: &str,
+        fill: &PaperFill,
+    ) -> Result<(), RedisClientError> {
+        let mut conn = self.pool.get().await?;
+        let key = format!("paper:fills:{}", user_id);
+        let _: () = pipe()
+            .atomic()
+            .cmd("LPUSH")
+            .arg(&key)
+            .arg(serde_json::to_string(fill)?)
+            .ignore()
+            .cmd("LTRIM")
+            .arg(&key)
+            .arg(0)
+            .arg(PAPER_FILLS_MAXLEN - 1)
+            .ignore()
+            .query_async(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Appends the JSON encoded event under the `data` field, the stream is
+    /// trimmed to approximately `maxlen` entries; returns the entry id
+    pub async fn xadd<T: Serialize>(
+        &self,
+        stream: &str,
+        maxlen: usize,
+        event: &T,
+    ) -> Result<String, RedisClientError> {
+        let mut conn = self.pool.get().await?;
+        let id: String = cmd("XADD")
+            .arg(stream)
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(maxlen)
+            .arg("*")
+            .arg("data")
+            .arg(serde_json::to_string(event)?)
+            .query_async(&mut *conn)
+            .await?;
+        Ok(id)
+    }
+
+    /// Up to `count` entries of the stream, after the `after` id if given
+    pub async fn xrange<T: DeserializeOwned>(
+        &self,
+        stream: &str,
+        after: Option<&str>,
+        count: usize,
+    ) -> Result<Vec<(String, T)>, RedisClientError> {
+        let mut conn = self.pool.get().await?;
+        let start = after.map_or_else(|| "-".to_string(), |id| format!("({}", id));
+        let entries: Vec<(String, HashMap<String, String>)> = cmd("XRANGE")
+            .arg(stream)
+            .arg(start)
+            .arg("+")
+            .arg("COUNT")
+            .arg(count)
+            .query_async(&mut *conn)
+            .await?;
+
+        entries
+            .into_iter()
+            .filter_map(|(id, mut fields)| fields.remove("data").map(|data| (id, data)))
+            .map(|(id, data)| {
+                serde_json::from_str(&data)
+                    .map(|event| (id, event))
+                    .map_err(RedisClientError::DeserializeError)
+            })
+            .collect()
+    }
+
     pub async fn incr(&self, key: &str, increment: u32) -> Result<u32, RedisClientError> {
         let mut conn = self.pool.get().await?;
         let result: u32 = cmd("INCRBY")
@@ -4,6 +4,9 @@ use std::time::Duration;
 
 pub enum RateLimitType {
     EmailNotifications,
+    WebhookNotifications,
+    TelegramNotifications,
+    DiscordNotifications,
     ActivePipelines,
 }
 
@@ -26,26 +29,30 @@ impl RateLimitType {
     pub fn key(&self) -> &str {
         match self {
             RateLimitType::EmailNotifications => "email_notifications",
+            RateLimitType::WebhookNotifications => "webhook_notifications",
+            RateLimitType::TelegramNotifications => "telegram_notifications",
+            RateLimitType::DiscordNotifications => "discord_notifications",
             RateLimitType::ActivePipelines => "active_pipelines",
         }
     }
 
-    pub fn default_limit(&self, plan: Option<UserPlan>) -> u32 {
-        let plan = plan.unwrap_or(UserPlan::Free);
-
+    /// the limits do not depend on the plan yet
+    pub fn default_limit(&self, _plan: Option<UserPlan>) -> u32 {
         match self {
-            RateLimitType::EmailNotifications => match plan {
-                _ => 5,
-            },
-            RateLimitType::ActivePipelines => match plan {
-                _ => 1000,
-            },
+            RateLimitType::EmailNotifications => 5,
+            RateLimitType::WebhookNotifications => 500,
+            RateLimitType::TelegramNotifications => 100,
+            RateLimitType::DiscordNotifications => 100,
+            RateLimitType::ActivePipelines => 1000,
         }
     }
 
     pub fn default_window(&self) -> Duration {
         match self {
             RateLimitType::EmailNotifications => Duration::from_secs(24 * 60 * 60), // 24 hours
+            RateLimitType::WebhookNotifications
+            | RateLimitType::TelegramNotifications
+            | RateLimitType::DiscordNotifications => Duration::from_secs(24 * 60 * 60),
             RateLimitType::ActivePipelines => Duration::from_secs(0), // No expiry for active pipelines
         }
     }
@@ -53,7 +60,10 @@ impl RateLimitType {
     pub fn is_blocking(&self) -> bool {
         match self {
             RateLimitType::EmailNotifications => true, // Block when limit reached
-            RateLimitType::ActivePipelines => true,    // Block when limit reached
+            RateLimitType::WebhookNotifications
+            | RateLimitType::TelegramNotifications
+            | RateLimitType::DiscordNotifications => true,
+            RateLimitType::ActivePipelines => true, // Block when limit reached
         }
     }
 }
@@ -76,7 +86,7 @@ impl RedisClient {
         let ttl: Option<i64> = self.ttl(&key).await?;
 
         let count = count.unwrap_or(0);
-        let remaining = if count > limit { 0 } else { limit - count };
+        let remaining = limit.saturating_sub(count);
 
         // Convert TTL to reset timestamp if available
         let reset_at = ttl.and_then(|ttl| {
@@ -113,11 +123,7 @@ impl RedisClient {
         }
 
         let limit = self.get_user_limit(user_id, limit_type).await?;
-        let remaining = if new_count > limit {
-            0
-        } else {
-            limit - new_count
-        };
+        let remaining = limit.saturating_sub(new_count);
 
         // Get the TTL to determine when the limit resets
         let ttl: Option<i64> = self.ttl(&key).await?;
@@ -136,6 +142,28 @@ impl RedisClient {
         })
     }
 
+    /// Takes one unit of the limit, false if none was left; the counter is
+    /// incremented before the comparison so that concurrent callers can not
+    /// both take the last unit
+    pub async fn reserve_rate_limit(
+        &self,
+        user_id: &str,
+        limit_type: &RateLimitType,
+    ) -> Result<bool, RedisClientError> {
+        let key = format!("rate_limit:{}:{}", user_id, limit_type.key());
+
+        let new_count: u32 = self.incr(&key, 1).await?;
+        if new_count == 1 {
+            let window = limit_type.default_window();
+            if window.as_secs() > 0 {
+                self.expire(&key, window.as_secs() as usize).await?;
+            }
+        }
+
+        let limit = self.get_user_limit(user_id, limit_type).await?;
+        Ok(new_count <= limit)
+    }
+
     pub async fn check_rate_limit(
         &self,
         user_id: &str,
@@ -171,11 +199,7 @@ impl RedisClient {
                 let new_count: u32 = self.incr(&key, u32::MAX - 1 + 1).await?; // Equivalent to -1
 
                 let limit = self.get_user_limit(user_id, limit_type).await?;
-                let remaining = if new_count > limit {
-                    0
-                } else {
-                    limit - new_count
-                };
+                let remaining = limit.saturating_sub(new_count);
 
                 // Get the TTL
                 let ttl: Option<i64> = self.ttl(&key).await?;
@@ -23,6 +23,15 @@ pub struct PriceUpdate {
     pub multi_hop: bool,
     pub is_buy: bool,
     pub is_pump: bool,
+    /// false if the indexer had no metadata of the token, the name and the
+    /// market cap are empty then
+    #[serde(default = "metadata_known")]
+    pub has_metadata: bool,
+}
+
+/// updates from indexers that do not flag it
+fn metadata_known() -> bool {
+    true
 }
 
 #[derive(Error, Debug)]
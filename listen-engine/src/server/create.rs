@@ -30,6 +30,14 @@ pub async fn create_pipeline_common(
         }));
     }
 
+    if let Err(e) = wire.validate() {
+        metrics::counter!("pipeline_creation_errors_invalid", 1);
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Invalid pipeline: {}", e)
+        }));
+    }
+
     let pipeline: Pipeline = (wire, pipeline_params).into();
 
     tracing::info!(pipeline = ?pipeline, "creating pipeline");
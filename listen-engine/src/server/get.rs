@@ -1,6 +1,31 @@
 use super::state::{AppState, EngineMessage};
-use actix_web::{web::Data, HttpRequest, HttpResponse, Responder};
+use actix_web::{
+    web::{Data, Path, Query},
+    HttpRequest, HttpResponse, Responder,
+};
+use serde::Deserialize;
 use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::common::{handle_engine_response, verify_auth};
+use crate::engine::history::DEFAULT_EVENTS_PAGE;
+
+#[derive(Deserialize)]
+pub struct EventsParams {
+    /// id of the last event of the previous page
+    start: Option<String>,
+    count: Option<usize>,
+}
+
+/// Stream entry ids are `<millis>-<sequence>`
+fn is_stream_id(id: &str) -> bool {
+    id.split_once('-').is_some_and(|(millis, seq)| {
+        !millis.is_empty()
+            && !seq.is_empty()
+            && millis.bytes().all(|b| b.is_ascii_digit())
+            && seq.bytes().all(|b| b.is_ascii_digit())
+    })
+}
 
 pub async fn get_pipelines(state: Data<AppState>, req: HttpRequest) -> impl Responder {
     let auth_token = match req.headers().get("authorization") {
@@ -76,3 +101,46 @@ pub async fn get_pipelines(state: Data<AppState>, req: HttpRequest) -> impl Resp
         "pipelines": pipelines
     }))
 }
+
+pub async fn get_pipeline_events(
+    state: Data<AppState>,
+    req: HttpRequest,
+    path: Path<Uuid>,
+    params: Query<EventsParams>,
+) -> impl Responder {
+    let pipeline_id = path.into_inner();
+    let EventsParams { start, count } = params.into_inner();
+    if start.as_deref().is_some_and(|start| !is_stream_id(start)) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": "start must be the id of an event"
+        }));
+    }
+
+    // Authenticate user
+    let user = match verify_auth(&state, &req).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let (response_tx, response_rx) = oneshot::channel();
+
+    if let Err(e) = state
+        .engine_bridge_tx
+        .send(EngineMessage::GetPipelineEvents {
+            user_id: user.user_id.clone(),
+            pipeline_id,
+            start,
+            count: count.unwrap_or(DEFAULT_EVENTS_PAGE).max(1),
+            response_tx,
+        })
+        .await
+    {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Failed to communicate with engine: {}", e)
+        }));
+    }
+
+    handle_engine_response(response_rx, "Pipeline events").await
+}
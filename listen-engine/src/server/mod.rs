@@ -8,13 +8,14 @@ use std::sync::Arc;
 use tokio::sync::mpsc;
 
 use crate::{engine::Engine, metrics::metrics_handler, server::state::AppState};
-use privy::{config::PrivyConfig, Privy};
 
 pub mod cancel;
 pub mod common;
 pub mod create;
 pub mod get;
 pub mod internal;
+pub mod notifications;
+pub mod paper;
 pub mod state;
 
 pub async fn run() -> std::io::Result<()> {
@@ -30,10 +31,7 @@ pub async fn run() -> std::io::Result<()> {
         Ok((engine, rx)) => (engine, rx),
         Err(e) => {
             tracing::error!("Failed to create engine: {}", e);
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to create engine",
-            ));
+            return Err(std::io::Error::other("Failed to create engine"));
         }
     };
     let engine = Arc::new(engine);
@@ -49,9 +47,8 @@ pub async fn run() -> std::io::Result<()> {
         }
     });
 
-    let privy = Arc::new(Privy::new(PrivyConfig::from_env().map_err(|_| {
-        std::io::Error::new(std::io::ErrorKind::Other, "Failed to create privy config")
-    })?));
+    // the engine falls back to an empty config in paper-only mode
+    let privy = engine.privy.clone();
 
     // Create a shared AppState for both servers
     let app_state = Data::new(AppState {
@@ -82,6 +79,10 @@ pub async fn run() -> std::io::Result<()> {
             .route("/healthz", web::get().to(healthz))
             .route("/pipeline", web::post().to(create::create_pipeline))
             .route("/pipelines", web::get().to(get::get_pipelines))
+            .route(
+                "/pipeline/{pipeline_id}/events",
+                web::get().to(get::get_pipeline_events),
+            )
             .route(
                 "/pipeline/{pipeline_id}/cancel",
                 web::post().to(cancel::cancel_pipeline),
@@ -90,6 +91,15 @@ pub async fn run() -> std::io::Result<()> {
                 "/pipeline/{pipeline_id}/step/{step_id}/cancel",
                 web::post().to(cancel::cancel_step),
             )
+            .route("/paper/balances", web::get().to(paper::get_paper_balances))
+            .route(
+                "/notifications/settings",
+                web::get().to(notifications::get_notification_settings),
+            )
+            .route(
+                "/notifications/settings",
+                web::put().to(notifications::set_notification_settings),
+            )
             .route("/metrics", web::get().to(metrics_handler))
     })
     .bind(("0.0.0.0", 6966))?;
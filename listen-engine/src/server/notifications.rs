@@ -0,0 +1,72 @@
+use super::state::{AppState, EngineMessage};
+use crate::engine::notifications::NotificationSettings;
+use actix_web::{
+    web::{self, Data},
+    HttpRequest, HttpResponse, Responder,
+};
+use tokio::sync::oneshot;
+
+use super::common::{handle_engine_response, verify_auth};
+
+pub async fn get_notification_settings(state: Data<AppState>, req: HttpRequest) -> impl Responder {
+    let user = match verify_auth(&state, &req).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let (response_tx, response_rx) = oneshot::channel();
+
+    if let Err(e) = state
+        .engine_bridge_tx
+        .send(EngineMessage::GetNotificationSettings {
+            user_id: user.user_id.clone(),
+            response_tx,
+        })
+        .await
+    {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Failed to communicate with engine: {}", e)
+        }));
+    }
+
+    handle_engine_response(response_rx, "Notification settings").await
+}
+
+pub async fn set_notification_settings(
+    state: Data<AppState>,
+    req: HttpRequest,
+    settings: web::Json<NotificationSettings>,
+) -> impl Responder {
+    let user = match verify_auth(&state, &req).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let settings = settings.into_inner();
+    if let Err(e) = settings.validate() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Invalid notification settings: {}", e)
+        }));
+    }
+
+    let (response_tx, response_rx) = oneshot::channel();
+
+    if let Err(e) = state
+        .engine_bridge_tx
+        .send(EngineMessage::SetNotificationSettings {
+            user_id: user.user_id.clone(),
+            settings,
+            response_tx,
+        })
+        .await
+    {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Failed to communicate with engine: {}", e)
+        }));
+    }
+
+    handle_engine_response(response_rx, "Notification settings updated").await
+}
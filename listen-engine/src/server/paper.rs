@@ -0,0 +1,31 @@
+use super::state::{AppState, EngineMessage};
+use actix_web::{web::Data, HttpRequest, HttpResponse, Responder};
+use tokio::sync::oneshot;
+
+use super::common::{handle_engine_response, verify_auth};
+
+pub async fn get_paper_balances(state: Data<AppState>, req: HttpRequest) -> impl Responder {
+    // Authenticate user
+    let user = match verify_auth(&state, &req).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let (response_tx, response_rx) = oneshot::channel();
+
+    if let Err(e) = state
+        .engine_bridge_tx
+        .send(EngineMessage::GetPaperBalances {
+            user_id: user.user_id.clone(),
+            response_tx,
+        })
+        .await
+    {
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "status": "error",
+            "message": format!("Failed to communicate with engine: {}", e)
+        }));
+    }
+
+    handle_engine_response(response_rx, "Paper balances").await
+}
@@ -1,5 +1,8 @@
 use crate::engine::error::EngineError;
+use crate::engine::history::PipelineEvent;
+use crate::engine::notifications::NotificationSettings;
 use crate::engine::pipeline::Pipeline;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use privy::Privy;
@@ -38,6 +41,26 @@ pub enum EngineMessage {
         step_id: Uuid,
         response_tx: oneshot::Sender<Result<(), EngineError>>,
     },
+    GetPipelineEvents {
+        user_id: String,
+        pipeline_id: Uuid,
+        start: Option<String>,
+        count: usize,
+        response_tx: oneshot::Sender<Result<Vec<PipelineEvent>, EngineError>>,
+    },
+    GetPaperBalances {
+        user_id: String,
+        response_tx: oneshot::Sender<Result<HashMap<String, i64>, EngineError>>,
+    },
+    GetNotificationSettings {
+        user_id: String,
+        response_tx: oneshot::Sender<Result<NotificationSettings, EngineError>>,
+    },
+    SetNotificationSettings {
+        user_id: String,
+        settings: NotificationSettings,
+        response_tx: oneshot::Sender<Result<(), EngineError>>,
+    },
 }
 
 pub struct AppState {
@@ -54,7 +54,11 @@ import { RawTokenMetadataDisplay } from "./RawTokenMetadataDisplay";
 import { embedResearchAnchors } from "./ResearchOutput";
 import { RiskAnalysisDisplay, RiskAnalysisSchema } from "./RiskDisplay";
 import { TokenDisplay } from "./TokenDisplay";
-import { TopTokensDisplay, TopTokensResponseSchema } from "./TopTokensDisplay";
+import {
+  ScreenerPageSchema,
+  TopTokensDisplay,
+  TopTokensResponseSchema,
+} from "./TopTokensDisplay";
 import { TopicDisplay, TopicSchema } from "./TopicDisplay";
 
 const SplTokenBalanceSchema = z.tuple([z.string(), z.number(), z.string()]);
@@ -754,10 +758,15 @@ export const ToolMessage = ({
     }
   }
 
-  if (
-    toolOutput.name === "fetch_top_tokens" ||
-    toolOutput.name === "fetch_top_tokens_by_chain_id"
-  ) {
+  if (toolOutput.name === "fetch_top_tokens") {
+    try {
+      const parsed = ScreenerPageSchema.parse(JSON.parse(toolOutput.result));
+      return <TopTokensDisplay tokens={parsed.tokens} />;
+    } catch (e) {
+      console.error("Failed to parse screener response:", e);
+    }
+  }
+  if (toolOutput.name === "fetch_top_tokens_by_chain_id") {
     try {
       const parsed = TopTokensResponseSchema.parse(
         JSON.parse(toolOutput.result)
@@ -20,6 +20,28 @@ export const TopTokenSchema = z.object({
 
 export const TopTokensResponseSchema = z.array(TopTokenSchema);
 
+// the screener page, its volume and price change are over the screened
+// timeframe
+export const ScreenerPageSchema = z.object({
+  tokens: z.array(
+    z
+      .object({
+        name: z.string(),
+        pubkey: z.string(),
+        price: z.number(),
+        market_cap: z.number(),
+        volume: z.number(),
+        price_change: z.number(),
+      })
+      .transform(({ volume, price_change, ...token }) => ({
+        ...token,
+        volume_24h: volume,
+        price_change_24h: price_change,
+      }))
+  ),
+  next_offset: z.number().nullable().optional(),
+});
+
 type TopToken = z.infer<typeof TopTokenSchema>;
 
 interface TopTokensDisplayProps {
@@ -13,8 +13,8 @@ use crate::common::{openrouter_agent_builder, OpenRouterAgent};
 use crate::cross_chain::tools::{GetQuote, Swap};
 use crate::data::{
     AnalyzePageContent, FetchPriceActionAnalysis, FetchTokenMetadata,
-    FetchTopTokens, FetchXPost, GetToken, ResearchXProfile, SearchTweets,
-    SearchWeb,
+    FetchTopTokens, FetchWalletPnl, FetchXPost, GetToken, ResearchXProfile,
+    SearchTweets, SearchWeb,
 };
 use crate::dexscreener::tools::SearchOnDexScreener;
 use crate::faster100x::AnalyzeHolderDistribution;
@@ -74,6 +74,7 @@ pub fn equip_with_tools<M: StreamingCompletionModel>(
         .tool(FetchPriceActionAnalysis)
         .tool(Think)
         .tool(AnalyzeHolderDistribution)
+        .tool(FetchWalletPnl)
         .tool(AnalyzeSentiment)
         .tool(GetCurrentTime)
         .tool(SearchWeb)
@@ -5,7 +5,7 @@ use crate::{
         evm_fallback_tools::{
             FetchPriceActionAnalysisEvm, FetchTokenMetadataEvm,
         },
-        FetchTokenMetadata, FetchTokenPrice,
+        FetchTokenMetadata, FetchTokenPrice, FetchWalletPnl,
     },
     evm::tools::{GetErc20Balance, GetEthBalance},
     faster100x::AnalyzeHolderDistribution,
@@ -56,6 +56,7 @@ pub fn create_trader_agent(locale: String) -> GeminiAgent {
         .tool(FetchTokenMetadata)
         .tool(GetSolBalance)
         .tool(AnalyzeHolderDistribution)
+        .tool(FetchWalletPnl)
         .tool(GetSplTokenBalance)
         .tool(FetchTokenPrice)
         .tool(AnalyzeRisk)
@@ -8,6 +8,8 @@ use crate::{
 use anyhow::{anyhow, Result};
 use rig_tool_macro::tool;
 use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Candlestick {
@@ -121,55 +123,196 @@ pub async fn fetch_token_price(mint: String) -> Result<f64> {
     Ok(price)
 }
 
+/// A token of the Listen API screener, the volume and price change are over
+/// the screened timeframe
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScreenedToken {
+    pub name: String,
+    pub pubkey: String,
+    pub price: f64,
+    pub market_cap: f64,
+    pub volume: f64,
+    pub price_change: f64,
+    pub trades: u64,
+    pub buys: u64,
+    pub sells: u64,
+    pub unique_buyers: u64,
+    pub unique_sellers: u64,
+    pub unique_traders: u64,
+    pub buy_sell_ratio: f64,
+    pub top_trader_share: f64,
+    pub first_seen: u64,
+    pub is_pump: bool,
+}
+
+/// a page of the screener, `next_offset` is set when more tokens match
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScreenerPage {
+    pub tokens: Vec<ScreenedToken>,
+    pub next_offset: Option<usize>,
+}
+
+/// seconds, either plain or with a `m`, `h` or `d` suffix
+fn parse_timeframe(timeframe: &str) -> Result<u64> {
+    let timeframe = timeframe.trim().to_lowercase();
+    let (value, unit) = match timeframe.char_indices().last() {
+        Some((i, 'm')) => (&timeframe[..i], 60),
+        Some((i, 'h')) => (&timeframe[..i], 3600),
+        Some((i, 'd')) => (&timeframe[..i], 86400),
+        _ => (timeframe.as_str(), 1),
+    };
+    value
+        .parse::<u64>()
+        .map(|value| value * unit)
+        .map_err(|_| anyhow!("Invalid timeframe: {}", timeframe))
+}
+
 #[tool(description = "
-Fetch top Solana tokens based on volume from the Listen API.
+Screen Solana tokens with the Listen API, ranked over a chosen timeframe.
 
 Parameters:
-- limit (optional, string): number of tokens to return (default: 4)
-- min_market_cap (optional, string): minimum market cap filter (default: 1000000)
-- max_market_cap (optional, string): maximum market cap filter (default: no limit)
-- timeframe (optional, string): timeframe in seconds (default: 7200)
+- limit (optional, string): number of tokens to return (default: 4, max: 100)
+- sort_by (optional, string): one of volume, price_change, trades,
+  unique_buyers, buy_sell_ratio, market_cap, age (default: volume)
+- timeframe (optional, string): window the stats are computed over, e.g.
+  '15m', '1h', '24h' or seconds (default: 2h, max: 7d)
+- min_market_cap (optional, string): minimum market cap (default: 1000000)
+- max_market_cap (optional, string): maximum market cap (default: no limit)
+- min_volume (optional, string): minimum USD volume in the timeframe
+- min_price_change (optional, string): minimum price change in percent
+- min_unique_buyers (optional, string): minimum number of distinct buying wallets
+- min_buy_sell_ratio (optional, string): minimum buys per sell
+- max_top_trader_share (optional, string): maximum share of the volume coming
+  from the single most active wallet, 0 to 1; lower means more organic trading
+- max_age (optional, string): only tokens that first traded within this
+  long, same format as timeframe
+- only_pumpfun_tokens (optional, string): 'true' or 'false' (default: true)
+- offset (optional, string): skip this many tokens, for the next page
 
 Keep the default set of params unless the user asks for something different
 
-Returns a list of top tokens with their market data.
+Returns the tokens with price, market cap, volume, price change, trade
+and buy/sell counts, unique buyers, sellers and traders, the buy/sell ratio,
+the top trader share and when the token first traded (unix seconds), along
+with next_offset: pass it as offset to fetch the next page, it is null when
+there are no more matching tokens.
 ")]
+#[allow(clippy::too_many_arguments)]
 pub async fn fetch_top_tokens(
     limit: Option<String>,
+    sort_by: Option<String>,
+    timeframe: Option<String>,
     min_market_cap: Option<String>,
     max_market_cap: Option<String>,
-    timeframe: Option<String>,
-) -> Result<Vec<TopToken>> {
-    let limit = limit.unwrap_or("4".to_string());
-    let min_market_cap = min_market_cap.unwrap_or("1000000".to_string());
-    let max_market_cap = max_market_cap.unwrap_or("0".to_string());
-    let timeframe = timeframe.unwrap_or("7200".to_string());
-
-    let mut url = format!("{}/top-tokens", LISTEN_API_BASE);
-    let mut query_params = vec![];
-
-    query_params.push(format!("limit={}", limit));
-    query_params.push(format!("min_market_cap={}", min_market_cap));
-    if max_market_cap != "0" {
-        query_params.push(format!("max_market_cap={}", max_market_cap));
+    min_volume: Option<String>,
+    min_price_change: Option<String>,
+    min_unique_buyers: Option<String>,
+    min_buy_sell_ratio: Option<String>,
+    max_top_trader_share: Option<String>,
+    max_age: Option<String>,
+    only_pumpfun_tokens: Option<String>,
+    offset: Option<String>,
+) -> Result<ScreenerPage> {
+    let timeframe = parse_timeframe(&timeframe.unwrap_or("2h".to_string()))?;
+
+    let mut query_params = vec![
+        ("limit", limit.unwrap_or("4".to_string())),
+        ("sort_by", sort_by.unwrap_or("volume".to_string())),
+        ("timeframe", timeframe.to_string()),
+        (
+            "min_market_cap",
+            min_market_cap.unwrap_or("1000000".to_string()),
+        ),
+        (
+            "only_pumpfun_tokens",
+            only_pumpfun_tokens.unwrap_or("true".to_string()),
+        ),
+    ];
+    if let Some(max_age) = max_age {
+        query_params
+            .push(("max_age", parse_timeframe(&max_age)?.to_string()));
     }
-    query_params.push(format!("timeframe={}", timeframe));
-    query_params.push("only_pumpfun_tokens=true".to_string());
-
-    if !query_params.is_empty() {
-        url = format!("{}?{}", url, query_params.join("&"));
+    for (key, value) in [
+        ("max_market_cap", max_market_cap),
+        ("min_volume", min_volume),
+        ("min_price_change", min_price_change),
+        ("min_unique_buyers", min_unique_buyers),
+        ("min_buy_sell_ratio", min_buy_sell_ratio),
+        ("max_top_trader_share", max_top_trader_share),
+        ("offset", offset),
+    ] {
+        if let Some(value) = value {
+            query_params.push((key, value));
+        }
     }
 
-    let response = reqwest::get(&url)
+    let response = reqwest::Client::new()
+        .get(format!("{}/screener", LISTEN_API_BASE))
+        .query(&query_params)
+        .send()
         .await
         .map_err(|e| anyhow!("Failed to fetch top tokens: {}", e))?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Failed to fetch top tokens: {}",
+            response.text().await.unwrap_or_default()
+        ));
+    }
 
-    let tokens = response
-        .json::<Vec<TopToken>>()
+    response
+        .json::<ScreenerPage>()
         .await
-        .map_err(|e| anyhow!("Failed to parse response: {}", e))?;
+        .map_err(|e| anyhow!("Failed to parse response: {}", e))
+}
 
-    Ok(tokens)
+#[tool(description = "
+Fetch the trading performance of any Solana wallet from the swaps indexed by
+the Listen API.
+
+Parameters:
+- wallet (string): The wallet address (the fee payer of the swaps)
+- mint (optional, string): only the trades of this token
+
+Returns the FIFO cost basis ledger of the wallet:
+- realized, unrealized and total PnL in USD
+- win rate, the share of the tokens sold at a profit
+- per token: buys, sells, amount still held, cost basis, PnL and win rate of
+  the sells
+
+Use this to tell whether a wallet is a good trader. Sells of tokens bought
+before the indexed history have no cost basis and are left out of the PnL.
+")]
+pub async fn fetch_wallet_pnl(
+    wallet: String,
+    mint: Option<String>,
+) -> Result<serde_json::Value> {
+    if Pubkey::from_str(&wallet).is_err() {
+        return Err(anyhow!(
+            "Invalid wallet: {}, this has to be a Base58 string",
+            wallet
+        ));
+    }
+
+    let mut url = format!("{}/wallet-pnl?owner={}", LISTEN_API_BASE, wallet);
+    if let Some(mint) = mint {
+        validate_mint(&mint)?;
+        url = format!("{}&mint={}", url, mint);
+    }
+
+    let response = reqwest::get(&url)
+        .await
+        .map_err(|e| anyhow!("Failed to fetch wallet pnl: {}", e))?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Failed to fetch wallet pnl: {}",
+            response.text().await.unwrap_or_default()
+        ));
+    }
+
+    response
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| anyhow!("Failed to parse response: {}", e))
 }
 
 #[tool(description = "
@@ -277,14 +420,32 @@ mod tests {
     async fn test_fetch_top_tokens() {
         fetch_top_tokens(
             Some("10".to_string()),
-            Some("1000000000000000000".to_string()),
-            Some("1000000000000000000".to_string()),
+            Some("unique_buyers".to_string()),
             Some("1d".to_string()),
+            Some("1000000".to_string()),
+            None,
+            None,
+            None,
+            Some("10".to_string()),
+            None,
+            Some("0.5".to_string()),
+            Some("7d".to_string()),
+            None,
+            None,
         )
         .await
         .unwrap();
     }
 
+    #[test]
+    fn test_parse_timeframe() {
+        assert_eq!(parse_timeframe("7200").unwrap(), 7200);
+        assert_eq!(parse_timeframe("15m").unwrap(), 900);
+        assert_eq!(parse_timeframe("24H").unwrap(), 86400);
+        assert_eq!(parse_timeframe("7d").unwrap(), 604800);
+        assert!(parse_timeframe("soon").is_err());
+    }
+
     #[tokio::test]
     async fn test_fetch_price_action_analysis() {
         // FIXME thread local signer needs init
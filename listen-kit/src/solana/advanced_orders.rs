@@ -12,7 +12,9 @@ pub struct Order {
     pub amount: String,
     pub price: f64,
     pub price_asset_mint: String,
-    pub condition: String, // "PriceBelow" or "PriceAbove"
+    pub condition: String, // see create_advanced_order for the conditions
+    #[serde(default)]
+    pub targets: Vec<f64>,
     pub user_id: String,
 }
 
@@ -23,9 +25,12 @@ Params:
   input_token: the mint of the token to be swapped
   output_token: the mint of the token to be received
   amount: the amount of the input token to be swapped, accounting for decimals
-  price: the price of the price_asset_mint, denoted in USD
+  price: the price of the price_asset_mint, denoted in USD; for \"TrailingStop\" it is the trail percentage (e.g. 10 for 10%)
   price_asset_mint: the mint of the asset of which price is checked by the condition.
-  condition: the condition of the order. Can be \"PriceBelow\" or \"PriceAbove\".
+  condition: the condition of the order. Can be \"PriceBelow\", \"PriceAbove\", \"TrailingStop\" or \"TakeProfitLadder\".
+    \"TrailingStop\" executes once the price drops by the trail percentage from the highest price seen since the order was created.
+    \"TakeProfitLadder\" executes a part of the amount every time one of the targets is reached.
+  targets (optional): comma-separated USD target prices of the price_asset_mint, required for \"TakeProfitLadder\", e.g. \"0.1,0.15,0.2\"
 
 returns the ID of the submitted order
 ")]
@@ -35,7 +40,8 @@ pub async fn create_advanced_order(
     amount: String,
     price: f64,
     price_asset_mint: String,
-    condition: String, // "PriceBelow" or "PriceAbove"
+    condition: String,
+    targets: Option<String>,
 ) -> Result<String> {
     let user_id = match SignerContext::current().await.user_id() {
         Some(user_id) => user_id,
@@ -45,6 +51,15 @@ pub async fn create_advanced_order(
             ))
         }
     };
+    let targets = match targets {
+        Some(targets) => parse_targets(&targets)?,
+        None => vec![],
+    };
+    if condition == "TakeProfitLadder" && targets.is_empty() {
+        return Err(anyhow::anyhow!(
+            "TakeProfitLadder requires at least one target price"
+        ));
+    }
     let order = Order {
         input_token,
         output_token,
@@ -52,12 +67,25 @@ pub async fn create_advanced_order(
         price,
         price_asset_mint,
         condition,
+        targets,
         user_id,
     };
 
     submit_order_internal(&order).await
 }
 
+fn parse_targets(targets: &str) -> Result<Vec<f64>> {
+    targets
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(|t| {
+            t.parse::<f64>()
+                .map_err(|e| anyhow::anyhow!("Invalid target {}: {}", t, e))
+        })
+        .collect()
+}
+
 pub fn list_orders() -> Result<Vec<Order>> {
     Ok(vec![])
 }
@@ -79,6 +107,7 @@ pub async fn submit_order_internal(order: &Order) -> Result<String> {
                             "type": order.condition,
                             "asset": order.price_asset_mint,
                             "value": order.price,
+                            "targets": order.targets,
                         }
                     ]
                 }
@@ -139,9 +168,19 @@ mod tests {
                 .to_string(),
             condition: "PriceBelow".to_string(),
             price: 0.00019,
+            targets: vec![],
             user_id: "did:privy:cm6cxky3i00ondmuatkemmffm".to_string(),
         };
         let id = submit_order_internal(&order).await.unwrap();
         tracing::info!("{}", id);
     }
+
+    #[test]
+    fn test_parse_targets() {
+        assert_eq!(
+            parse_targets("0.1, 0.15,0.2").unwrap(),
+            vec![0.1, 0.15, 0.2]
+        );
+        assert!(parse_targets("0.1,abc").is_err());
+    }
 }